@@ -0,0 +1,15 @@
+pub mod canalysis;
+pub mod ceffects;
+pub mod cmidi;
+pub mod cmidiplayer;
+pub mod cmidisynth;
+pub mod cmixer;
+pub mod cresample;
+pub mod csfx;
+pub mod csink;
+pub mod csound;
+pub mod csoundfont;
+pub mod cspatial;
+pub mod cstream;
+pub mod csynth;
+pub mod cwav;
\ No newline at end of file
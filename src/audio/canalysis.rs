@@ -0,0 +1,762 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::audio::csound::Sound;
+use crate::graphics::craster::CRaster;
+
+///Complex FFT of a fixed power-of-two size, with twiddle factors and bit-reversal computed once.
+pub struct Fft {
+    size: usize,
+    twiddles: Vec<(f32, f32)>,
+    reversed: Vec<usize>,
+}
+
+impl Fft {
+
+    pub fn new(size: usize) -> Fft {
+        let size: usize = size.max(1).next_power_of_two();
+        let bits: u32 = size.trailing_zeros();
+        Fft {
+            size,
+            twiddles: (0..size / 2).map(|k| {
+                let a: f64 = -2.0*PI*k as f64 / size as f64;
+                (a.cos() as f32, a.sin() as f32)
+            }).collect(),
+            reversed: (0..size).map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) }).collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    ///In-place forward transform of `data`, which must hold `size` (re, im) pairs.
+    pub fn process(&self, data: &mut [(f32, f32)]) {
+        let n: usize = self.size;
+        for i in 0..n {
+            let j: usize = self.reversed[i];
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+        //Iterative radix-2 butterflies
+        let mut len: usize = 2;
+        while len <= n {
+            let stride: usize = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (wr, wi) = self.twiddles[k*stride];
+                    let (ar, ai) = data[start + k];
+                    let (br, bi) = data[start + k + len / 2];
+                    let (tr, ti) = (br*wr - bi*wi, br*wi + bi*wr);
+                    data[start + k] = (ar + tr, ai + ti);
+                    data[start + k + len / 2] = (ar - tr, ai - ti);
+                }
+            }
+            len *= 2;
+        }
+    }
+
+}
+
+///FFT of real input, computed with a complex FFT of half the size.
+pub struct RealFft {
+    size: usize,
+    half: Fft,
+    twiddles: Vec<(f32, f32)>,
+    buffer: Vec<(f32, f32)>,
+}
+
+impl RealFft {
+
+    ///`size` is rounded up to a power of two, at least 2.
+    pub fn new(size: usize) -> RealFft {
+        let size: usize = size.max(2).next_power_of_two();
+        RealFft {
+            size,
+            half: Fft::new(size / 2),
+            twiddles: (0..size / 2).map(|k| {
+                let a: f64 = -2.0*PI*k as f64 / size as f64;
+                (a.cos() as f32, a.sin() as f32)
+            }).collect(),
+            buffer: vec![(0.0, 0.0); size / 2],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    ///Transforms `size` real samples (missing ones count as zero) into `size / 2 + 1` bins, from 0 Hz to Nyquist.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<(f32, f32)>) {
+        let n: usize = self.size / 2;
+        let sample = |i: usize| input.get(i).copied().unwrap_or(0.0);
+        //Even samples go in the real part and odd samples in the imaginary part
+        for k in 0..n {
+            self.buffer[k] = (sample(2*k), sample(2*k + 1));
+        }
+        self.half.process(&mut self.buffer);
+
+        output.clear();
+        output.reserve(n + 1);
+        for k in 0..=n {
+            let z: (f32, f32) = self.buffer[k % n];
+            let zc: (f32, f32) = {
+                let m: (f32, f32) = self.buffer[(n - k) % n];
+                (m.0, -m.1)
+            };
+            //Split into the spectra of the even and odd samples, then combine with the twiddle
+            let even: (f32, f32) = ((z.0 + zc.0)*0.5, (z.1 + zc.1)*0.5);
+            let odd: (f32, f32) = ((z.1 - zc.1)*0.5, -(z.0 - zc.0)*0.5);
+            let w: (f32, f32) = if k < n { self.twiddles[k] } else { (-1.0, 0.0) };
+            let t: (f32, f32) = (odd.0*w.0 - odd.1*w.1, odd.0*w.1 + odd.1*w.0);
+            output.push((even.0 + t.0, even.1 + t.1));
+        }
+    }
+
+}
+
+///Taper applied to a block before the FFT, trading frequency resolution for less leakage between bins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let n: f64 = (size.max(2) - 1) as f64;
+        (0..size)
+            .map(|i| {
+                let x: f64 = 2.0*PI*i as f64 / n;
+                (match self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => 0.5 - 0.5*x.cos(),
+                    Window::Hamming => 0.54 - 0.46*x.cos(),
+                    Window::Blackman => 0.42 - 0.5*x.cos() + 0.08*(2.0*x).cos(),
+                }) as f32
+            })
+            .collect()
+    }
+
+}
+
+///RMS and peak levels per channel with meter ballistics: peaks jump up at once and fall back at `release` seconds
+/// per 20 dB.
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    pub release: f32,
+    ///Seconds the highest peak is held before it starts to fall.
+    pub hold: f32,
+    rms: Vec<f32>,
+    peak: Vec<f32>,
+    held: Vec<(f32, f32)>,
+}
+
+impl LevelMeter {
+
+    pub fn new() -> LevelMeter {
+        LevelMeter { release: 0.3, hold: 1.0, rms: Vec::new(), peak: Vec::new(), held: Vec::new() }
+    }
+
+    ///Measures a block of interleaved samples lasting `samples.len() / channels / sample_rate` seconds.
+    pub fn process(&mut self, samples: &[f32], channels: usize, sample_rate: u32) {
+        let channels: usize = channels.max(1);
+        let frames: usize = samples.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        self.rms.resize(channels, 0.0);
+        self.peak.resize(channels, 0.0);
+        self.held.resize(channels, (0.0, 0.0));
+        let seconds: f32 = frames as f32 / sample_rate.max(1) as f32;
+        let fall: f32 = 0.1f32.powf(seconds / self.release.max(1e-3));
+        for c in 0..channels {
+            let mut sum: f32 = 0.0;
+            let mut peak: f32 = 0.0;
+            for frame in samples.chunks_exact(channels) {
+                sum += frame[c]*frame[c];
+                peak = peak.max(frame[c].abs());
+            }
+            self.rms[c] = (sum / frames as f32).sqrt();
+            self.peak[c] = peak.max(self.peak[c]*fall);
+            let (held, age) = self.held[c];
+            self.held[c] = if peak >= held || age + seconds > self.hold { (peak.max(held*fall), 0.0) } else { (held, age + seconds) };
+        }
+    }
+
+    ///RMS of the last block, per channel.
+    pub fn rms(&self) -> &[f32] {
+        &self.rms
+    }
+
+    ///Peak with release, per channel.
+    pub fn peak(&self) -> &[f32] {
+        &self.peak
+    }
+
+    ///Highest recent peak, per channel.
+    pub fn peak_hold(&self) -> Vec<f32> {
+        self.held.iter().map(|h| h.0).collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.rms.clear();
+        self.peak.clear();
+        self.held.clear();
+    }
+
+}
+
+impl Default for LevelMeter {
+    fn default() -> LevelMeter {
+        LevelMeter::new()
+    }
+}
+
+///Finds note and drum onsets from rises in the spectrum (spectral flux), and estimates the tempo from the spacing
+/// between them.
+#[derive(Clone, Debug)]
+pub struct OnsetDetector {
+    ///How far above the recent average flux counts as an onset, in standard deviations.
+    pub sensitivity: f32,
+    ///Shortest gap between onsets, in seconds.
+    pub min_interval: f64,
+    previous: Vec<f32>,
+    flux: VecDeque<f32>,
+    onsets: VecDeque<f64>,
+    last: Option<f64>,
+}
+
+///Flux values the adaptive threshold looks back over.
+const FLUX_HISTORY: usize = 43;
+///Onset times kept for the tempo estimate.
+const ONSET_HISTORY: usize = 32;
+
+impl OnsetDetector {
+
+    pub fn new() -> OnsetDetector {
+        OnsetDetector { sensitivity: 1.5, min_interval: 0.1, previous: Vec::new(), flux: VecDeque::new(), onsets: VecDeque::new(), last: None }
+    }
+
+    ///Feeds the magnitude spectrum of the block ending at `time` seconds. Returns true on an onset.
+    pub fn process(&mut self, magnitudes: &[f32], time: f64) -> bool {
+        //Only increases count, compressed so quiet instruments still register
+        let flux: f32 = if self.previous.len() == magnitudes.len() {
+            magnitudes.iter().zip(self.previous.iter()).map(|(m, p)| ((1.0 + 100.0*m).ln() - (1.0 + 100.0*p).ln()).max(0.0)).sum()
+        }
+        else {
+            0.0
+        };
+        self.previous.clear();
+        self.previous.extend_from_slice(magnitudes);
+
+        let n: f32 = self.flux.len() as f32;
+        let mean: f32 = if n > 0.0 { self.flux.iter().sum::<f32>() / n } else { 0.0 };
+        let deviation: f32 = if n > 0.0 { (self.flux.iter().map(|f| (f - mean)*(f - mean)).sum::<f32>() / n).sqrt() } else { 0.0 };
+        self.flux.push_back(flux);
+        if self.flux.len() > FLUX_HISTORY {
+            self.flux.pop_front();
+        }
+
+        let ready: bool = self.last.is_none_or(|last| time - last >= self.min_interval);
+        let onset: bool = n >= 4.0 && ready && flux > mean + self.sensitivity*deviation && flux > 1e-3;
+        if onset {
+            self.last = Some(time);
+            self.onsets.push_back(time);
+            if self.onsets.len() > ONSET_HISTORY {
+                self.onsets.pop_front();
+            }
+        }
+        onset
+    }
+
+    ///Time of the latest onset, in seconds.
+    pub fn last_onset(&self) -> Option<f64> {
+        self.last
+    }
+
+    ///Tempo in beats per minute, folded into 60..180, from the most common gap between recent onsets.
+    pub fn bpm(&self) -> Option<f32> {
+        if self.onsets.len() < 4 {
+            return None;
+        }
+        //Vote in 1 BPM bins, letting each gap also count for its double and half
+        let mut votes: [f32; 121] = [0.0; 121];
+        let times: Vec<f64> = self.onsets.iter().copied().collect();
+        for (i, a) in times.iter().enumerate() {
+            for b in times[i + 1..].iter().take(4) {
+                let mut bpm: f64 = 60.0 / (b - a);
+                if !bpm.is_finite() || bpm <= 0.0 {
+                    continue;
+                }
+                while bpm < 60.0 {
+                    bpm *= 2.0;
+                }
+                while bpm >= 180.0 {
+                    bpm /= 2.0;
+                }
+                let bin: usize = (bpm.round() as usize - 60).min(120);
+                votes[bin] += 1.0;
+                if bin > 0 {
+                    votes[bin - 1] += 0.5;
+                }
+                if bin < 120 {
+                    votes[bin + 1] += 0.5;
+                }
+            }
+        }
+        let (best, score) = votes.iter().enumerate().fold((0, 0.0), |b, (i, v)| if *v > b.1 { (i, *v) } else { b });
+        if score < 2.0 { None } else { Some((best + 60) as f32) }
+    }
+
+    ///1 at the latest onset, fading to 0 over `decay` seconds, for visuals that pulse on the beat.
+    pub fn pulse(&self, time: f64, decay: f64) -> f32 {
+        match self.last {
+            Some(last) if time >= last => (1.0 - (time - last) / decay.max(1e-6)).max(0.0) as f32,
+            _ => 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.flux.clear();
+        self.onsets.clear();
+        self.last = None;
+    }
+
+}
+
+impl Default for OnsetDetector {
+    fn default() -> OnsetDetector {
+        OnsetDetector::new()
+    }
+}
+
+///Keeps the most recent audio and turns it into a magnitude spectrum, log-spaced bands, levels and onsets. Feed it
+/// the mixer's output (or any interleaved samples) with `push`; it analyzes once every `hop` frames.
+pub struct AudioAnalyzer {
+    pub sample_rate: u32,
+    pub meter: LevelMeter,
+    pub onsets: OnsetDetector,
+    ///Frames between analyses. Defaults to half the FFT size.
+    pub hop: usize,
+    fft: RealFft,
+    window: Vec<f32>,
+    window_gain: f32,
+    history: Vec<f32>,
+    write: usize,
+    since_analysis: usize,
+    frames: u64,
+    scratch: Vec<f32>,
+    bins: Vec<(f32, f32)>,
+    magnitudes: Vec<f32>,
+    onset: bool,
+}
+
+impl AudioAnalyzer {
+
+    ///`size` is the FFT size, rounded up to a power of two; 2048 resolves about 23 Hz at 48 kHz.
+    pub fn new(size: usize, sample_rate: u32, window: Window) -> AudioAnalyzer {
+        let fft: RealFft = RealFft::new(size);
+        let size: usize = fft.size();
+        let window: Vec<f32> = window.coefficients(size);
+        let window_gain: f32 = window.iter().sum::<f32>().max(1e-9);
+        AudioAnalyzer {
+            sample_rate,
+            meter: LevelMeter::new(),
+            onsets: OnsetDetector::new(),
+            hop: size / 2,
+            fft,
+            window,
+            window_gain,
+            history: vec![0.0; size],
+            write: 0,
+            since_analysis: 0,
+            frames: 0,
+            scratch: vec![0.0; size],
+            bins: Vec::new(),
+            magnitudes: vec![0.0; size / 2 + 1],
+            onset: false,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.fft.size()
+    }
+
+    ///Adds interleaved samples, mixed down to mono for the spectrum. Returns true if an onset was found in them.
+    pub fn push(&mut self, samples: &[f32], channels: usize) -> bool {
+        let channels: usize = channels.max(1);
+        self.meter.process(samples, channels, self.sample_rate);
+        let mut onset: bool = false;
+        for frame in samples.chunks_exact(channels) {
+            self.history[self.write] = frame.iter().sum::<f32>() / channels as f32;
+            self.write = (self.write + 1) % self.history.len();
+            self.frames += 1;
+            self.since_analysis += 1;
+            if self.since_analysis >= self.hop.max(1) {
+                self.since_analysis = 0;
+                self.analyze();
+                onset |= self.onset;
+            }
+        }
+        self.onset = onset;
+        onset
+    }
+
+    ///Recomputes the spectrum from the most recent `size` frames.
+    pub fn analyze(&mut self) {
+        let size: usize = self.history.len();
+        for i in 0..size {
+            self.scratch[i] = self.history[(self.write + i) % size]*self.window[i];
+        }
+        self.fft.process(&self.scratch, &mut self.bins);
+        //Scaled so a full-scale sine peaks at 1 whatever the window
+        let scale: f32 = 2.0 / self.window_gain;
+        for (m, (re, im)) in self.magnitudes.iter_mut().zip(self.bins.iter()) {
+            *m = (re*re + im*im).sqrt()*scale;
+        }
+        let time: f64 = self.frames as f64 / self.sample_rate.max(1) as f64;
+        self.onset = self.onsets.process(&self.magnitudes, time);
+    }
+
+    ///Linear magnitude of each FFT bin, from 0 Hz to Nyquist.
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32*self.sample_rate as f32 / self.size() as f32
+    }
+
+    ///Groups the spectrum into `count` bands spaced evenly in pitch between `min_hz` and `max_hz`, each the peak
+    /// magnitude within it in dB (0 dB is a full-scale sine).
+    pub fn bands(&self, count: usize, min_hz: f32, max_hz: f32) -> Vec<f32> {
+        let bin_hz: f32 = self.sample_rate as f32 / self.size() as f32;
+        let last: usize = self.magnitudes.len() - 1;
+        let ratio: f32 = (max_hz.max(min_hz*1.001) / min_hz.max(1.0)).ln();
+        (0..count)
+            .map(|b| {
+                let lo: f32 = min_hz.max(1.0)*(ratio*b as f32 / count as f32).exp();
+                let hi: f32 = min_hz.max(1.0)*(ratio*(b + 1) as f32 / count as f32).exp();
+                let (first, end) = ((lo / bin_hz).round() as usize, (hi / bin_hz).round() as usize);
+                //Low bands can be narrower than a bin; they share the nearest one
+                let first: usize = first.min(last);
+                let end: usize = end.clamp(first + 1, last + 1);
+                let peak: f32 = self.magnitudes[first..end].iter().fold(0.0, |a, m| a.max(*m));
+                20.0*peak.max(1e-10).log10()
+            })
+            .collect()
+    }
+
+    ///Whether the latest `push` contained an onset.
+    pub fn onset(&self) -> bool {
+        self.onset
+    }
+
+    ///Seconds of audio pushed so far.
+    pub fn time(&self) -> f64 {
+        self.frames as f64 / self.sample_rate.max(1) as f64
+    }
+
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.magnitudes.fill(0.0);
+        self.since_analysis = 0;
+        self.frames = 0;
+        self.meter.reset();
+        self.onsets.reset();
+    }
+
+}
+
+impl Sound {
+
+    pub fn peak(&self) -> f32 {
+        self.samples.iter().fold(0.0, |a, s| a.max(s.abs()))
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        (self.samples.iter().map(|s| s*s).sum::<f32>() / self.samples.len() as f32).sqrt()
+    }
+
+    ///Magnitude spectrum of `size` frames starting at `seconds`, as `AudioAnalyzer::magnitudes` gives.
+    pub fn spectrum_at(&self, seconds: f64, size: usize, window: Window) -> Vec<f32> {
+        let mut analyzer: AudioAnalyzer = AudioAnalyzer::new(size, self.sample_rate, window);
+        let channels: usize = self.channels.max(1) as usize;
+        let start: usize = ((seconds*self.sample_rate as f64).max(0.0) as usize*channels).min(self.samples.len());
+        let end: usize = (start + analyzer.size()*channels).min(self.samples.len());
+        analyzer.hop = usize::MAX;
+        analyzer.push(&self.samples[start..end], channels);
+        analyzer.analyze();
+        analyzer.magnitudes
+    }
+
+    ///Times in seconds of every onset in the sound, and its tempo estimate.
+    pub fn onsets(&self) -> (Vec<f64>, Option<f32>) {
+        let mut analyzer: AudioAnalyzer = AudioAnalyzer::new(1024, self.sample_rate, Window::Hann);
+        let channels: usize = self.channels.max(1) as usize;
+        let mut times: Vec<f64> = Vec::new();
+        for block in self.samples.chunks(analyzer.hop*channels) {
+            if analyzer.push(block, channels) {
+                times.push(analyzer.onsets.last_onset().unwrap_or(0.0));
+            }
+        }
+        (times, analyzer.onsets.bpm())
+    }
+
+}
+
+impl CRaster {
+
+    ///Draws `bands` (in dB, as `AudioAnalyzer::bands` gives) as bars filling the rectangle `(x, y, w, h)`, with
+    /// `floor_db` at the bottom and 0 dB at the top.
+    pub fn draw_spectrum(&mut self, bands: &[f32], floor_db: f32, rect: (i32, i32, i32, i32), color: u32) {
+        let (x, y, w, h) = rect;
+        if bands.is_empty() || w <= 0 || h <= 0 {
+            return;
+        }
+        for px in 0..w {
+            let band: f32 = bands[(px as usize*bands.len() / w as usize).min(bands.len() - 1)];
+            let level: f32 = ((band - floor_db) / -floor_db.min(-1e-3)).clamp(0.0, 1.0);
+            let top: i32 = h - (level*h as f32).round() as i32;
+            for py in top..h {
+                self.set_clipped(x + px, y + py, color);
+            }
+        }
+    }
+
+    ///Draws interleaved `samples`, mixed down to mono, as a min/max trace across the rectangle `(x, y, w, h)`.
+    pub fn draw_waveform(&mut self, samples: &[f32], channels: usize, rect: (i32, i32, i32, i32), color: u32) {
+        let (x, y, w, h) = rect;
+        let channels: usize = channels.max(1);
+        let frames: usize = samples.len() / channels;
+        if frames == 0 || w <= 0 || h <= 0 {
+            return;
+        }
+        let mid: f32 = (h - 1) as f32*0.5;
+        let to_y = |s: f32| y + (mid - s.clamp(-1.0, 1.0)*mid).round() as i32;
+        let mut previous: Option<(i32, i32)> = None;
+        for px in 0..w {
+            let (a, b) = (px as usize*frames / w as usize, ((px + 1) as usize*frames / w as usize).max(px as usize*frames / w as usize + 1).min(frames));
+            let (mut lo, mut hi) = (f32::MAX, f32::MIN);
+            for frame in samples[a*channels..b*channels].chunks_exact(channels) {
+                let s: f32 = frame.iter().sum::<f32>() / channels as f32;
+                lo = lo.min(s);
+                hi = hi.max(s);
+            }
+            let (mut top, mut bottom) = (to_y(hi), to_y(lo));
+            //Join to the previous column so fast waves do not break into dots
+            if let Some((pt, pb)) = previous {
+                top = top.min(pb);
+                bottom = bottom.max(pt);
+            }
+            for py in top..=bottom {
+                self.set_clipped(x + px, py, color);
+            }
+            previous = Some((to_y(hi), to_y(lo)));
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn naive_dft(input: &[f32]) -> Vec<(f32, f32)> {
+        let n: usize = input.len();
+        (0..=n / 2)
+            .map(|k| {
+                let (mut re, mut im): (f64, f64) = (0.0, 0.0);
+                for (i, x) in input.iter().enumerate() {
+                    let a: f64 = -2.0*PI*(k*i) as f64 / n as f64;
+                    re += *x as f64*a.cos();
+                    im += *x as f64*a.sin();
+                }
+                (re as f32, im as f32)
+            })
+            .collect()
+    }
+
+    fn sine(frequency: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| (i as f32*frequency*std::f32::consts::TAU / sample_rate as f32).sin()).collect()
+    }
+
+    //A short decaying burst every `interval` seconds
+    fn clicks(interval: f64, seconds: f64, sample_rate: u32) -> Sound {
+        let frames: usize = (seconds*sample_rate as f64) as usize;
+        let period: usize = (interval*sample_rate as f64) as usize;
+        let mut noise: crate::audio::csynth::Noise = crate::audio::csynth::Noise::new(crate::audio::csynth::NoiseColor::White, 1);
+        Sound::new(sample_rate, 1, (0..frames).map(|i| noise.next_sample()*(-((i % period) as f32) / 200.0).exp()).collect())
+    }
+
+    #[test]
+    fn real_fft_matches_a_naive_dft() {
+        for size in [2, 4, 16, 64] {
+            let input: Vec<f32> = (0..size).map(|i| ((i*7 + 3) % 11) as f32 / 11.0 - 0.4).collect();
+            let mut fft: RealFft = RealFft::new(size);
+            let mut output: Vec<(f32, f32)> = Vec::new();
+            fft.process(&input, &mut output);
+            let expected: Vec<(f32, f32)> = naive_dft(&input);
+            assert_eq!(output.len(), size / 2 + 1);
+            for (k, (a, b)) in output.iter().zip(expected.iter()).enumerate() {
+                assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4, "size {} bin {}: {:?} vs {:?}", size, k, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn complex_fft_of_an_impulse_is_flat() {
+        let fft: Fft = Fft::new(8);
+        let mut data: Vec<(f32, f32)> = vec![(0.0, 0.0); 8];
+        data[0] = (1.0, 0.0);
+        fft.process(&mut data);
+        assert!(data.iter().all(|(re, im)| (re - 1.0).abs() < 1e-6 && im.abs() < 1e-6));
+        assert_eq!(Fft::new(5).size(), 8);
+        assert_eq!(RealFft::new(0).size(), 2);
+    }
+
+    #[test]
+    fn short_input_is_zero_padded() {
+        let mut fft: RealFft = RealFft::new(8);
+        let mut output: Vec<(f32, f32)> = Vec::new();
+        fft.process(&[1.0, 1.0], &mut output);
+        let expected: Vec<(f32, f32)> = naive_dft(&[1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!(output.iter().zip(expected.iter()).all(|(a, b)| (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5));
+    }
+
+    #[test]
+    fn windows_are_symmetric_and_tapered() {
+        for window in [Window::Hann, Window::Hamming, Window::Blackman] {
+            let c: Vec<f32> = window.coefficients(9);
+            assert!((c[4] - 1.0).abs() < 1e-6, "{:?}", window);
+            assert!(c.iter().zip(c.iter().rev()).all(|(a, b)| (a - b).abs() < 1e-6));
+            assert!(c[0] < 0.1);
+        }
+        assert_eq!(Window::Rectangular.coefficients(3), vec![1.0; 3]);
+        assert_eq!(Window::Hann.coefficients(1).len(), 1);
+    }
+
+    #[test]
+    fn sine_peaks_in_its_bin_at_full_scale() {
+        //1 kHz lands exactly on bin 32 of 1024 at 32 kHz
+        for window in [Window::Rectangular, Window::Hann, Window::Blackman] {
+            let sound: Sound = Sound::new(32000, 1, sine(1000.0, 32000, 4096));
+            let magnitudes: Vec<f32> = sound.spectrum_at(0.0, 1024, window);
+            let peak: usize = magnitudes.iter().enumerate().fold(0, |b, (i, m)| if *m > magnitudes[b] { i } else { b });
+            assert_eq!(peak, 32, "{:?}", window);
+            assert!((magnitudes[peak] - 1.0).abs() < 0.02, "{:?} peak {}", window, magnitudes[peak]);
+        }
+    }
+
+    #[test]
+    fn bands_find_the_tone() {
+        let mut analyzer: AudioAnalyzer = AudioAnalyzer::new(1024, 32000, Window::Hann);
+        analyzer.push(&sine(1000.0, 32000, 1024), 1);
+        assert!((analyzer.bin_frequency(32) - 1000.0).abs() < 1e-3);
+        let bands: Vec<f32> = analyzer.bands(10, 20.0, 16000.0);
+        let loudest: usize = bands.iter().enumerate().fold(0, |b, (i, v)| if *v > bands[b] { i } else { b });
+        let lo: f32 = 20.0*(800f32.ln()*loudest as f32 / 10.0).exp();
+        let hi: f32 = 20.0*(800f32.ln()*(loudest + 1) as f32 / 10.0).exp();
+        assert!(lo <= 1000.0 && 1000.0 <= hi, "band {} covers {}..{}", loudest, lo, hi);
+        assert!(bands[loudest].abs() < 0.5);
+    }
+
+    #[test]
+    fn level_meter_ballistics() {
+        let mut meter: LevelMeter = LevelMeter::new();
+        let block: Vec<f32> = [0.5, -1.0].repeat(50);
+        meter.process(&block, 2, 100);
+        assert_eq!(meter.rms(), &[0.5, 1.0]);
+        assert_eq!(meter.peak(), &[0.5, 1.0]);
+
+        //Silence lets the peak fall 20 dB per release time, while the hold keeps the old peak for a second
+        meter.process(&vec![0.0; 60], 2, 100);
+        assert_eq!(meter.rms(), &[0.0, 0.0]);
+        assert!((meter.peak()[1] - 0.1).abs() < 1e-5);
+        assert_eq!(meter.peak_hold(), vec![0.5, 1.0]);
+        meter.process(&vec![0.0; 200], 2, 100);
+        assert!(meter.peak_hold()[1] < 0.01);
+
+        meter.process(&[], 2, 100);
+        meter.reset();
+        assert!(meter.rms().is_empty());
+    }
+
+    #[test]
+    fn onsets_and_tempo_of_clicks() {
+        //At 25.6 kHz the hop is 20 ms, so clicks 0.6 seconds apart (100 BPM) land on whole hops
+        let (times, bpm) = clicks(0.6, 8.0, 25600).onsets();
+        assert!(times.len() >= 12 && times.len() <= 14, "{} onsets", times.len());
+        for pair in times.windows(2) {
+            assert!((pair[1] - pair[0] - 0.6).abs() < 1e-6, "{:?}", times);
+        }
+        assert_eq!(bpm, Some(100.0));
+    }
+
+    #[test]
+    fn fast_tempos_fold_into_range() {
+        //200 BPM is halved into the 60..180 range
+        let (_, bpm) = clicks(0.3, 6.0, 25600).onsets();
+        assert_eq!(bpm, Some(100.0));
+    }
+
+    #[test]
+    fn silence_has_no_onsets() {
+        let (times, bpm) = Sound::silence(16000, 2, 32000).onsets();
+        assert!(times.is_empty());
+        assert_eq!(bpm, None);
+    }
+
+    #[test]
+    fn pulse_fades_after_an_onset() {
+        let mut detector: OnsetDetector = OnsetDetector::new();
+        assert_eq!(detector.pulse(1.0, 0.5), 0.0);
+        detector.last = Some(1.0);
+        assert_eq!(detector.pulse(1.25, 0.5), 0.5);
+        assert_eq!(detector.pulse(2.0, 0.5), 0.0);
+        assert_eq!(detector.pulse(0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn sound_levels() {
+        let sound: Sound = Sound::new(100, 1, vec![0.5, -0.5, 0.5, -1.0]);
+        assert_eq!(sound.peak(), 1.0);
+        assert!((sound.rms() - (1.75f32 / 4.0).sqrt()).abs() < 1e-6);
+        assert_eq!(Sound::silence(100, 1, 0).rms(), 0.0);
+    }
+
+    #[test]
+    fn spectrum_bars_fill_from_the_bottom() {
+        let mut raster: CRaster = CRaster::new(4, 4);
+        raster.draw_spectrum(&[0.0, -30.0, -60.0, -90.0], -60.0, (0, 0, 4, 4), 1);
+        let heights: Vec<usize> = (0..4).map(|x| (0..4).filter(|y| raster.get(x, *y) == 1).count()).collect();
+        assert_eq!(heights, vec![4, 2, 0, 0]);
+        //Off the raster and degenerate rectangles draw nothing and do not panic
+        raster.draw_spectrum(&[0.0], -60.0, (-10, -10, 4, 4), 2);
+        raster.draw_spectrum(&[], -60.0, (0, 0, 4, 4), 2);
+        assert!(!raster.data.contains(&2));
+    }
+
+    #[test]
+    fn waveform_traces_the_signal() {
+        let mut raster: CRaster = CRaster::new(4, 5);
+        raster.draw_waveform(&[1.0, 1.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0], 2, (0, 0, 4, 5), 1);
+        let column = |x: usize| -> Vec<usize> { (0..5).filter(|y| raster.get(x, *y) == 1).collect() };
+        assert_eq!(column(0), vec![0]);
+        //Each column reaches back to the previous one so the trace stays connected
+        assert_eq!(column(1), vec![0, 1, 2]);
+        assert_eq!(column(2), vec![2, 3, 4]);
+        assert_eq!(column(3), vec![2, 3, 4]);
+    }
+
+}
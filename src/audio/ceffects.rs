@@ -0,0 +1,975 @@
+use std::any::Any;
+use std::f32::consts::PI;
+
+use crate::audio::csound::Sound;
+
+///Time parameter changes take to glide to their new value, so turning a knob at runtime does not click.
+pub const DEFAULT_SMOOTHING: f32 = 0.02;
+
+///A processor that changes interleaved f32 samples in place, keeping whatever history it needs between calls.
+pub trait Effect: Send {
+    fn process(&mut self, samples: &mut [f32], channels: usize);
+
+    ///Clears delay lines and filter history, e.g. after seeking.
+    fn reset(&mut self) {}
+
+    ///Lets `EffectChain::get_mut` hand back the concrete effect.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+///A parameter that follows its target with a one-pole glide instead of jumping.
+#[derive(Clone, Debug)]
+pub struct Param {
+    value: f32,
+    target: f32,
+    coeff: f32,
+}
+
+impl Param {
+
+    pub fn new(value: f32, smoothing: f32, sample_rate: u32) -> Param {
+        let coeff: f32 = if smoothing <= 0.0 { 1.0 } else { 1.0 - (-1.0 / (smoothing*sample_rate.max(1) as f32)).exp() };
+        Param { value, target: value, coeff }
+    }
+
+    ///Glides towards `target`.
+    pub fn set(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    ///Jumps straight to `value`.
+    pub fn set_immediate(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.value == self.target
+    }
+
+    ///Advances the glide by one frame and returns the new value.
+    pub fn tick(&mut self) -> f32 {
+        if self.value != self.target {
+            let next: f32 = self.value + (self.target - self.value)*self.coeff;
+            //Snap once the rest of the glide would be inaudible, or too small for f32 to move at all
+            if next == self.value || (self.target - next).abs() <= 1e-6*self.target.abs().max(1e-3) {
+                self.value = self.target;
+            }
+            else {
+                self.value = next;
+            }
+        }
+        self.value
+    }
+
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0*gain.max(1e-10).log10()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+    ///Passes a band around the frequency, with 0 dB at its peak.
+    BandPass,
+    ///Boosts or cuts a band around the frequency by `gain_db`, leaving the rest alone.
+    Peaking,
+}
+
+///Second-order IIR filter from the RBJ cookbook. Frequency, Q and gain glide when changed.
+pub struct Biquad {
+    pub kind: FilterKind,
+    sample_rate: u32,
+    frequency: Param,
+    q: Param,
+    gain_db: Param,
+    coeffs: [f32; 5],  //b0, b1, b2, a1, a2, normalized by a0
+    state: Vec<[f32; 2]>,
+}
+
+impl Biquad {
+
+    pub fn new(kind: FilterKind, frequency: f32, q: f32, sample_rate: u32) -> Biquad {
+        let mut filter: Biquad = Biquad {
+            kind,
+            //Below 4 Hz there is no room between 1 Hz and Nyquist to place the cutoff
+            sample_rate: sample_rate.max(4),
+            frequency: Param::new(frequency, DEFAULT_SMOOTHING, sample_rate),
+            q: Param::new(q, DEFAULT_SMOOTHING, sample_rate),
+            gain_db: Param::new(0.0, DEFAULT_SMOOTHING, sample_rate),
+            coeffs: [1.0, 0.0, 0.0, 0.0, 0.0],
+            state: Vec::new(),
+        };
+        filter.update_coeffs();
+        filter
+    }
+
+    ///A Butterworth low-pass, flat below `frequency`.
+    pub fn low_pass(frequency: f32, sample_rate: u32) -> Biquad {
+        Biquad::new(FilterKind::LowPass, frequency, std::f32::consts::FRAC_1_SQRT_2, sample_rate)
+    }
+
+    pub fn high_pass(frequency: f32, sample_rate: u32) -> Biquad {
+        Biquad::new(FilterKind::HighPass, frequency, std::f32::consts::FRAC_1_SQRT_2, sample_rate)
+    }
+
+    pub fn band_pass(frequency: f32, q: f32, sample_rate: u32) -> Biquad {
+        Biquad::new(FilterKind::BandPass, frequency, q, sample_rate)
+    }
+
+    pub fn peaking(frequency: f32, q: f32, gain_db: f32, sample_rate: u32) -> Biquad {
+        let mut filter: Biquad = Biquad::new(FilterKind::Peaking, frequency, q, sample_rate);
+        filter.gain_db.set_immediate(gain_db);
+        filter.update_coeffs();
+        filter
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency.set(frequency);
+    }
+
+    pub fn set_q(&mut self, q: f32) {
+        self.q.set(q);
+    }
+
+    pub fn set_gain_db(&mut self, gain_db: f32) {
+        self.gain_db.set(gain_db);
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency.target()
+    }
+
+    ///False while a parameter change is still gliding.
+    pub fn is_settled(&self) -> bool {
+        self.frequency.is_settled() && self.q.is_settled() && self.gain_db.is_settled()
+    }
+
+    fn update_coeffs(&mut self) {
+        let nyquist: f32 = self.sample_rate as f32*0.5;
+        let w0: f32 = 2.0*PI*self.frequency.value().clamp(1.0, nyquist*0.99) / self.sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha: f32 = sin / (2.0*self.q.value().max(0.01));
+        let a: f32 = 10f32.powf(self.gain_db.value() / 40.0);
+        let (b0, b1, b2, a0, a1, a2) = match self.kind {
+            FilterKind::LowPass => ((1.0 - cos)*0.5, 1.0 - cos, (1.0 - cos)*0.5, 1.0 + alpha, -2.0*cos, 1.0 - alpha),
+            FilterKind::HighPass => ((1.0 + cos)*0.5, -(1.0 + cos), (1.0 + cos)*0.5, 1.0 + alpha, -2.0*cos, 1.0 - alpha),
+            FilterKind::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0*cos, 1.0 - alpha),
+            FilterKind::Peaking => (1.0 + alpha*a, -2.0*cos, 1.0 - alpha*a, 1.0 + alpha / a, -2.0*cos, 1.0 - alpha / a),
+        };
+        self.coeffs = [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0];
+    }
+
+    fn tick_params(&mut self) {
+        if !self.is_settled() {
+            self.frequency.tick();
+            self.q.tick();
+            self.gain_db.tick();
+            self.update_coeffs();
+        }
+    }
+
+    ///Filters one sample of `channel`. Call `advance` once per frame when driving the filter this way.
+    pub fn process_sample(&mut self, x: f32, channel: usize) -> f32 {
+        if channel >= self.state.len() {
+            self.state.resize(channel + 1, [0.0; 2]);
+        }
+        //Transposed direct form II
+        let [b0, b1, b2, a1, a2] = self.coeffs;
+        let s: &mut [f32; 2] = &mut self.state[channel];
+        let y: f32 = b0*x + s[0];
+        s[0] = b1*x - a1*y + s[1];
+        s[1] = b2*x - a2*y;
+        y
+    }
+
+    ///Moves parameter glides on by one frame.
+    pub fn advance(&mut self) {
+        self.tick_params();
+    }
+
+}
+
+impl Effect for Biquad {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let channels: usize = channels.max(1);
+        for frame in samples.chunks_exact_mut(channels) {
+            self.tick_params();
+            for (c, s) in frame.iter_mut().enumerate() {
+                *s = self.process_sample(*s, c);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.clear();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///Feedback echo. Changing the delay time glides the read head, bending pitch like a tape delay.
+pub struct Delay {
+    sample_rate: u32,
+    max_time: f32,
+    time: Param,
+    feedback: Param,
+    mix: Param,
+    lines: Vec<Vec<f32>>,
+    write: usize,
+}
+
+impl Delay {
+
+    ///`time` in seconds, `feedback` is how much of each echo returns (below 1), and `mix` blends dry (0) to wet (1).
+    pub fn new(time: f32, feedback: f32, mix: f32, sample_rate: u32) -> Delay {
+        Delay::with_max_time(time, feedback, mix, time.max(2.0), sample_rate)
+    }
+
+    ///Like `new`, but reserves room for delays up to `max_time` seconds.
+    pub fn with_max_time(time: f32, feedback: f32, mix: f32, max_time: f32, sample_rate: u32) -> Delay {
+        let sample_rate: u32 = sample_rate.max(1);
+        //Room for at least a couple of samples, so the read head always has somewhere to be
+        let max_time: f32 = max_time.max(4.0 / sample_rate as f32);
+        Delay {
+            sample_rate,
+            max_time,
+            time: Param::new(time.clamp(0.0, max_time), DEFAULT_SMOOTHING*5.0, sample_rate),
+            feedback: Param::new(feedback, DEFAULT_SMOOTHING, sample_rate),
+            mix: Param::new(mix, DEFAULT_SMOOTHING, sample_rate),
+            lines: Vec::new(),
+            write: 0,
+        }
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time.set(time.clamp(0.0, self.max_time));
+    }
+
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback.set(feedback);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix.set(mix);
+    }
+
+}
+
+impl Effect for Delay {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let channels: usize = channels.max(1);
+        let len: usize = (self.max_time*self.sample_rate as f32) as usize + 2;
+        if self.lines.len() != channels {
+            self.lines = vec![vec![0.0; len]; channels];
+            self.write = 0;
+        }
+        for frame in samples.chunks_exact_mut(channels) {
+            let delay: f32 = (self.time.tick()*self.sample_rate as f32).clamp(1.0, (len - 2) as f32);
+            let feedback: f32 = self.feedback.tick();
+            let mix: f32 = self.mix.tick();
+            let read: f32 = self.write as f32 + len as f32 - delay;
+            let i: usize = read as usize;
+            let t: f32 = read - i as f32;
+            for (c, s) in frame.iter_mut().enumerate() {
+                let line: &mut Vec<f32> = &mut self.lines[c];
+                let echo: f32 = line[i % len]*(1.0 - t) + line[(i + 1) % len]*t;
+                line[self.write] = *s + echo*feedback;
+                *s = *s*(1.0 - mix) + echo*mix;
+            }
+            self.write = (self.write + 1) % len;
+        }
+    }
+
+    fn reset(&mut self) {
+        for line in self.lines.iter_mut() {
+            line.fill(0.0);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+//Freeverb's tunings, in samples at 44.1 kHz
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const REVERB_INPUT_GAIN: f32 = 0.015;
+const REVERB_WET_SCALE: f32 = 3.0;
+
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    store: f32,
+}
+
+impl Comb {
+
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let out: f32 = self.buffer[self.index];
+        self.store = out*(1.0 - damp) + self.store*damp;
+        self.buffer[self.index] = input + self.store*feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        out
+    }
+
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed: f32 = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed*0.5;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+
+}
+
+///Freeverb: eight parallel damped combs into four allpasses per side, with the right side slightly detuned for
+/// width. Processes the first two channels; any others pass through.
+pub struct Reverb {
+    room_size: Param,
+    damping: Param,
+    wet: Param,
+    dry: Param,
+    width: Param,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Reverb {
+
+    pub fn new(sample_rate: u32) -> Reverb {
+        let scale = |n: usize| ((n as f64*sample_rate as f64 / 44100.0) as usize).max(1);
+        let side = |spread: usize| -> (Vec<Comb>, Vec<Allpass>) {
+            (
+                COMB_TUNING.iter().map(|n| Comb { buffer: vec![0.0; scale(n + spread)], index: 0, store: 0.0 }).collect(),
+                ALLPASS_TUNING.iter().map(|n| Allpass { buffer: vec![0.0; scale(n + spread)], index: 0 }).collect(),
+            )
+        };
+        let (left_combs, left_allpasses) = side(0);
+        let (right_combs, right_allpasses) = side(STEREO_SPREAD);
+        Reverb {
+            room_size: Param::new(0.5, DEFAULT_SMOOTHING, sample_rate),
+            damping: Param::new(0.5, DEFAULT_SMOOTHING, sample_rate),
+            wet: Param::new(0.33, DEFAULT_SMOOTHING, sample_rate),
+            dry: Param::new(1.0, DEFAULT_SMOOTHING, sample_rate),
+            width: Param::new(1.0, DEFAULT_SMOOTHING, sample_rate),
+            combs: [left_combs, right_combs],
+            allpasses: [left_allpasses, right_allpasses],
+        }
+    }
+
+    ///0 is a small room, 1 a hall with a long tail.
+    pub fn room_size(mut self, room_size: f32) -> Self {
+        self.room_size.set_immediate(room_size);
+        self
+    }
+
+    ///How quickly high frequencies die away in the tail.
+    pub fn damping(mut self, damping: f32) -> Self {
+        self.damping.set_immediate(damping);
+        self
+    }
+
+    pub fn wet(mut self, wet: f32) -> Self {
+        self.wet.set_immediate(wet);
+        self
+    }
+
+    pub fn dry(mut self, dry: f32) -> Self {
+        self.dry.set_immediate(dry);
+        self
+    }
+
+    ///Stereo width of the tail, from mono (0) to fully separate sides (1).
+    pub fn width(mut self, width: f32) -> Self {
+        self.width.set_immediate(width);
+        self
+    }
+
+    pub fn set_room_size(&mut self, room_size: f32) {
+        self.room_size.set(room_size);
+    }
+
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping.set(damping);
+    }
+
+    pub fn set_wet(&mut self, wet: f32) {
+        self.wet.set(wet);
+    }
+
+    pub fn set_dry(&mut self, dry: f32) {
+        self.dry.set(dry);
+    }
+
+    pub fn set_width(&mut self, width: f32) {
+        self.width.set(width);
+    }
+
+}
+
+impl Effect for Reverb {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let channels: usize = channels.max(1);
+        for frame in samples.chunks_exact_mut(channels) {
+            let feedback: f32 = self.room_size.tick().clamp(0.0, 1.0)*0.28 + 0.7;
+            let damp: f32 = self.damping.tick().clamp(0.0, 1.0)*0.4;
+            let wet: f32 = self.wet.tick()*REVERB_WET_SCALE;
+            let dry: f32 = self.dry.tick();
+            let width: f32 = self.width.tick().clamp(0.0, 1.0);
+            let stereo: bool = channels >= 2;
+            let input: f32 = if stereo { frame[0] + frame[1] } else { frame[0]*2.0 }*REVERB_INPUT_GAIN;
+
+            let mut out: [f32; 2] = [0.0; 2];
+            for (side, o) in out.iter_mut().enumerate() {
+                let mut acc: f32 = self.combs[side].iter_mut().map(|c| c.process(input, feedback, damp)).sum();
+                for allpass in self.allpasses[side].iter_mut() {
+                    acc = allpass.process(acc);
+                }
+                *o = acc;
+            }
+
+            if stereo {
+                let wet1: f32 = wet*(width*0.5 + 0.5);
+                let wet2: f32 = wet*(1.0 - width)*0.5;
+                frame[0] = frame[0]*dry + out[0]*wet1 + out[1]*wet2;
+                frame[1] = frame[1]*dry + out[1]*wet1 + out[0]*wet2;
+            }
+            else {
+                frame[0] = frame[0]*dry + (out[0] + out[1])*0.5*wet;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.fill(0.0);
+            comb.store = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.fill(0.0);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///Soft-clipping overdrive. The output is normalized so a full-scale input stays full scale at any drive.
+pub struct Distortion {
+    drive: Param,
+    mix: Param,
+}
+
+impl Distortion {
+
+    ///`drive` is the input gain into the clipper: 1 barely colours, 20 is heavy fuzz.
+    pub fn new(drive: f32, sample_rate: u32) -> Distortion {
+        Distortion { drive: Param::new(drive, DEFAULT_SMOOTHING, sample_rate), mix: Param::new(1.0, DEFAULT_SMOOTHING, sample_rate) }
+    }
+
+    pub fn set_drive(&mut self, drive: f32) {
+        self.drive.set(drive);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix.set(mix);
+    }
+
+}
+
+impl Effect for Distortion {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_exact_mut(channels.max(1)) {
+            let drive: f32 = self.drive.tick().max(0.01);
+            let mix: f32 = self.mix.tick();
+            let norm: f32 = 1.0 / drive.tanh();
+            for s in frame.iter_mut() {
+                *s = *s*(1.0 - mix) + (*s*drive).tanh()*norm*mix;
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///Lo-fi degradation: quantizes to fewer bits and holds samples to lower the effective sample rate.
+pub struct Bitcrusher {
+    bits: Param,
+    downsample: Param,
+    mix: Param,
+    held: Vec<f32>,
+    counter: f32,
+}
+
+impl Bitcrusher {
+
+    ///`bits` may be fractional for gradual sweeps. `downsample` holds each sample for that many frames.
+    pub fn new(bits: f32, downsample: f32, sample_rate: u32) -> Bitcrusher {
+        Bitcrusher {
+            bits: Param::new(bits, DEFAULT_SMOOTHING, sample_rate),
+            downsample: Param::new(downsample, DEFAULT_SMOOTHING, sample_rate),
+            mix: Param::new(1.0, DEFAULT_SMOOTHING, sample_rate),
+            held: Vec::new(),
+            counter: 0.0,
+        }
+    }
+
+    pub fn set_bits(&mut self, bits: f32) {
+        self.bits.set(bits);
+    }
+
+    pub fn set_downsample(&mut self, downsample: f32) {
+        self.downsample.set(downsample);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix.set(mix);
+    }
+
+}
+
+impl Effect for Bitcrusher {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let channels: usize = channels.max(1);
+        self.held.resize(channels, 0.0);
+        for frame in samples.chunks_exact_mut(channels) {
+            let levels: f32 = 2f32.powf(self.bits.tick().clamp(1.0, 24.0) - 1.0);
+            let downsample: f32 = self.downsample.tick().max(1.0);
+            let mix: f32 = self.mix.tick();
+            self.counter += 1.0;
+            let sample_now: bool = self.counter >= downsample;
+            if sample_now {
+                self.counter -= downsample;
+            }
+            for (c, s) in frame.iter_mut().enumerate() {
+                if sample_now {
+                    self.held[c] = (*s*levels).round() / levels;
+                }
+                *s = *s*(1.0 - mix) + self.held[c]*mix;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.held.fill(0.0);
+        self.counter = 0.0;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///Feed-forward peak compressor. Channels are detected together so the stereo image does not shift.
+pub struct Compressor {
+    sample_rate: u32,
+    threshold_db: Param,
+    ratio: Param,
+    makeup_db: Param,
+    ///Seconds to clamp down once the level goes over the threshold.
+    pub attack: f32,
+    ///Seconds to recover once it falls back under.
+    pub release: f32,
+    reduction_db: f32,
+}
+
+impl Compressor {
+
+    pub fn new(threshold_db: f32, ratio: f32, attack: f32, release: f32, sample_rate: u32) -> Compressor {
+        Compressor {
+            sample_rate,
+            threshold_db: Param::new(threshold_db, DEFAULT_SMOOTHING, sample_rate),
+            ratio: Param::new(ratio, DEFAULT_SMOOTHING, sample_rate),
+            makeup_db: Param::new(0.0, DEFAULT_SMOOTHING, sample_rate),
+            attack,
+            release,
+            reduction_db: 0.0,
+        }
+    }
+
+    ///A brickwall-ratio compressor with a fast attack, for keeping the master output under `ceiling_db`. Peaks
+    /// shorter than the attack can still pass, so the output is also hard-clipped at the ceiling.
+    pub fn limiter(ceiling_db: f32, sample_rate: u32) -> Compressor {
+        Compressor::new(ceiling_db, f32::INFINITY, 0.001, 0.1, sample_rate)
+    }
+
+    pub fn makeup(mut self, makeup_db: f32) -> Self {
+        self.makeup_db.set_immediate(makeup_db);
+        self
+    }
+
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_db.set(threshold_db);
+    }
+
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio.set(ratio);
+    }
+
+    pub fn set_makeup_db(&mut self, makeup_db: f32) {
+        self.makeup_db.set(makeup_db);
+    }
+
+    ///How far the level is currently being pulled down, for metering.
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.reduction_db
+    }
+
+    fn coeff(&self, seconds: f32) -> f32 {
+        if seconds <= 0.0 {
+            0.0
+        }
+        else {
+            (-1.0 / (seconds*self.sample_rate.max(1) as f32)).exp()
+        }
+    }
+
+}
+
+impl Effect for Compressor {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let attack: f32 = self.coeff(self.attack);
+        let release: f32 = self.coeff(self.release);
+        let limiting: bool = self.ratio.target().is_infinite();
+        for frame in samples.chunks_exact_mut(channels.max(1)) {
+            let threshold: f32 = self.threshold_db.tick();
+            //Infinity does not glide, so a limiter keeps its ratio as is
+            let ratio: f32 = if limiting { f32::INFINITY } else { self.ratio.tick().max(1.0) };
+            let makeup: f32 = self.makeup_db.tick();
+            let peak: f32 = frame.iter().fold(0.0, |m, s| m.max(s.abs()));
+            let over: f32 = gain_to_db(peak) - threshold;
+            let target: f32 = if over > 0.0 { over*(1.0 - 1.0 / ratio) } else { 0.0 };
+            let coeff: f32 = if target > self.reduction_db { attack } else { release };
+            self.reduction_db = target + (self.reduction_db - target)*coeff;
+            let gain: f32 = db_to_gain(makeup - self.reduction_db);
+            let ceiling: f32 = db_to_gain(threshold);
+            for s in frame.iter_mut() {
+                *s *= gain;
+                if limiting {
+                    *s = s.clamp(-ceiling, ceiling);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///Effects run one after another over the same buffer. Entries can be bypassed without losing their state.
+#[derive(Default)]
+pub struct EffectChain {
+    effects: Vec<(Box<dyn Effect>, bool)>,
+}
+
+impl EffectChain {
+
+    pub fn new() -> EffectChain {
+        EffectChain { effects: Vec::new() }
+    }
+
+    pub fn with(mut self, effect: impl Effect + 'static) -> Self {
+        self.push(Box::new(effect));
+        self
+    }
+
+    ///Appends `effect` to the end of the chain and returns its index.
+    pub fn push(&mut self, effect: Box<dyn Effect>) -> usize {
+        self.effects.push((effect, true));
+        self.effects.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Box<dyn Effect> {
+        self.effects.remove(index).0
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    ///The effect at `index`, if it is a `T`, for changing its parameters.
+    pub fn get_mut<T: Effect + 'static>(&mut self, index: usize) -> Option<&mut T> {
+        self.effects.get_mut(index)?.0.as_any_mut().downcast_mut::<T>()
+    }
+
+    ///Bypasses or re-enables the effect at `index`.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(entry) = self.effects.get_mut(index) {
+            entry.1 = enabled;
+        }
+    }
+
+}
+
+impl Effect for EffectChain {
+
+    fn process(&mut self, samples: &mut [f32], channels: usize) {
+        for (effect, enabled) in self.effects.iter_mut() {
+            if *enabled {
+                effect.process(samples, channels);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for (effect, _) in self.effects.iter_mut() {
+            effect.reset();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+impl Sound {
+
+    ///Runs the whole sound through `effect`. Tails longer than the sound, like reverb, are cut off; append
+    /// silence first to keep them.
+    pub fn apply(&mut self, effect: &mut dyn Effect) {
+        effect.process(&mut self.samples, self.channels as usize);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn tone(frequency: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| (i as f32*frequency*2.0*PI / sample_rate as f32).sin()).collect()
+    }
+
+    //Peak level of the second half, after the filter has settled
+    fn settled_peak(samples: &[f32]) -> f32 {
+        samples[samples.len() / 2..].iter().fold(0.0, |m, s| m.max(s.abs()))
+    }
+
+    fn impulse(frames: usize) -> Vec<f32> {
+        let mut samples: Vec<f32> = vec![0.0; frames];
+        samples[0] = 1.0;
+        samples
+    }
+
+    #[test]
+    fn param_glides_and_snaps() {
+        let mut param: Param = Param::new(0.0, 0.01, 1000);
+        param.set(1.0);
+        let first: f32 = param.tick();
+        assert!(first > 0.0 && first < 1.0);
+        for _ in 0..1000 {
+            param.tick();
+        }
+        assert!(param.is_settled());
+        assert_eq!(param.value(), 1.0);
+
+        let mut instant: Param = Param::new(0.0, 0.0, 1000);
+        instant.set(3.0);
+        assert_eq!(instant.tick(), 3.0);
+    }
+
+    #[test]
+    fn decibels_round_trip() {
+        for db in [-60.0, -6.0, 0.0, 12.0] {
+            assert!((gain_to_db(db_to_gain(db)) - db).abs() < 1e-4);
+        }
+        assert!((db_to_gain(-6.0) - 0.501).abs() < 1e-3);
+        assert!(gain_to_db(0.0).is_finite());
+    }
+
+    #[test]
+    fn low_and_high_pass_split_the_spectrum() {
+        let rate: u32 = 44100;
+        for (mut filter, low, high) in [(Biquad::low_pass(1000.0, rate), 1.0, 0.0), (Biquad::high_pass(1000.0, rate), 0.0, 1.0)] {
+            let mut bass: Vec<f32> = tone(50.0, rate, 8000);
+            let mut treble: Vec<f32> = tone(15000.0, rate, 8000);
+            filter.process(&mut bass, 1);
+            filter.reset();
+            filter.process(&mut treble, 1);
+            assert!((settled_peak(&bass) - low).abs() < 0.05, "{:?} bass {}", filter.kind, settled_peak(&bass));
+            assert!((settled_peak(&treble) - high).abs() < 0.05, "{:?} treble {}", filter.kind, settled_peak(&treble));
+        }
+    }
+
+    #[test]
+    fn band_pass_and_peaking_at_their_center() {
+        let rate: u32 = 44100;
+        let mut band: Vec<f32> = tone(2000.0, rate, 8000);
+        Biquad::band_pass(2000.0, 2.0, rate).process(&mut band, 1);
+        assert!((settled_peak(&band) - 1.0).abs() < 0.05);
+
+        let mut boosted: Vec<f32> = tone(2000.0, rate, 8000);
+        Biquad::peaking(2000.0, 1.0, 6.0, rate).process(&mut boosted, 1);
+        assert!((gain_to_db(settled_peak(&boosted)) - 6.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn biquad_survives_tiny_sample_rates() {
+        let mut filter: Biquad = Biquad::low_pass(1000.0, 1);
+        let mut samples: Vec<f32> = tone(0.1, 1, 100);
+        filter.process(&mut samples, 1);
+        assert!(samples.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn biquad_frequency_glides() {
+        let mut filter: Biquad = Biquad::low_pass(1000.0, 44100);
+        filter.set_frequency(5000.0);
+        assert_eq!(filter.frequency(), 5000.0);
+        assert!(!filter.is_settled());
+        let mut samples: Vec<f32> = vec![0.0; 44100];
+        filter.process(&mut samples, 2);
+        assert!(filter.is_settled());
+    }
+
+    #[test]
+    fn delay_repeats_with_feedback() {
+        //A tenth of a second is 10 frames at 100 Hz
+        let mut delay: Delay = Delay::new(0.1, 0.5, 1.0, 100);
+        let mut samples: Vec<f32> = impulse(40);
+        delay.process(&mut samples, 1);
+        assert_eq!(samples[0], 0.0);
+        assert!((samples[10] - 1.0).abs() < 1e-6 && (samples[20] - 0.5).abs() < 1e-6 && (samples[30] - 0.25).abs() < 1e-6);
+        assert_eq!(samples.iter().filter(|s| **s != 0.0).count(), 3);
+
+        delay.reset();
+        let mut silence: Vec<f32> = vec![0.0; 40];
+        delay.process(&mut silence, 1);
+        assert!(silence.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn delay_with_no_room_still_echoes() {
+        let mut delay: Delay = Delay::with_max_time(1.0, 0.0, 1.0, 0.0, 100);
+        let mut samples: Vec<f32> = impulse(10);
+        delay.process(&mut samples, 1);
+        assert!(samples.iter().all(|s| s.is_finite()));
+        assert_eq!(samples.iter().position(|s| *s != 0.0), Some(4));
+    }
+
+    #[test]
+    fn reverb_leaves_a_decaying_tail() {
+        let mut reverb: Reverb = Reverb::new(44100).room_size(0.5).dry(0.0);
+        let mut samples: Vec<f32> = vec![0.0; 2*44100];
+        samples[0] = 1.0;
+        samples[1] = 1.0;
+        reverb.process(&mut samples, 2);
+        let energy = |s: &[f32]| s.iter().map(|x| x*x).sum::<f32>();
+        assert_eq!(samples[0], 0.0);
+        assert!(energy(&samples[..22050]) > 0.0);
+        assert!(energy(&samples[66150..]) < energy(&samples[..22050])*0.01);
+        assert!(samples.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn distortion_keeps_full_scale() {
+        let mut distortion: Distortion = Distortion::new(20.0, 44100);
+        let mut samples: Vec<f32> = vec![1.0, -1.0, 0.1, 0.0];
+        distortion.process(&mut samples, 1);
+        assert!((samples[0] - 1.0).abs() < 1e-5 && (samples[1] + 1.0).abs() < 1e-5);
+        assert!(samples[2] > 0.9);
+        assert_eq!(samples[3], 0.0);
+    }
+
+    #[test]
+    fn bitcrusher_quantizes_and_holds() {
+        let mut crusher: Bitcrusher = Bitcrusher::new(2.0, 2.0, 44100);
+        let mut samples: Vec<f32> = vec![0.3, 0.9, -0.2, 0.8, 0.7, 0.1];
+        crusher.process(&mut samples, 1);
+        //Two bits leave steps of 0.5, and each new value is taken every second frame
+        assert_eq!(samples, vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn compressor_reduces_by_its_ratio() {
+        let mut compressor: Compressor = Compressor::new(-12.0, 4.0, 0.001, 0.1, 44100);
+        let mut samples: Vec<f32> = vec![1.0; 44100];
+        compressor.process(&mut samples, 1);
+        //12 dB over the threshold at 4:1 leaves 3 dB over it
+        assert!((compressor.gain_reduction_db() - 9.0).abs() < 0.01);
+        assert!((gain_to_db(samples[44099]) + 9.0).abs() < 0.01);
+
+        compressor.reset();
+        assert_eq!(compressor.gain_reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling() {
+        let mut limiter: Compressor = Compressor::limiter(-6.0, 44100);
+        let mut samples: Vec<f32> = tone(100.0, 44100, 4410).iter().map(|s| s*4.0).collect();
+        limiter.process(&mut samples, 1);
+        let ceiling: f32 = db_to_gain(-6.0);
+        assert!(samples.iter().all(|s| s.abs() <= ceiling + 1e-6));
+    }
+
+    #[test]
+    fn chain_runs_in_order_and_can_bypass() {
+        let mut chain: EffectChain = EffectChain::new().with(Bitcrusher::new(2.0, 1.0, 100)).with(Distortion::new(1.0, 100));
+        assert_eq!(chain.len(), 2);
+        assert!(chain.get_mut::<Bitcrusher>(0).is_some());
+        assert!(chain.get_mut::<Distortion>(0).is_none());
+        assert!(chain.get_mut::<Delay>(5).is_none());
+
+        chain.set_enabled(1, false);
+        let mut sound: Sound = Sound::new(100, 1, vec![0.3, 0.8]);
+        sound.apply(&mut chain);
+        assert_eq!(sound.samples, vec![0.5, 1.0]);
+
+        chain.remove(0);
+        assert!(chain.get_mut::<Distortion>(0).is_some());
+    }
+
+}
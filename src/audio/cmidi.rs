@@ -0,0 +1,408 @@
+use crate::audio::csound::AudioError;
+
+///Tempo assumed until the file sets one: 120 beats per minute.
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+///A channel voice message, or the meta events the sequencer cares about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, key: u8, velocity: u8 },
+    ///A note-on with velocity 0 is turned into a `NoteOff` while parsing.
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    PolyPressure { channel: u8, key: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    ///-8192..8191, centered on 0.
+    PitchBend { channel: u8, value: i16 },
+    ///Microseconds per quarter note.
+    Tempo(u32),
+    EndOfTrack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiEvent {
+    ///Absolute time in ticks from the start of the track.
+    pub tick: u64,
+    pub message: MidiMessage,
+}
+
+///How ticks relate to time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Division {
+    ///Ticks per quarter note; real time then depends on the tempo map.
+    Metrical(u16),
+    ///Absolute time: frames per second and ticks per frame, regardless of tempo.
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+///A parsed Standard MIDI File (format 0 or 1).
+#[derive(Clone, Debug)]
+pub struct MidiFile {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Vec<MidiEvent>>,
+    ///Tempo changes as (tick, microseconds per quarter note), starting at tick 0.
+    pub tempo_map: Vec<(u64, u32)>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], AudioError> {
+        let slice: &[u8] = self.data.get(self.at..self.at + n).ok_or_else(|| AudioError::Format("truncated MIDI file".to_string()))?;
+        self.at += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, AudioError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, AudioError> {
+        let b: &[u8] = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, AudioError> {
+        let b: &[u8] = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    //Variable-length quantity: seven bits per byte, high bit set on all but the last
+    fn vlq(&mut self) -> Result<u32, AudioError> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let b: u8 = self.u8()?;
+            value = (value << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(AudioError::Format("MIDI variable-length value is longer than 4 bytes".to_string()))
+    }
+
+}
+
+fn parse_track(data: &[u8]) -> Result<Vec<MidiEvent>, AudioError> {
+    let mut r: Reader = Reader { data, at: 0 };
+    let mut events: Vec<MidiEvent> = Vec::new();
+    let mut tick: u64 = 0;
+    let mut running: Option<u8> = None;
+    while r.at < data.len() {
+        tick += r.vlq()? as u64;
+        let mut status: u8 = r.u8()?;
+        let first: u8;
+        if status < 0x80 {
+            //Running status: the byte just read is the first data byte of a repeat of the last message type
+            first = status;
+            status = running.ok_or_else(|| AudioError::Format("MIDI data byte without a status".to_string()))?;
+        }
+        else if status >= 0xf0 {
+            //System exclusive and meta events cancel running status
+            running = None;
+            match status {
+                0xff => {
+                    let kind: u8 = r.u8()?;
+                    let len: usize = r.vlq()? as usize;
+                    let body: &[u8] = r.bytes(len)?;
+                    match kind {
+                        0x51 if len == 3 => {
+                            let tempo: u32 = (body[0] as u32) << 16 | (body[1] as u32) << 8 | body[2] as u32;
+                            events.push(MidiEvent { tick, message: MidiMessage::Tempo(tempo.max(1)) });
+                        }
+                        0x2f => {
+                            events.push(MidiEvent { tick, message: MidiMessage::EndOfTrack });
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                0xf0 | 0xf7 => {
+                    let len: usize = r.vlq()? as usize;
+                    r.bytes(len)?;
+                }
+                _ => return Err(AudioError::Format(format!("unexpected MIDI status byte {:#04x} in a file", status))),
+            }
+            continue;
+        }
+        else {
+            running = Some(status);
+            first = r.u8()?;
+        }
+
+        let channel: u8 = status & 0x0f;
+        let message: MidiMessage = match status & 0xf0 {
+            0x80 => MidiMessage::NoteOff { channel, key: first, velocity: r.u8()? },
+            0x90 => {
+                let velocity: u8 = r.u8()?;
+                if velocity == 0 {
+                    MidiMessage::NoteOff { channel, key: first, velocity: 64 }
+                }
+                else {
+                    MidiMessage::NoteOn { channel, key: first, velocity }
+                }
+            }
+            0xa0 => MidiMessage::PolyPressure { channel, key: first, pressure: r.u8()? },
+            0xb0 => MidiMessage::ControlChange { channel, controller: first, value: r.u8()? },
+            0xc0 => MidiMessage::ProgramChange { channel, program: first },
+            0xd0 => MidiMessage::ChannelPressure { channel, pressure: first },
+            _ => {
+                let high: u8 = r.u8()?;
+                MidiMessage::PitchBend { channel, value: (((high as i16) << 7) | first as i16) - 8192 }
+            }
+        };
+        events.push(MidiEvent { tick, message });
+    }
+    Ok(events)
+}
+
+impl MidiFile {
+
+    pub fn parse(data: &[u8]) -> Result<MidiFile, AudioError> {
+        let mut r: Reader = Reader { data, at: 0 };
+        if r.bytes(4)? != b"MThd" {
+            return Err(AudioError::Format("not a Standard MIDI File".to_string()));
+        }
+        let header_len: usize = r.u32()? as usize;
+        let header: &[u8] = r.bytes(header_len)?;
+        let mut h: Reader = Reader { data: header, at: 0 };
+        let format: u16 = h.u16()?;
+        let track_count: u16 = h.u16()?;
+        let raw_division: u16 = h.u16()?;
+        if format > 1 {
+            return Err(AudioError::Format(format!("MIDI format {} is not supported, only 0 and 1", format)));
+        }
+        let division: Division = if raw_division & 0x8000 != 0 {
+            //The high byte is the frame rate negated as a signed byte
+            let fps: u8 = ((raw_division >> 8) as u8 as i8).unsigned_abs();
+            if ![24, 25, 29, 30].contains(&fps) {
+                return Err(AudioError::Format(format!("MIDI timecode frame rate {} is not 24, 25, 29 or 30", fps)));
+            }
+            if raw_division as u8 == 0 {
+                return Err(AudioError::Format("MIDI timecode division has 0 ticks per frame".to_string()));
+            }
+            Division::Timecode { fps, ticks_per_frame: raw_division as u8 }
+        }
+        else {
+            Division::Metrical(raw_division.max(1))
+        };
+
+        let mut tracks: Vec<Vec<MidiEvent>> = Vec::new();
+        while r.at + 8 <= data.len() && tracks.len() < track_count as usize {
+            let id: &[u8] = r.bytes(4)?;
+            let len: usize = r.u32()? as usize;
+            //Some writers get the last chunk's length wrong, so parse whatever is there
+            let body: &[u8] = &data[r.at..(r.at + len).min(data.len())];
+            r.at += body.len();
+            if id == b"MTrk" {
+                tracks.push(parse_track(body)?);
+            }
+        }
+        if tracks.len() < track_count as usize {
+            println!("[MIDI/WARN] header promises {} tracks but the file holds {}", track_count, tracks.len());
+        }
+
+        let mut tempo_map: Vec<(u64, u32)> = vec![(0, DEFAULT_TEMPO)];
+        let mut changes: Vec<(u64, u32)> = tracks.iter().flatten()
+            .filter_map(|e| match e.message { MidiMessage::Tempo(t) => Some((e.tick, t)), _ => None })
+            .collect();
+        changes.sort_by_key(|c| c.0);
+        for (tick, tempo) in changes {
+            if tempo_map.last().is_some_and(|last| last.0 == tick) {
+                tempo_map.last_mut().unwrap().1 = tempo;
+            }
+            else {
+                tempo_map.push((tick, tempo));
+            }
+        }
+        Ok(MidiFile { format, division, tracks, tempo_map })
+    }
+
+    pub fn load(path: &str) -> Result<MidiFile, AudioError> {
+        MidiFile::parse(&std::fs::read(path)?)
+    }
+
+    ///Every track's events in one list, ordered by tick. Events at the same tick keep their track order.
+    pub fn merged(&self) -> Vec<MidiEvent> {
+        let mut events: Vec<MidiEvent> = self.tracks.iter().flatten()
+            .filter(|e| e.message != MidiMessage::EndOfTrack)
+            .copied()
+            .collect();
+        events.sort_by_key(|e| e.tick);
+        events
+    }
+
+    ///Tick of the last event in any track.
+    pub fn end_tick(&self) -> u64 {
+        self.tracks.iter().filter_map(|t| t.last()).map(|e| e.tick).max().unwrap_or(0)
+    }
+
+    ///Seconds from the start to `tick`, following the tempo map.
+    pub fn seconds_at(&self, tick: u64) -> f64 {
+        match self.division {
+            Division::Timecode { fps, ticks_per_frame } => {
+                //29 in the header means 29.97 drop-frame
+                let fps: f64 = if fps == 29 { 29.97 } else { fps as f64 };
+                tick as f64 / (fps*ticks_per_frame.max(1) as f64)
+            }
+            Division::Metrical(tpq) => {
+                let mut seconds: f64 = 0.0;
+                for (i, (start, tempo)) in self.tempo_map.iter().enumerate() {
+                    if *start >= tick {
+                        break;
+                    }
+                    let end: u64 = self.tempo_map.get(i + 1).map_or(tick, |next| next.0.min(tick));
+                    seconds += (end - start) as f64*(*tempo as f64*1e-6) / tpq as f64;
+                }
+                seconds
+            }
+        }
+    }
+
+    ///Length of the song in seconds at its written tempo.
+    pub fn duration(&self) -> f64 {
+        self.seconds_at(self.end_tick())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn vlq(mut value: u32) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![(value & 0x7f) as u8];
+        value >>= 7;
+        while value > 0 {
+            bytes.insert(0, (value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes
+    }
+
+    fn smf(format: u16, division: u16, tracks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data: Vec<u8> = b"MThd\0\0\0\x06".to_vec();
+        data.extend_from_slice(&format.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&division.to_be_bytes());
+        for track in tracks {
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            data.extend_from_slice(track);
+        }
+        data
+    }
+
+    fn messages(track: &[MidiEvent]) -> Vec<(u64, MidiMessage)> {
+        track.iter().map(|e| (e.tick, e.message)).collect()
+    }
+
+    #[test]
+    fn vlq_round_trips_at_every_length() {
+        for value in [0, 0x7f, 0x80, 0x3fff, 0x4000, 0x1f_ffff, 0x20_0000, 0x0fff_ffff] {
+            let bytes: Vec<u8> = vlq(value);
+            let mut r: Reader = Reader { data: &bytes, at: 0 };
+            assert_eq!(r.vlq().unwrap(), value);
+            assert_eq!(r.at, bytes.len());
+        }
+        let mut too_long: Reader = Reader { data: &[0x81, 0x80, 0x80, 0x80, 0x00], at: 0 };
+        assert!(too_long.vlq().is_err());
+        let mut truncated: Reader = Reader { data: &[0x81], at: 0 };
+        assert!(truncated.vlq().is_err());
+    }
+
+    #[test]
+    fn running_status_and_silent_note_ons() {
+        let track: Vec<u8> = [
+            &[0x00, 0x91, 60, 100][..],
+            &[0x10, 62, 90],                //Running status: another note-on
+            &[0x10, 60, 0],                 //Velocity 0 is a note-off
+            &vlq(200)[..], &[0xb1, 7, 127],
+            &[0x00, 0xff, 0x2f, 0x00],
+        ].concat();
+        let file: MidiFile = MidiFile::parse(&smf(0, 96, &[track])).unwrap();
+        assert_eq!(messages(&file.tracks[0]), vec![
+            (0, MidiMessage::NoteOn { channel: 1, key: 60, velocity: 100 }),
+            (16, MidiMessage::NoteOn { channel: 1, key: 62, velocity: 90 }),
+            (32, MidiMessage::NoteOff { channel: 1, key: 60, velocity: 64 }),
+            (232, MidiMessage::ControlChange { channel: 1, controller: 7, value: 127 }),
+            (232, MidiMessage::EndOfTrack),
+        ]);
+    }
+
+    #[test]
+    fn other_channel_messages() {
+        let track: Vec<u8> = [
+            &[0x00, 0xc2, 5][..],
+            &[0x00, 0xd2, 40],
+            &[0x00, 0xa2, 60, 30],
+            &[0x00, 0xe2, 0x00, 0x40],
+            &[0x00, 0xe2, 0x7f, 0x7f],
+            &[0x00, 0xe2, 0x00, 0x00],
+        ].concat();
+        let file: MidiFile = MidiFile::parse(&smf(0, 96, &[track])).unwrap();
+        assert_eq!(messages(&file.tracks[0]), vec![
+            (0, MidiMessage::ProgramChange { channel: 2, program: 5 }),
+            (0, MidiMessage::ChannelPressure { channel: 2, pressure: 40 }),
+            (0, MidiMessage::PolyPressure { channel: 2, key: 60, pressure: 30 }),
+            (0, MidiMessage::PitchBend { channel: 2, value: 0 }),
+            (0, MidiMessage::PitchBend { channel: 2, value: 8191 }),
+            (0, MidiMessage::PitchBend { channel: 2, value: -8192 }),
+        ]);
+    }
+
+    #[test]
+    fn sysex_cancels_running_status() {
+        let track: Vec<u8> = [&[0x00, 0x90, 60, 100][..], &[0x00, 0xf0, 0x02, 0x7e, 0xf7], &[0x00, 62, 100]].concat();
+        assert!(MidiFile::parse(&smf(0, 96, &[track])).is_err());
+    }
+
+    #[test]
+    fn tempo_map_and_seconds() {
+        //One beat at 120 bpm, then 60 bpm from tick 96
+        let conductor: Vec<u8> = [&[0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20][..], &[0x60, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40]].concat();
+        let notes: Vec<u8> = [&[0x00, 0x90, 60, 100][..], &vlq(192)[..], &[0x80, 60, 0]].concat();
+        let file: MidiFile = MidiFile::parse(&smf(1, 96, &[conductor, notes])).unwrap();
+        assert_eq!(file.tempo_map, vec![(0, 500_000), (96, 1_000_000)]);
+        assert!((file.seconds_at(48) - 0.25).abs() < 1e-9);
+        assert!((file.seconds_at(192) - 1.5).abs() < 1e-9);
+        assert_eq!(file.end_tick(), 192);
+        assert!((file.duration() - 1.5).abs() < 1e-9);
+        assert_eq!(file.merged().len(), 4);
+    }
+
+    #[test]
+    fn timecode_division() {
+        //-25 fps, 40 ticks per frame: a millisecond per tick
+        let file: MidiFile = MidiFile::parse(&smf(0, 0xe728, &[vec![0x00, 0xff, 0x2f, 0x00]])).unwrap();
+        assert_eq!(file.division, Division::Timecode { fps: 25, ticks_per_frame: 40 });
+        assert!((file.seconds_at(1000) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn header_errors() {
+        let empty: Vec<Vec<u8>> = vec![vec![0x00, 0xff, 0x2f, 0x00]];
+        assert!(MidiFile::parse(b"RIFF\0\0\0\x06").is_err());
+        assert!(MidiFile::parse(&smf(2, 96, &empty)).is_err());
+        //-23 is not a frame rate, and a timecode needs ticks
+        assert!(MidiFile::parse(&smf(0, 0xe928, &empty)).is_err());
+        assert!(MidiFile::parse(&smf(0, 0xe700, &empty)).is_err());
+        assert!(MidiFile::parse(&smf(0, 96, &[vec![0x00, 60, 100]])).is_err());
+        assert!(MidiFile::parse(&smf(0, 96, &[vec![0x00, 0xf4]])).is_err());
+    }
+
+    #[test]
+    fn truncated_last_track_is_read_as_far_as_it_goes() {
+        let mut data: Vec<u8> = smf(0, 96, &[vec![0x00, 0x90, 60, 100, 0x00, 0xff, 0x2f, 0x00]]);
+        data.truncate(data.len() - 4);
+        let file: MidiFile = MidiFile::parse(&data).unwrap();
+        assert_eq!(file.tracks[0].len(), 1);
+    }
+
+}
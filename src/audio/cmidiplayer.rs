@@ -0,0 +1,410 @@
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::audio::cmidi::{Division, MidiEvent, MidiFile, MidiMessage};
+use crate::audio::cmidisynth::{MidiSynth, DRUM_CHANNEL};
+use crate::audio::csound::AudioError;
+use crate::audio::csoundfont::SoundFont;
+use crate::audio::cstream::AudioSource;
+
+///Playback controls shared between a `MidiPlayer` and the game, so they keep working after the player has been
+/// handed to `Mixer::play_stream`.
+#[derive(Debug)]
+pub struct MidiControls {
+    tempo: AtomicU32,
+    transpose: AtomicI32,
+    mutes: AtomicU32,
+}
+
+impl MidiControls {
+
+    fn new() -> MidiControls {
+        MidiControls { tempo: AtomicU32::new(1f32.to_bits()), transpose: AtomicI32::new(0), mutes: AtomicU32::new(0) }
+    }
+
+    ///Playback speed relative to the file's own tempo map; 2 plays twice as fast.
+    pub fn set_tempo(&self, scale: f32) {
+        self.tempo.store(scale.max(0.01).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn tempo(&self) -> f32 {
+        f32::from_bits(self.tempo.load(Ordering::Relaxed))
+    }
+
+    ///Shifts every note except drums by `semitones`. Notes already sounding keep their pitch.
+    pub fn set_transpose(&self, semitones: i32) {
+        self.transpose.store(semitones, Ordering::Relaxed);
+    }
+
+    pub fn transpose(&self) -> i32 {
+        self.transpose.load(Ordering::Relaxed)
+    }
+
+    ///Silences MIDI channel 0 to 15. Sounding notes on it are released.
+    pub fn set_muted(&self, channel: u8, muted: bool) {
+        let bit: u32 = 1 << (channel & 15);
+        if muted {
+            self.mutes.fetch_or(bit, Ordering::Relaxed);
+        }
+        else {
+            self.mutes.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_muted(&self, channel: u8) -> bool {
+        self.mutes.load(Ordering::Relaxed) & (1 << (channel & 15)) != 0
+    }
+
+}
+
+///Sequences a `MidiFile` through a `MidiSynth`, producing interleaved stereo PCM a block at a time. Implements
+/// `AudioSource`, so it can be streamed through the mixer like a music file.
+pub struct MidiPlayer {
+    pub synth: MidiSynth,
+    ///Starts over at the end of the song instead of finishing.
+    pub looping: bool,
+    file: MidiFile,
+    events: Vec<MidiEvent>,
+    next_event: usize,
+    tick: f64,
+    tempo: u32,
+    end_tick: u64,
+    frames: usize,
+    controls: Arc<MidiControls>,
+    mutes: u32,
+    ///The key each (channel, written key) is sounding as, so note-offs still match after a transpose change.
+    sounding: Vec<[Option<u8>; 128]>,
+}
+
+impl MidiPlayer {
+
+    pub fn new(file: MidiFile, sample_rate: u32) -> MidiPlayer {
+        let events: Vec<MidiEvent> = file.merged();
+        let end_tick: u64 = file.end_tick();
+        let tempo: u32 = file.tempo_map[0].1;
+        MidiPlayer {
+            synth: MidiSynth::new(sample_rate),
+            looping: false,
+            file,
+            events,
+            next_event: 0,
+            tick: 0.0,
+            tempo,
+            end_tick,
+            frames: 0,
+            controls: Arc::new(MidiControls::new()),
+            mutes: 0,
+            sounding: vec![[None; 128]; 16],
+        }
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    ///Plays instruments from `soundfont` instead of the built-in oscillator patches.
+    pub fn with_soundfont(mut self, soundfont: Arc<SoundFont>) -> Self {
+        self.synth.set_soundfont(Some(soundfont));
+        self
+    }
+
+    pub fn file(&self) -> &MidiFile {
+        &self.file
+    }
+
+    ///A handle to the tempo, transpose and mute controls.
+    pub fn controls(&self) -> Arc<MidiControls> {
+        self.controls.clone()
+    }
+
+    pub fn set_tempo(&self, scale: f32) {
+        self.controls.set_tempo(scale);
+    }
+
+    pub fn set_transpose(&self, semitones: i32) {
+        self.controls.set_transpose(semitones);
+    }
+
+    pub fn set_muted(&self, channel: u8, muted: bool) {
+        self.controls.set_muted(channel, muted);
+    }
+
+    ///True once the song and every note's release have played out. A looping player never finishes.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next_event >= self.events.len() && self.tick >= self.end_tick as f64 && self.synth.active_voices() == 0
+    }
+
+    ///Song position in seconds at the written tempo.
+    pub fn seconds(&self) -> f64 {
+        self.file.seconds_at(self.tick as u64)
+    }
+
+    fn ticks_per_frame(&self) -> f64 {
+        let per_second: f64 = match self.file.division {
+            Division::Metrical(tpq) => tpq as f64*1e6 / self.tempo as f64,
+            Division::Timecode { fps, ticks_per_frame } => {
+                let fps: f64 = if fps == 29 { 29.97 } else { fps as f64 };
+                fps*ticks_per_frame.max(1) as f64
+            }
+        };
+        per_second*self.controls.tempo() as f64 / self.synth.sample_rate.max(1) as f64
+    }
+
+    //Sends one event to the synth. While seeking, notes are skipped but everything else still applies
+    fn dispatch(&mut self, event: MidiEvent, audible: bool) {
+        match event.message {
+            MidiMessage::Tempo(tempo) => self.tempo = tempo,
+            MidiMessage::NoteOn { channel, key, velocity } => {
+                if !audible || self.mutes & (1 << channel) != 0 {
+                    return;
+                }
+                let shift: i32 = if channel == DRUM_CHANNEL { 0 } else { self.controls.transpose() };
+                let played: i32 = key as i32 + shift;
+                if (0..128).contains(&played) {
+                    self.sounding[channel as usize][key as usize & 127] = Some(played as u8);
+                    self.synth.note_on(channel, played as u8, velocity);
+                }
+            }
+            MidiMessage::NoteOff { channel, key, .. } => {
+                if let Some(played) = self.sounding[channel as usize][key as usize & 127].take() {
+                    self.synth.note_off(channel, played);
+                }
+            }
+            message => self.synth.handle(&message),
+        }
+    }
+
+    fn restart(&mut self) {
+        for channel in 0..16 {
+            self.synth.all_notes_off(channel);
+            //Reset All Controllers, so bends and pedals from the end of the song do not carry into the next pass
+            self.synth.handle(&MidiMessage::ControlChange { channel, controller: 121, value: 0 });
+        }
+        self.sounding = vec![[None; 128]; 16];
+        self.next_event = 0;
+        self.tick = 0.0;
+        self.tempo = self.file.tempo_map[0].1;
+    }
+
+    fn apply_mutes(&mut self) {
+        let mutes: u32 = self.controls.mutes.load(Ordering::Relaxed);
+        let newly: u32 = mutes & !self.mutes;
+        self.mutes = mutes;
+        for channel in 0..16u8 {
+            if newly & (1 << channel) != 0 {
+                self.synth.all_notes_off(channel);
+                self.sounding[channel as usize] = [None; 128];
+            }
+        }
+    }
+
+    //Advances `frames` frames, rendering into `out` when given, otherwise only applying events. Returns the frames
+    // covered, which falls short only when the song ends
+    fn advance(&mut self, mut out: Option<&mut [f32]>, frames: usize) -> usize {
+        self.apply_mutes();
+        let mut done: usize = 0;
+        while done < frames {
+            while self.next_event < self.events.len() && self.events[self.next_event].tick as f64 <= self.tick {
+                let event: MidiEvent = self.events[self.next_event];
+                self.next_event += 1;
+                self.dispatch(event, out.is_some());
+            }
+            let song_over: bool = self.next_event >= self.events.len() && self.tick >= self.end_tick as f64;
+            if song_over {
+                if self.looping && self.end_tick > 0 {
+                    self.restart();
+                    continue;
+                }
+                if out.is_none() || self.synth.active_voices() == 0 {
+                    break;
+                }
+            }
+            let step: f64 = self.ticks_per_frame();
+            let next_tick: f64 = match self.events.get(self.next_event) {
+                Some(e) => e.tick as f64,
+                None if !song_over => self.end_tick as f64,
+                None => f64::INFINITY,
+            };
+            let until: usize = ((next_tick - self.tick) / step).ceil().clamp(1.0, (frames - done) as f64) as usize;
+            if let Some(out) = out.as_deref_mut() {
+                self.synth.render(&mut out[done*2..(done + until)*2]);
+            }
+            self.tick += until as f64*step;
+            done += until;
+        }
+        self.frames += done;
+        done
+    }
+
+    ///Fills `out` with the next interleaved stereo frames and returns how many were written; 0 once finished.
+    pub fn render(&mut self, out: &mut [f32]) -> usize {
+        let frames: usize = out.len() / 2;
+        self.advance(Some(out), frames)
+    }
+
+}
+
+impl AudioSource for MidiPlayer {
+
+    fn sample_rate(&self) -> u32 {
+        self.synth.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    ///Unknown, since the tempo can change during playback.
+    fn frames(&self) -> Option<usize> {
+        None
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        Ok(self.render(out))
+    }
+
+    ///Replays the song silently up to `frame`, so tempo, programs and controllers are right when it resumes.
+    /// Notes that would still be sounding there are not restarted.
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError> {
+        self.restart();
+        self.synth.reset();
+        self.frames = 0;
+        let looping: bool = self.looping;
+        self.looping = false;
+        self.advance(None, frame);
+        self.looping = looping;
+        self.frames = frame;
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.frames
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    //One tick per second, and the player runs at 100 frames per second
+    fn player(events: &[(u64, MidiMessage)]) -> MidiPlayer {
+        let track: Vec<MidiEvent> = events.iter().map(|(tick, message)| MidiEvent { tick: *tick, message: *message }).collect();
+        let file: MidiFile = MidiFile { format: 0, division: Division::Metrical(1), tracks: vec![track], tempo_map: vec![(0, 1_000_000)] };
+        MidiPlayer::new(file, 100)
+    }
+
+    fn note(channel: u8, key: u8, on: bool) -> MidiMessage {
+        if on { MidiMessage::NoteOn { channel, key, velocity: 100 } } else { MidiMessage::NoteOff { channel, key, velocity: 64 } }
+    }
+
+    fn render(player: &mut MidiPlayer, frames: usize) -> usize {
+        let mut out: Vec<f32> = vec![0.0; frames*2];
+        player.render(&mut out)
+    }
+
+    //Keys currently held down, as played after transposition
+    fn keys(player: &MidiPlayer) -> Vec<u8> {
+        let mut keys: Vec<u8> = player.sounding.iter().flatten().flatten().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn plays_events_on_time_and_finishes() {
+        let mut player: MidiPlayer = player(&[(0, note(0, 60, true)), (1, note(0, 60, false)), (2, note(0, 62, true)), (3, note(0, 62, false))]);
+        assert_eq!(render(&mut player, 50), 50);
+        assert_eq!(keys(&player), vec![60]);
+        render(&mut player, 100);
+        assert_eq!(keys(&player), vec![]);
+        render(&mut player, 100);
+        assert_eq!(keys(&player), vec![62]);
+        assert!((player.tick - 2.5).abs() < 1e-9);
+        assert_eq!(player.seconds(), 2.0);
+
+        //The last release still plays after the song's end, then the player reports nothing more
+        let mut total: usize = 0;
+        while !player.is_finished() {
+            total += render(&mut player, 100);
+            assert!(total < 10000);
+        }
+        assert_eq!(render(&mut player, 100), 0);
+    }
+
+    #[test]
+    fn transpose_spares_drums_and_matches_note_offs() {
+        let mut player: MidiPlayer = player(&[(0, note(0, 60, true)), (0, note(DRUM_CHANNEL, 36, true)), (1, note(0, 60, false))]);
+        player.set_transpose(12);
+        render(&mut player, 50);
+        assert_eq!(keys(&player), vec![36, 72]);
+        //Changing the transpose mid-note still releases the note that was started
+        player.set_transpose(-3);
+        render(&mut player, 100);
+        assert!(!keys(&player).contains(&72));
+    }
+
+    #[test]
+    fn transposing_out_of_range_drops_the_note() {
+        let mut player: MidiPlayer = player(&[(0, note(0, 120, true)), (1, note(0, 120, false))]);
+        player.set_transpose(12);
+        render(&mut player, 10);
+        assert_eq!(player.synth.active_voices(), 0);
+    }
+
+    #[test]
+    fn muting_releases_and_silences_a_channel() {
+        let mut player: MidiPlayer = player(&[(0, note(1, 60, true)), (1, note(1, 62, true)), (2, note(1, 60, false))]);
+        render(&mut player, 50);
+        let controls: Arc<MidiControls> = player.controls();
+        controls.set_muted(1, true);
+        assert!(controls.is_muted(1) && !controls.is_muted(0));
+        render(&mut player, 100);
+        assert_eq!(keys(&player), vec![]);
+    }
+
+    #[test]
+    fn tempo_scale_speeds_up_the_song() {
+        let mut player: MidiPlayer = player(&[(0, note(0, 60, true)), (10, note(0, 60, false))]);
+        player.controls().set_tempo(2.0);
+        render(&mut player, 100);
+        assert!((player.tick - 2.0).abs() < 1e-9);
+        player.controls().set_tempo(0.0);
+        assert_eq!(player.controls().tempo(), 0.01);
+    }
+
+    #[test]
+    fn tempo_events_change_the_rate() {
+        let mut player: MidiPlayer = player(&[(0, MidiMessage::Tempo(500_000)), (0, note(0, 60, true)), (4, note(0, 60, false))]);
+        render(&mut player, 100);
+        assert!((player.tick - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn seek_replays_everything_but_notes() {
+        let mut player: MidiPlayer = player(&[
+            (0, note(0, 60, true)),
+            (1, MidiMessage::Tempo(2_000_000)),
+            (3, note(0, 60, false)),
+        ]);
+        //One tick at the first tempo, then half a tick at the slower one
+        player.seek(150).unwrap();
+        assert_eq!(player.position(), 150);
+        assert_eq!(player.tempo, 2_000_000);
+        assert!((player.tick - 1.25).abs() < 1e-9);
+        assert_eq!(player.synth.active_voices(), 0);
+        assert_eq!(keys(&player), vec![]);
+    }
+
+    #[test]
+    fn looping_starts_over() {
+        let mut player: MidiPlayer = player(&[(0, note(0, 60, true)), (1, note(0, 60, false)), (2, note(0, 62, true))]).looping(true);
+        render(&mut player, 250);
+        assert!(player.tick < 1.0);
+        assert_eq!(keys(&player), vec![60]);
+        assert!(!player.is_finished());
+        assert_eq!(player.frames(), None);
+    }
+
+}
@@ -0,0 +1,587 @@
+use std::sync::Arc;
+
+use crate::audio::cmidi::MidiMessage;
+use crate::audio::csoundfont::{LoopMode, Region, SoundFont};
+use crate::audio::csynth::{Adsr, Noise, NoiseColor, Oscillator, Waveform};
+
+///The General MIDI percussion channel (channel 10, counting from 1).
+pub const DRUM_CHANNEL: u8 = 9;
+
+///How the built-in synthesizer plays one General MIDI program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    pub waveform: Waveform,
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f32,
+    pub release: f64,
+    pub gain: f32,
+}
+
+impl Patch {
+
+    pub fn new(waveform: Waveform, attack: f64, decay: f64, sustain: f32, release: f64) -> Patch {
+        Patch { waveform, attack, decay, sustain, release, gain: 1.0 }
+    }
+
+    ///A rough stand-in for a General MIDI program, by its family of eight.
+    pub fn general_midi(program: u8) -> Patch {
+        match program / 8 {
+            0 => Patch::new(Waveform::Triangle, 0.002, 1.2, 0.2, 0.3),                  //Piano
+            1 => Patch::new(Waveform::Sine, 0.001, 0.5, 0.0, 0.3),                      //Chromatic percussion
+            2 => Patch::new(Waveform::Square(0.5), 0.01, 0.1, 0.9, 0.05),               //Organ
+            3 => Patch::new(Waveform::Saw, 0.002, 0.8, 0.15, 0.2),                      //Guitar
+            4 => Patch::new(Waveform::Triangle, 0.005, 0.4, 0.6, 0.1),                  //Bass
+            5 | 6 => Patch::new(Waveform::Saw, 0.15, 0.3, 0.8, 0.4),                    //Strings and ensemble
+            7 => Patch::new(Waveform::Square(0.3), 0.04, 0.2, 0.75, 0.15),              //Brass
+            8 => Patch::new(Waveform::Square(0.2), 0.03, 0.2, 0.7, 0.1),                //Reed
+            9 => Patch::new(Waveform::Sine, 0.05, 0.2, 0.85, 0.15),                     //Pipe
+            10 => Patch::new(Waveform::Saw, 0.005, 0.2, 0.7, 0.1),                      //Synth lead
+            11 => Patch::new(Waveform::Triangle, 0.4, 0.5, 0.8, 0.8),                   //Synth pad
+            12 => Patch::new(Waveform::Square(0.4), 0.2, 0.6, 0.5, 0.8),                //Synth effects
+            13 => Patch::new(Waveform::Saw, 0.002, 0.6, 0.2, 0.2),                      //Ethnic
+            14 => Patch::new(Waveform::Sine, 0.001, 0.3, 0.0, 0.1),                     //Percussive
+            _ => Patch::new(Waveform::Square(0.5), 0.01, 0.3, 0.3, 0.3),                //Sound effects
+        }
+    }
+
+}
+
+#[derive(Clone, Debug)]
+struct ChannelState {
+    program: u8,
+    bank: u16,
+    volume: f32,
+    expression: f32,
+    pan: f32,
+    ///Current bend in semitones.
+    bend: f32,
+    bend_range: f32,
+    sustain: bool,
+    rpn: (u8, u8),
+}
+
+impl ChannelState {
+
+    fn new() -> ChannelState {
+        ChannelState { program: 0, bank: 0, volume: 100.0 / 127.0, expression: 1.0, pan: 0.0, bend: 0.0, bend_range: 2.0, sustain: false, rpn: (127, 127) }
+    }
+
+    fn reset_controllers(&mut self) {
+        self.expression = 1.0;
+        self.bend = 0.0;
+        self.sustain = false;
+        self.rpn = (127, 127);
+    }
+
+}
+
+//Synthesized drum: a pitched body sweeping down, plus a noise burst, both decaying exponentially
+struct Drum {
+    tone: Oscillator,
+    end_freq: f64,
+    sweep: f64,
+    tone_level: f32,
+    noise: Noise,
+    noise_level: f32,
+    ///Whether the noise is differentiated, a crude high-pass that makes cymbals bright.
+    bright: bool,
+    last_noise: f32,
+    decay: f32,
+    level: f32,
+}
+
+impl Drum {
+
+    fn new(key: u8, sample_rate: u32) -> Drum {
+        let rate: f32 = sample_rate as f32;
+        //(start Hz, end Hz, tone level, noise level, bright, decay seconds)
+        let (start, end, tone, noise, bright, seconds) = match key {
+            35 | 36 => (160.0, 45.0, 1.0, 0.05, false, 0.35),                       //Kick
+            37 => (900.0, 900.0, 0.3, 0.6, true, 0.05),                             //Side stick
+            38 | 40 => (220.0, 160.0, 0.45, 0.7, false, 0.18),                      //Snare
+            39 => (0.0, 0.0, 0.0, 0.8, true, 0.15),                                 //Clap
+            41 | 43 | 45 | 47 | 48 | 50 => {
+                //Toms, tuned by key
+                let f: f64 = 60.0*2f64.powf((key as f64 - 41.0) / 12.0);
+                (f*1.6, f, 1.0, 0.1, false, 0.4)
+            }
+            42 | 44 => (0.0, 0.0, 0.0, 0.5, true, 0.05),                            //Closed and pedal hi-hat
+            46 => (0.0, 0.0, 0.0, 0.5, true, 0.3),                                  //Open hi-hat
+            49 | 52 | 55 | 57 => (0.0, 0.0, 0.0, 0.6, true, 1.2),                   //Crash
+            51 | 53 | 59 => (0.0, 0.0, 0.0, 0.35, true, 0.8),                       //Ride
+            _ => (400.0, 300.0, 0.4, 0.4, false, 0.12),
+        };
+        let samples: f32 = (seconds*rate).max(1.0);
+        Drum {
+            tone: Oscillator::new(Waveform::Sine, start, sample_rate),
+            end_freq: end,
+            //Reaches most of the way to the end pitch in a twentieth of a second
+            sweep: (-1.0 / (0.05*sample_rate as f64)).exp(),
+            tone_level: tone,
+            noise: Noise::new(NoiseColor::White, key as u64),
+            noise_level: noise,
+            bright,
+            last_noise: 0.0,
+            //-60 dB over the decay time
+            decay: (0.001f32.ln() / samples).exp(),
+            level: 1.0,
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        self.tone.frequency = self.end_freq + (self.tone.frequency - self.end_freq)*self.sweep;
+        let mut noise: f32 = self.noise.next_sample();
+        if self.bright {
+            let n: f32 = noise;
+            noise = (noise - self.last_noise)*0.5;
+            self.last_noise = n;
+        }
+        let s: f32 = (self.tone.next_sample()*self.tone_level + noise*self.noise_level)*self.level;
+        self.level *= self.decay;
+        s
+    }
+
+}
+
+struct SampleVoice {
+    region: Region,
+    samples: Arc<Vec<f32>>,
+    position: f64,
+    ///Source frames per output frame with no pitch bend.
+    step: f64,
+}
+
+enum VoiceKind {
+    Tone(Oscillator),
+    Drum(Drum),
+    Sample(SampleVoice),
+}
+
+struct Voice {
+    channel: u8,
+    key: u8,
+    gain: f32,
+    pan: f32,
+    kind: VoiceKind,
+    env: Adsr,
+    released: bool,
+    ///Key let go while the sustain pedal was down; released when the pedal lifts.
+    held: bool,
+    age: u64,
+    finished: bool,
+}
+
+fn key_frequency(key: f32) -> f64 {
+    440.0*2f64.powf((key as f64 - 69.0) / 12.0)
+}
+
+///A General MIDI-style polyphonic synthesizer driven by MIDI messages. Plays oscillator patches by default, or
+/// sampled instruments once a SoundFont is set.
+pub struct MidiSynth {
+    pub sample_rate: u32,
+    ///Voices beyond this steal the oldest, preferring ones already released.
+    pub max_voices: usize,
+    ///Output gain, leaving headroom for many notes at once.
+    pub gain: f32,
+    patches: Vec<Patch>,
+    soundfont: Option<Arc<SoundFont>>,
+    channels: Vec<ChannelState>,
+    voices: Vec<Voice>,
+    clock: u64,
+}
+
+impl MidiSynth {
+
+    pub fn new(sample_rate: u32) -> MidiSynth {
+        MidiSynth {
+            sample_rate,
+            max_voices: 48,
+            gain: 0.3,
+            patches: (0..128).map(Patch::general_midi).collect(),
+            soundfont: None,
+            channels: vec![ChannelState::new(); 16],
+            voices: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    ///Replaces the oscillator patch used for `program`.
+    pub fn set_patch(&mut self, program: u8, patch: Patch) {
+        self.patches[program as usize & 127] = patch;
+    }
+
+    ///Plays sampled instruments from `soundfont`; `None` goes back to the oscillator patches.
+    pub fn set_soundfont(&mut self, soundfont: Option<Arc<SoundFont>>) {
+        self.soundfont = soundfont;
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn { channel, key, velocity } => self.note_on(channel, key, velocity),
+            MidiMessage::NoteOff { channel, key, .. } => self.note_off(channel, key),
+            MidiMessage::ControlChange { channel, controller, value } => self.control_change(channel, controller, value),
+            MidiMessage::ProgramChange { channel, program } => self.channels[channel as usize & 15].program = program & 127,
+            MidiMessage::PitchBend { channel, value } => {
+                let state: &mut ChannelState = &mut self.channels[channel as usize & 15];
+                state.bend = value as f32 / 8192.0*state.bend_range;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        let channel: u8 = channel & 15;
+        if velocity == 0 {
+            self.note_off(channel, key);
+            return;
+        }
+        //Retriggering a key cuts its previous note short
+        for v in self.voices.iter_mut().filter(|v| v.channel == channel && v.key == key && !v.released) {
+            v.released = true;
+            v.env.note_off();
+        }
+        let gain: f32 = (velocity as f32 / 127.0).powi(2);
+        let state: &ChannelState = &self.channels[channel as usize];
+        let mut new_voices: Vec<Voice> = Vec::new();
+
+        if let Some(soundfont) = &self.soundfont {
+            let bank: u16 = if channel == DRUM_CHANNEL { 128 } else { state.bank };
+            if let Some(preset) = soundfont.preset(bank, state.program as u16) {
+                for region in preset.regions.iter().filter(|r| r.matches(key, velocity)) {
+                    let cents: f64 = (key as f64 - region.root_key as f64)*region.scale_tuning as f64 + region.tune as f64;
+                    let step: f64 = region.sample_rate as f64 / self.sample_rate as f64*2f64.powf(cents / 1200.0);
+                    let mut env: Adsr = Adsr::new(region.attack, region.hold + region.decay, region.sustain, region.release, self.sample_rate);
+                    env.note_on();
+                    new_voices.push(Voice {
+                        channel,
+                        key,
+                        gain: gain*region.gain,
+                        pan: region.pan,
+                        kind: VoiceKind::Sample(SampleVoice { region: region.clone(), samples: soundfont.samples.clone(), position: region.start as f64, step }),
+                        env,
+                        released: false,
+                        held: false,
+                        age: self.clock,
+                        finished: false,
+                    });
+                }
+            }
+        }
+        else if channel == DRUM_CHANNEL {
+            let mut env: Adsr = Adsr::new(0.0, 0.0, 1.0, 0.05, self.sample_rate);
+            env.note_on();
+            new_voices.push(Voice { channel, key, gain, pan: 0.0, kind: VoiceKind::Drum(Drum::new(key, self.sample_rate)), env, released: false, held: false, age: self.clock, finished: false });
+        }
+        else {
+            let patch: &Patch = &self.patches[state.program as usize];
+            let mut env: Adsr = Adsr::new(patch.attack, patch.decay, patch.sustain, patch.release, self.sample_rate);
+            env.note_on();
+            let osc: Oscillator = Oscillator::new(patch.waveform.clone(), key_frequency(key as f32 + state.bend), self.sample_rate);
+            new_voices.push(Voice { channel, key, gain: gain*patch.gain, pan: 0.0, kind: VoiceKind::Tone(osc), env, released: false, held: false, age: self.clock, finished: false });
+        }
+
+        for voice in new_voices {
+            if self.voices.len() >= self.max_voices.max(1) {
+                let steal: Option<usize> = self.voices.iter().enumerate()
+                    .min_by_key(|(_, v)| (!v.released, v.age))
+                    .map(|(i, _)| i);
+                if let Some(i) = steal {
+                    self.voices.swap_remove(i);
+                }
+            }
+            self.voices.push(voice);
+        }
+        self.clock += 1;
+    }
+
+    pub fn note_off(&mut self, channel: u8, key: u8) {
+        let sustain: bool = self.channels[channel as usize & 15].sustain;
+        //Synthesized drums ring out on their own, as General MIDI drum kits ignore note-offs
+        for v in self.voices.iter_mut().filter(|v| v.channel == channel & 15 && v.key == key && !v.released && !matches!(v.kind, VoiceKind::Drum(_))) {
+            if sustain {
+                v.held = true;
+            }
+            else {
+                v.released = true;
+                v.env.note_off();
+            }
+        }
+    }
+
+    ///Releases every note on `channel`, letting them fade out.
+    pub fn all_notes_off(&mut self, channel: u8) {
+        for v in self.voices.iter_mut().filter(|v| v.channel == channel & 15) {
+            v.released = true;
+            v.held = false;
+            v.env.note_off();
+        }
+    }
+
+    ///Silences everything at once and resets every channel.
+    pub fn reset(&mut self) {
+        self.voices.clear();
+        for state in self.channels.iter_mut() {
+            *state = ChannelState::new();
+        }
+    }
+
+    fn control_change(&mut self, channel: u8, controller: u8, value: u8) {
+        let state: &mut ChannelState = &mut self.channels[channel as usize & 15];
+        let v: f32 = value as f32 / 127.0;
+        match controller {
+            0 => state.bank = value as u16,
+            //Data entry for the pitch bend range RPN
+            6 if state.rpn == (0, 0) => state.bend_range = value as f32,
+            7 => state.volume = v,
+            10 => state.pan = (value as f32 - 64.0) / 63.0,
+            11 => state.expression = v,
+            64 => {
+                state.sustain = value >= 64;
+                if !state.sustain {
+                    for voice in self.voices.iter_mut().filter(|voice| voice.channel == channel & 15 && voice.held) {
+                        voice.held = false;
+                        voice.released = true;
+                        voice.env.note_off();
+                    }
+                }
+            }
+            100 => state.rpn.1 = value,
+            101 => state.rpn.0 = value,
+            120 => self.voices.retain(|voice| voice.channel != channel & 15),
+            121 => state.reset_controllers(),
+            123 => self.all_notes_off(channel),
+            _ => {}
+        }
+    }
+
+    ///Overwrites `out` with the next `out.len() / 2` interleaved stereo frames.
+    pub fn render(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        for voice in self.voices.iter_mut() {
+            let state: &ChannelState = &self.channels[voice.channel as usize];
+            let gain: f32 = voice.gain*state.volume*state.expression*self.gain*std::f32::consts::SQRT_2;
+            let angle: f32 = ((state.pan + voice.pan).clamp(-1.0, 1.0) + 1.0)*std::f32::consts::FRAC_PI_4;
+            let (left, right) = (angle.cos()*gain, angle.sin()*gain);
+            let bend: f64 = 2f64.powf(state.bend as f64 / 12.0);
+            if let VoiceKind::Tone(osc) = &mut voice.kind {
+                osc.frequency = key_frequency(voice.key as f32 + state.bend);
+            }
+
+            for frame in out.chunks_exact_mut(2) {
+                let s: f32 = match &mut voice.kind {
+                    VoiceKind::Tone(osc) => osc.next_sample(),
+                    VoiceKind::Drum(drum) => {
+                        if drum.level < 1e-4 {
+                            voice.finished = true;
+                            break;
+                        }
+                        drum.next_sample()
+                    }
+                    VoiceKind::Sample(sv) => {
+                        let r: &Region = &sv.region;
+                        let looping: bool = r.loop_mode == LoopMode::Continuous || (r.loop_mode == LoopMode::UntilRelease && !voice.released);
+                        if looping && sv.position >= r.loop_end as f64 {
+                            //A short loop played far above its root key can be stepped over several times in one frame
+                            let length: f64 = (r.loop_end - r.loop_start).max(1) as f64;
+                            sv.position = r.loop_start as f64 + (sv.position - r.loop_start as f64) % length;
+                        }
+                        if !looping && sv.position >= r.end as f64 - 1.0 {
+                            voice.finished = true;
+                            break;
+                        }
+                        let i: usize = (sv.position as usize).min(sv.samples.len() - 1);
+                        let t: f32 = (sv.position - i as f64) as f32;
+                        let next: usize = if looping && i + 1 >= r.loop_end { r.loop_start } else { i + 1 };
+                        let s: f32 = sv.samples[i]*(1.0 - t) + sv.samples[next.min(sv.samples.len() - 1)]*t;
+                        sv.position += sv.step*bend;
+                        s
+                    }
+                };
+                let env: f32 = voice.env.next_sample();
+                frame[0] += s*env*left;
+                frame[1] += s*env*right;
+            }
+            if !voice.env.is_active() {
+                voice.finished = true;
+            }
+        }
+        self.voices.retain(|v| !v.finished);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::audio::csoundfont::Preset;
+
+    fn render(synth: &mut MidiSynth, frames: usize) -> Vec<f32> {
+        let mut out: Vec<f32> = vec![0.0; frames*2];
+        synth.render(&mut out);
+        out
+    }
+
+    fn cc(synth: &mut MidiSynth, channel: u8, controller: u8, value: u8) {
+        synth.handle(&MidiMessage::ControlChange { channel, controller, value });
+    }
+
+    fn sampled_font(region: Region, samples: Vec<f32>) -> Arc<SoundFont> {
+        Arc::new(SoundFont { presets: vec![Preset { name: "Test".to_string(), bank: 0, program: 0, regions: vec![region] }], samples: Arc::new(samples) })
+    }
+
+    fn region() -> Region {
+        Region {
+            key_range: (0, 127),
+            velocity_range: (0, 127),
+            start: 0,
+            end: 100,
+            loop_start: 50,
+            loop_end: 100,
+            loop_mode: LoopMode::Continuous,
+            sample_rate: 1000,
+            root_key: 60,
+            tune: 0.0,
+            scale_tuning: 100.0,
+            pan: 0.0,
+            gain: 1.0,
+            attack: 0.0,
+            hold: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.01,
+        }
+    }
+
+    #[test]
+    fn notes_sound_and_release() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.note_on(0, 69, 127);
+        assert_eq!(synth.active_voices(), 1);
+        assert!(render(&mut synth, 100).iter().any(|s| *s != 0.0));
+        synth.note_off(0, 69);
+        render(&mut synth, 1000);
+        assert_eq!(synth.active_voices(), 0);
+    }
+
+    #[test]
+    fn zero_velocity_is_a_note_off() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.note_on(0, 60, 100);
+        synth.note_on(0, 60, 0);
+        assert!(synth.voices.iter().all(|v| v.released));
+    }
+
+    #[test]
+    fn sustain_pedal_holds_notes() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        cc(&mut synth, 0, 64, 127);
+        synth.note_on(0, 60, 100);
+        synth.note_off(0, 60);
+        assert!(synth.voices[0].held && !synth.voices[0].released);
+        cc(&mut synth, 0, 64, 0);
+        assert!(!synth.voices[0].held && synth.voices[0].released);
+    }
+
+    #[test]
+    fn stealing_prefers_released_voices() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.max_voices = 2;
+        synth.note_on(0, 60, 100);
+        synth.note_on(0, 62, 100);
+        synth.note_off(0, 62);
+        synth.note_on(0, 64, 100);
+        let mut keys: Vec<u8> = synth.voices.iter().map(|v| v.key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![60, 64]);
+        //With nothing released, the oldest goes
+        synth.note_on(0, 65, 100);
+        let mut keys: Vec<u8> = synth.voices.iter().map(|v| v.key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![64, 65]);
+    }
+
+    #[test]
+    fn drums_ignore_note_offs_and_ring_out() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.note_on(DRUM_CHANNEL, 42, 127);
+        synth.note_off(DRUM_CHANNEL, 42);
+        assert!(!synth.voices[0].released);
+        render(&mut synth, 1000);
+        assert_eq!(synth.active_voices(), 0);
+    }
+
+    #[test]
+    fn bend_range_is_set_through_the_rpn() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        cc(&mut synth, 3, 101, 0);
+        cc(&mut synth, 3, 100, 0);
+        cc(&mut synth, 3, 6, 12);
+        synth.handle(&MidiMessage::PitchBend { channel: 3, value: -8192 });
+        assert_eq!(synth.channels[3].bend, -12.0);
+        //Reset All Controllers recenters the bend but keeps the range
+        cc(&mut synth, 3, 121, 0);
+        assert_eq!((synth.channels[3].bend, synth.channels[3].bend_range), (0.0, 12.0));
+        //Data entry does nothing while another RPN is selected
+        cc(&mut synth, 3, 6, 2);
+        assert_eq!(synth.channels[3].bend_range, 12.0);
+    }
+
+    #[test]
+    fn pan_and_volume_controllers() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.set_patch(0, Patch::new(Waveform::Square(1.0), 0.0, 0.0, 1.0, 0.01));
+        cc(&mut synth, 0, 10, 127);
+        synth.note_on(0, 60, 127);
+        let out: Vec<f32> = render(&mut synth, 10);
+        assert!(out[18].abs() < 1e-6 && out[19] > 0.0);
+        cc(&mut synth, 0, 7, 0);
+        assert!(render(&mut synth, 10).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn sound_off_and_reset() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.note_on(0, 60, 100);
+        synth.note_on(1, 60, 100);
+        cc(&mut synth, 0, 120, 0);
+        assert_eq!(synth.voices.iter().map(|v| v.channel).collect::<Vec<u8>>(), vec![1]);
+        synth.handle(&MidiMessage::ProgramChange { channel: 1, program: 40 });
+        synth.reset();
+        assert_eq!((synth.active_voices(), synth.channels[1].program), (0, 0));
+    }
+
+    #[test]
+    fn sampled_notes_are_pitched_from_the_root_key() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.set_soundfont(Some(sampled_font(region(), vec![0.5; 100])));
+        synth.note_on(0, 72, 127);
+        match &synth.voices[0].kind {
+            VoiceKind::Sample(sv) => assert!((sv.step - 2.0).abs() < 1e-9),
+            _ => panic!("expected a sampled voice"),
+        }
+        //The loop keeps the note going well past the end of the sample
+        let out: Vec<f32> = render(&mut synth, 500);
+        assert!(out[998].abs() > 0.0);
+        assert_eq!(synth.active_voices(), 1);
+    }
+
+    #[test]
+    fn unlooped_samples_end() {
+        let mut synth: MidiSynth = MidiSynth::new(1000);
+        synth.set_soundfont(Some(sampled_font(Region { loop_mode: LoopMode::None, ..region() }, vec![0.5; 100])));
+        synth.note_on(0, 60, 127);
+        render(&mut synth, 200);
+        assert_eq!(synth.active_voices(), 0);
+        //Keys outside every region play nothing
+        synth.set_soundfont(Some(sampled_font(Region { key_range: (10, 20), ..region() }, vec![0.5; 100])));
+        synth.note_on(0, 60, 127);
+        assert_eq!(synth.active_voices(), 0);
+    }
+
+}
@@ -0,0 +1,631 @@
+use std::sync::Arc;
+
+use crate::audio::ceffects::{Effect, EffectChain};
+use crate::audio::cresample::{interpolation_weights, Interpolation};
+use crate::audio::csound::Sound;
+use crate::audio::cstream::AudioSource;
+
+///Group of voices sharing a gain control, so music, effects and dialogue can be balanced separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Music,
+    Sfx,
+    Voice,
+}
+
+impl Bus {
+    pub const ALL: [Bus; 3] = [Bus::Music, Bus::Sfx, Bus::Voice];
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+///Identifies a playing voice. Ids are never reused, so a stale id is simply ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceId(u64);
+
+///Settings a voice starts with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayOptions {
+    pub bus: Bus,
+    pub volume: f32,
+    ///-1 is fully left, 0 centered and 1 fully right.
+    pub pan: f32,
+    ///Playback rate; 2.0 plays an octave up and twice as fast. A voice at 0 cannot advance, so it stops.
+    pub pitch: f64,
+    pub looping: bool,
+    ///Seconds to fade in from silence.
+    pub fade_in: f64,
+}
+
+impl PlayOptions {
+
+    pub fn new(bus: Bus) -> PlayOptions {
+        PlayOptions { bus, volume: 1.0, pan: 0.0, pitch: 1.0, looping: false, fade_in: 0.0 }
+    }
+
+    pub fn volume(mut self, volume: f32) -> PlayOptions {
+        self.volume = volume;
+        self
+    }
+
+    pub fn pan(mut self, pan: f32) -> PlayOptions {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self
+    }
+
+    pub fn pitch(mut self, pitch: f64) -> PlayOptions {
+        self.pitch = pitch.max(0.0);
+        self
+    }
+
+    pub fn looping(mut self, looping: bool) -> PlayOptions {
+        self.looping = looping;
+        self
+    }
+
+    pub fn fade_in(mut self, seconds: f64) -> PlayOptions {
+        self.fade_in = seconds;
+        self
+    }
+
+}
+
+impl Default for PlayOptions {
+    fn default() -> PlayOptions {
+        PlayOptions::new(Bus::Sfx)
+    }
+}
+
+//A stream's decoded frames, from frame `start` of the voice onward
+struct StreamBuffer {
+    source: Box<dyn AudioSource + Send>,
+    frames: Vec<(f32, f32)>,
+    start: i64,
+    passes: Vec<(i64, usize)>,  //(voice frame, source frame) where playback started or looped back, oldest first
+    chunk: Vec<f32>,
+    ended: bool,
+}
+
+impl StreamBuffer {
+
+    fn end(&self) -> i64 {
+        self.start + self.frames.len() as i64
+    }
+
+    //Decodes until frame `last` is buffered or the source ends
+    fn fill_to(&mut self, last: i64, looping: bool) {
+        let channels: usize = self.source.channels().max(1) as usize;
+        let mut restarted: bool = false;
+        while !self.ended && self.end() <= last {
+            match self.source.read(&mut self.chunk) {
+                Ok(0) if looping && !restarted => {
+                    restarted = true;
+                    match self.source.seek(0) {
+                        Ok(()) => self.passes.push((self.end(), 0)),
+                        Err(e) => {
+                            println!("[Mixer/WARN] Stopping a stream that cannot loop: {}", e);
+                            self.ended = true;
+                        }
+                    }
+                }
+                Ok(0) => self.ended = true,
+                Ok(n) => {
+                    restarted = false;
+                    for f in self.chunk[..n*channels].chunks_exact(channels) {
+                        self.frames.push(if channels == 1 { (f[0], f[0]) } else { (f[0], f[1]) });
+                    }
+                }
+                Err(e) => {
+                    println!("[Mixer/WARN] Stopping a stream that failed to decode: {}", e);
+                    self.ended = true;
+                }
+            }
+        }
+    }
+
+    fn get(&self, i: i64) -> (f32, f32) {
+        let k: i64 = i - self.start;
+        if k < 0 || k >= self.frames.len() as i64 { (0.0, 0.0) } else { self.frames[k as usize] }
+    }
+
+    //Source frame playing at voice position `position`
+    fn source_frame(&self, position: f64) -> f64 {
+        let (frame, source) = self.passes.iter().rev().find(|p| p.0 as f64 <= position).copied().unwrap_or(self.passes[0]);
+        source as f64 + position - frame as f64
+    }
+
+    //Forgets frames before `first`
+    fn trim(&mut self, first: i64) {
+        let n: usize = (first - self.start).clamp(0, self.frames.len() as i64) as usize;
+        self.frames.drain(..n);
+        self.start += n as i64;
+        while self.passes.len() > 1 && self.passes[1].0 <= first {
+            self.passes.remove(0);
+        }
+    }
+
+}
+
+enum VoiceSource {
+    Sound(Arc<Sound>),
+    Stream(StreamBuffer),
+}
+
+struct Voice {
+    id: VoiceId,
+    source: VoiceSource,
+    options: PlayOptions,
+    position: f64,          //In source frames
+    paused: bool,
+    fade: f32,              //Current fade gain
+    fade_target: f32,
+    fade_step: f32,         //Fade gain change per output frame
+    stop_after_fade: bool,
+    gains: (f32, f32),      //Left and right gain used at the end of the last block, to ramp from
+    finished: bool,
+}
+
+impl Voice {
+
+    fn target_gains(&self, bus_gain: f32) -> (f32, f32) {
+        //Constant-power panning, normalized so a centered voice plays at full volume
+        let angle: f32 = (self.options.pan + 1.0)*std::f32::consts::FRAC_PI_4;
+        let g: f32 = self.options.volume*bus_gain*std::f32::consts::SQRT_2;
+        (angle.cos()*g, angle.sin()*g)
+    }
+
+    fn source_rate(&self) -> u32 {
+        match &self.source {
+            VoiceSource::Sound(sound) => sound.sample_rate,
+            VoiceSource::Stream(stream) => stream.source.sample_rate(),
+        }
+    }
+
+    //Stereo frame `i` of the source; frames outside it are silent, or wrap around for looping sounds
+    fn frame(&self, i: i64) -> (f32, f32) {
+        match &self.source {
+            VoiceSource::Sound(sound) => {
+                let frames: i64 = sound.frames() as i64;
+                let i: i64 = if self.options.looping { i.rem_euclid(frames) } else { i };
+                if i < 0 || i >= frames { (0.0, 0.0) } else { sound.stereo_frame(i as usize) }
+            }
+            VoiceSource::Stream(stream) => stream.get(i),
+        }
+    }
+
+    fn render(&mut self, out: &mut [f32], sample_rate: u32, bus_gain: f32, quality: Interpolation, weights: &mut Vec<(i64, f32)>) {
+        let step: f64 = self.options.pitch*self.source_rate().max(1) as f64 / sample_rate.max(1) as f64;
+        if step.is_nan() || step <= 0.0 {
+            self.finished = true;
+            return;
+        }
+
+        //Volume and pan changes are ramped over the block so they do not click
+        let target: (f32, f32) = self.target_gains(bus_gain);
+        let count: usize = out.len() / 2;
+        let ramp: (f32, f32) = ((target.0 - self.gains.0) / count.max(1) as f32, (target.1 - self.gains.1) / count.max(1) as f32);
+        let mut gains: (f32, f32) = self.gains;
+
+        for frame in out.chunks_exact_mut(2) {
+            interpolation_weights(self.position, step, quality, weights);
+            let last: i64 = weights.iter().map(|w| w.0).max().unwrap_or(0);
+            let end: f64 = match &mut self.source {
+                VoiceSource::Sound(sound) => sound.frames() as f64,
+                VoiceSource::Stream(stream) => {
+                    stream.fill_to(last, self.options.looping);
+                    if stream.ended { stream.end() as f64 } else { f64::INFINITY }
+                }
+            };
+            if self.position >= end {
+                self.finished = true;
+                break;
+            }
+
+            let (mut l, mut r) = (0.0, 0.0);
+            for (k, w) in weights.iter() {
+                let (a, b) = self.frame(*k);
+                l += a*w;
+                r += b*w;
+            }
+            gains = (gains.0 + ramp.0, gains.1 + ramp.1);
+            frame[0] += l*gains.0*self.fade;
+            frame[1] += r*gains.1*self.fade;
+
+            self.position += step;
+            if self.options.looping {
+                if let VoiceSource::Sound(sound) = &self.source {
+                    self.position %= sound.frames().max(1) as f64;
+                }
+            }
+            if self.fade != self.fade_target {
+                self.fade += self.fade_step;
+                if (self.fade_step > 0.0 && self.fade >= self.fade_target) || (self.fade_step < 0.0 && self.fade <= self.fade_target) || self.fade_step == 0.0 {
+                    self.fade = self.fade_target;
+                    if self.stop_after_fade && self.fade <= 0.0 {
+                        self.finished = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if let VoiceSource::Stream(stream) = &mut self.source {
+            stream.trim(self.position.floor() as i64 - 64);
+        }
+        self.gains = target;
+    }
+
+}
+
+///Mixes any number of playing sounds into interleaved stereo f32 frames at a fixed output sample rate. Call
+/// `render` from whatever drives the audio device (or a file, or a test).
+pub struct Mixer {
+    pub sample_rate: u32,
+    ///Gain applied after the buses.
+    pub master_gain: f32,
+    ///How voices are resampled to the output rate and pitched.
+    pub interpolation: Interpolation,
+    ///Master insert effects, run over every block after mixing.
+    pub effects: EffectChain,
+    bus_gains: [f32; 3],
+    voices: Vec<Voice>,
+    next_id: u64,
+    weights: Vec<(i64, f32)>,
+}
+
+impl Mixer {
+
+    pub fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sample_rate,
+            master_gain: 1.0,
+            interpolation: Interpolation::Cubic,
+            effects: EffectChain::new(),
+            bus_gains: [1.0; 3],
+            voices: Vec::new(),
+            next_id: 0,
+            weights: Vec::new(),
+        }
+    }
+
+    pub fn play(&mut self, sound: Arc<Sound>, bus: Bus) -> VoiceId {
+        self.play_with(sound, PlayOptions::new(bus))
+    }
+
+    pub fn play_with(&mut self, sound: Arc<Sound>, options: PlayOptions) -> VoiceId {
+        if sound.frames() == 0 {
+            //Nothing to play, so the voice is over before it starts
+            let id: VoiceId = VoiceId(self.next_id);
+            self.next_id += 1;
+            return id;
+        }
+        self.start_voice(VoiceSource::Sound(sound), options)
+    }
+
+    ///Plays audio decoded a chunk at a time, e.g. a `WavStream` of a long music track. A looping voice seeks the
+    /// source back to its start when it runs out; use `LoopingSource` for loop points.
+    pub fn play_stream(&mut self, source: Box<dyn AudioSource + Send>, options: PlayOptions) -> VoiceId {
+        let chunk: Vec<f32> = vec![0.0; 1024*source.channels().max(1) as usize];
+        let base: usize = source.position();
+        self.start_voice(VoiceSource::Stream(StreamBuffer { source, frames: Vec::new(), start: 0, passes: vec![(0, base)], chunk, ended: false }), options)
+    }
+
+    fn start_voice(&mut self, source: VoiceSource, options: PlayOptions) -> VoiceId {
+        let id: VoiceId = VoiceId(self.next_id);
+        self.next_id += 1;
+        let fading: bool = options.fade_in > 0.0;
+        let mut voice: Voice = Voice {
+            id,
+            source,
+            options,
+            position: 0.0,
+            paused: false,
+            fade: if fading { 0.0 } else { 1.0 },
+            fade_target: 1.0,
+            fade_step: if fading { 1.0 / (options.fade_in*self.sample_rate as f64) as f32 } else { 0.0 },
+            stop_after_fade: false,
+            gains: (0.0, 0.0),
+            finished: false,
+        };
+        voice.gains = voice.target_gains(self.bus_gains[options.bus.index()]*self.master_gain);
+        self.voices.push(voice);
+        id
+    }
+
+    fn voice_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        self.voices.iter_mut().find(|v| v.id == id)
+    }
+
+    pub fn set_volume(&mut self, id: VoiceId, volume: f32) {
+        if let Some(v) = self.voice_mut(id) {
+            v.options.volume = volume;
+        }
+    }
+
+    pub fn set_pan(&mut self, id: VoiceId, pan: f32) {
+        if let Some(v) = self.voice_mut(id) {
+            v.options.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_pitch(&mut self, id: VoiceId, pitch: f64) {
+        if let Some(v) = self.voice_mut(id) {
+            v.options.pitch = pitch.max(0.0);
+        }
+    }
+
+    pub fn set_looping(&mut self, id: VoiceId, looping: bool) {
+        if let Some(v) = self.voice_mut(id) {
+            v.options.looping = looping;
+        }
+    }
+
+    ///Fades the voice to `volume` times its normal gain over `seconds`.
+    pub fn fade_to(&mut self, id: VoiceId, volume: f32, seconds: f64) {
+        let sample_rate: u32 = self.sample_rate;
+        if let Some(v) = self.voice_mut(id) {
+            let frames: f32 = (seconds*sample_rate as f64).max(1.0) as f32;
+            v.fade_target = volume.max(0.0);
+            v.fade_step = (v.fade_target - v.fade) / frames;
+            v.stop_after_fade = false;
+        }
+    }
+
+    ///Fades the voice out over `seconds`, then stops it.
+    pub fn fade_out(&mut self, id: VoiceId, seconds: f64) {
+        self.fade_to(id, 0.0, seconds);
+        if let Some(v) = self.voice_mut(id) {
+            v.stop_after_fade = true;
+        }
+    }
+
+    pub fn pause(&mut self, id: VoiceId) {
+        if let Some(v) = self.voice_mut(id) {
+            v.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self, id: VoiceId) {
+        if let Some(v) = self.voice_mut(id) {
+            v.paused = false;
+        }
+    }
+
+    pub fn stop(&mut self, id: VoiceId) {
+        self.voices.retain(|v| v.id != id);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    ///Stops every voice on `bus`.
+    pub fn stop_bus(&mut self, bus: Bus) {
+        self.voices.retain(|v| v.options.bus != bus);
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voices.iter().any(|v| v.id == id)
+    }
+
+    ///Playback position of the voice in seconds of its sound.
+    pub fn position(&self, id: VoiceId) -> Option<f64> {
+        self.voices.iter().find(|v| v.id == id).map(|v| {
+            let frame: f64 = match &v.source {
+                VoiceSource::Sound(_) => v.position,
+                VoiceSource::Stream(stream) => stream.source_frame(v.position),
+            };
+            frame / v.source_rate().max(1) as f64
+        })
+    }
+
+    ///Jumps to `seconds` into the voice's sound.
+    pub fn seek(&mut self, id: VoiceId, seconds: f64) {
+        if let Some(v) = self.voice_mut(id) {
+            let frame: f64 = (seconds*v.source_rate() as f64).max(0.0);
+            match &mut v.source {
+                VoiceSource::Sound(_) => v.position = frame,
+                VoiceSource::Stream(stream) => {
+                    if let Err(e) = stream.source.seek(frame as usize) {
+                        println!("[Mixer/WARN] Cannot seek stream: {}", e);
+                        return;
+                    }
+                    stream.frames.clear();
+                    stream.start = 0;
+                    stream.passes = vec![(0, frame as usize)];
+                    stream.ended = false;
+                    v.position = frame.fract();
+                }
+            }
+        }
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn set_bus_gain(&mut self, bus: Bus, gain: f32) {
+        self.bus_gains[bus.index()] = gain;
+    }
+
+    pub fn bus_gain(&self, bus: Bus) -> f32 {
+        self.bus_gains[bus.index()]
+    }
+
+    ///Overwrites `out` with the next `out.len() / 2` interleaved stereo frames. Finished voices are removed.
+    pub fn render(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        for voice in self.voices.iter_mut() {
+            //Fading out from silence has nothing left to ramp, so it stops at once
+            if voice.stop_after_fade && voice.fade <= 0.0 {
+                voice.finished = true;
+                continue;
+            }
+            if voice.paused {
+                continue;
+            }
+            let gain: f32 = self.bus_gains[voice.options.bus.index()]*self.master_gain;
+            voice.render(out, self.sample_rate, gain, self.interpolation, &mut self.weights);
+        }
+        self.voices.retain(|v| !v.finished);
+        self.effects.process(out, 2);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::audio::cstream::SoundSource;
+
+    //Mixer and sounds share a 10 Hz rate so one source frame is one output frame
+    fn mixer() -> Mixer {
+        let mut mixer: Mixer = Mixer::new(10);
+        mixer.interpolation = Interpolation::Linear;
+        mixer
+    }
+
+    fn ones(frames: usize) -> Arc<Sound> {
+        Arc::new(Sound::new(10, 1, vec![1.0; frames]))
+    }
+
+    fn render(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut out: Vec<f32> = vec![0.0; frames*2];
+        mixer.render(&mut out);
+        out
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn centered_voice_plays_at_unit_gain_and_finishes() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play(ones(4), Bus::Sfx);
+        let out: Vec<f32> = render(&mut mixer, 6);
+        for (i, s) in out.iter().enumerate() {
+            let expected: f32 = if i < 8 { 1.0 } else { 0.0 };
+            assert!(close(*s, expected), "sample {} is {}", i, s);
+        }
+        assert!(!mixer.is_playing(id));
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn empty_sound_never_starts() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play(Arc::new(Sound::silence(10, 1, 0)), Bus::Sfx);
+        assert!(!mixer.is_playing(id));
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn pan_is_constant_power() {
+        let mut mixer: Mixer = mixer();
+        mixer.play_with(ones(4), PlayOptions::new(Bus::Sfx).pan(-1.0));
+        let out: Vec<f32> = render(&mut mixer, 1);
+        assert!(close(out[0], std::f32::consts::SQRT_2));
+        assert!(close(out[1], 0.0));
+    }
+
+    #[test]
+    fn bus_and_master_gains_multiply() {
+        let mut mixer: Mixer = mixer();
+        mixer.set_bus_gain(Bus::Music, 0.5);
+        mixer.master_gain = 0.5;
+        mixer.play_with(ones(4), PlayOptions::new(Bus::Music).volume(0.5));
+        mixer.play(ones(4), Bus::Sfx);
+        assert_eq!(mixer.bus_gain(Bus::Music), 0.5);
+        let out: Vec<f32> = render(&mut mixer, 1);
+        assert!(close(out[0], 0.125 + 0.5));
+    }
+
+    #[test]
+    fn zero_pitch_finishes_the_voice() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play_with(ones(4), PlayOptions::new(Bus::Sfx).pitch(0.0));
+        let out: Vec<f32> = render(&mut mixer, 2);
+        assert!(out.iter().all(|s| *s == 0.0));
+        assert!(!mixer.is_playing(id));
+    }
+
+    #[test]
+    fn fade_out_ramps_then_stops() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play(ones(10), Bus::Sfx);
+        mixer.fade_out(id, 0.2);
+        let out: Vec<f32> = render(&mut mixer, 4);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert!(close(left[0], 1.0) && close(left[1], 0.5) && close(left[2], 0.0) && close(left[3], 0.0));
+        assert!(!mixer.is_playing(id));
+    }
+
+    #[test]
+    fn fade_out_from_silence_stops_at_once() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play_with(ones(10), PlayOptions::new(Bus::Sfx).fade_in(1.0));
+        mixer.fade_out(id, 1.0);
+        let out: Vec<f32> = render(&mut mixer, 2);
+        assert!(out.iter().all(|s| *s == 0.0));
+        assert!(!mixer.is_playing(id));
+    }
+
+    #[test]
+    fn pause_holds_position() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play(ones(10), Bus::Sfx);
+        render(&mut mixer, 3);
+        mixer.pause(id);
+        let out: Vec<f32> = render(&mut mixer, 3);
+        assert!(out.iter().all(|s| *s == 0.0));
+        assert!(close(mixer.position(id).unwrap() as f32, 0.3));
+        mixer.resume(id);
+        render(&mut mixer, 1);
+        assert!(close(mixer.position(id).unwrap() as f32, 0.4));
+    }
+
+    #[test]
+    fn seek_and_looping_sound_wrap() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play_with(ones(10), PlayOptions::new(Bus::Sfx).looping(true));
+        mixer.seek(id, 0.5);
+        render(&mut mixer, 7);
+        assert!(close(mixer.position(id).unwrap() as f32, 0.2));
+        assert!(mixer.is_playing(id));
+    }
+
+    #[test]
+    fn looping_stream_position_restarts_from_the_top() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play_stream(Box::new(SoundSource::new(ones(10))), PlayOptions::new(Bus::Music).looping(true));
+        let out: Vec<f32> = render(&mut mixer, 12);
+        assert!(out.iter().all(|s| close(*s, 1.0)));
+        assert!(close(mixer.position(id).unwrap() as f32, 0.2));
+    }
+
+    #[test]
+    fn stream_ends_with_its_source() {
+        let mut mixer: Mixer = mixer();
+        let id: VoiceId = mixer.play_stream(Box::new(SoundSource::new(ones(3))), PlayOptions::new(Bus::Music));
+        let out: Vec<f32> = render(&mut mixer, 5);
+        assert!(close(out[4], 1.0) && close(out[6], 0.0));
+        assert!(!mixer.is_playing(id));
+    }
+
+    #[test]
+    fn stop_bus_leaves_other_buses_and_stale_ids_are_ignored() {
+        let mut mixer: Mixer = mixer();
+        let music: VoiceId = mixer.play(ones(10), Bus::Music);
+        let sfx: VoiceId = mixer.play(ones(10), Bus::Sfx);
+        mixer.stop_bus(Bus::Music);
+        assert!(!mixer.is_playing(music) && mixer.is_playing(sfx));
+        mixer.set_volume(music, 0.0);
+        assert_eq!(mixer.position(music), None);
+        mixer.stop(sfx);
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+}
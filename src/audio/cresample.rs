@@ -0,0 +1,287 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use crate::audio::csound::Sound;
+
+///Zero crossings of the sinc kernel on each side of the center.
+const SINC_ZEROS: usize = 8;
+///Kernel table entries per zero crossing.
+const SINC_RESOLUTION: usize = 512;
+///Lowest cutoff the sinc filter is widened for, which bounds its cost when pitching far down.
+const MIN_CUTOFF: f64 = 1.0 / 8.0;
+
+///How samples between the stored ones are reconstructed when changing rate or pitch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    ///Straight lines between neighbouring samples. Cheap, but dulls highs and lets some aliasing through.
+    Linear,
+    ///Catmull-Rom through four samples.
+    Cubic,
+    ///Blackman-windowed sinc over 16 samples, low-passed when lowering the rate so nothing aliases.
+    Sinc,
+}
+
+//One side of a Blackman-windowed sinc, sampled from 0 to SINC_ZEROS
+fn sinc_table() -> &'static [f32] {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let n: usize = SINC_ZEROS*SINC_RESOLUTION;
+        (0..=n + 1)
+            .map(|i| {
+                let x: f64 = i as f64 / SINC_RESOLUTION as f64;
+                if x >= SINC_ZEROS as f64 {
+                    return 0.0;
+                }
+                let sinc: f64 = if i == 0 { 1.0 } else { (PI*x).sin() / (PI*x) };
+                let t: f64 = 0.5 + 0.5*x / SINC_ZEROS as f64;
+                let window: f64 = 0.42 - 0.5*(2.0*PI*t).cos() + 0.08*(4.0*PI*t).cos();
+                (sinc*window) as f32
+            })
+            .collect()
+    })
+}
+
+fn sinc_kernel(x: f64) -> f32 {
+    let table: &[f32] = sinc_table();
+    let p: f64 = x.abs()*SINC_RESOLUTION as f64;
+    let i: usize = p as usize;
+    if i + 1 >= table.len() {
+        return 0.0;
+    }
+    let t: f32 = (p - i as f64) as f32;
+    table[i] + (table[i + 1] - table[i])*t
+}
+
+///Fills `weights` with the source frames and weights that reconstruct the signal at `position`. `step` is how
+/// many source frames pass per output frame; above 1 the sinc filter is widened to remove what would alias.
+pub fn interpolation_weights(position: f64, step: f64, quality: Interpolation, weights: &mut Vec<(i64, f32)>) {
+    weights.clear();
+    let i: i64 = position.floor() as i64;
+    let t: f32 = (position - i as f64) as f32;
+    match quality {
+        Interpolation::Linear => {
+            weights.push((i, 1.0 - t));
+            weights.push((i + 1, t));
+        }
+        Interpolation::Cubic => {
+            let (t2, t3) = (t*t, t*t*t);
+            weights.push((i - 1, -0.5*t3 + t2 - 0.5*t));
+            weights.push((i, 1.5*t3 - 2.5*t2 + 1.0));
+            weights.push((i + 1, -1.5*t3 + 2.0*t2 + 0.5*t));
+            weights.push((i + 2, 0.5*t3 - 0.5*t2));
+        }
+        Interpolation::Sinc => {
+            let cutoff: f64 = (1.0 / step.max(1.0)).max(MIN_CUTOFF);
+            let half: i64 = (SINC_ZEROS as f64 / cutoff).ceil() as i64;
+            let mut sum: f32 = 0.0;
+            for k in i - half + 1..=i + half {
+                let w: f32 = sinc_kernel((position - k as f64)*cutoff);
+                if w != 0.0 {
+                    weights.push((k, w));
+                    sum += w;
+                }
+            }
+            //Normalizing keeps DC gain at exactly 1 despite the table's finite resolution
+            if sum != 0.0 {
+                for w in weights.iter_mut() {
+                    w.1 /= sum;
+                }
+            }
+        }
+    }
+}
+
+///Converts a stream of interleaved samples from one rate to another, keeping the history it needs between calls.
+pub struct Resampler {
+    channels: usize,
+    ///Source frames per output frame.
+    step: f64,
+    pub quality: Interpolation,
+    buffer: Vec<f32>,        //Pending source frames, starting at frame `start`
+    start: i64,
+    position: f64,           //Next output position, in source frames
+    weights: Vec<(i64, f32)>,
+}
+
+impl Resampler {
+
+    pub fn new(channels: u16, from_rate: u32, to_rate: u32, quality: Interpolation) -> Resampler {
+        Resampler {
+            channels: channels.max(1) as usize,
+            //A rate of 0 would never advance through the input, so it counts as 1
+            step: from_rate.max(1) as f64 / to_rate.max(1) as f64,
+            quality,
+            buffer: Vec::new(),
+            start: 0,
+            position: 0.0,
+            weights: Vec::new(),
+        }
+    }
+
+    ///Changes the conversion ratio, e.g. to bend pitch. Takes effect from the next output frame.
+    pub fn set_rates(&mut self, from_rate: u32, to_rate: u32) {
+        self.step = from_rate.max(1) as f64 / to_rate.max(1) as f64;
+    }
+
+    //Source frames the kernel reaches past the one it is centered on
+    fn reach(&self) -> i64 {
+        match self.quality {
+            Interpolation::Linear => 1,
+            Interpolation::Cubic => 2,
+            Interpolation::Sinc => (SINC_ZEROS as f64 / (1.0 / self.step.max(1.0)).max(MIN_CUTOFF)).ceil() as i64,
+        }
+    }
+
+    ///Appends `input` and writes every output frame it makes possible to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.buffer.extend_from_slice(&input[..input.len() / self.channels*self.channels]);
+        let end: i64 = self.start + (self.buffer.len() / self.channels) as i64;
+        while self.position.floor() as i64 + self.reach() < end {
+            self.emit(output);
+        }
+        self.trim();
+    }
+
+    ///Writes the output for the remaining input, treating everything after it as silence.
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        let end: i64 = self.start + (self.buffer.len() / self.channels) as i64;
+        while (self.position.floor() as i64) < end {
+            self.emit(output);
+        }
+        self.trim();
+    }
+
+    fn emit(&mut self, output: &mut Vec<f32>) {
+        interpolation_weights(self.position, self.step, self.quality, &mut self.weights);
+        let frames: i64 = (self.buffer.len() / self.channels) as i64;
+        for c in 0..self.channels {
+            let mut sum: f32 = 0.0;
+            for (k, w) in self.weights.iter() {
+                let i: i64 = k - self.start;
+                if i >= 0 && i < frames {
+                    sum += self.buffer[i as usize*self.channels + c]*w;
+                }
+            }
+            output.push(sum);
+        }
+        self.position += self.step;
+    }
+
+    //Drops source frames the kernel can no longer reach
+    fn trim(&mut self) {
+        let keep_from: i64 = self.position.floor() as i64 - self.reach();
+        let drop: usize = (keep_from - self.start).clamp(0, (self.buffer.len() / self.channels) as i64) as usize;
+        self.buffer.drain(..drop*self.channels);
+        self.start += drop as i64;
+    }
+
+}
+
+impl Sound {
+
+    ///A copy of the sound converted to `sample_rate`.
+    pub fn resampled(&self, sample_rate: u32, quality: Interpolation) -> Sound {
+        if sample_rate == self.sample_rate {
+            return self.clone();
+        }
+        let mut resampler: Resampler = Resampler::new(self.channels, self.sample_rate, sample_rate, quality);
+        let mut samples: Vec<f32> = Vec::with_capacity((self.samples.len() as f64*sample_rate as f64 / self.sample_rate.max(1) as f64) as usize + 16);
+        resampler.process(&self.samples, &mut samples);
+        resampler.flush(&mut samples);
+        Sound::new(sample_rate, self.channels, samples)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn tone(sample_rate: u32, frequency: f32, frames: usize) -> Sound {
+        Sound::new(sample_rate, 1, (0..frames).map(|i| (i as f32*frequency*std::f32::consts::TAU / sample_rate as f32).sin()).collect())
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s*s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        let mut weights: Vec<(i64, f32)> = Vec::new();
+        for quality in [Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+            for position in [0.0, 3.25, 7.5, -2.75] {
+                for step in [0.5, 1.0, 3.0] {
+                    interpolation_weights(position, step, quality, &mut weights);
+                    let sum: f32 = weights.iter().map(|w| w.1).sum();
+                    assert!((sum - 1.0).abs() < 1e-4, "{:?} at {} step {}: {}", quality, position, step, sum);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn whole_positions_hit_the_stored_sample() {
+        let mut weights: Vec<(i64, f32)> = Vec::new();
+        for quality in [Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+            interpolation_weights(5.0, 1.0, quality, &mut weights);
+            for (k, w) in weights.iter() {
+                let expected: f32 = if *k == 5 { 1.0 } else { 0.0 };
+                assert!((w - expected).abs() < 1e-3, "{:?} weight for {} is {}", quality, k, w);
+            }
+        }
+    }
+
+    #[test]
+    fn upsampling_doubles_the_length_and_keeps_dc() {
+        let sound: Sound = Sound::new(8000, 2, vec![0.5; 200]);
+        let up: Sound = sound.resampled(16000, Interpolation::Cubic);
+        assert_eq!((up.sample_rate, up.channels, up.frames()), (16000, 2, 200));
+        //The ends ramp against the silence around the sound
+        assert!(up.samples[20..380].iter().all(|s| (s - 0.5).abs() < 1e-5));
+    }
+
+    #[test]
+    fn same_rate_is_a_copy() {
+        let sound: Sound = tone(8000, 440.0, 50);
+        assert_eq!(sound.resampled(8000, Interpolation::Sinc), sound);
+    }
+
+    #[test]
+    fn chunked_input_matches_one_block() {
+        let sound: Sound = tone(44100, 1000.0, 1000);
+        for quality in [Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+            let whole: Sound = sound.resampled(48000, quality);
+            let mut resampler: Resampler = Resampler::new(1, 44100, 48000, quality);
+            let mut chunked: Vec<f32> = Vec::new();
+            for block in sound.samples.chunks(37) {
+                resampler.process(block, &mut chunked);
+            }
+            resampler.flush(&mut chunked);
+            assert_eq!(chunked, whole.samples, "{:?}", quality);
+        }
+    }
+
+    #[test]
+    fn sinc_removes_what_would_alias() {
+        //3 kHz is above the 2 kHz limit of a 4 kHz rate
+        let sound: Sound = tone(8000, 3000.0, 4000);
+        let sinc: Sound = sound.resampled(4000, Interpolation::Sinc);
+        let linear: Sound = sound.resampled(4000, Interpolation::Linear);
+        assert!(rms(&sinc.samples[100..1900]) < 0.05, "sinc leaves {}", rms(&sinc.samples[100..1900]));
+        assert!(rms(&linear.samples[100..1900]) > 0.2);
+        //A tone well below the limit passes
+        let low: Sound = tone(8000, 500.0, 4000).resampled(4000, Interpolation::Sinc);
+        assert!((rms(&low.samples[100..1900]) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02);
+    }
+
+    #[test]
+    fn zero_rates_still_advance() {
+        let mut resampler: Resampler = Resampler::new(1, 0, 0, Interpolation::Linear);
+        let mut out: Vec<f32> = Vec::new();
+        resampler.process(&[1.0, 2.0, 3.0], &mut out);
+        resampler.flush(&mut out);
+        assert_eq!(out, vec![1.0, 2.0, 3.0]);
+    }
+
+}
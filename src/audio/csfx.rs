@@ -0,0 +1,653 @@
+use crate::audio::cresample::Interpolation;
+use crate::audio::csound::Sound;
+use crate::audio::csynth::Rng;
+
+///The rate sfxr's parameters were tuned for. Sounds are synthesized at this rate and resampled afterwards.
+const SFX_RATE: u32 = 44100;
+///Oversampling factor, which keeps the naive waveforms from aliasing badly.
+const SUPERSAMPLE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SfxWave {
+    Square,
+    Saw,
+    Sine,
+    Noise,
+    Triangle,
+}
+
+///Parameters of a retro sound effect in the style of sfxr. Nearly every field is a unitless knob in 0..1 (or
+/// -1..1 for ramps and offsets), so descriptions can be tweaked by hand, randomized, or shared as a handful of
+/// numbers. `render` turns one into a `Sound` that can be played by the mixer or saved as a WAV.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfxDesc {
+    pub wave: SfxWave,
+    ///Starting pitch.
+    pub base_freq: f32,
+    ///Pitch below which the sound stops, so falling sounds do not drone on.
+    pub freq_limit: f32,
+    ///Pitch slide; positive slides up.
+    pub freq_ramp: f32,
+    ///Change of the pitch slide over time.
+    pub freq_dramp: f32,
+    ///Square wave duty cycle, from 50% at 0 towards a thin pulse at 1.
+    pub duty: f32,
+    pub duty_ramp: f32,
+    pub vib_strength: f32,
+    pub vib_speed: f32,
+    pub env_attack: f32,
+    pub env_sustain: f32,
+    ///Extra volume at the start of the sustain, fading over it.
+    pub env_punch: f32,
+    pub env_decay: f32,
+    ///Low-pass cutoff; 1 disables the filter.
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub lpf_resonance: f32,
+    ///High-pass cutoff; 0 disables the filter.
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+    ///Flanger delay.
+    pub pha_offset: f32,
+    pub pha_ramp: f32,
+    ///How often the pitch and duty restart, for repeating sounds like alarms. 0 never repeats.
+    pub repeat_speed: f32,
+    ///How soon the pitch jumps by `arp_mod`. 0 never jumps.
+    pub arp_speed: f32,
+    ///Size of the pitch jump; positive jumps up.
+    pub arp_mod: f32,
+    pub volume: f32,
+    ///Seed for the noise waveform, so rendering is repeatable.
+    pub seed: u64,
+}
+
+impl Default for SfxDesc {
+    fn default() -> SfxDesc {
+        SfxDesc {
+            wave: SfxWave::Square,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_dramp: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            vib_strength: 0.0,
+            vib_speed: 0.0,
+            env_attack: 0.0,
+            env_sustain: 0.3,
+            env_punch: 0.0,
+            env_decay: 0.4,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            pha_offset: 0.0,
+            pha_ramp: 0.0,
+            repeat_speed: 0.0,
+            arp_speed: 0.0,
+            arp_mod: 0.0,
+            volume: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+//sfxr's rnd(n): an integer in 0..=n
+fn rnd(rng: &mut Rng, n: u32) -> u32 {
+    (rng.next_u64() % (n as u64 + 1)) as u32
+}
+
+impl SfxDesc {
+
+    pub fn new() -> SfxDesc {
+        SfxDesc::default()
+    }
+
+    pub fn wave(mut self, wave: SfxWave) -> Self {
+        self.wave = wave;
+        self
+    }
+
+    pub fn base_freq(mut self, base_freq: f32) -> Self {
+        self.base_freq = base_freq;
+        self
+    }
+
+    pub fn freq_ramp(mut self, freq_ramp: f32) -> Self {
+        self.freq_ramp = freq_ramp;
+        self
+    }
+
+    pub fn duty(mut self, duty: f32) -> Self {
+        self.duty = duty;
+        self
+    }
+
+    pub fn envelope(mut self, attack: f32, sustain: f32, punch: f32, decay: f32) -> Self {
+        self.env_attack = attack;
+        self.env_sustain = sustain;
+        self.env_punch = punch;
+        self.env_decay = decay;
+        self
+    }
+
+    pub fn vibrato(mut self, strength: f32, speed: f32) -> Self {
+        self.vib_strength = strength;
+        self.vib_speed = speed;
+        self
+    }
+
+    pub fn arpeggio(mut self, speed: f32, modulation: f32) -> Self {
+        self.arp_speed = speed;
+        self.arp_mod = modulation;
+        self
+    }
+
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    ///A coin or item pickup.
+    pub fn pickup(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        d.base_freq = 0.4 + rng.range(0.5);
+        d.env_attack = 0.0;
+        d.env_sustain = rng.range(0.1);
+        d.env_decay = 0.1 + rng.range(0.4);
+        d.env_punch = 0.3 + rng.range(0.3);
+        if rnd(&mut rng, 1) == 1 {
+            d.arp_speed = 0.5 + rng.range(0.2);
+            d.arp_mod = 0.2 + rng.range(0.4);
+        }
+        d
+    }
+
+    ///A laser or shot.
+    pub fn laser(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        d.wave = match rnd(&mut rng, 2) {
+            0 => SfxWave::Square,
+            1 => SfxWave::Saw,
+            _ => if rnd(&mut rng, 1) == 1 { SfxWave::Saw } else { SfxWave::Sine },
+        };
+        d.base_freq = 0.5 + rng.range(0.5);
+        d.freq_limit = (d.base_freq - 0.2 - rng.range(0.6)).max(0.2);
+        d.freq_ramp = -0.15 - rng.range(0.2);
+        if rnd(&mut rng, 2) == 0 {
+            d.base_freq = 0.3 + rng.range(0.6);
+            d.freq_limit = rng.range(0.1);
+            d.freq_ramp = -0.35 - rng.range(0.3);
+        }
+        if rnd(&mut rng, 1) == 1 {
+            d.duty = rng.range(0.5);
+            d.duty_ramp = rng.range(0.2);
+        }
+        else {
+            d.duty = 0.4 + rng.range(0.5);
+            d.duty_ramp = -rng.range(0.7);
+        }
+        d.env_attack = 0.0;
+        d.env_sustain = 0.1 + rng.range(0.2);
+        d.env_decay = rng.range(0.4);
+        if rnd(&mut rng, 1) == 1 {
+            d.env_punch = rng.range(0.3);
+        }
+        if rnd(&mut rng, 2) == 0 {
+            d.pha_offset = rng.range(0.2);
+            d.pha_ramp = -rng.range(0.2);
+        }
+        if rnd(&mut rng, 1) == 1 {
+            d.hpf_freq = rng.range(0.3);
+        }
+        d
+    }
+
+    pub fn explosion(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, wave: SfxWave::Noise, ..SfxDesc::default() };
+        if rnd(&mut rng, 1) == 1 {
+            d.base_freq = 0.1 + rng.range(0.4);
+            d.freq_ramp = -0.1 + rng.range(0.4);
+        }
+        else {
+            d.base_freq = 0.2 + rng.range(0.7);
+            d.freq_ramp = -0.2 - rng.range(0.2);
+        }
+        d.base_freq *= d.base_freq;
+        if rnd(&mut rng, 4) == 0 {
+            d.freq_ramp = 0.0;
+        }
+        if rnd(&mut rng, 2) == 0 {
+            d.repeat_speed = 0.3 + rng.range(0.5);
+        }
+        d.env_attack = 0.0;
+        d.env_sustain = 0.1 + rng.range(0.3);
+        d.env_decay = rng.range(0.5);
+        if rnd(&mut rng, 1) == 0 {
+            d.pha_offset = -0.3 + rng.range(0.9);
+            d.pha_ramp = -rng.range(0.3);
+        }
+        d.env_punch = 0.2 + rng.range(0.6);
+        if rnd(&mut rng, 1) == 1 {
+            d.vib_strength = rng.range(0.7);
+            d.vib_speed = rng.range(0.6);
+        }
+        if rnd(&mut rng, 2) == 0 {
+            d.arp_speed = 0.6 + rng.range(0.3);
+            d.arp_mod = 0.8 - rng.range(1.6);
+        }
+        d
+    }
+
+    pub fn powerup(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        if rnd(&mut rng, 1) == 1 {
+            d.wave = SfxWave::Saw;
+        }
+        else {
+            d.duty = rng.range(0.6);
+        }
+        d.base_freq = 0.2 + rng.range(0.3);
+        if rnd(&mut rng, 1) == 1 {
+            d.freq_ramp = 0.1 + rng.range(0.4);
+            d.repeat_speed = 0.4 + rng.range(0.4);
+        }
+        else {
+            d.freq_ramp = 0.05 + rng.range(0.2);
+            if rnd(&mut rng, 1) == 1 {
+                d.vib_strength = rng.range(0.7);
+                d.vib_speed = rng.range(0.6);
+            }
+        }
+        d.env_attack = 0.0;
+        d.env_sustain = rng.range(0.4);
+        d.env_decay = 0.1 + rng.range(0.4);
+        d
+    }
+
+    ///A hit or hurt.
+    pub fn hit(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        d.wave = match rnd(&mut rng, 2) {
+            0 => SfxWave::Square,
+            1 => SfxWave::Saw,
+            _ => SfxWave::Noise,
+        };
+        if d.wave == SfxWave::Square {
+            d.duty = rng.range(0.6);
+        }
+        d.base_freq = 0.2 + rng.range(0.6);
+        d.freq_ramp = -0.3 - rng.range(0.4);
+        d.env_attack = 0.0;
+        d.env_sustain = rng.range(0.1);
+        d.env_decay = 0.1 + rng.range(0.2);
+        if rnd(&mut rng, 1) == 1 {
+            d.hpf_freq = rng.range(0.3);
+        }
+        d
+    }
+
+    pub fn jump(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        d.duty = rng.range(0.6);
+        d.base_freq = 0.3 + rng.range(0.3);
+        d.freq_ramp = 0.1 + rng.range(0.2);
+        d.env_attack = 0.0;
+        d.env_sustain = 0.1 + rng.range(0.3);
+        d.env_decay = 0.1 + rng.range(0.2);
+        if rnd(&mut rng, 1) == 1 {
+            d.hpf_freq = rng.range(0.3);
+        }
+        if rnd(&mut rng, 1) == 1 {
+            d.lpf_freq = 1.0 - rng.range(0.6);
+        }
+        d
+    }
+
+    ///A short menu blip.
+    pub fn blip(seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = SfxDesc { seed, ..SfxDesc::default() };
+        if rnd(&mut rng, 1) == 1 {
+            d.wave = SfxWave::Saw;
+        }
+        else {
+            d.duty = rng.range(0.6);
+        }
+        d.base_freq = 0.2 + rng.range(0.4);
+        d.env_attack = 0.0;
+        d.env_sustain = 0.1 + rng.range(0.1);
+        d.env_decay = rng.range(0.2);
+        d.hpf_freq = 0.1;
+        d
+    }
+
+    ///A copy with every parameter nudged slightly, for variations on a sound.
+    pub fn mutated(&self, seed: u64) -> SfxDesc {
+        let mut rng: Rng = Rng::new(seed);
+        let mut d: SfxDesc = self.clone();
+        let fields: [&mut f32; 22] = [
+            &mut d.base_freq, &mut d.freq_limit, &mut d.freq_ramp, &mut d.freq_dramp, &mut d.duty, &mut d.duty_ramp,
+            &mut d.vib_strength, &mut d.vib_speed, &mut d.env_attack, &mut d.env_sustain, &mut d.env_punch,
+            &mut d.env_decay, &mut d.lpf_freq, &mut d.lpf_ramp, &mut d.lpf_resonance, &mut d.hpf_freq,
+            &mut d.hpf_ramp, &mut d.pha_offset, &mut d.pha_ramp, &mut d.repeat_speed, &mut d.arp_speed,
+            &mut d.arp_mod,
+        ];
+        for field in fields {
+            if rnd(&mut rng, 1) == 1 {
+                *field += rng.range(0.1) - 0.05;
+            }
+        }
+        d.seed = seed;
+        d
+    }
+
+    ///Synthesizes the effect as a mono sound at `sample_rate`.
+    pub fn render(&self, sample_rate: u32) -> Sound {
+        let samples: Vec<f32> = SfxSynth::new(self).run();
+        let sound: Sound = Sound::new(SFX_RATE, 1, samples);
+        if sample_rate == SFX_RATE {
+            sound
+        }
+        else {
+            sound.resampled(sample_rate, Interpolation::Sinc)
+        }
+    }
+
+}
+
+//Playback state of one rendering, following sfxr's reference synthesizer
+struct SfxSynth<'a> {
+    desc: &'a SfxDesc,
+    rng: Rng,
+    phase: usize,
+    fperiod: f64,
+    fmaxperiod: f64,
+    fslide: f64,
+    fdslide: f64,
+    square_duty: f32,
+    square_slide: f32,
+    arp_mod: f64,
+    arp_time: usize,
+    arp_limit: usize,
+    env_stage: usize,
+    env_time: usize,
+    env_length: [usize; 3],
+    env_vol: f32,
+    fphase: f32,
+    fdphase: f32,
+    ipp: usize,
+    phaser: Vec<f32>,
+    noise: [f32; 32],
+    fltp: f32,
+    fltdp: f32,
+    fltw: f32,
+    fltw_d: f32,
+    fltdmp: f32,
+    fltphp: f32,
+    flthp: f32,
+    flthp_d: f32,
+    vib_phase: f64,
+    vib_speed: f64,
+    vib_amp: f64,
+    rep_time: usize,
+    rep_limit: usize,
+}
+
+impl<'a> SfxSynth<'a> {
+
+    fn new(desc: &'a SfxDesc) -> SfxSynth<'a> {
+        let lpf_freq: f32 = desc.lpf_freq.clamp(0.0, 1.0);
+        let fltw: f32 = lpf_freq.powi(3)*0.1;
+        let mut synth: SfxSynth = SfxSynth {
+            desc,
+            rng: Rng::new(desc.seed),
+            phase: 0,
+            fperiod: 0.0,
+            fmaxperiod: 0.0,
+            fslide: 0.0,
+            fdslide: 0.0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            arp_mod: 0.0,
+            arp_time: 0,
+            arp_limit: 0,
+            env_stage: 0,
+            env_time: 0,
+            env_length: [
+                (desc.env_attack*desc.env_attack*100000.0) as usize,
+                (desc.env_sustain*desc.env_sustain*100000.0) as usize,
+                (desc.env_decay*desc.env_decay*100000.0) as usize,
+            ],
+            env_vol: 0.0,
+            fphase: desc.pha_offset.powi(2)*1020.0*desc.pha_offset.signum(),
+            fdphase: desc.pha_ramp.powi(2)*desc.pha_ramp.signum(),
+            ipp: 0,
+            phaser: vec![0.0; 1024],
+            noise: [0.0; 32],
+            fltp: 0.0,
+            fltdp: 0.0,
+            fltw,
+            fltw_d: 1.0 + desc.lpf_ramp*0.0001,
+            fltdmp: (5.0 / (1.0 + desc.lpf_resonance.powi(2)*20.0)*(0.01 + fltw)).min(0.8),
+            fltphp: 0.0,
+            flthp: desc.hpf_freq.powi(2)*0.1,
+            flthp_d: 1.0 + desc.hpf_ramp*0.0003,
+            vib_phase: 0.0,
+            vib_speed: (desc.vib_speed as f64).powi(2)*0.01,
+            vib_amp: desc.vib_strength as f64*0.5,
+            rep_time: 0,
+            rep_limit: if desc.repeat_speed == 0.0 { 0 } else { ((1.0 - desc.repeat_speed).powi(2)*20000.0 + 32.0) as usize },
+        };
+        synth.restart();
+        synth.refill_noise();
+        synth
+    }
+
+    //Resets pitch, duty and arpeggio; repeats call this again
+    fn restart(&mut self) {
+        let d: &SfxDesc = self.desc;
+        self.fperiod = 100.0 / ((d.base_freq as f64).powi(2) + 0.001);
+        self.fmaxperiod = 100.0 / ((d.freq_limit as f64).powi(2) + 0.001);
+        self.fslide = 1.0 - (d.freq_ramp as f64).powi(3)*0.01;
+        self.fdslide = -(d.freq_dramp as f64).powi(3)*0.000001;
+        self.square_duty = 0.5 - d.duty*0.5;
+        self.square_slide = -d.duty_ramp*0.00005;
+        self.arp_mod = if d.arp_mod >= 0.0 { 1.0 - (d.arp_mod as f64).powi(2)*0.9 } else { 1.0 + (d.arp_mod as f64).powi(2)*10.0 };
+        self.arp_time = 0;
+        self.arp_limit = if d.arp_speed == 1.0 { 0 } else { ((1.0 - d.arp_speed).powi(2)*20000.0 + 32.0) as usize };
+    }
+
+    fn refill_noise(&mut self) {
+        for n in self.noise.iter_mut() {
+            *n = self.rng.next_f32()*2.0 - 1.0;
+        }
+    }
+
+    fn run(&mut self) -> Vec<f32> {
+        let mut out: Vec<f32> = Vec::new();
+        //At volume 1 a square wave reaches full scale; punch and resonance can still clip
+        let gain: f32 = 2.0*self.desc.volume;
+        while let Some(sample) = self.next_sample() {
+            out.push((sample*gain).clamp(-1.0, 1.0));
+        }
+        out
+    }
+
+    //One output sample, or None once the envelope or the frequency limit has ended the sound
+    fn next_sample(&mut self) -> Option<f32> {
+        let d: &SfxDesc = self.desc;
+        self.rep_time += 1;
+        if self.rep_limit != 0 && self.rep_time >= self.rep_limit {
+            self.rep_time = 0;
+            self.restart();
+        }
+        self.arp_time += 1;
+        if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+            self.arp_limit = 0;
+            self.fperiod *= self.arp_mod;
+        }
+        self.fslide += self.fdslide;
+        self.fperiod *= self.fslide;
+        if self.fperiod > self.fmaxperiod {
+            self.fperiod = self.fmaxperiod;
+            if d.freq_limit > 0.0 {
+                return None;
+            }
+        }
+        let mut rfperiod: f64 = self.fperiod;
+        if self.vib_amp > 0.0 {
+            self.vib_phase += self.vib_speed;
+            rfperiod = self.fperiod*(1.0 + self.vib_phase.sin()*self.vib_amp);
+        }
+        let period: usize = (rfperiod as usize).max(8);
+        self.square_duty = (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+        self.env_time += 1;
+        if self.env_time > self.env_length[self.env_stage] {
+            self.env_time = 0;
+            self.env_stage += 1;
+            if self.env_stage == 3 {
+                return None;
+            }
+        }
+        let t: f32 = self.env_time as f32 / self.env_length[self.env_stage].max(1) as f32;
+        self.env_vol = match self.env_stage {
+            0 => t,
+            1 => 1.0 + (1.0 - t)*2.0*d.env_punch,
+            _ => 1.0 - t,
+        };
+
+        self.fphase += self.fdphase;
+        let iphase: usize = (self.fphase.abs() as usize).min(1023);
+        if self.flthp_d != 0.0 {
+            self.flthp = (self.flthp*self.flthp_d).clamp(0.00001, 0.1);
+        }
+
+        let mut total: f32 = 0.0;
+        for _ in 0..SUPERSAMPLE {
+            self.phase += 1;
+            if self.phase >= period {
+                self.phase %= period;
+                if d.wave == SfxWave::Noise {
+                    self.refill_noise();
+                }
+            }
+            let fp: f32 = self.phase as f32 / period as f32;
+            let mut sample: f32 = match d.wave {
+                SfxWave::Square => if fp < self.square_duty { 0.5 } else { -0.5 },
+                SfxWave::Saw => 1.0 - fp*2.0,
+                SfxWave::Sine => (fp*std::f32::consts::TAU).sin(),
+                SfxWave::Noise => self.noise[self.phase*32 / period],
+                SfxWave::Triangle => 1.0 - 4.0*(fp - 0.5).abs(),
+            };
+            //Resonant low-pass
+            let pp: f32 = self.fltp;
+            self.fltw = (self.fltw*self.fltw_d).clamp(0.0, 0.1);
+            if d.lpf_freq < 1.0 {
+                self.fltdp += (sample - self.fltp)*self.fltw;
+                self.fltdp -= self.fltdp*self.fltdmp;
+            }
+            else {
+                self.fltp = sample;
+                self.fltdp = 0.0;
+            }
+            self.fltp += self.fltdp;
+            //High-pass
+            self.fltphp += self.fltp - pp;
+            self.fltphp -= self.fltphp*self.flthp;
+            sample = self.fltphp;
+            //Phaser
+            self.phaser[self.ipp & 1023] = sample;
+            sample += self.phaser[(self.ipp + 1024 - iphase) & 1023];
+            self.ipp = (self.ipp + 1) & 1023;
+            total += sample*self.env_vol;
+        }
+        Some(total / SUPERSAMPLE as f32)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn presets(seed: u64) -> [SfxDesc; 7] {
+        [
+            SfxDesc::pickup(seed),
+            SfxDesc::laser(seed),
+            SfxDesc::explosion(seed),
+            SfxDesc::powerup(seed),
+            SfxDesc::hit(seed),
+            SfxDesc::jump(seed),
+            SfxDesc::blip(seed),
+        ]
+    }
+
+    #[test]
+    fn presets_render_repeatably() {
+        for seed in 0..8 {
+            for desc in presets(seed) {
+                let a: Sound = desc.render(SFX_RATE);
+                assert!(a.frames() > 0, "{:?}", desc);
+                assert!(a.samples.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+                assert_eq!(a, desc.render(SFX_RATE));
+            }
+        }
+        assert_ne!(SfxDesc::laser(1), SfxDesc::laser(2));
+    }
+
+    #[test]
+    fn length_follows_the_envelope() {
+        //No attack, then 0.3^2 and 0.4^2 of 100000 samples of sustain and decay
+        let sound: Sound = SfxDesc::new().render(SFX_RATE);
+        assert!((sound.frames() as i64 - 25000).abs() <= 3, "{} frames", sound.frames());
+    }
+
+    #[test]
+    fn frequency_limit_ends_a_falling_sound() {
+        let falling: SfxDesc = SfxDesc::new().freq_ramp(-0.5);
+        let limited: SfxDesc = SfxDesc { freq_limit: 0.2, ..falling.clone() };
+        assert!(limited.render(SFX_RATE).frames() < falling.render(SFX_RATE).frames() / 2);
+    }
+
+    #[test]
+    fn render_resamples_to_the_requested_rate() {
+        let desc: SfxDesc = SfxDesc::blip(3);
+        let full: Sound = desc.render(SFX_RATE);
+        let half: Sound = desc.render(SFX_RATE / 2);
+        assert_eq!(half.sample_rate, SFX_RATE / 2);
+        assert!((half.frames() as i64 - full.frames() as i64 / 2).abs() <= 1);
+    }
+
+    #[test]
+    fn volume_scales_the_output() {
+        let loud: Sound = SfxDesc::new().wave(SfxWave::Sine).volume(0.2).render(SFX_RATE);
+        let quiet: Sound = SfxDesc::new().wave(SfxWave::Sine).volume(0.1).render(SFX_RATE);
+        assert!(loud.samples.iter().zip(quiet.samples.iter()).all(|(a, b)| (a - 2.0*b).abs() < 1e-5));
+    }
+
+    #[test]
+    fn mutation_is_seeded_and_small() {
+        let desc: SfxDesc = SfxDesc::powerup(5);
+        let a: SfxDesc = desc.mutated(9);
+        assert_eq!(a, desc.mutated(9));
+        assert_ne!(a, desc);
+        assert_eq!(a.seed, 9);
+        assert!((a.base_freq - desc.base_freq).abs() <= 0.05 && (a.env_decay - desc.env_decay).abs() <= 0.05);
+    }
+
+}
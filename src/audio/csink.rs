@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::audio::cmixer::Mixer;
+use crate::audio::csound::AudioError;
+use crate::audio::cwav::{WavEncoding, WavWriter};
+
+///Somewhere to send interleaved f32 samples: a sound card, a file, or nowhere.
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    ///Accepts interleaved samples, `channels` per frame.
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError>;
+
+    fn flush(&mut self) -> Result<(), AudioError> {
+        Ok(())
+    }
+}
+
+///Discards everything written to it, counting the frames. Lets audio code run on machines without a sound card.
+pub struct NullSink {
+    sample_rate: u32,
+    channels: u16,
+    frames: usize,
+}
+
+impl NullSink {
+
+    pub fn new(sample_rate: u32, channels: u16) -> NullSink {
+        NullSink { sample_rate, channels: channels.max(1), frames: 0 }
+    }
+
+    pub fn frames_written(&self) -> usize {
+        self.frames
+    }
+
+}
+
+impl AudioSink for NullSink {
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        self.frames += samples.len() / self.channels as usize;
+        Ok(())
+    }
+
+}
+
+///Records everything written to it into a WAV file. The file is complete once `finish` is called or the sink is
+/// dropped.
+pub struct WavFileSink {
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavFileSink {
+
+    pub fn create(path: &str, sample_rate: u32, channels: u16, encoding: WavEncoding) -> Result<WavFileSink, AudioError> {
+        Ok(WavFileSink { writer: WavWriter::create(path, sample_rate, channels, encoding)? })
+    }
+
+    pub fn frames_written(&self) -> usize {
+        self.writer.frames()
+    }
+
+    pub fn finish(self) -> Result<(), AudioError> {
+        self.writer.finish()?;
+        Ok(())
+    }
+
+}
+
+impl AudioSink for WavFileSink {
+
+    fn sample_rate(&self) -> u32 {
+        self.writer.sample_rate()
+    }
+
+    fn channels(&self) -> u16 {
+        self.writer.channels()
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        self.writer.write(samples)
+    }
+
+}
+
+impl Mixer {
+
+    ///Renders `frames` frames and writes them to `sink`, mixed down to mono if the sink has one channel.
+    pub fn render_to(&mut self, sink: &mut dyn AudioSink, frames: usize) -> Result<(), AudioError> {
+        let mut buffer: Vec<f32> = vec![0.0; 2*frames.min(4096)];
+        let mut left: usize = frames;
+        while left > 0 {
+            let n: usize = left.min(4096);
+            let block: &mut [f32] = &mut buffer[..2*n];
+            self.render(block);
+            if sink.channels() == 1 {
+                let mono: Vec<f32> = block.chunks_exact(2).map(|f| (f[0] + f[1])*0.5).collect();
+                sink.write(&mono)?;
+            }
+            else {
+                sink.write(block)?;
+            }
+            left -= n;
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::audio::cmixer::Bus;
+    use crate::audio::csound::Sound;
+
+    #[test]
+    fn null_sink_counts_frames() {
+        let mut mixer: Mixer = Mixer::new(8000);
+        let mut sink: NullSink = NullSink::new(8000, 2);
+        mixer.render_to(&mut sink, 5000).unwrap();
+        assert_eq!(sink.frames_written(), 5000);
+    }
+
+    #[test]
+    fn wav_sink_mixes_down_to_mono() {
+        let path: String = std::env::temp_dir().join(format!("cerulean_sink_{}.wav", std::process::id())).to_string_lossy().into_owned();
+        let mut mixer: Mixer = Mixer::new(8000);
+        mixer.play(Arc::new(Sound::new(8000, 2, [0.5, -0.5].repeat(100))), Bus::Sfx);
+        let mut sink: WavFileSink = WavFileSink::create(&path, 8000, 1, WavEncoding::Float32).unwrap();
+        mixer.render_to(&mut sink, 50).unwrap();
+        assert_eq!(sink.frames_written(), 50);
+        sink.finish().unwrap();
+
+        let sound: Sound = Sound::load_wav(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((sound.channels, sound.frames()), (1, 50));
+        assert!(sound.samples.iter().all(|s| s.abs() < 1e-6));
+    }
+
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AudioError {
+    Io(std::io::Error),
+    ///The data is not audio of the expected format, or is truncated.
+    Format(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::Io(e) => write!(f, "[Audio] {}", e),
+            AudioError::Format(s) => write!(f, "[Audio] {}", s),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+impl From<std::io::Error> for AudioError {
+    fn from(e: std::io::Error) -> AudioError {
+        AudioError::Io(e)
+    }
+}
+
+///Decoded audio: interleaved f32 samples in -1..1 at `sample_rate` frames per second. A frame holds one sample per
+/// channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sound {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Sound {
+
+    pub fn new(sample_rate: u32, channels: u16, samples: Vec<f32>) -> Sound {
+        Sound { sample_rate: sample_rate.max(1), channels: channels.max(1), samples }
+    }
+
+    ///`frames` frames of silence.
+    pub fn silence(sample_rate: u32, channels: u16, frames: usize) -> Sound {
+        Sound::new(sample_rate, channels, vec![0.0; frames*channels.max(1) as usize])
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    ///Length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate.max(1) as f64
+    }
+
+    ///The left and right samples of `frame`. Mono sounds give the same sample twice; channels past the second are
+    /// ignored.
+    pub fn stereo_frame(&self, frame: usize) -> (f32, f32) {
+        let i: usize = frame*self.channels as usize;
+        match self.channels {
+            1 => (self.samples[i], self.samples[i]),
+            _ => (self.samples[i], self.samples[i + 1]),
+        }
+    }
+
+}
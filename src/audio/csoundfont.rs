@@ -0,0 +1,537 @@
+use std::sync::Arc;
+
+use crate::audio::csound::AudioError;
+
+//Generator numbers from the SoundFont 2.01 specification
+const GEN_START_OFFSET: u16 = 0;
+const GEN_END_OFFSET: u16 = 1;
+const GEN_LOOP_START_OFFSET: u16 = 2;
+const GEN_LOOP_END_OFFSET: u16 = 3;
+const GEN_START_COARSE_OFFSET: u16 = 4;
+const GEN_END_COARSE_OFFSET: u16 = 12;
+const GEN_PAN: u16 = 17;
+const GEN_ATTACK_VOL_ENV: u16 = 34;
+const GEN_HOLD_VOL_ENV: u16 = 35;
+const GEN_DECAY_VOL_ENV: u16 = 36;
+const GEN_SUSTAIN_VOL_ENV: u16 = 37;
+const GEN_RELEASE_VOL_ENV: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_VEL_RANGE: u16 = 44;
+const GEN_LOOP_START_COARSE_OFFSET: u16 = 45;
+const GEN_INITIAL_ATTENUATION: u16 = 48;
+const GEN_LOOP_END_COARSE_OFFSET: u16 = 50;
+const GEN_COARSE_TUNE: u16 = 51;
+const GEN_FINE_TUNE: u16 = 52;
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+const GEN_SCALE_TUNING: u16 = 56;
+const GEN_OVERRIDING_ROOT_KEY: u16 = 58;
+const GEN_COUNT: usize = 61;
+
+///How a region's sample repeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    ///Plays once, start to end.
+    None,
+    ///Loops for as long as the note sounds, including its release.
+    Continuous,
+    ///Loops while the key is held, then plays on to the end of the sample.
+    UntilRelease,
+}
+
+///One playable zone: a sample with the key and velocity range it covers and how to play it. Preset and
+/// instrument generators are already combined.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub key_range: (u8, u8),
+    pub velocity_range: (u8, u8),
+    ///Sample frames in `SoundFont::samples`; `end` is exclusive.
+    pub start: usize,
+    pub end: usize,
+    pub loop_start: usize,
+    pub loop_end: usize,
+    pub loop_mode: LoopMode,
+    pub sample_rate: u32,
+    ///Key at which the sample plays at its recorded pitch.
+    pub root_key: u8,
+    ///Extra tuning in cents.
+    pub tune: f32,
+    ///Cents per key; 100 for normally tuned instruments, 0 for ones that play the same pitch on every key.
+    pub scale_tuning: f32,
+    ///-1 (left) to 1 (right).
+    pub pan: f32,
+    pub gain: f32,
+    ///Envelope times in seconds and sustain level in 0..1.
+    pub attack: f64,
+    pub hold: f64,
+    pub decay: f64,
+    pub sustain: f32,
+    pub release: f64,
+}
+
+impl Region {
+
+    pub fn matches(&self, key: u8, velocity: u8) -> bool {
+        (self.key_range.0..=self.key_range.1).contains(&key) && (self.velocity_range.0..=self.velocity_range.1).contains(&velocity)
+    }
+
+}
+
+#[derive(Clone, Debug)]
+pub struct Preset {
+    pub name: String,
+    pub bank: u16,
+    pub program: u16,
+    pub regions: Vec<Region>,
+}
+
+///Sampled instruments loaded from a SoundFont 2 (.sf2) file. Modulators and the modulation envelope and LFOs
+/// are not supported; samples play with their volume envelope, tuning, pan and loops.
+#[derive(Clone, Debug)]
+pub struct SoundFont {
+    pub presets: Vec<Preset>,
+    ///Every sample in the file, converted to f32.
+    pub samples: Arc<Vec<f32>>,
+}
+
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+}
+
+//A zone's generators; None where the zone does not set one
+type Generators = [Option<i16>; GEN_COUNT];
+
+fn le16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn le32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+//Children of a RIFF LIST body as (id, data)
+fn chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut out: Vec<(&[u8], &[u8])> = Vec::new();
+    let mut at: usize = 0;
+    while at + 8 <= data.len() {
+        let id: &[u8] = &data[at..at + 4];
+        let len: usize = le32(data, at + 4) as usize;
+        let body: &[u8] = &data[at + 8..(at + 8 + len).min(data.len())];
+        out.push((id, body));
+        at += 8 + len + (len & 1);
+    }
+    out
+}
+
+fn name(bytes: &[u8]) -> String {
+    let end: usize = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+fn range(value: Option<i16>) -> Option<(u8, u8)> {
+    value.map(|v| {
+        let [lo, hi] = v.to_le_bytes();
+        (lo, hi)
+    })
+}
+
+fn timecents(value: Option<i16>) -> f64 {
+    //-12000 (the default) is about a millisecond, which is as good as instant
+    2f64.powf(value.unwrap_or(-12000) as f64 / 1200.0)
+}
+
+//Splits `bags` records into zones of (generator index range), using each bag's first generator index
+fn zones(bags: &[u8], first: usize, last: usize) -> Vec<(usize, usize)> {
+    (first..last).filter(|b| (b + 1)*4 + 2 <= bags.len()).map(|b| (le16(bags, b*4) as usize, le16(bags, (b + 1)*4) as usize)).collect()
+}
+
+fn generators(gens: &[u8], range: (usize, usize)) -> Generators {
+    let mut out: Generators = [None; GEN_COUNT];
+    for g in range.0..range.1 {
+        if (g + 1)*4 > gens.len() {
+            break;
+        }
+        let oper: usize = le16(gens, g*4) as usize;
+        if oper < GEN_COUNT {
+            out[oper] = Some(le16(gens, g*4 + 2) as i16);
+        }
+    }
+    out
+}
+
+//Applies a global zone's generators wherever the zone leaves them unset
+fn with_global(mut zone: Generators, global: &Option<Generators>) -> Generators {
+    if let Some(global) = global {
+        for (g, v) in zone.iter_mut().enumerate() {
+            if v.is_none() {
+                *v = global[g];
+            }
+        }
+    }
+    zone
+}
+
+fn intersect(a: (u8, u8), b: Option<(u8, u8)>) -> (u8, u8) {
+    match b {
+        Some(b) => (a.0.max(b.0), a.1.min(b.1)),
+        None => a,
+    }
+}
+
+impl SoundFont {
+
+    pub fn parse(data: &[u8]) -> Result<SoundFont, AudioError> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+            return Err(AudioError::Format("not a SoundFont 2 file".to_string()));
+        }
+        let mut smpl: &[u8] = &[];
+        let mut pdta: Vec<(&[u8], &[u8])> = Vec::new();
+        for (id, body) in chunks(&data[12..]) {
+            if id == b"LIST" && body.len() >= 4 {
+                match &body[0..4] {
+                    b"sdta" => {
+                        if let Some((_, s)) = chunks(&body[4..]).into_iter().find(|(id, _)| *id == b"smpl") {
+                            smpl = s;
+                        }
+                    }
+                    b"pdta" => pdta = chunks(&body[4..]),
+                    _ => {}
+                }
+            }
+        }
+        let get = |name: &[u8; 4]| -> Result<&[u8], AudioError> {
+            pdta.iter().find(|(id, _)| *id == name).map(|(_, b)| *b)
+                .ok_or_else(|| AudioError::Format(format!("SoundFont is missing its {} chunk", String::from_utf8_lossy(name))))
+        };
+        let (phdr, pbag, pgen) = (get(b"phdr")?, get(b"pbag")?, get(b"pgen")?);
+        let (inst, ibag, igen, shdr) = (get(b"inst")?, get(b"ibag")?, get(b"igen")?, get(b"shdr")?);
+
+        let samples: Vec<f32> = smpl.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0).collect();
+        let headers: Vec<SampleHeader> = shdr.chunks_exact(46)
+            .map(|h| SampleHeader {
+                start: le32(h, 20),
+                end: le32(h, 24),
+                loop_start: le32(h, 28),
+                loop_end: le32(h, 32),
+                sample_rate: le32(h, 36),
+                original_pitch: h[40],
+                pitch_correction: h[41] as i8,
+            })
+            .collect();
+
+        //Each instrument as a list of zones, global zone already folded in
+        let instrument_count: usize = (inst.len() / 22).saturating_sub(1);
+        let mut instruments: Vec<Vec<Generators>> = Vec::with_capacity(instrument_count);
+        for i in 0..instrument_count {
+            let (first, last) = (le16(inst, i*22 + 20) as usize, le16(inst, (i + 1)*22 + 20) as usize);
+            let mut global: Option<Generators> = None;
+            let mut list: Vec<Generators> = Vec::new();
+            for (z, range) in zones(ibag, first, last).into_iter().enumerate() {
+                let g: Generators = generators(igen, range);
+                if g[GEN_SAMPLE_ID as usize].is_none() {
+                    if z == 0 {
+                        global = Some(g);
+                    }
+                    continue;
+                }
+                list.push(with_global(g, &global));
+            }
+            instruments.push(list);
+        }
+
+        let mut presets: Vec<Preset> = Vec::new();
+        for p in 0..(phdr.len() / 38).saturating_sub(1) {
+            let h: &[u8] = &phdr[p*38..p*38 + 38];
+            let (first, last) = (le16(h, 24) as usize, le16(phdr, (p + 1)*38 + 24) as usize);
+            let mut global: Option<Generators> = None;
+            let mut regions: Vec<Region> = Vec::new();
+            for (z, range) in zones(pbag, first, last).into_iter().enumerate() {
+                let pg: Generators = generators(pgen, range);
+                let Some(instrument) = pg[GEN_INSTRUMENT as usize] else {
+                    if z == 0 {
+                        global = Some(pg);
+                    }
+                    continue;
+                };
+                let pg: Generators = with_global(pg, &global);
+                let Some(zones) = instruments.get(instrument as u16 as usize) else { continue };
+                for ig in zones.iter() {
+                    if let Some(region) = SoundFont::region(&pg, ig, &headers, samples.len()) {
+                        regions.push(region);
+                    }
+                }
+            }
+            presets.push(Preset { name: name(&h[0..20]), bank: le16(h, 22), program: le16(h, 20), regions });
+        }
+        presets.sort_by_key(|p| (p.bank, p.program));
+        Ok(SoundFont { presets, samples: Arc::new(samples) })
+    }
+
+    pub fn load(path: &str) -> Result<SoundFont, AudioError> {
+        SoundFont::parse(&std::fs::read(path)?)
+    }
+
+    //Combines a preset zone and an instrument zone. Preset generators are offsets added to the instrument's, except
+    // ranges, which narrow it
+    fn region(pg: &Generators, ig: &Generators, headers: &[SampleHeader], sample_count: usize) -> Option<Region> {
+        let header: &SampleHeader = headers.get(ig[GEN_SAMPLE_ID as usize]? as u16 as usize)?;
+        let i = |g: u16| ig[g as usize].unwrap_or(0) as i64;
+        let sum = |g: u16| ig[g as usize].unwrap_or(0) as f64 + pg[g as usize].unwrap_or(0) as f64;
+        let env = |g: u16| {
+            let instrument: f64 = timecents(ig[g as usize]);
+            instrument*2f64.powf(pg[g as usize].unwrap_or(0) as f64 / 1200.0)
+        };
+        let address = |base: u32, fine: u16, coarse: u16| -> usize {
+            (base as i64 + i(fine) + i(coarse)*32768).clamp(0, sample_count as i64) as usize
+        };
+
+        let start: usize = address(header.start, GEN_START_OFFSET, GEN_START_COARSE_OFFSET);
+        let end: usize = address(header.end, GEN_END_OFFSET, GEN_END_COARSE_OFFSET).max(start);
+        if end == start {
+            return None;
+        }
+        let loop_start: usize = address(header.loop_start, GEN_LOOP_START_OFFSET, GEN_LOOP_START_COARSE_OFFSET).clamp(start, end);
+        let loop_end: usize = address(header.loop_end, GEN_LOOP_END_OFFSET, GEN_LOOP_END_COARSE_OFFSET).clamp(loop_start, end);
+        let loop_mode: LoopMode = match i(GEN_SAMPLE_MODES) & 3 {
+            1 if loop_end > loop_start => LoopMode::Continuous,
+            3 if loop_end > loop_start => LoopMode::UntilRelease,
+            _ => LoopMode::None,
+        };
+        let root_key: u8 = match ig[GEN_OVERRIDING_ROOT_KEY as usize] {
+            Some(k) if (0..128).contains(&k) => k as u8,
+            _ => header.original_pitch.min(127),
+        };
+        let sustain_cb: f64 = sum(GEN_SUSTAIN_VOL_ENV).clamp(0.0, 1440.0);
+        Some(Region {
+            key_range: intersect(intersect((0, 127), range(ig[GEN_KEY_RANGE as usize])), range(pg[GEN_KEY_RANGE as usize])),
+            velocity_range: intersect(intersect((0, 127), range(ig[GEN_VEL_RANGE as usize])), range(pg[GEN_VEL_RANGE as usize])),
+            start,
+            end,
+            loop_start,
+            loop_end,
+            loop_mode,
+            sample_rate: header.sample_rate.max(1),
+            root_key,
+            tune: (sum(GEN_COARSE_TUNE)*100.0 + sum(GEN_FINE_TUNE) + header.pitch_correction as f64) as f32,
+            scale_tuning: ig[GEN_SCALE_TUNING as usize].unwrap_or(100) as f32 + pg[GEN_SCALE_TUNING as usize].unwrap_or(0) as f32,
+            pan: (sum(GEN_PAN) / 500.0).clamp(-1.0, 1.0) as f32,
+            //Attenuation and sustain are in centibels
+            gain: 10f64.powf(-sum(GEN_INITIAL_ATTENUATION).max(0.0) / 200.0) as f32,
+            attack: env(GEN_ATTACK_VOL_ENV),
+            hold: env(GEN_HOLD_VOL_ENV),
+            decay: env(GEN_DECAY_VOL_ENV),
+            sustain: 10f64.powf(-sustain_cb / 200.0) as f32,
+            release: env(GEN_RELEASE_VOL_ENV),
+        })
+    }
+
+    ///The preset for `program` in `bank`, falling back to any kit in the percussion bank (128), then the same
+    /// program in bank 0, then the first preset.
+    pub fn preset(&self, bank: u16, program: u16) -> Option<&Preset> {
+        self.presets.iter().find(|p| p.bank == bank && p.program == program)
+            .or_else(|| if bank == 128 { self.presets.iter().find(|p| p.bank == 128) } else { None })
+            .or_else(|| self.presets.iter().find(|p| p.bank == 0 && p.program == program))
+            .or_else(|| self.presets.first())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    type Zone = Vec<(u16, i16)>;
+
+    fn key_range(lo: u8, hi: u8) -> i16 {
+        i16::from_le_bytes([lo, hi])
+    }
+
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        [id, &(body.len() as u32).to_le_bytes(), body, if body.len() % 2 == 1 { &[0] } else { &[] }].concat()
+    }
+
+    fn list(kind: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[kind, &children.concat()].concat())
+    }
+
+    fn name20(name: &str) -> Vec<u8> {
+        let mut bytes: Vec<u8> = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes
+    }
+
+    //Bag and generator records for each item's zones, and the index of each item's first bag
+    fn zone_tables(items: &[Vec<Zone>]) -> (Vec<u8>, Vec<u8>, Vec<u16>) {
+        let (mut bags, mut gens, mut firsts): (Vec<u8>, Vec<u8>, Vec<u16>) = (Vec::new(), Vec::new(), Vec::new());
+        let mut gen_count: u16 = 0;
+        for zones in items.iter() {
+            firsts.push((bags.len() / 4) as u16);
+            for zone in zones.iter() {
+                bags.extend_from_slice(&[gen_count.to_le_bytes(), [0, 0]].concat());
+                for (oper, amount) in zone.iter() {
+                    gens.extend_from_slice(&[oper.to_le_bytes(), amount.to_le_bytes()].concat());
+                    gen_count += 1;
+                }
+            }
+        }
+        firsts.push((bags.len() / 4) as u16);
+        bags.extend_from_slice(&[gen_count.to_le_bytes(), [0, 0]].concat());
+        gens.extend_from_slice(&[0; 4]);
+        (bags, gens, firsts)
+    }
+
+    //Presets are (program, bank, zones); headers are (start, end, loop start, loop end, rate, pitch, correction)
+    fn sf2(presets: &[(u16, u16, Vec<Zone>)], instruments: &[Vec<Zone>], headers: &[(u32, u32, u32, u32, u32, u8, i8)], samples: &[i16]) -> Vec<u8> {
+        let (pbag, pgen, pfirst) = zone_tables(&presets.iter().map(|p| p.2.clone()).collect::<Vec<Vec<Zone>>>());
+        let (ibag, igen, ifirst) = zone_tables(instruments);
+        let mut phdr: Vec<u8> = Vec::new();
+        for (i, first) in pfirst.iter().enumerate() {
+            let (program, bank): (u16, u16) = presets.get(i).map_or((0, 0), |p| (p.0, p.1));
+            phdr.extend_from_slice(&[&name20(&format!("Preset {}", i))[..], &program.to_le_bytes(), &bank.to_le_bytes(), &first.to_le_bytes(), &[0; 12]].concat());
+        }
+        let inst: Vec<u8> = ifirst.iter().flat_map(|first| [name20("Instrument"), first.to_le_bytes().to_vec()].concat()).collect();
+        let mut shdr: Vec<u8> = Vec::new();
+        for h in headers.iter().chain([(0, 0, 0, 0, 0, 0, 0)].iter()) {
+            shdr.extend_from_slice(&name20("Sample"));
+            for v in [h.0, h.1, h.2, h.3, h.4] {
+                shdr.extend_from_slice(&v.to_le_bytes());
+            }
+            shdr.extend_from_slice(&[h.5, h.6 as u8, 0, 0, 1, 0]);
+        }
+        let smpl: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let body: Vec<u8> = [
+            &b"sfbk"[..],
+            &list(b"INFO", &[chunk(b"ifil", &[2, 0, 1, 0])]),
+            &list(b"sdta", &[chunk(b"smpl", &smpl)]),
+            &list(b"pdta", &[
+                chunk(b"phdr", &phdr), chunk(b"pbag", &pbag), chunk(b"pmod", &[0; 10]), chunk(b"pgen", &pgen),
+                chunk(b"inst", &inst), chunk(b"ibag", &ibag), chunk(b"imod", &[0; 10]), chunk(b"igen", &igen),
+                chunk(b"shdr", &shdr),
+            ]),
+        ].concat();
+        chunk(b"RIFF", &body)
+    }
+
+    const HEADER: (u32, u32, u32, u32, u32, u8, i8) = (0, 100, 20, 80, 22050, 60, -5);
+
+    fn one_region(preset: Zone, instrument: Zone) -> Region {
+        let data: Vec<u8> = sf2(&[(0, 0, vec![preset])], &[vec![instrument]], &[HEADER], &[0; 120]);
+        let font: SoundFont = SoundFont::parse(&data).unwrap();
+        assert_eq!(font.presets.len(), 1);
+        assert_eq!(font.presets[0].regions.len(), 1);
+        font.presets[0].regions[0].clone()
+    }
+
+    #[test]
+    fn preset_and_instrument_zones_combine() {
+        let region: Region = one_region(
+            vec![(GEN_KEY_RANGE, key_range(50, 100)), (GEN_COARSE_TUNE, 1), (GEN_FINE_TUNE, 5), (GEN_INITIAL_ATTENUATION, 60), (GEN_INSTRUMENT, 0)],
+            vec![(GEN_KEY_RANGE, key_range(40, 80)), (GEN_FINE_TUNE, 10), (GEN_SAMPLE_MODES, 1), (GEN_SAMPLE_ID, 0)],
+        );
+        //Ranges narrow, tunings add, and the sample's own correction applies on top
+        assert_eq!(region.key_range, (50, 80));
+        assert_eq!(region.velocity_range, (0, 127));
+        assert_eq!(region.tune, 110.0);
+        assert_eq!((region.start, region.end, region.loop_start, region.loop_end), (0, 100, 20, 80));
+        assert_eq!(region.loop_mode, LoopMode::Continuous);
+        assert_eq!((region.sample_rate, region.root_key, region.scale_tuning), (22050, 60, 100.0));
+        assert!((region.gain - 10f32.powf(-0.3)).abs() < 1e-5);
+        assert!(region.matches(50, 1) && region.matches(80, 127) && !region.matches(49, 64) && !region.matches(81, 64));
+    }
+
+    #[test]
+    fn address_offsets_move_and_clamp() {
+        let region: Region = one_region(
+            vec![(GEN_INSTRUMENT, 0)],
+            vec![(GEN_START_OFFSET, 10), (GEN_END_COARSE_OFFSET, 1), (GEN_LOOP_START_OFFSET, -30), (GEN_LOOP_END_OFFSET, 500), (GEN_SAMPLE_ID, 0)],
+        );
+        //The coarse end offset runs off the sample data, and the loop is kept inside the played part
+        assert_eq!((region.start, region.end, region.loop_start, region.loop_end), (10, 120, 10, 120));
+    }
+
+    #[test]
+    fn empty_regions_and_loops_are_dropped() {
+        let data: Vec<u8> = sf2(&[(0, 0, vec![vec![(GEN_INSTRUMENT, 0)]])], &[vec![vec![(GEN_END_OFFSET, -100), (GEN_SAMPLE_ID, 0)]]], &[HEADER], &[0; 120]);
+        assert!(SoundFont::parse(&data).unwrap().presets[0].regions.is_empty());
+
+        let region: Region = one_region(vec![(GEN_INSTRUMENT, 0)], vec![(GEN_LOOP_END_OFFSET, -60), (GEN_SAMPLE_MODES, 3), (GEN_SAMPLE_ID, 0)]);
+        assert_eq!(region.loop_mode, LoopMode::None);
+        let region: Region = one_region(vec![(GEN_INSTRUMENT, 0)], vec![(GEN_SAMPLE_MODES, 3), (GEN_SAMPLE_ID, 0)]);
+        assert_eq!(region.loop_mode, LoopMode::UntilRelease);
+    }
+
+    #[test]
+    fn root_key_override() {
+        let region: Region = one_region(vec![(GEN_INSTRUMENT, 0)], vec![(GEN_OVERRIDING_ROOT_KEY, 72), (GEN_SAMPLE_ID, 0)]);
+        assert_eq!(region.root_key, 72);
+        //-1 means "not set" in many files
+        let region: Region = one_region(vec![(GEN_INSTRUMENT, 0)], vec![(GEN_OVERRIDING_ROOT_KEY, -1), (GEN_SAMPLE_ID, 0)]);
+        assert_eq!(region.root_key, 60);
+    }
+
+    #[test]
+    fn envelope_times_and_levels() {
+        let region: Region = one_region(
+            vec![(GEN_ATTACK_VOL_ENV, 1200), (GEN_SUSTAIN_VOL_ENV, 100), (GEN_INSTRUMENT, 0)],
+            vec![(GEN_ATTACK_VOL_ENV, 0), (GEN_DECAY_VOL_ENV, -1200), (GEN_SUSTAIN_VOL_ENV, 100), (GEN_PAN, -250), (GEN_SAMPLE_ID, 0)],
+        );
+        assert!((region.attack - 2.0).abs() < 1e-9);
+        assert!((region.decay - 0.5).abs() < 1e-9);
+        assert!((region.release - 2f64.powf(-10.0)).abs() < 1e-9);
+        assert!((region.sustain - 0.1).abs() < 1e-6);
+        assert_eq!(region.pan, -0.5);
+    }
+
+    #[test]
+    fn global_zones_fill_in_unset_generators() {
+        let data: Vec<u8> = sf2(
+            &[(0, 0, vec![vec![(GEN_FINE_TUNE, 7)], vec![(GEN_INSTRUMENT, 0)]])],
+            &[vec![vec![(GEN_PAN, 500)], vec![(GEN_SAMPLE_ID, 0)], vec![(GEN_PAN, -500), (GEN_SAMPLE_ID, 0)]]],
+            &[HEADER],
+            &[0; 120],
+        );
+        let font: SoundFont = SoundFont::parse(&data).unwrap();
+        let pans: Vec<f32> = font.presets[0].regions.iter().map(|r| r.pan).collect();
+        assert_eq!(pans, vec![1.0, -1.0]);
+        assert!(font.presets[0].regions.iter().all(|r| r.tune == 2.0));
+    }
+
+    #[test]
+    fn samples_are_converted() {
+        let data: Vec<u8> = sf2(&[(0, 0, vec![vec![(GEN_INSTRUMENT, 0)]])], &[vec![vec![(GEN_SAMPLE_ID, 0)]]], &[(0, 3, 0, 0, 44100, 60, 0)], &[16384, -32768, 0]);
+        assert_eq!(*SoundFont::parse(&data).unwrap().samples, vec![0.5, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn preset_lookup_falls_back() {
+        let zones: Vec<Zone> = vec![vec![(GEN_INSTRUMENT, 0)]];
+        let data: Vec<u8> = sf2(
+            &[(5, 0, zones.clone()), (0, 128, zones.clone()), (0, 0, zones.clone())],
+            &[vec![vec![(GEN_SAMPLE_ID, 0)]]],
+            &[HEADER],
+            &[0; 120],
+        );
+        let font: SoundFont = SoundFont::parse(&data).unwrap();
+        let found = |bank: u16, program: u16| font.preset(bank, program).map(|p| (p.bank, p.program));
+        assert_eq!(found(0, 5), Some((0, 5)));
+        assert_eq!(found(128, 9), Some((128, 0)));
+        assert_eq!(found(3, 5), Some((0, 5)));
+        assert_eq!(found(3, 9), Some((0, 0)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(SoundFont::parse(b"RIFF\0\0\0\0WAVE").is_err());
+        let missing: Vec<u8> = chunk(b"RIFF", &[&b"sfbk"[..], &list(b"pdta", &[chunk(b"phdr", &[0; 38])])].concat());
+        assert!(SoundFont::parse(&missing).is_err());
+    }
+
+}
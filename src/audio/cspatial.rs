@@ -0,0 +1,564 @@
+use std::sync::Arc;
+
+use crate::audio::ceffects::Biquad;
+use crate::audio::cresample::{interpolation_weights, Interpolation};
+use crate::audio::csound::Sound;
+use crate::graphics::ccamera::CCamera;
+use crate::graphics::cmodel_instance::CModelInstance;
+use crate::math::cmatrix::Matrix3x3;
+
+type V3 = (f64, f64, f64);
+
+fn sub(a: V3, b: V3) -> V3 {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+
+fn dot(a: V3, b: V3) -> f64 {
+    a.0*b.0 + a.1*b.1 + a.2*b.2
+}
+
+fn length(a: V3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+///How volume falls off between an emitter's `min_distance` and `max_distance`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rolloff {
+    ///Physically plausible 1/distance falloff; halves with every doubling of distance at a factor of 1.
+    Inverse,
+    ///Straight line from full volume at `min_distance` to silence at `max_distance`.
+    Linear,
+    ///(distance / min_distance)^-factor.
+    Exponential,
+}
+
+///Distance attenuation. Closer than `min_distance` plays at full volume; further than `max_distance` stops
+/// getting quieter (or, for `Linear`, is silent).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub rolloff: Rolloff,
+    pub min_distance: f64,
+    pub max_distance: f64,
+    ///Steepness of the curve; 1 is the natural rate.
+    pub factor: f64,
+}
+
+impl Attenuation {
+
+    pub fn new(rolloff: Rolloff, min_distance: f64, max_distance: f64) -> Attenuation {
+        Attenuation { rolloff, min_distance, max_distance, factor: 1.0 }
+    }
+
+    pub fn factor(mut self, factor: f64) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    ///Volume multiplier at `distance`, from 0 to 1.
+    pub fn gain(&self, distance: f64) -> f32 {
+        let min: f64 = self.min_distance.max(1e-6);
+        let max: f64 = self.max_distance.max(min);
+        let d: f64 = distance.clamp(min, max);
+        let gain: f64 = match self.rolloff {
+            Rolloff::Inverse => min / (min + self.factor*(d - min)),
+            Rolloff::Linear => {
+                if max > min { 1.0 - self.factor*(d - min) / (max - min) } else { 1.0 }
+            }
+            Rolloff::Exponential => (d / min).powf(-self.factor),
+        };
+        gain.clamp(0.0, 1.0) as f32
+    }
+
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation::new(Rolloff::Inverse, 1.0, 50.0)
+    }
+}
+
+///Where an emitter is in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    ///A fixed world position. Move it with `Emitter::set_position`.
+    Position(V3),
+    ///Follows the transform of the model instance at this index in the scene passed to `SpatialAudio::update`,
+    /// offset by a point in the model's own space.
+    Instance(usize, V3),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EmitterId(u64);
+
+///A sound playing from a point in the scene. Stereo sounds are mixed down to mono before being placed.
+pub struct Emitter {
+    pub anchor: Anchor,
+    pub attenuation: Attenuation,
+    pub volume: f32,
+    pub pitch: f64,
+    pub looping: bool,
+    ///How much Doppler shift this emitter gets; 0 turns it off.
+    pub doppler: f64,
+    ///How blocked the path to the listener is, from 0 (clear) to 1 (behind a thick wall). Muffles and quietens.
+    pub occlusion: f32,
+    sound: Arc<Sound>,
+    cursor: f64,
+    position: V3,
+    velocity: V3,
+    moved: bool,
+    target_gains: (f32, f32),
+    gains: (f32, f32),
+    target_step: f64,
+    step: f64,
+    filter: Option<Biquad>,
+    finished: bool,
+}
+
+impl Emitter {
+
+    pub fn new(sound: Arc<Sound>) -> Emitter {
+        Emitter {
+            anchor: Anchor::Position((0.0, 0.0, 0.0)),
+            attenuation: Attenuation::default(),
+            volume: 1.0,
+            pitch: 1.0,
+            looping: false,
+            doppler: 1.0,
+            occlusion: 0.0,
+            sound,
+            cursor: 0.0,
+            position: (0.0, 0.0, 0.0),
+            velocity: (0.0, 0.0, 0.0),
+            moved: false,
+            target_gains: (0.0, 0.0),
+            gains: (0.0, 0.0),
+            target_step: 0.0,
+            step: 0.0,
+            filter: None,
+            finished: false,
+        }
+    }
+
+    pub fn at(mut self, position: V3) -> Self {
+        self.anchor = Anchor::Position(position);
+        self.position = position;
+        self
+    }
+
+    ///Attaches the emitter to the model instance at `index` in the scene given to `SpatialAudio::update`.
+    pub fn attached_to(mut self, index: usize) -> Self {
+        self.anchor = Anchor::Instance(index, (0.0, 0.0, 0.0));
+        self
+    }
+
+    pub fn attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    ///Playback rate, as in `PlayOptions::pitch`. An emitter at 0 cannot advance, so it stops.
+    pub fn pitch(mut self, pitch: f64) -> Self {
+        self.pitch = pitch.max(0.0);
+        self
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn doppler(mut self, doppler: f64) -> Self {
+        self.doppler = doppler;
+        self
+    }
+
+    pub fn occlusion(mut self, occlusion: f32) -> Self {
+        self.occlusion = occlusion;
+        self
+    }
+
+    pub fn set_position(&mut self, position: V3) {
+        self.anchor = Anchor::Position(position);
+    }
+
+    ///World position as of the last update.
+    pub fn position(&self) -> V3 {
+        self.position
+    }
+
+    ///World velocity as of the last update, measured from how far the emitter moved.
+    pub fn velocity(&self) -> V3 {
+        self.velocity
+    }
+
+    fn resolve(&self, scene: &[CModelInstance]) -> Option<V3> {
+        match self.anchor {
+            Anchor::Position(p) => Some(p),
+            Anchor::Instance(index, offset) => {
+                let instance: &CModelInstance = scene.get(index)?;
+                let mut p: (f64, f64, f64, f64) = (offset.0, offset.1, offset.2, 1.0);
+                instance.transform.asMatrix().applyTo(&mut p);
+                Some((p.0, p.1, p.2))
+            }
+        }
+    }
+
+    fn mono(&self, frame: i64) -> f32 {
+        let frames: i64 = self.sound.frames() as i64;
+        let frame: i64 = if self.looping { frame.rem_euclid(frames) } else { frame };
+        if frame < 0 || frame >= frames {
+            return 0.0;
+        }
+        let (l, r) = self.sound.stereo_frame(frame as usize);
+        (l + r)*0.5
+    }
+
+    fn render(&mut self, out: &mut [f32], sample_rate: u32, quality: Interpolation, weights: &mut Vec<(i64, f32)>) {
+        let count: usize = out.len() / 2;
+        let frames: f64 = self.sound.frames() as f64;
+        let inv: f32 = 1.0 / count.max(1) as f32;
+        let gain_ramp: (f32, f32) = ((self.target_gains.0 - self.gains.0)*inv, (self.target_gains.1 - self.gains.1)*inv);
+        let step_ramp: f64 = (self.target_step - self.step)*inv as f64;
+        //A sound that is not moving forward would never reach its end
+        if self.target_step.is_nan() || self.target_step <= 0.0 {
+            self.finished = true;
+            return;
+        }
+
+        //Muffling is a low-pass that closes from the top of the spectrum down to a few hundred hertz
+        let occlusion: f32 = self.occlusion.clamp(0.0, 1.0);
+        if occlusion > 0.0 && self.filter.is_none() {
+            self.filter = Some(Biquad::low_pass(sample_rate as f32*0.45, sample_rate));
+        }
+        if let Some(filter) = self.filter.as_mut() {
+            filter.set_frequency(sample_rate as f32*0.45*(1.0 - occlusion).powi(3) + 300.0*occlusion);
+        }
+
+        for frame in out.chunks_exact_mut(2) {
+            self.gains.0 += gain_ramp.0;
+            self.gains.1 += gain_ramp.1;
+            self.step += step_ramp;
+            interpolation_weights(self.cursor, self.step, quality, weights);
+            let mut s: f32 = weights.iter().map(|(k, w)| self.mono(*k)*w).sum();
+            if let Some(filter) = self.filter.as_mut() {
+                filter.advance();
+                s = filter.process_sample(s, 0);
+            }
+            frame[0] += s*self.gains.0;
+            frame[1] += s*self.gains.1;
+            self.cursor += self.step;
+            if self.cursor >= frames {
+                if self.looping {
+                    self.cursor %= frames;
+                }
+                else {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        self.gains = self.target_gains;
+        self.step = self.target_step;
+        //Drop the filter once the path has fully cleared and it has opened back up
+        if occlusion == 0.0 && self.filter.as_ref().is_some_and(|f| f.is_settled()) {
+            self.filter = None;
+        }
+    }
+
+}
+
+///The ears of the scene, normally following the camera.
+pub struct Listener {
+    pub position: V3,
+    pub velocity: V3,
+    ///World to listener space: x to the right, y up, z forward.
+    to_local: Matrix3x3,
+    placed: bool,
+}
+
+impl Listener {
+
+    pub fn new() -> Listener {
+        Listener { position: (0.0, 0.0, 0.0), velocity: (0.0, 0.0, 0.0), to_local: Matrix3x3::new(), placed: false }
+    }
+
+    ///`point` in the listener's own space, the same view space `CRen::render_scene` uses.
+    pub fn to_local(&self, point: V3) -> V3 {
+        let mut v: V3 = sub(point, self.position);
+        self.to_local.applyTo(&mut v);
+        v
+    }
+
+}
+
+impl Default for Listener {
+    fn default() -> Listener {
+        Listener::new()
+    }
+}
+
+///Plays emitters placed in the scene and renders them, panned, attenuated and Doppler-shifted, into interleaved
+/// stereo frames. Call `update` once per game frame and `render` from the audio loop, then mix the result with the
+/// `Mixer`'s output.
+pub struct SpatialAudio {
+    pub sample_rate: u32,
+    pub listener: Listener,
+    ///In world units per second.
+    pub speed_of_sound: f64,
+    ///Scales the Doppler shift of every emitter.
+    pub doppler_factor: f64,
+    pub interpolation: Interpolation,
+    emitters: Vec<(EmitterId, Emitter)>,
+    next_id: u64,
+    weights: Vec<(i64, f32)>,
+}
+
+impl SpatialAudio {
+
+    pub fn new(sample_rate: u32) -> SpatialAudio {
+        SpatialAudio {
+            sample_rate,
+            listener: Listener::new(),
+            speed_of_sound: 343.0,
+            doppler_factor: 1.0,
+            interpolation: Interpolation::Cubic,
+            emitters: Vec::new(),
+            next_id: 0,
+            weights: Vec::new(),
+        }
+    }
+
+    pub fn play(&mut self, emitter: Emitter) -> EmitterId {
+        let id: EmitterId = EmitterId(self.next_id);
+        self.next_id += 1;
+        if emitter.sound.frames() == 0 {
+            return id;
+        }
+        let mut emitter: Emitter = emitter;
+        if let Anchor::Position(p) = emitter.anchor {
+            emitter.position = p;
+        }
+        self.spatialize(&mut emitter);
+        emitter.gains = emitter.target_gains;
+        emitter.step = emitter.target_step;
+        self.emitters.push((id, emitter));
+        id
+    }
+
+    pub fn emitter_mut(&mut self, id: EmitterId) -> Option<&mut Emitter> {
+        self.emitters.iter_mut().find(|(i, _)| *i == id).map(|(_, e)| e)
+    }
+
+    pub fn stop(&mut self, id: EmitterId) {
+        self.emitters.retain(|(i, _)| *i != id);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.emitters.clear();
+    }
+
+    pub fn is_playing(&self, id: EmitterId) -> bool {
+        self.emitters.iter().any(|(i, _)| *i == id)
+    }
+
+    pub fn emitter_count(&self) -> usize {
+        self.emitters.len()
+    }
+
+    ///Moves the listener to `camera` and emitters to their anchors in `scene`, deriving velocities from how far
+    /// everything moved in `dt` seconds, then recomputes each emitter's gains and pitch.
+    pub fn update(&mut self, camera: &CCamera, scene: &[CModelInstance], dt: f64) {
+        let position: V3 = camera.transform.pos;
+        if dt > 0.0 && self.listener.placed {
+            let moved: V3 = sub(position, self.listener.position);
+            self.listener.velocity = (moved.0 / dt, moved.1 / dt, moved.2 / dt);
+        }
+        self.listener.position = position;
+        self.listener.placed = true;
+        let (rx, ry, rz) = camera.transform.rot;
+        self.listener.to_local = Matrix3x3::new_rot(rx, ry, rz);
+        self.listener.to_local.transpose();
+
+        let mut emitters: Vec<(EmitterId, Emitter)> = std::mem::take(&mut self.emitters);
+        for (_, emitter) in emitters.iter_mut() {
+            if let Some(p) = emitter.resolve(scene) {
+                //The first sighting has no previous position to measure speed from
+                if dt > 0.0 && emitter.moved {
+                    let moved: V3 = sub(p, emitter.position);
+                    emitter.velocity = (moved.0 / dt, moved.1 / dt, moved.2 / dt);
+                }
+                emitter.position = p;
+                emitter.moved = true;
+            }
+            self.spatialize(emitter);
+        }
+        self.emitters = emitters;
+    }
+
+    fn spatialize(&self, emitter: &mut Emitter) {
+        let local: V3 = self.listener.to_local(emitter.position);
+        let distance: f64 = length(local);
+        let gain: f32 = emitter.attenuation.gain(distance)*emitter.volume*(1.0 - 0.5*emitter.occlusion.clamp(0.0, 1.0));
+        //Constant-power pan on the left-right component of the direction, matching the mixer's pan law
+        let pan: f64 = if distance > 1e-9 { (local.0 / distance).clamp(-1.0, 1.0) } else { 0.0 };
+        let angle: f32 = ((pan + 1.0)*std::f64::consts::FRAC_PI_4) as f32;
+        let g: f32 = gain*std::f32::consts::SQRT_2;
+        emitter.target_gains = (angle.cos()*g, angle.sin()*g);
+
+        let mut doppler: f64 = 1.0;
+        let factor: f64 = self.doppler_factor*emitter.doppler;
+        if factor > 0.0 && distance > 1e-9 {
+            //Speeds along the line from emitter to listener; approaching raises the pitch
+            let to_listener: V3 = sub(self.listener.position, emitter.position);
+            let d: f64 = length(to_listener);
+            let dir: V3 = (to_listener.0 / d, to_listener.1 / d, to_listener.2 / d);
+            let limit: f64 = self.speed_of_sound / factor*0.99;
+            let vl: f64 = dot(self.listener.velocity, dir).clamp(-limit, limit);
+            let vs: f64 = dot(emitter.velocity, dir).clamp(-limit, limit);
+            doppler = (self.speed_of_sound - factor*vl) / (self.speed_of_sound - factor*vs);
+        }
+        emitter.target_step = emitter.pitch*doppler*emitter.sound.sample_rate.max(1) as f64 / self.sample_rate.max(1) as f64;
+    }
+
+    ///Overwrites `out` with the next `out.len() / 2` interleaved stereo frames. Finished emitters are removed.
+    pub fn render(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        for (_, emitter) in self.emitters.iter_mut() {
+            emitter.render(out, self.sample_rate, self.interpolation, &mut self.weights);
+        }
+        self.emitters.retain(|(_, e)| !e.finished);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::graphics::{ccamera, cmodel_instance};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn ones(frames: usize) -> Arc<Sound> {
+        Arc::new(Sound::new(100, 1, vec![1.0; frames]))
+    }
+
+    fn spatial() -> SpatialAudio {
+        let mut spatial: SpatialAudio = SpatialAudio::new(100);
+        spatial.interpolation = Interpolation::Linear;
+        spatial
+    }
+
+    fn render(spatial: &mut SpatialAudio, frames: usize) -> Vec<f32> {
+        let mut out: Vec<f32> = vec![0.0; frames*2];
+        spatial.render(&mut out);
+        out
+    }
+
+    #[test]
+    fn rolloff_curves() {
+        let inverse: Attenuation = Attenuation::new(Rolloff::Inverse, 1.0, 50.0);
+        assert_eq!(inverse.gain(0.5), 1.0);
+        assert!(close(inverse.gain(2.0), 0.5) && close(inverse.gain(4.0), 0.25));
+        assert!(close(inverse.gain(1000.0), 0.02));
+
+        let linear: Attenuation = Attenuation::new(Rolloff::Linear, 0.0, 10.0);
+        assert!(close(linear.gain(5.0), 0.5));
+        assert_eq!(linear.gain(20.0), 0.0);
+
+        let exponential: Attenuation = Attenuation::new(Rolloff::Exponential, 1.0, 50.0).factor(2.0);
+        assert!(close(exponential.gain(2.0), 0.25));
+
+        //A range with no width plays at full volume rather than dividing by zero
+        assert_eq!(Attenuation::new(Rolloff::Linear, 5.0, 5.0).gain(5.0), 1.0);
+    }
+
+    #[test]
+    fn distance_quietens_and_side_pans() {
+        let mut spatial: SpatialAudio = spatial();
+        let attenuation: Attenuation = Attenuation::new(Rolloff::Inverse, 1.0, 50.0);
+        spatial.play(Emitter::new(ones(10)).at((0.0, 0.0, 4.0)).attenuation(attenuation));
+        let out: Vec<f32> = render(&mut spatial, 1);
+        assert!(close(out[0], 0.25) && close(out[1], 0.25), "{:?}", out);
+
+        spatial.stop_all();
+        spatial.play(Emitter::new(ones(10)).at((3.0, 0.0, 0.0)).attenuation(Attenuation::new(Rolloff::Linear, 10.0, 20.0)));
+        let out: Vec<f32> = render(&mut spatial, 1);
+        assert!(close(out[0], 0.0) && close(out[1], std::f32::consts::SQRT_2));
+    }
+
+    #[test]
+    fn turning_the_listener_swaps_sides() {
+        let mut spatial: SpatialAudio = spatial();
+        spatial.play(Emitter::new(ones(10)).at((3.0, 0.0, 0.0)));
+        let mut camera: ccamera::CCamera = ccamera::new();
+        camera.transform.rot = (0.0, std::f64::consts::PI, 0.0);
+        spatial.update(&camera, &[], 0.0);
+        let out: Vec<f32> = render(&mut spatial, 2);
+        assert!(out[2] > 0.0 && close(out[3], 0.0), "{:?}", out);
+    }
+
+    #[test]
+    fn approaching_raises_the_pitch() {
+        let mut spatial: SpatialAudio = spatial();
+        let camera: ccamera::CCamera = ccamera::new();
+        let id: EmitterId = spatial.play(Emitter::new(ones(1000)).at((0.0, 0.0, 100.0)));
+        spatial.update(&camera, &[], 0.1);
+        spatial.emitter_mut(id).unwrap().set_position((0.0, 0.0, 99.0));
+        spatial.update(&camera, &[], 0.1);
+        let emitter: &mut Emitter = spatial.emitter_mut(id).unwrap();
+        assert_eq!(emitter.velocity(), (0.0, 0.0, -10.0));
+        assert!((emitter.target_step - 343.0 / 333.0).abs() < 1e-9);
+
+        emitter.doppler = 0.0;
+        spatial.update(&camera, &[], 0.1);
+        assert_eq!(spatial.emitter_mut(id).unwrap().target_step, 1.0);
+    }
+
+    #[test]
+    fn attached_emitters_follow_their_instance() {
+        let mut spatial: SpatialAudio = spatial();
+        let camera: ccamera::CCamera = ccamera::new();
+        let scene: Vec<CModelInstance> = vec![cmodel_instance::new_cube(0.0, 0.0, 0.0), cmodel_instance::new_cube(3.0, 1.0, 2.0)];
+        let id: EmitterId = spatial.play(Emitter::new(ones(10)).attached_to(1));
+        spatial.update(&camera, &scene, 0.1);
+        assert_eq!(spatial.emitter_mut(id).unwrap().position(), (3.0, 1.0, 2.0));
+        //A missing instance leaves the emitter where it was last seen
+        spatial.update(&camera, &scene[..1], 0.1);
+        assert_eq!(spatial.emitter_mut(id).unwrap().position(), (3.0, 1.0, 2.0));
+    }
+
+    #[test]
+    fn occlusion_quietens_and_muffles() {
+        let mut spatial: SpatialAudio = spatial();
+        spatial.play(Emitter::new(ones(100)).occlusion(1.0).looping(true));
+        let out: Vec<f32> = render(&mut spatial, 100);
+        //A low-pass still lets the constant signal through at half volume once it settles
+        assert!((out[198] - 0.5).abs() < 0.01, "{}", out[198]);
+        assert!(spatial.emitters[0].1.filter.is_some());
+    }
+
+    #[test]
+    fn emitters_end_unless_looping() {
+        let mut spatial: SpatialAudio = spatial();
+        let once: EmitterId = spatial.play(Emitter::new(ones(3)));
+        let looping: EmitterId = spatial.play(Emitter::new(ones(3)).looping(true));
+        let empty: EmitterId = spatial.play(Emitter::new(ones(0)));
+        let still: EmitterId = spatial.play(Emitter::new(ones(3)).pitch(0.0));
+        assert!(!spatial.is_playing(empty));
+        render(&mut spatial, 5);
+        assert!(!spatial.is_playing(once) && !spatial.is_playing(still));
+        assert!(spatial.is_playing(looping));
+        assert_eq!(spatial.emitter_count(), 1);
+        spatial.stop(looping);
+        assert_eq!(spatial.emitter_count(), 0);
+    }
+
+}
@@ -0,0 +1,470 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use crate::audio::cresample::{Interpolation, Resampler};
+use crate::audio::csound::{AudioError, Sound};
+use crate::audio::cwav::{decode_samples, WavEncoding, WavFormat};
+
+///Frames decoded at a time by `WavStream` for PCM data.
+const CHUNK_FRAMES: usize = 4096;
+
+///Audio produced on demand, a chunk at a time, so long tracks never have to be decoded fully into memory.
+pub trait AudioSource {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    ///Total length in frames, if known.
+    fn frames(&self) -> Option<usize>;
+
+    ///Fills `out` with up to `out.len() / channels` interleaved frames and returns how many were written. Returns 0
+    /// only at the end of the audio.
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError>;
+
+    ///Moves to `frame`; the next `read` starts there.
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError>;
+
+    ///The frame the next `read` starts at.
+    fn position(&self) -> usize;
+}
+
+///Plays a decoded `Sound` through the `AudioSource` interface.
+pub struct SoundSource {
+    pub sound: Arc<Sound>,
+    position: usize,
+}
+
+impl SoundSource {
+
+    pub fn new(sound: Arc<Sound>) -> SoundSource {
+        SoundSource { sound, position: 0 }
+    }
+
+}
+
+impl AudioSource for SoundSource {
+
+    fn sample_rate(&self) -> u32 {
+        self.sound.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.sound.channels
+    }
+
+    fn frames(&self) -> Option<usize> {
+        Some(self.sound.frames())
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        let channels: usize = self.sound.channels as usize;
+        let n: usize = (out.len() / channels).min(self.sound.frames().saturating_sub(self.position));
+        out[..n*channels].copy_from_slice(&self.sound.samples[self.position*channels..(self.position + n)*channels]);
+        self.position += n;
+        Ok(n)
+    }
+
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError> {
+        self.position = frame.min(self.sound.frames());
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+}
+
+///Decodes a WAV file from a reader as it is played. Supports everything `Sound::from_wav` does.
+pub struct WavStream<R: Read + Seek> {
+    reader: R,
+    format: WavFormat,
+    encoding: WavEncoding,
+    data_start: u64,
+    data_bytes: u64,
+    frames: usize,
+    position: usize,
+    decoded: Vec<f32>,          //Decoded frames starting at `decoded_start`
+    decoded_start: usize,
+}
+
+impl WavStream<BufReader<File>> {
+
+    pub fn open(path: &str) -> Result<WavStream<BufReader<File>>, AudioError> {
+        WavStream::new(BufReader::new(File::open(path)?))
+    }
+
+}
+
+impl<R: Read + Seek> WavStream<R> {
+
+    ///Reads the header; no samples are decoded until `read`.
+    pub fn new(mut reader: R) -> Result<WavStream<R>, AudioError> {
+        let length: u64 = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header: [u8; 12] = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(AudioError::Format("not a RIFF WAVE file".to_string()));
+        }
+
+        let mut format: Option<WavFormat> = None;
+        let mut data: Option<(u64, u64)> = None;
+        let mut fact: Option<usize> = None;
+        let mut at: u64 = 12;
+        while at + 8 <= length && (format.is_none() || data.is_none()) {
+            reader.seek(SeekFrom::Start(at))?;
+            let mut chunk: [u8; 8] = [0; 8];
+            reader.read_exact(&mut chunk)?;
+            let size: u64 = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            let available: u64 = size.min(length - at - 8);
+            match &chunk[0..4] {
+                b"fmt " | b"fact" => {
+                    let mut body: Vec<u8> = vec![0; available as usize];
+                    reader.read_exact(&mut body)?;
+                    if &chunk[0..4] == b"fmt " {
+                        format = Some(WavFormat::parse(&body)?);
+                    }
+                    else if body.len() >= 4 {
+                        fact = Some(u32::from_le_bytes([body[0], body[1], body[2], body[3]]) as usize);
+                    }
+                }
+                b"data" => data = Some((at + 8, available)),
+                _ => {}
+            }
+            at += 8 + size + (size & 1);
+        }
+
+        let format: WavFormat = format.ok_or_else(|| AudioError::Format("WAV file has no fmt chunk".to_string()))?;
+        let (data_start, data_bytes) = data.ok_or_else(|| AudioError::Format("WAV file has no data chunk".to_string()))?;
+        let encoding: WavEncoding = format.encoding()?;
+        let mut frames: usize = match encoding {
+            WavEncoding::ImaAdpcm => (data_bytes / format.block_align as u64) as usize*format.samples_per_block as usize,
+            _ => (data_bytes / format.block_align as u64) as usize,
+        };
+        if let Some(f) = fact {
+            frames = frames.min(f);
+        }
+        Ok(WavStream { reader, format, encoding, data_start, data_bytes, frames, position: 0, decoded: Vec::new(), decoded_start: 0 })
+    }
+
+    pub fn format(&self) -> &WavFormat {
+        &self.format
+    }
+
+    //Decodes the chunk (PCM) or block (ADPCM) containing `frame`
+    fn decode_at(&mut self, frame: usize) -> Result<(), AudioError> {
+        let block: u64 = self.format.block_align as u64;
+        let (start, offset, bytes): (usize, u64, u64) = match self.encoding {
+            WavEncoding::ImaAdpcm => {
+                let per_block: usize = self.format.samples_per_block.max(1) as usize;
+                let index: u64 = (frame / per_block) as u64;
+                (index as usize*per_block, index*block, block)
+            }
+            _ => (frame, frame as u64*block, CHUNK_FRAMES as u64*block),
+        };
+        let bytes: u64 = bytes.min(self.data_bytes.saturating_sub(offset));
+        let mut raw: Vec<u8> = vec![0; bytes as usize];
+        self.reader.seek(SeekFrom::Start(self.data_start + offset))?;
+        self.reader.read_exact(&mut raw)?;
+        self.decoded = decode_samples(&self.format, &raw)?;
+        self.decoded_start = start;
+        Ok(())
+    }
+
+}
+
+impl<R: Read + Seek> AudioSource for WavStream<R> {
+
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn frames(&self) -> Option<usize> {
+        Some(self.frames)
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        let channels: usize = self.format.channels as usize;
+        let wanted: usize = (out.len() / channels).min(self.frames.saturating_sub(self.position));
+        let mut written: usize = 0;
+        while written < wanted {
+            let decoded_frames: usize = self.decoded.len() / channels;
+            if self.position < self.decoded_start || self.position >= self.decoded_start + decoded_frames {
+                self.decode_at(self.position)?;
+                if self.position >= self.decoded_start + self.decoded.len() / channels {
+                    break;
+                }
+                continue;
+            }
+            let from: usize = self.position - self.decoded_start;
+            let n: usize = (wanted - written).min(decoded_frames - from);
+            out[written*channels..(written + n)*channels].copy_from_slice(&self.decoded[from*channels..(from + n)*channels]);
+            written += n;
+            self.position += n;
+        }
+        Ok(written)
+    }
+
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError> {
+        self.position = frame.min(self.frames);
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+}
+
+///Repeats the part of a source between two loop points, for music with an intro before the looping section.
+pub struct LoopingSource<S: AudioSource> {
+    pub source: S,
+    pub loop_start: usize,
+    ///Frame the loop jumps back from; the end of the source if `None`.
+    pub loop_end: Option<usize>,
+    ///Times left to jump back, or `None` to loop forever.
+    pub loops_left: Option<u32>,
+}
+
+impl<S: AudioSource> LoopingSource<S> {
+
+    ///Loops the whole source forever.
+    pub fn new(source: S) -> LoopingSource<S> {
+        LoopingSource { source, loop_start: 0, loop_end: None, loops_left: None }
+    }
+
+    pub fn with_loop_points(mut self, start: usize, end: Option<usize>) -> LoopingSource<S> {
+        self.loop_start = start;
+        self.loop_end = end;
+        self
+    }
+
+    pub fn with_loop_count(mut self, count: u32) -> LoopingSource<S> {
+        self.loops_left = Some(count);
+        self
+    }
+
+}
+
+impl<S: AudioSource> AudioSource for LoopingSource<S> {
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn frames(&self) -> Option<usize> {
+        if self.loops_left.is_none() { None } else { self.source.frames() }
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        let channels: usize = self.source.channels() as usize;
+        let wanted: usize = out.len() / channels;
+        let mut written: usize = 0;
+        let mut jumped_empty: bool = false;
+        while written < wanted {
+            let looping: bool = self.loops_left != Some(0);
+            let limit: usize = match self.loop_end {
+                Some(end) if looping && self.source.position() < end => end - self.source.position(),
+                _ => wanted - written,
+            };
+            let n: usize = self.source.read(&mut out[written*channels..(written + limit.min(wanted - written))*channels])?;
+            written += n;
+            let at_loop_end: bool = n == 0 || self.loop_end.is_some_and(|end| self.source.position() == end);
+            if at_loop_end && looping && (n > 0 || !jumped_empty) {
+                //A loop that produces nothing would spin forever, so give up after one empty pass
+                jumped_empty = n == 0;
+                self.source.seek(self.loop_start)?;
+                if let Some(count) = self.loops_left.as_mut() {
+                    *count -= 1;
+                }
+            }
+            else if n == 0 {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError> {
+        self.source.seek(frame)
+    }
+
+    fn position(&self) -> usize {
+        self.source.position()
+    }
+
+}
+
+///Converts another source to a different sample rate as it is read.
+pub struct ResampledSource<S: AudioSource> {
+    source: S,
+    sample_rate: u32,
+    quality: Interpolation,
+    resampler: Resampler,
+    output: Vec<f32>,           //Converted samples not yet read
+    input: Vec<f32>,
+    position: usize,
+    flushed: bool,
+}
+
+impl<S: AudioSource> ResampledSource<S> {
+
+    pub fn new(source: S, sample_rate: u32, quality: Interpolation) -> ResampledSource<S> {
+        let resampler: Resampler = Resampler::new(source.channels(), source.sample_rate(), sample_rate, quality);
+        let input: Vec<f32> = vec![0.0; 1024*source.channels() as usize];
+        ResampledSource { source, sample_rate, quality, resampler, output: Vec::new(), input, position: 0, flushed: false }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+}
+
+impl<S: AudioSource> AudioSource for ResampledSource<S> {
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn frames(&self) -> Option<usize> {
+        self.source.frames().map(|f| (f as f64*self.sample_rate as f64 / self.source.sample_rate() as f64).ceil() as usize)
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        let channels: usize = self.source.channels() as usize;
+        let wanted: usize = out.len() / channels*channels;
+        while self.output.len() < wanted && !self.flushed {
+            let n: usize = self.source.read(&mut self.input)?;
+            if n == 0 {
+                self.resampler.flush(&mut self.output);
+                self.flushed = true;
+            }
+            else {
+                self.resampler.process(&self.input[..n*channels], &mut self.output);
+            }
+        }
+        let n: usize = wanted.min(self.output.len());
+        out[..n].copy_from_slice(&self.output[..n]);
+        self.output.drain(..n);
+        self.position += n / channels;
+        Ok(n / channels)
+    }
+
+    fn seek(&mut self, frame: usize) -> Result<(), AudioError> {
+        let source_frame: f64 = frame as f64*self.source.sample_rate() as f64 / self.sample_rate as f64;
+        self.source.seek(source_frame as usize)?;
+        self.resampler = Resampler::new(self.source.channels(), self.source.sample_rate(), self.sample_rate, self.quality);
+        self.output.clear();
+        self.flushed = false;
+        self.position = frame;
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+
+    fn ramp(frames: usize, channels: u16) -> Arc<Sound> {
+        Arc::new(Sound::new(8000, channels, (0..frames*channels as usize).map(|i| (i % 200) as f32 / 200.0 - 0.5).collect()))
+    }
+
+    //Reads the whole source `chunk` frames at a time
+    fn read_all(source: &mut dyn AudioSource, chunk: usize) -> Vec<f32> {
+        let mut buffer: Vec<f32> = vec![0.0; chunk*source.channels() as usize];
+        let mut out: Vec<f32> = Vec::new();
+        loop {
+            let n: usize = source.read(&mut buffer).unwrap();
+            if n == 0 {
+                return out;
+            }
+            out.extend_from_slice(&buffer[..n*source.channels() as usize]);
+        }
+    }
+
+    #[test]
+    fn wav_stream_matches_whole_decode() {
+        for encoding in [WavEncoding::Pcm16, WavEncoding::Float32, WavEncoding::ImaAdpcm] {
+            let data: Vec<u8> = ramp(5000, 2).to_wav(encoding).unwrap();
+            let whole: Sound = Sound::from_wav(&data).unwrap();
+            let mut stream: WavStream<Cursor<Vec<u8>>> = WavStream::new(Cursor::new(data)).unwrap();
+            assert_eq!(stream.frames(), Some(5000), "{:?}", encoding);
+            assert_eq!(read_all(&mut stream, 333), whole.samples, "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn wav_stream_seeks() {
+        let data: Vec<u8> = ramp(5000, 1).to_wav(WavEncoding::ImaAdpcm).unwrap();
+        let whole: Sound = Sound::from_wav(&data).unwrap();
+        let mut stream: WavStream<Cursor<Vec<u8>>> = WavStream::new(Cursor::new(data)).unwrap();
+        stream.seek(4500).unwrap();
+        assert_eq!(stream.position(), 4500);
+        assert_eq!(read_all(&mut stream, 64), whole.samples[4500..]);
+        stream.seek(9999).unwrap();
+        assert_eq!(stream.position(), 5000);
+    }
+
+    #[test]
+    fn wav_stream_rejects_bad_files() {
+        assert!(WavStream::new(Cursor::new(b"RIFF\0\0\0\0AVI LIST".to_vec())).is_err());
+        let mut data: Vec<u8> = ramp(10, 1).to_wav(WavEncoding::Pcm16).unwrap();
+        data[36..40].copy_from_slice(b"junk");
+        assert!(WavStream::new(Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn looping_source_repeats_between_loop_points() {
+        let sound: Arc<Sound> = ramp(10, 1);
+        let mut looping: LoopingSource<SoundSource> = LoopingSource::new(SoundSource::new(sound.clone()))
+            .with_loop_points(2, Some(6))
+            .with_loop_count(2);
+        let out: Vec<f32> = read_all(&mut looping, 3);
+        let expected: Vec<f32> = [&sound.samples[0..6], &sound.samples[2..6], &sound.samples[2..10]].concat();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn looping_an_empty_source_ends() {
+        let mut looping: LoopingSource<SoundSource> = LoopingSource::new(SoundSource::new(Arc::new(Sound::silence(8000, 1, 0))));
+        assert_eq!(looping.frames(), None);
+        let mut buffer: Vec<f32> = vec![0.0; 16];
+        assert_eq!(looping.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn resampled_source_matches_resampled_sound() {
+        let sound: Arc<Sound> = ramp(3000, 2);
+        let mut source: ResampledSource<SoundSource> = ResampledSource::new(SoundSource::new(sound.clone()), 11025, Interpolation::Sinc);
+        assert_eq!((source.sample_rate(), source.frames()), (11025, Some(4135)));
+        let out: Vec<f32> = read_all(&mut source, 500);
+        assert_eq!(out, sound.resampled(11025, Interpolation::Sinc).samples);
+
+        source.seek(0).unwrap();
+        assert_eq!(source.position(), 0);
+        assert_eq!(read_all(&mut source, 500), out);
+    }
+
+}
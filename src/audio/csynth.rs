@@ -0,0 +1,403 @@
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+///Small xorshift generator, so synthesized noise is the same on every run for a given seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Rng {
+        //Zero would stay zero forever
+        Rng { state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    ///Uniform in 0..1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    ///Uniform in 0..`range`.
+    pub fn range(&mut self, range: f32) -> f32 {
+        self.next_f32()*range
+    }
+
+}
+
+///The shape of one cycle of an oscillator.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    ///High for the given fraction of each cycle (0.5 is a square wave).
+    Square(f32),
+    Saw,
+    Triangle,
+    ///One cycle of any shape, played with linear interpolation.
+    Wavetable(Arc<Vec<f32>>),
+}
+
+//Smooths the jump of a band-limited step at phase 0, removing most of the aliasing of naive square and saw waves
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let t: f64 = t / dt;
+        2.0*t - t*t - 1.0
+    }
+    else if t > 1.0 - dt {
+        let t: f64 = (t - 1.0) / dt;
+        t*t + 2.0*t + 1.0
+    }
+    else {
+        0.0
+    }
+}
+
+///A periodic waveform generator. Square and saw waves are band-limited so high notes do not alias.
+#[derive(Clone, Debug)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    ///Cycles per second.
+    pub frequency: f64,
+    ///Position in the current cycle, from 0 to 1.
+    pub phase: f64,
+    pub sample_rate: u32,
+}
+
+impl Oscillator {
+
+    pub fn new(waveform: Waveform, frequency: f64, sample_rate: u32) -> Oscillator {
+        Oscillator { waveform, frequency, phase: 0.0, sample_rate }
+    }
+
+    ///The next sample, in -1..1.
+    pub fn next_sample(&mut self) -> f32 {
+        let dt: f64 = (self.frequency / self.sample_rate.max(1) as f64).abs().min(0.5);
+        let t: f64 = self.phase;
+        let value: f64 = match &self.waveform {
+            Waveform::Sine => (t*TAU).sin(),
+            Waveform::Square(width) => {
+                let width: f64 = (*width as f64).clamp(0.0, 1.0);
+                let mut v: f64 = if t < width { 1.0 } else { -1.0 };
+                v += poly_blep(t, dt);
+                v -= poly_blep((t + 1.0 - width) % 1.0, dt);
+                v
+            }
+            Waveform::Saw => 2.0*t - 1.0 - poly_blep(t, dt),
+            Waveform::Triangle => 1.0 - 4.0*(t - 0.5).abs(),
+            Waveform::Wavetable(table) => {
+                if table.is_empty() {
+                    0.0
+                }
+                else {
+                    let p: f64 = t*table.len() as f64;
+                    let i: usize = p as usize % table.len();
+                    let f: f64 = p - p.floor();
+                    table[i] as f64*(1.0 - f) + table[(i + 1) % table.len()] as f64*f
+                }
+            }
+        };
+        self.phase = (self.phase + self.frequency / self.sample_rate.max(1) as f64).rem_euclid(1.0);
+        value as f32
+    }
+
+    ///Fills `out` with consecutive samples.
+    pub fn render(&mut self, out: &mut [f32]) {
+        for s in out.iter_mut() {
+            *s = self.next_sample();
+        }
+    }
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseColor {
+    ///Equal energy at every frequency; a hiss.
+    White,
+    ///Energy falling 3 dB per octave; a softer rumble, like rain.
+    Pink,
+}
+
+#[derive(Clone, Debug)]
+pub struct Noise {
+    pub color: NoiseColor,
+    rng: Rng,
+    pink: [f32; 7],
+}
+
+impl Noise {
+
+    pub fn new(color: NoiseColor, seed: u64) -> Noise {
+        Noise { color, rng: Rng::new(seed), pink: [0.0; 7] }
+    }
+
+    ///The next sample, roughly in -1..1.
+    pub fn next_sample(&mut self) -> f32 {
+        let white: f32 = self.rng.next_f32()*2.0 - 1.0;
+        match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                //Paul Kellet's filter: a sum of one-pole low-passes approximating a -3 dB/octave slope
+                let b: &mut [f32; 7] = &mut self.pink;
+                b[0] = 0.99886*b[0] + white*0.0555179;
+                b[1] = 0.99332*b[1] + white*0.0750759;
+                b[2] = 0.96900*b[2] + white*0.153852;
+                b[3] = 0.86650*b[3] + white*0.3104856;
+                b[4] = 0.55000*b[4] + white*0.5329522;
+                b[5] = -0.7616*b[5] - white*0.0168980;
+                let pink: f32 = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white*0.5362;
+                b[6] = white*0.115926;
+                pink*0.11
+            }
+        }
+    }
+
+    pub fn render(&mut self, out: &mut [f32]) {
+        for s in out.iter_mut() {
+            *s = self.next_sample();
+        }
+    }
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdsrStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+///Attack-decay-sustain-release envelope. Times are in seconds and `sustain` is a level from 0 to 1.
+#[derive(Clone, Debug)]
+pub struct Adsr {
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f32,
+    pub release: f64,
+    pub sample_rate: u32,
+    stage: AdsrStage,
+    level: f32,
+    release_step: f32,
+}
+
+impl Adsr {
+
+    pub fn new(attack: f64, decay: f64, sustain: f32, release: f64, sample_rate: u32) -> Adsr {
+        Adsr { attack, decay, sustain, release, sample_rate, stage: AdsrStage::Idle, level: 0.0, release_step: 0.0 }
+    }
+
+    ///Starts the attack from the current level, so retriggering a sounding note does not click.
+    pub fn note_on(&mut self) {
+        self.stage = AdsrStage::Attack;
+    }
+
+    pub fn note_off(&mut self) {
+        if self.stage != AdsrStage::Idle {
+            self.stage = AdsrStage::Release;
+            self.release_step = self.level / self.samples(self.release);
+        }
+    }
+
+    fn samples(&self, seconds: f64) -> f32 {
+        (seconds*self.sample_rate as f64).max(1.0) as f32
+    }
+
+    ///The envelope level for the next sample.
+    pub fn next_sample(&mut self) -> f32 {
+        match self.stage {
+            AdsrStage::Idle => {}
+            AdsrStage::Attack => {
+                self.level += 1.0 / self.samples(self.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                self.level -= (1.0 - self.sustain) / self.samples(self.decay);
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Sustain => self.level = self.sustain,
+            AdsrStage::Release => {
+                self.level -= self.release_step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = AdsrStage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn stage(&self) -> AdsrStage {
+        self.stage
+    }
+
+    ///False once the release has finished.
+    pub fn is_active(&self) -> bool {
+        self.stage != AdsrStage::Idle
+    }
+
+}
+
+///Low-frequency oscillator for modulating pitch, volume, filter cutoff and so on. Produces values in
+/// `-depth..depth`, e.g. `osc.frequency = base*(1.0 + lfo.next_sample() as f64)` for vibrato.
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    pub oscillator: Oscillator,
+    pub depth: f32,
+}
+
+impl Lfo {
+
+    pub fn new(waveform: Waveform, rate: f64, depth: f32, sample_rate: u32) -> Lfo {
+        Lfo { oscillator: Oscillator::new(waveform, rate, sample_rate), depth }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        self.oscillator.next_sample()*self.depth
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn rng_is_repeatable_and_in_range() {
+        let mut a: Rng = Rng::new(0);
+        let mut b: Rng = Rng::new(0);
+        let values: Vec<f32> = (0..1000).map(|_| a.next_f32()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(values.iter().any(|v| *v != values[0]));
+        assert!(values.iter().all(|v| *v == b.next_f32()));
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn sine_hits_its_quarter_points() {
+        let mut osc: Oscillator = Oscillator::new(Waveform::Sine, 2.0, 8);
+        let mut out: [f32; 5] = [0.0; 5];
+        osc.render(&mut out);
+        let expected: [f32; 5] = [0.0, 1.0, 0.0, -1.0, 0.0];
+        assert!(out.iter().zip(expected.iter()).all(|(a, b)| close(*a, *b)), "{:?}", out);
+    }
+
+    #[test]
+    fn band_limited_waves_stay_centered_and_bounded() {
+        for waveform in [Waveform::Square(0.5), Waveform::Saw, Waveform::Triangle] {
+            let mut osc: Oscillator = Oscillator::new(waveform.clone(), 441.0, 44100);
+            let mut out: Vec<f32> = vec![0.0; 44100];
+            osc.render(&mut out);
+            let mean: f32 = out.iter().sum::<f32>() / out.len() as f32;
+            assert!(mean.abs() < 0.02, "{:?} mean {}", waveform, mean);
+            assert!(out.iter().all(|s| s.abs() <= 1.0 + 1e-6), "{:?}", waveform);
+        }
+    }
+
+    #[test]
+    fn square_width_sets_the_duty_cycle() {
+        let mut osc: Oscillator = Oscillator::new(Waveform::Square(0.25), 100.0, 44100);
+        let mut out: Vec<f32> = vec![0.0; 44100];
+        osc.render(&mut out);
+        let high: usize = out.iter().filter(|s| **s > 0.0).count();
+        assert!((high as f32 / out.len() as f32 - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn phase_wraps_for_negative_frequencies() {
+        let mut osc: Oscillator = Oscillator::new(Waveform::Saw, -1000.0, 8000);
+        for _ in 0..100 {
+            osc.next_sample();
+            assert!((0.0..1.0).contains(&osc.phase));
+        }
+    }
+
+    #[test]
+    fn wavetable_interpolates_and_wraps() {
+        let mut osc: Oscillator = Oscillator::new(Waveform::Wavetable(Arc::new(vec![0.0, 1.0])), 1.0, 4);
+        let mut out: [f32; 4] = [0.0; 4];
+        osc.render(&mut out);
+        assert_eq!(out, [0.0, 0.5, 1.0, 0.5]);
+
+        let mut empty: Oscillator = Oscillator::new(Waveform::Wavetable(Arc::new(Vec::new())), 1.0, 4);
+        assert_eq!(empty.next_sample(), 0.0);
+    }
+
+    #[test]
+    fn noise_is_seeded_and_bounded() {
+        for color in [NoiseColor::White, NoiseColor::Pink] {
+            let mut a: Vec<f32> = vec![0.0; 10000];
+            let mut b: Vec<f32> = vec![0.0; 10000];
+            Noise::new(color, 7).render(&mut a);
+            Noise::new(color, 7).render(&mut b);
+            assert_eq!(a, b);
+            assert!(a.iter().all(|s| s.abs() <= 1.0), "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn adsr_walks_through_its_stages() {
+        //Every stage takes two samples at 10 Hz
+        let mut env: Adsr = Adsr::new(0.2, 0.2, 0.5, 0.2, 10);
+        assert!(!env.is_active());
+        env.note_on();
+        let levels: Vec<f32> = (0..5).map(|_| env.next_sample()).collect();
+        assert!(levels.iter().zip([0.5, 1.0, 0.75, 0.5, 0.5].iter()).all(|(a, b)| close(*a, *b)), "{:?}", levels);
+        assert_eq!(env.stage(), AdsrStage::Sustain);
+
+        env.note_off();
+        assert!(close(env.next_sample(), 0.25));
+        assert!(close(env.next_sample(), 0.0));
+        assert_eq!(env.stage(), AdsrStage::Idle);
+    }
+
+    #[test]
+    fn adsr_retrigger_starts_from_the_current_level() {
+        let mut env: Adsr = Adsr::new(0.4, 0.0, 1.0, 0.0, 10);
+        env.note_on();
+        env.next_sample();
+        env.note_off();
+        assert_eq!(env.level(), 0.25);
+        env.note_on();
+        assert!(close(env.next_sample(), 0.5));
+    }
+
+    #[test]
+    fn note_off_while_idle_stays_idle() {
+        let mut env: Adsr = Adsr::new(0.1, 0.1, 0.5, 0.1, 100);
+        env.note_off();
+        assert_eq!(env.stage(), AdsrStage::Idle);
+        assert_eq!(env.next_sample(), 0.0);
+    }
+
+    #[test]
+    fn lfo_scales_by_depth() {
+        let mut lfo: Lfo = Lfo::new(Waveform::Triangle, 1.0, 0.25, 4);
+        let out: Vec<f32> = (0..4).map(|_| lfo.next_sample()).collect();
+        assert_eq!(out, vec![-0.25, 0.0, 0.25, 0.0]);
+    }
+
+}
@@ -0,0 +1,581 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Seek, SeekFrom, Write};
+
+use crate::audio::csound::{AudioError, Sound};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_IMA_ADPCM: u16 = 0x11;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+///Bytes per channel in an IMA-ADPCM block written by `WavWriter`.
+const ADPCM_BLOCK_BYTES: usize = 512;
+
+const IMA_INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const IMA_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97,
+    107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871,
+    5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623,
+    27086, 29794, 32767,
+];
+
+///How samples are stored in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavEncoding {
+    ///Unsigned 8-bit PCM.
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Float32,
+    ///4-bit IMA-ADPCM, about a quarter the size of 16-bit PCM.
+    ImaAdpcm,
+}
+
+impl WavEncoding {
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            WavEncoding::Float32 => FORMAT_FLOAT,
+            WavEncoding::ImaAdpcm => FORMAT_IMA_ADPCM,
+            _ => FORMAT_PCM,
+        }
+    }
+
+    //Files that are not plain PCM must give their frame count in a fact chunk
+    fn has_fact(&self) -> bool {
+        !matches!(self, WavEncoding::Pcm8 | WavEncoding::Pcm16 | WavEncoding::Pcm24)
+    }
+
+    fn bits(&self) -> u16 {
+        match self {
+            WavEncoding::Pcm8 => 8,
+            WavEncoding::Pcm16 => 16,
+            WavEncoding::Pcm24 => 24,
+            WavEncoding::Float32 => 32,
+            WavEncoding::ImaAdpcm => 4,
+        }
+    }
+
+}
+
+//Encoder/decoder state for one IMA-ADPCM channel
+#[derive(Clone, Copy, Default)]
+struct ImaState {
+    predictor: i32,
+    index: i32,
+}
+
+impl ImaState {
+
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step: i32 = IMA_STEP_TABLE[self.index as usize];
+        let mut diff: i32 = step >> 3;
+        if nibble & 1 != 0 { diff += step >> 2; }
+        if nibble & 2 != 0 { diff += step >> 1; }
+        if nibble & 4 != 0 { diff += step; }
+        if nibble & 8 != 0 { diff = -diff; }
+        self.predictor = (self.predictor + diff).clamp(-32768, 32767);
+        self.index = (self.index + IMA_INDEX_TABLE[nibble as usize & 15]).clamp(0, 88);
+        self.predictor as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step: i32 = IMA_STEP_TABLE[self.index as usize];
+        let mut diff: i32 = sample as i32 - self.predictor;
+        let mut nibble: u8 = 0;
+        if diff < 0 {
+            nibble = 8;
+            diff = -diff;
+        }
+        let mut s: i32 = step;
+        for bit in [4, 2, 1] {
+            if diff >= s {
+                nibble |= bit;
+                diff -= s;
+            }
+            s >>= 1;
+        }
+        //Decode the nibble so the encoder tracks exactly what a decoder will reconstruct
+        self.decode(nibble);
+        nibble
+    }
+
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0)*32767.0).round() as i16
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, AudioError> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(|| AudioError::Format("truncated WAV header".to_string()))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, AudioError> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| AudioError::Format("truncated WAV header".to_string()))
+}
+
+///The `fmt ` chunk of a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavFormat {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    ///Frames per block, for IMA-ADPCM.
+    pub samples_per_block: u16,
+}
+
+impl WavFormat {
+
+    pub(crate) fn parse(chunk: &[u8]) -> Result<WavFormat, AudioError> {
+        let mut format: WavFormat = WavFormat {
+            format_tag: read_u16(chunk, 0)?,
+            channels: read_u16(chunk, 2)?,
+            sample_rate: read_u32(chunk, 4)?,
+            block_align: read_u16(chunk, 12)?,
+            bits_per_sample: read_u16(chunk, 14)?,
+            samples_per_block: 0,
+        };
+        if format.format_tag == FORMAT_EXTENSIBLE {
+            //The real format is the first two bytes of the sub-format GUID
+            format.format_tag = read_u16(chunk, 24)?;
+        }
+        if format.format_tag == FORMAT_IMA_ADPCM {
+            format.samples_per_block = read_u16(chunk, 18)?;
+        }
+        if format.channels == 0 || format.block_align == 0 {
+            return Err(AudioError::Format("WAV file has no channels".to_string()));
+        }
+        if format.sample_rate == 0 {
+            return Err(AudioError::Format("WAV file has a sample rate of 0".to_string()));
+        }
+        Ok(format)
+    }
+
+    pub fn encoding(&self) -> Result<WavEncoding, AudioError> {
+        match (self.format_tag, self.bits_per_sample) {
+            (FORMAT_PCM, 8) => Ok(WavEncoding::Pcm8),
+            (FORMAT_PCM, 16) => Ok(WavEncoding::Pcm16),
+            (FORMAT_PCM, 24) => Ok(WavEncoding::Pcm24),
+            (FORMAT_FLOAT, 32) => Ok(WavEncoding::Float32),
+            (FORMAT_IMA_ADPCM, 4) => Ok(WavEncoding::ImaAdpcm),
+            (tag, bits) => Err(AudioError::Format(format!("unsupported WAV format {:#x} with {} bits per sample", tag, bits))),
+        }
+    }
+
+}
+
+///Finds the format and the sample data of a RIFF WAVE file. `fact` is the frame count, if the file gives one.
+pub(crate) fn parse_wav(data: &[u8]) -> Result<(WavFormat, &[u8], Option<usize>), AudioError> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(AudioError::Format("not a RIFF WAVE file".to_string()));
+    }
+    let mut format: Option<WavFormat> = None;
+    let mut samples: Option<&[u8]> = None;
+    let mut fact: Option<usize> = None;
+    let mut at: usize = 12;
+    while at + 8 <= data.len() {
+        let id: &[u8] = &data[at..at + 4];
+        let size: usize = read_u32(data, at + 4)? as usize;
+        //A truncated final chunk is read as far as it goes, as many writers leave the data size wrong
+        let body: &[u8] = &data[at + 8..(at + 8).saturating_add(size).min(data.len())];
+        match id {
+            b"fmt " => format = Some(WavFormat::parse(body)?),
+            b"data" => samples = Some(body),
+            b"fact" => fact = Some(read_u32(body, 0)? as usize),
+            _ => {}
+        }
+        //Chunks are padded to an even size
+        at = at.saturating_add(8 + size + (size & 1));
+    }
+    let format: WavFormat = format.ok_or_else(|| AudioError::Format("WAV file has no fmt chunk".to_string()))?;
+    let samples: &[u8] = samples.ok_or_else(|| AudioError::Format("WAV file has no data chunk".to_string()))?;
+    Ok((format, samples, fact))
+}
+
+///Decodes whole frames of `data` to interleaved f32 samples.
+pub(crate) fn decode_samples(format: &WavFormat, data: &[u8]) -> Result<Vec<f32>, AudioError> {
+    let channels: usize = format.channels as usize;
+    let samples: Vec<f32> = match format.encoding()? {
+        WavEncoding::Pcm8 => data.iter().map(|b| (*b as f32 - 128.0) / 128.0).collect(),
+        WavEncoding::Pcm16 => data.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0).collect(),
+        WavEncoding::Pcm24 => data
+            .chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8388608.0)
+            .collect(),
+        WavEncoding::Float32 => data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        WavEncoding::ImaAdpcm => {
+            let mut out: Vec<f32> = Vec::new();
+            for block in data.chunks(format.block_align as usize) {
+                decode_adpcm_block(block, channels, format.samples_per_block as usize, &mut out);
+            }
+            out
+        }
+    };
+    let whole: usize = samples.len() / channels*channels;
+    let mut samples: Vec<f32> = samples;
+    samples.truncate(whole);
+    Ok(samples)
+}
+
+fn decode_adpcm_block(block: &[u8], channels: usize, samples_per_block: usize, out: &mut Vec<f32>) {
+    if block.len() < 4*channels {
+        return;
+    }
+    let mut states: Vec<ImaState> = (0..channels)
+        .map(|c| ImaState {
+            predictor: i16::from_le_bytes([block[4*c], block[4*c + 1]]) as i32,
+            index: (block[4*c + 2] as i32).clamp(0, 88),
+        })
+        .collect();
+    let mut frames: Vec<Vec<i16>> = states.iter().map(|s| vec![s.predictor as i16]).collect();
+    //After the headers, each channel takes turns with 4 bytes (8 samples, low nibble first)
+    for (k, chunk) in block[4*channels..].chunks(4).enumerate() {
+        let c: usize = k % channels;
+        for byte in chunk.iter() {
+            frames[c].push(states[c].decode(byte & 15));
+            frames[c].push(states[c].decode(byte >> 4));
+        }
+    }
+    let count: usize = frames.iter().map(|f| f.len()).min().unwrap_or(0).min(samples_per_block.max(1));
+    for i in 0..count {
+        for channel in frames.iter() {
+            out.push(channel[i] as f32 / 32768.0);
+        }
+    }
+}
+
+impl Sound {
+
+    ///Reads a RIFF WAV file: 8, 16 or 24-bit PCM, 32-bit float or IMA-ADPCM, with any channel count and rate.
+    pub fn from_wav(data: &[u8]) -> Result<Sound, AudioError> {
+        let (format, bytes, fact) = parse_wav(data)?;
+        let mut samples: Vec<f32> = decode_samples(&format, bytes)?;
+        if let Some(frames) = fact {
+            samples.truncate(frames*format.channels as usize);
+        }
+        Ok(Sound::new(format.sample_rate, format.channels, samples))
+    }
+
+    pub fn load_wav(path: &str) -> Result<Sound, AudioError> {
+        Sound::from_wav(&std::fs::read(path)?)
+    }
+
+    pub fn to_wav(&self, encoding: WavEncoding) -> Result<Vec<u8>, AudioError> {
+        let mut writer: WavWriter<Cursor<Vec<u8>>> = WavWriter::new(Cursor::new(Vec::new()), self.sample_rate, self.channels, encoding)?;
+        writer.write(&self.samples)?;
+        Ok(writer.finish()?.into_inner())
+    }
+
+    pub fn save_wav(&self, path: &str, encoding: WavEncoding) -> Result<(), AudioError> {
+        let mut writer: WavWriter<BufWriter<File>> = WavWriter::create(path, self.sample_rate, self.channels, encoding)?;
+        writer.write(&self.samples)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+}
+
+///Writes a WAV file a block of samples at a time. The header sizes are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    out: Option<W>,
+    sample_rate: u32,
+    channels: u16,
+    encoding: WavEncoding,
+    frames: usize,
+    data_bytes: usize,
+    pending: Vec<i16>,              //IMA-ADPCM samples waiting for a full block
+    adpcm: Vec<ImaState>,
+}
+
+impl WavWriter<BufWriter<File>> {
+
+    pub fn create(path: &str, sample_rate: u32, channels: u16, encoding: WavEncoding) -> Result<WavWriter<BufWriter<File>>, AudioError> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate, channels, encoding)
+    }
+
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+
+    pub fn new(mut out: W, sample_rate: u32, channels: u16, encoding: WavEncoding) -> Result<WavWriter<W>, AudioError> {
+        let channels: u16 = channels.max(1);
+        if sample_rate == 0 {
+            return Err(AudioError::Format("cannot write a WAV file with a sample rate of 0".to_string()));
+        }
+        let (block_align, extra): (u64, Vec<u8>) = match encoding {
+            WavEncoding::ImaAdpcm => {
+                let per_block: u16 = (ADPCM_BLOCK_BYTES*2 - 7) as u16;
+                ((ADPCM_BLOCK_BYTES*channels as usize) as u64, [2u16.to_le_bytes(), per_block.to_le_bytes()].concat())
+            }
+            _ => (channels as u64*encoding.bits() as u64 / 8, Vec::new()),
+        };
+        let fmt_size: u32 = 16 + extra.len() as u32;
+        let bytes_per_second: u64 = match encoding {
+            WavEncoding::ImaAdpcm => sample_rate as u64*block_align / (ADPCM_BLOCK_BYTES*2 - 7) as u64,
+            _ => sample_rate as u64*block_align,
+        };
+        //Both fields are fixed-width in the header; anything bigger would be written wrapped around
+        let block_align: u16 = u16::try_from(block_align)
+            .map_err(|_| AudioError::Format(format!("{} channels do not fit in a WAV header", channels)))?;
+        let bytes_per_second: u32 = u32::try_from(bytes_per_second)
+            .map_err(|_| AudioError::Format(format!("{} Hz with {} channels does not fit in a WAV header", sample_rate, channels)))?;
+
+        let mut header: Vec<u8> = Vec::new();
+        header.extend_from_slice(b"RIFF\0\0\0\0WAVEfmt ");
+        header.extend_from_slice(&fmt_size.to_le_bytes());
+        header.extend_from_slice(&encoding.format_tag().to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&bytes_per_second.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&encoding.bits().to_le_bytes());
+        header.extend_from_slice(&extra);
+        if encoding.has_fact() {
+            header.extend_from_slice(b"fact\x04\0\0\0\0\0\0\0");
+        }
+        header.extend_from_slice(b"data\0\0\0\0");
+        out.write_all(&header)?;
+
+        Ok(WavWriter {
+            out: Some(out),
+            sample_rate,
+            channels,
+            encoding,
+            frames: 0,
+            data_bytes: 0,
+            pending: Vec::new(),
+            adpcm: vec![ImaState::default(); channels as usize],
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    ///Frames written so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    ///Appends interleaved samples. A trailing partial frame is dropped.
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        let channels: usize = self.channels as usize;
+        let samples: &[f32] = &samples[..samples.len() / channels*channels];
+        self.frames += samples.len() / channels;
+        let mut bytes: Vec<u8> = Vec::new();
+        match self.encoding {
+            WavEncoding::Pcm8 => bytes.extend(samples.iter().map(|s| ((s.clamp(-1.0, 1.0)*127.0).round() + 128.0) as u8)),
+            WavEncoding::Pcm16 => {
+                for s in samples.iter() {
+                    bytes.extend_from_slice(&to_i16(*s).to_le_bytes());
+                }
+            }
+            WavEncoding::Pcm24 => {
+                for s in samples.iter() {
+                    let v: i32 = (s.clamp(-1.0, 1.0)*8388607.0).round() as i32;
+                    bytes.extend_from_slice(&v.to_le_bytes()[..3]);
+                }
+            }
+            WavEncoding::Float32 => {
+                for s in samples.iter() {
+                    bytes.extend_from_slice(&s.to_le_bytes());
+                }
+            }
+            WavEncoding::ImaAdpcm => {
+                self.pending.extend(samples.iter().map(|s| to_i16(*s)));
+                let block_samples: usize = (ADPCM_BLOCK_BYTES*2 - 7)*channels;
+                while self.pending.len() >= block_samples {
+                    let block: Vec<i16> = self.pending.drain(..block_samples).collect();
+                    self.encode_adpcm_block(&block, &mut bytes);
+                }
+            }
+        }
+        self.write_data(&bytes)
+    }
+
+    fn write_data(&mut self, bytes: &[u8]) -> Result<(), AudioError> {
+        self.data_bytes += bytes.len();
+        match self.out.as_mut() {
+            Some(out) => Ok(out.write_all(bytes)?),
+            None => Err(AudioError::Format("WAV writer is already finished".to_string())),
+        }
+    }
+
+    //Encodes one full block of interleaved samples, padding with silence if short
+    fn encode_adpcm_block(&mut self, block: &[i16], bytes: &mut Vec<u8>) {
+        let channels: usize = self.channels as usize;
+        let per_block: usize = ADPCM_BLOCK_BYTES*2 - 7;
+        let sample = |frame: usize, c: usize| block.get(frame*channels + c).copied().unwrap_or(0);
+        for c in 0..channels {
+            //The first sample of each block is stored whole in the header
+            self.adpcm[c].predictor = sample(0, c) as i32;
+            bytes.extend_from_slice(&sample(0, c).to_le_bytes());
+            bytes.push(self.adpcm[c].index as u8);
+            bytes.push(0);
+        }
+        for group in 0..(per_block - 1) / 8 {
+            for c in 0..channels {
+                for pair in 0..4 {
+                    let frame: usize = 1 + group*8 + pair*2;
+                    let lo: u8 = self.adpcm[c].encode(sample(frame, c));
+                    let hi: u8 = self.adpcm[c].encode(sample(frame + 1, c));
+                    bytes.push(lo | (hi << 4));
+                }
+            }
+        }
+    }
+
+    ///Writes any buffered samples, fills in the header sizes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, AudioError> {
+        self.flush_header()?;
+        Ok(self.out.take().unwrap())
+    }
+
+    fn flush_header(&mut self) -> Result<(), AudioError> {
+        if self.out.is_none() {
+            return Ok(());
+        }
+        if !self.pending.is_empty() {
+            let block: Vec<i16> = std::mem::take(&mut self.pending);
+            let mut bytes: Vec<u8> = Vec::new();
+            self.encode_adpcm_block(&block, &mut bytes);
+            self.write_data(&bytes)?;
+        }
+        if self.data_bytes & 1 == 1 {
+            self.out.as_mut().unwrap().write_all(&[0])?;
+        }
+        let has_fact: bool = self.encoding.has_fact();
+        let fmt_size: usize = if self.encoding == WavEncoding::ImaAdpcm { 20 } else { 16 };
+        let data_at: usize = 12 + 8 + fmt_size + if has_fact { 12 } else { 0 };
+        let riff_size: usize = data_at + 8 + self.data_bytes + (self.data_bytes & 1) - 8;
+
+        let out: &mut W = self.out.as_mut().unwrap();
+        out.seek(SeekFrom::Start(4))?;
+        out.write_all(&(riff_size as u32).to_le_bytes())?;
+        if has_fact {
+            out.seek(SeekFrom::Start((data_at - 4) as u64))?;
+            out.write_all(&(self.frames as u32).to_le_bytes())?;
+        }
+        out.seek(SeekFrom::Start((data_at + 4) as u64))?;
+        out.write_all(&(self.data_bytes as u32).to_le_bytes())?;
+        out.seek(SeekFrom::End(0))?;
+        out.flush()?;
+        Ok(())
+    }
+
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        //Best effort; call finish to see errors
+        let _ = self.flush_header();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn sine(frames: usize, channels: u16) -> Sound {
+        let samples: Vec<f32> = (0..frames*channels as usize)
+            .map(|i| {
+                let (frame, c) = (i / channels as usize, i % channels as usize);
+                (frame as f32*0.05 + c as f32).sin()*0.8
+            })
+            .collect();
+        Sound::new(8000, channels, samples)
+    }
+
+    fn max_error(a: &Sound, b: &Sound) -> f32 {
+        a.samples.iter().zip(b.samples.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+    }
+
+    fn round_trip(sound: &Sound, encoding: WavEncoding) -> Sound {
+        Sound::from_wav(&sound.to_wav(encoding).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pcm_and_float_round_trip() {
+        let sound: Sound = sine(100, 2);
+        for (encoding, tolerance) in [(WavEncoding::Pcm8, 1.0 / 100.0), (WavEncoding::Pcm16, 1e-4), (WavEncoding::Pcm24, 1e-6), (WavEncoding::Float32, 0.0)] {
+            let back: Sound = round_trip(&sound, encoding);
+            assert_eq!((back.sample_rate, back.channels, back.frames()), (8000, 2, 100), "{:?}", encoding);
+            assert!(max_error(&sound, &back) <= tolerance, "{:?} error {}", encoding, max_error(&sound, &back));
+        }
+    }
+
+    #[test]
+    fn adpcm_round_trip_keeps_the_frame_count() {
+        //Longer than one block so the predictor is reset at a block boundary
+        for channels in [1, 2] {
+            let sound: Sound = sine(1500, channels);
+            let back: Sound = round_trip(&sound, WavEncoding::ImaAdpcm);
+            assert_eq!((back.channels, back.frames()), (channels, 1500));
+            //The step size starts at its smallest, so the first few samples lag behind a steep signal
+            let skip: usize = 16*channels as usize;
+            let error: f32 = sound.samples[skip..].iter().zip(back.samples[skip..].iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max);
+            assert!(error < 0.02, "error {}", error);
+            assert!((sound.samples[0] - back.samples[0]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn odd_data_is_padded() {
+        let data: Vec<u8> = sine(3, 1).to_wav(WavEncoding::Pcm8).unwrap();
+        assert_eq!(data.len() % 2, 0);
+        assert_eq!(read_u32(&data, 4).unwrap() as usize, data.len() - 8);
+        assert_eq!(Sound::from_wav(&data).unwrap().frames(), 3);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let good: Vec<u8> = sine(4, 1).to_wav(WavEncoding::Pcm16).unwrap();
+        assert!(Sound::from_wav(b"RIFX\0\0\0\0WAVE").is_err());
+        assert!(Sound::from_wav(&good[..36]).is_err());
+
+        let mut zero_rate: Vec<u8> = good.clone();
+        zero_rate[24..28].copy_from_slice(&0u32.to_le_bytes());
+        assert!(Sound::from_wav(&zero_rate).is_err());
+
+        let mut odd_bits: Vec<u8> = good.clone();
+        odd_bits[34..36].copy_from_slice(&12u16.to_le_bytes());
+        assert!(Sound::from_wav(&odd_bits).is_err());
+    }
+
+    #[test]
+    fn truncated_data_chunk_is_read_as_far_as_it_goes() {
+        let good: Vec<u8> = sine(4, 1).to_wav(WavEncoding::Pcm16).unwrap();
+        //Cut through the last sample so only whole frames remain
+        let sound: Sound = Sound::from_wav(&good[..good.len() - 3]).unwrap();
+        assert_eq!(sound.frames(), 2);
+    }
+
+    #[test]
+    fn writer_rejects_headers_that_do_not_fit() {
+        let out = || Cursor::new(Vec::new());
+        assert!(WavWriter::new(out(), 0, 1, WavEncoding::Pcm16).is_err());
+        assert!(WavWriter::new(out(), 44100, u16::MAX, WavEncoding::Float32).is_err());
+        assert!(WavWriter::new(out(), u32::MAX, 2, WavEncoding::Pcm16).is_err());
+        assert!(WavWriter::new(out(), 44100, 2, WavEncoding::Pcm16).is_ok());
+    }
+
+    #[test]
+    fn writer_drops_partial_frames() {
+        let mut writer: WavWriter<Cursor<Vec<u8>>> = WavWriter::new(Cursor::new(Vec::new()), 8000, 2, WavEncoding::Float32).unwrap();
+        writer.write(&[0.1, 0.2, 0.3]).unwrap();
+        writer.write(&[0.4, 0.5]).unwrap();
+        assert_eq!(writer.frames(), 2);
+        let sound: Sound = Sound::from_wav(&writer.finish().unwrap().into_inner()).unwrap();
+        assert_eq!(sound.samples, vec![0.1, 0.2, 0.4, 0.5]);
+    }
+
+}
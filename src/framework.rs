@@ -1 +1,11 @@
+pub mod cactions;
+pub mod capp;
+pub mod ckey;
+#[cfg(feature = "minifb")]
+pub mod cminifb;
+pub mod cmouse;
+pub mod cpresenter;
+pub mod cprofile;
+pub mod crecord;
+pub mod ctime;
 pub mod cwin;
\ No newline at end of file
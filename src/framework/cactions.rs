@@ -0,0 +1,508 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use crate::framework::ckey::{CKey, Keyboard, Modifiers};
+use crate::framework::cmouse::{Mouse, MouseButton};
+use crate::framework::cwin::CWin;
+
+///Something the player can press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    Key(CKey),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+}
+
+///A source plus the modifier keys that must be held with it, e.g. Ctrl+S.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub source: Source,
+    pub modifiers: Modifiers,
+}
+
+impl Binding {
+
+    pub fn key(key: CKey) -> Binding {
+        Binding { source: Source::Key(key), modifiers: Modifiers::NONE }
+    }
+
+    pub fn mouse(button: MouseButton) -> Binding {
+        Binding { source: Source::Mouse(button), modifiers: Modifiers::NONE }
+    }
+
+    pub fn with_modifiers(self, modifiers: Modifiers) -> Binding {
+        Binding { modifiers, ..self }
+    }
+
+}
+
+///One input feeding an axis action.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisBinding {
+    ///-1 while `negative` is held, +1 while `positive` is held, 0 for both or neither.
+    Pair(Binding, Binding),
+    ///Horizontal mouse motion in raster pixels per frame, times the scale.
+    MouseX(f64),
+    MouseY(f64),
+    ///Vertical wheel scroll per frame, times the scale.
+    Wheel(f64),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ButtonAction {
+    pub bindings: Vec<Binding>,
+    down: bool,
+    was_down: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AxisAction {
+    pub bindings: Vec<AxisBinding>,
+    ///Values with a magnitude below this read as 0, and the rest of the range is rescaled to start from 0.
+    pub dead_zone: f64,
+    value: f64,
+}
+
+///Maps named actions such as "jump" or "move_x" to keys and mouse input. Call `update` once per frame after the
+/// window has polled its input, then query actions by name.
+#[derive(Clone, Debug, Default)]
+pub struct InputMap {
+    pub buttons: BTreeMap<String, ButtonAction>,
+    pub axes: BTreeMap<String, AxisAction>,
+}
+
+impl InputMap {
+
+    pub fn new() -> InputMap {
+        InputMap { buttons: BTreeMap::new(), axes: BTreeMap::new() }
+    }
+
+    ///Adds a binding to a digital action, creating the action if needed.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let a: &mut ButtonAction = self.buttons.entry(action.to_string()).or_default();
+        if !a.bindings.contains(&binding) {
+            a.bindings.push(binding);
+        }
+    }
+
+    pub fn bind_axis(&mut self, action: &str, binding: AxisBinding) {
+        self.axes.entry(action.to_string()).or_default().bindings.push(binding);
+    }
+
+    pub fn set_dead_zone(&mut self, action: &str, dead_zone: f64) {
+        self.axes.entry(action.to_string()).or_default().dead_zone = dead_zone.clamp(0.0, 0.99);
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(a) = self.buttons.get_mut(action) {
+            a.bindings.retain(|b| *b != binding);
+        }
+    }
+
+    ///Replaces all bindings of a digital action with `binding`.
+    pub fn rebind(&mut self, action: &str, binding: Binding) {
+        self.buttons.entry(action.to_string()).or_default().bindings = vec![binding];
+    }
+
+    pub fn clear_bindings(&mut self, action: &str) {
+        if let Some(a) = self.buttons.get_mut(action) {
+            a.bindings.clear();
+        }
+        if let Some(a) = self.axes.get_mut(action) {
+            a.bindings.clear();
+        }
+    }
+
+    ///Recomputes every action from the window's keyboard and mouse.
+    pub fn update(&mut self, win: &CWin) {
+        self.update_from(&win.keyboard, &win.mouse);
+    }
+
+    pub fn update_from(&mut self, keyboard: &Keyboard, mouse: &Mouse) {
+        let state: InputState = InputState::new(keyboard, mouse, self.all_bindings());
+        for action in self.buttons.values_mut() {
+            action.was_down = action.down;
+            action.down = action.bindings.iter().any(|b| state.is_active(b));
+        }
+        for action in self.axes.values_mut() {
+            let mut value: f64 = 0.0;
+            for binding in action.bindings.iter() {
+                value += match *binding {
+                    AxisBinding::Pair(neg, pos) => state.is_active(&pos) as i32 as f64 - state.is_active(&neg) as i32 as f64,
+                    AxisBinding::MouseX(scale) => mouse.delta().0*scale,
+                    AxisBinding::MouseY(scale) => mouse.delta().1*scale,
+                    AxisBinding::Wheel(scale) => mouse.wheel().1*scale,
+                };
+            }
+            let dz: f64 = action.dead_zone;
+            action.value = if value.abs() <= dz { 0.0 } else { value.signum()*(value.abs() - dz) / (1.0 - dz) };
+        }
+    }
+
+    pub fn is_down(&self, action: &str) -> bool {
+        self.buttons.get(action).is_some_and(|a| a.down)
+    }
+
+    ///True only on the frame the action became active.
+    pub fn was_pressed(&self, action: &str) -> bool {
+        self.buttons.get(action).is_some_and(|a| a.down && !a.was_down)
+    }
+
+    pub fn was_released(&self, action: &str) -> bool {
+        self.buttons.get(action).is_some_and(|a| !a.down && a.was_down)
+    }
+
+    ///Current value of an axis action, 0 if there is no such action. Key pairs give -1 to 1; mouse and wheel axes
+    /// are unbounded.
+    pub fn axis(&self, action: &str) -> f64 {
+        self.axes.get(action).map_or(0.0, |a| a.value)
+    }
+
+    ///For rebinding screens: the first key or mouse button pressed this frame, with the modifiers held alongside it.
+    /// Modifier keys on their own are ignored so that chords can be captured.
+    pub fn capture(win: &CWin) -> Option<Binding> {
+        let modifiers: Modifiers = win.keyboard.modifiers();
+        let mut keys: Vec<CKey> = win.keyboard.keys_pressed();
+        keys.sort();
+        if let Some(key) = keys.into_iter().find(|k| !is_modifier(*k)) {
+            return Some(Binding::key(key).with_modifiers(modifiers));
+        }
+        MouseButton::ALL
+            .iter()
+            .find(|b| win.mouse.was_clicked(**b))
+            .map(|b| Binding::mouse(*b).with_modifiers(modifiers))
+    }
+
+    ///Parses a config with one action per line:
+    ///
+    ///     button jump = Space, Mouse:Left
+    ///     button save = Ctrl+S
+    ///     axis move_x = A/D, Left/Right
+    ///     axis look_x deadzone=0.1 = Mouse:X*0.02
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_config(text: &str) -> Result<InputMap, String> {
+        let mut map: InputMap = InputMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| format!("[InputMap] line {}: {}", n + 1, msg);
+            let (head, rest) = line.split_once('=').ok_or_else(|| err("expected '='".to_string()))?;
+            //"deadzone=0.1" also contains '=', so rejoin it with the head when present
+            let (head, rest) = match rest.split_once('=') {
+                Some((dz, bindings)) if head.trim_end().ends_with("deadzone") => (format!("{}={}", head, dz), bindings),
+                _ => (head.to_string(), rest),
+            };
+            let words: Vec<&str> = head.split_whitespace().collect();
+            let bindings = rest.split(',').map(str::trim).filter(|b| !b.is_empty());
+            match words.as_slice() {
+                ["button", name] => {
+                    map.buttons.entry(name.to_string()).or_default();
+                    for b in bindings {
+                        map.bind(name, b.parse().map_err(err)?);
+                    }
+                }
+                ["axis", name, options @ ..] => {
+                    map.axes.entry(name.to_string()).or_default();
+                    for option in options {
+                        let dz: f64 = option
+                            .strip_prefix("deadzone=")
+                            .and_then(|v| v.parse().ok())
+                            .ok_or_else(|| err(format!("unknown axis option '{}'", option)))?;
+                        map.set_dead_zone(name, dz);
+                    }
+                    for b in bindings {
+                        map.bind_axis(name, b.parse().map_err(err)?);
+                    }
+                }
+                _ => return Err(err("expected 'button <name>' or 'axis <name>'".to_string())),
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut out: String = String::new();
+        for (name, action) in self.buttons.iter() {
+            let list: Vec<String> = action.bindings.iter().map(|b| b.to_string()).collect();
+            out.push_str(&format!("button {} = {}\n", name, list.join(", ")));
+        }
+        for (name, action) in self.axes.iter() {
+            let list: Vec<String> = action.bindings.iter().map(|b| b.to_string()).collect();
+            let dz: String = if action.dead_zone > 0.0 { format!(" deadzone={}", action.dead_zone) } else { String::new() };
+            out.push_str(&format!("axis {}{} = {}\n", name, dz, list.join(", ")));
+        }
+        out
+    }
+
+    pub fn load(path: &str) -> Result<InputMap, String> {
+        let text: String = fs::read_to_string(path).map_err(|e| format!("[InputMap] {}: {}", path, e))?;
+        InputMap::from_config(&text)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_config()).map_err(|e| format!("[InputMap] {}: {}", path, e))
+    }
+
+    fn all_bindings(&self) -> Vec<Binding> {
+        let mut all: Vec<Binding> = Vec::new();
+        for action in self.buttons.values() {
+            all.extend(action.bindings.iter().copied());
+        }
+        for action in self.axes.values() {
+            for binding in action.bindings.iter() {
+                if let AxisBinding::Pair(neg, pos) = binding {
+                    all.push(*neg);
+                    all.push(*pos);
+                }
+            }
+        }
+        all
+    }
+
+}
+
+//Resolves which bindings are active this frame. When a chord such as Ctrl+S is held, bindings of the same source
+//with fewer modifiers (plain S) are suppressed so that the most specific chord wins.
+struct InputState<'a> {
+    keyboard: &'a Keyboard,
+    mouse: &'a Mouse,
+    modifiers: Modifiers,
+    most_specific: HashMap<Source, Modifiers>,
+}
+
+impl<'a> InputState<'a> {
+
+    fn new(keyboard: &'a Keyboard, mouse: &'a Mouse, bindings: Vec<Binding>) -> InputState<'a> {
+        let modifiers: Modifiers = keyboard.modifiers();
+        let mut most_specific: HashMap<Source, Modifiers> = HashMap::new();
+        for b in bindings.iter().filter(|b| contains(modifiers, b.modifiers)) {
+            let best: &mut Modifiers = most_specific.entry(b.source).or_insert(b.modifiers);
+            if count(b.modifiers) > count(*best) {
+                *best = b.modifiers;
+            }
+        }
+        InputState { keyboard, mouse, modifiers, most_specific }
+    }
+
+    fn is_active(&self, binding: &Binding) -> bool {
+        let held: bool = match binding.source {
+            Source::Key(k) => self.keyboard.is_key_down(k),
+            Source::Mouse(b) => self.mouse.is_down(b),
+            Source::WheelUp => self.mouse.wheel().1 > 0.0,
+            Source::WheelDown => self.mouse.wheel().1 < 0.0,
+        };
+        held && contains(self.modifiers, binding.modifiers)
+            && self.most_specific.get(&binding.source).is_none_or(|m| count(*m) <= count(binding.modifiers))
+    }
+
+}
+
+fn is_modifier(key: CKey) -> bool {
+    matches!(key,
+        CKey::LeftShift | CKey::RightShift | CKey::LeftCtrl | CKey::RightCtrl |
+        CKey::LeftAlt | CKey::RightAlt | CKey::LeftSuper | CKey::RightSuper)
+}
+
+//Whether every modifier in `needed` is in `held`
+fn contains(held: Modifiers, needed: Modifiers) -> bool {
+    (held.shift || !needed.shift) && (held.ctrl || !needed.ctrl) && (held.alt || !needed.alt) && (held.logo || !needed.logo)
+}
+
+fn count(m: Modifiers) -> u32 {
+    m.shift as u32 + m.ctrl as u32 + m.alt as u32 + m.logo as u32
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m: Modifiers = self.modifiers;
+        for (held, name) in [(m.ctrl, "Ctrl+"), (m.shift, "Shift+"), (m.alt, "Alt+"), (m.logo, "Super+")] {
+            if held {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.source {
+            Source::Key(k) => write!(f, "{}", k.name()),
+            Source::Mouse(b) => write!(f, "Mouse:{:?}", b),
+            Source::WheelUp => write!(f, "Wheel:Up"),
+            Source::WheelDown => write!(f, "Wheel:Down"),
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    ///Parses bindings written as by `Display`, such as `Space`, `Ctrl+Shift+S`, `Mouse:Right` or `Wheel:Up`.
+    fn from_str(s: &str) -> Result<Binding, String> {
+        let mut parts: Vec<&str> = s.trim().split('+').map(str::trim).collect();
+        let source: &str = parts.pop().unwrap_or("");
+        let mut modifiers: Modifiers = Modifiers::NONE;
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                "super" => modifiers.logo = true,
+                _ => return Err(format!("unknown modifier '{}'", part)),
+            }
+        }
+        let source: Source = match source.to_ascii_lowercase().as_str() {
+            "mouse:left" => Source::Mouse(MouseButton::Left),
+            "mouse:middle" => Source::Mouse(MouseButton::Middle),
+            "mouse:right" => Source::Mouse(MouseButton::Right),
+            "wheel:up" => Source::WheelUp,
+            "wheel:down" => Source::WheelDown,
+            _ => Source::Key(CKey::from_name(source).ok_or_else(|| format!("unknown key '{}'", source))?),
+        };
+        Ok(Binding { source, modifiers })
+    }
+}
+
+impl fmt::Display for AxisBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AxisBinding::Pair(neg, pos) => write!(f, "{}/{}", neg, pos),
+            AxisBinding::MouseX(scale) => write!(f, "Mouse:X*{}", scale),
+            AxisBinding::MouseY(scale) => write!(f, "Mouse:Y*{}", scale),
+            AxisBinding::Wheel(scale) => write!(f, "Wheel*{}", scale),
+        }
+    }
+}
+
+impl FromStr for AxisBinding {
+    type Err = String;
+
+    ///Parses `negative/positive` key pairs such as `A/D`, or `Mouse:X`, `Mouse:Y` and `Wheel` with an optional
+    /// `*scale`.
+    fn from_str(s: &str) -> Result<AxisBinding, String> {
+        if let Some((neg, pos)) = s.split_once('/') {
+            return Ok(AxisBinding::Pair(neg.parse()?, pos.parse()?));
+        }
+        let (name, scale) = match s.split_once('*') {
+            Some((name, scale)) => (name.trim(), scale.trim().parse::<f64>().map_err(|_| format!("bad scale in '{}'", s))?),
+            None => (s.trim(), 1.0),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "mouse:x" => Ok(AxisBinding::MouseX(scale)),
+            "mouse:y" => Ok(AxisBinding::MouseY(scale)),
+            "wheel" => Ok(AxisBinding::Wheel(scale)),
+            _ => Err(format!("unknown axis binding '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const CONFIG: &str = "# controls
+button jump = Space, Mouse:Left
+button save = Ctrl+S
+button zoom_in = Wheel:Up
+
+axis move_x = A/D, Left/Right
+axis look_x deadzone=0.1 = Mouse:X*0.02
+";
+
+    fn frame(map: &mut InputMap, keys: &[CKey], mouse_x: f64, wheel: f64) {
+        let mut keyboard: Keyboard = Keyboard::new();
+        keyboard.update(keys, &[], 0.016);
+        let mut mouse: Mouse = Mouse::new();
+        mouse.update(Some((0.0, 0.0)), true, [false; 3], (0.0, 0.0));
+        mouse.update(Some((mouse_x, 0.0)), true, [false; 3], (0.0, wheel));
+        map.update_from(&keyboard, &mouse);
+    }
+
+    #[test]
+    fn binding_round_trip() {
+        for text in ["Space", "Ctrl+Shift+S", "Alt+Super+F4", "Mouse:Right", "Ctrl+Wheel:Down"] {
+            assert_eq!(text.parse::<Binding>().unwrap().to_string(), text);
+        }
+        assert_eq!("shift+ctrl+s".parse::<Binding>().unwrap().to_string(), "Ctrl+Shift+S");
+        for text in ["A/D", "Mouse:X*0.5", "Mouse:Y*-1", "Wheel*2"] {
+            assert_eq!(text.parse::<AxisBinding>().unwrap().to_string(), text);
+        }
+        assert_eq!("wheel".parse::<AxisBinding>().unwrap(), AxisBinding::Wheel(1.0));
+
+        assert!("Hyper+S".parse::<Binding>().is_err());
+        assert!("Ctrl+".parse::<Binding>().is_err());
+        assert!("Mouse:X*fast".parse::<AxisBinding>().is_err());
+        assert!("A/Nope".parse::<AxisBinding>().is_err());
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let map: InputMap = InputMap::from_config(CONFIG).unwrap();
+        assert_eq!(map.buttons["jump"].bindings, vec![Binding::key(CKey::Space), Binding::mouse(MouseButton::Left)]);
+        assert_eq!(map.axes["look_x"].dead_zone, 0.1);
+        let text: String = map.to_config();
+        assert_eq!(InputMap::from_config(&text).unwrap().to_config(), text);
+        assert!(text.contains("axis look_x deadzone=0.1 = Mouse:X*0.02\n"));
+
+        //Actions without bindings survive a round trip too
+        let empty: InputMap = InputMap::from_config("button pause =\n").unwrap();
+        assert_eq!(empty.to_config(), "button pause = \n");
+        assert_eq!(InputMap::from_config(&empty.to_config()).unwrap().buttons.len(), 1);
+    }
+
+    #[test]
+    fn config_errors() {
+        let err = |text: &str| InputMap::from_config(text).unwrap_err();
+        assert!(err("\nbutton jump Space").starts_with("[InputMap] line 2:"));
+        assert!(err("trigger fire = Space").contains("expected 'button"));
+        assert!(err("button jump = Spacebar").contains("unknown key"));
+        assert!(err("axis x speed=2 = A/D").contains("unknown axis option"));
+    }
+
+    #[test]
+    fn buttons_and_chords() {
+        let mut map: InputMap = InputMap::from_config(CONFIG).unwrap();
+        map.bind("stop", Binding::key(CKey::S));
+
+        frame(&mut map, &[CKey::S], 0.0, 0.0);
+        assert!(map.was_pressed("stop") && !map.is_down("save"));
+        //Holding Ctrl turns S into the more specific Ctrl+S chord
+        frame(&mut map, &[CKey::S, CKey::RightCtrl], 0.0, 0.0);
+        assert!(map.was_pressed("save") && !map.is_down("stop") && map.was_released("stop"));
+
+        frame(&mut map, &[], 0.0, 1.0);
+        assert!(map.is_down("zoom_in"));
+        assert!(!map.is_down("missing") && !map.was_pressed("missing"));
+
+        map.rebind("jump", Binding::key(CKey::W));
+        frame(&mut map, &[CKey::Space], 0.0, 0.0);
+        assert!(!map.is_down("jump"));
+        map.unbind("jump", Binding::key(CKey::W));
+        map.clear_bindings("move_x");
+        assert!(map.buttons["jump"].bindings.is_empty() && map.axes["move_x"].bindings.is_empty());
+    }
+
+    #[test]
+    fn axes() {
+        let mut map: InputMap = InputMap::from_config(CONFIG).unwrap();
+        frame(&mut map, &[CKey::D], 0.0, 0.0);
+        assert_eq!(map.axis("move_x"), 1.0);
+        frame(&mut map, &[CKey::A, CKey::D], 0.0, 0.0);
+        assert_eq!(map.axis("move_x"), 0.0);
+        frame(&mut map, &[CKey::A, CKey::Left], 0.0, 0.0);
+        assert_eq!(map.axis("move_x"), -2.0);
+
+        //Motion below the dead zone reads 0, and the rest of the range is rescaled from it
+        frame(&mut map, &[], 4.0, 0.0);
+        assert_eq!(map.axis("look_x"), 0.0);
+        frame(&mut map, &[], 50.0, 0.0);
+        assert!((map.axis("look_x") - 1.0).abs() < 1e-12);
+        assert_eq!(map.axis("missing"), 0.0);
+
+        map.set_dead_zone("look_x", 5.0);
+        assert_eq!(map.axes["look_x"].dead_zone, 0.99);
+    }
+
+}
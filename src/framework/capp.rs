@@ -0,0 +1,297 @@
+use crate::framework::cprofile::Profiler;
+use crate::framework::ctime::FrameTimer;
+use crate::framework::cwin::{CWin, WinEvent};
+use crate::graphics::cren::CRen;
+
+///A program driven by `Runner`. `update` advances the simulation by exactly `dt` seconds, however fast frames are
+/// drawn; `render` draws into `runner.ren`, with `alpha` (0 to 1) saying how far the present lies between the
+/// last update and the next, for interpolating positions.
+pub trait App {
+    fn init(&mut self, _runner: &mut Runner) {}
+
+    fn update(&mut self, runner: &mut Runner, dt: f64);
+
+    fn render(&mut self, runner: &mut Runner, alpha: f64);
+
+    ///Called for every window event, before the frame's updates.
+    fn event(&mut self, _runner: &mut Runner, _event: WinEvent) {}
+
+    fn shutdown(&mut self, _runner: &mut Runner) {}
+}
+
+///Shortest update step accepted, so a zero or negative `fixed_dt` cannot stall or break the update loop.
+const MIN_FIXED_DT: f64 = 1e-4;
+
+///Owns the window, renderer and clock and runs an `App` with a fixed-timestep update and a variable render rate.
+pub struct Runner {
+    pub win: CWin,
+    pub ren: CRen,
+    ///Statistics of the time between frames.
+    pub timer: FrameTimer,
+    ///Seconds per update, at least 0.1 ms.
+    pub fixed_dt: f64,
+    ///Most updates run for one rendered frame. When the simulation falls further behind than this, the missing
+    /// time is dropped and the game slows down instead of spiralling. At least 1.
+    pub max_frame_skip: usize,
+    ///Stop updating while the window is not focused.
+    pub pause_on_focus_loss: bool,
+    accumulator: f64,
+    time: f64,
+    ticks: u64,
+    frames: u64,
+    paused: bool,
+    quit: bool,
+}
+
+impl Runner {
+
+    pub fn new(win: CWin, ren: CRen) -> Runner {
+        Runner {
+            win,
+            ren,
+            timer: FrameTimer::default(),
+            fixed_dt: 1.0 / 60.0,
+            max_frame_skip: 5,
+            pause_on_focus_loss: true,
+            accumulator: 0.0,
+            time: 0.0,
+            ticks: 0,
+            frames: 0,
+            paused: false,
+            quit: false,
+        }
+    }
+
+    pub fn fixed_dt(mut self, dt: f64) -> Runner {
+        self.fixed_dt = dt.max(MIN_FIXED_DT);
+        self
+    }
+
+    ///Sets the update rate, in updates per second.
+    pub fn update_rate(mut self, hz: f64) -> Runner {
+        self.fixed_dt = (1.0 / hz).max(MIN_FIXED_DT);
+        self
+    }
+
+    pub fn max_frame_skip(mut self, updates: usize) -> Runner {
+        self.max_frame_skip = updates.max(1);
+        self
+    }
+
+    pub fn pause_on_focus_loss(mut self, pause: bool) -> Runner {
+        self.pause_on_focus_loss = pause;
+        self
+    }
+
+    ///Runs `app` until the window closes or `quit` is called, then calls its `shutdown`.
+    pub fn run(&mut self, app: &mut dyn App) {
+        app.init(self);
+        while self.win.is_open() && !self.quit {
+            self.frame(app);
+        }
+        app.shutdown(self);
+    }
+
+    ///Runs the updates for one frame, then renders and presents it. Each frame is a profiler frame.
+    pub fn frame(&mut self, app: &mut dyn App) {
+        Profiler::begin_frame();
+        for event in self.win.poll_events() {
+            match event {
+                WinEvent::FocusLost => self.paused = self.pause_on_focus_loss,
+                //Time spent unfocused is not made up for
+                WinEvent::FocusGained => {
+                    self.paused = false;
+                    self.accumulator = 0.0;
+                }
+                _ => {}
+            }
+            app.event(self, event);
+        }
+
+        //The fields are public, so clamp them again in case they were set directly
+        self.fixed_dt = self.fixed_dt.max(MIN_FIXED_DT);
+        self.max_frame_skip = self.max_frame_skip.max(1);
+        if !self.paused {
+            self.accumulator += self.win.frame_dt();
+            let mut updates: usize = 0;
+            while self.accumulator >= self.fixed_dt && updates < self.max_frame_skip {
+                crate::profile_scope!("update");
+                app.update(self, self.fixed_dt);
+                self.accumulator -= self.fixed_dt;
+                self.time += self.fixed_dt;
+                self.ticks += 1;
+                updates += 1;
+            }
+            if self.accumulator >= self.fixed_dt {
+                self.accumulator %= self.fixed_dt;
+            }
+        }
+
+        let alpha: f64 = (self.accumulator / self.fixed_dt).clamp(0.0, 1.0);
+        {
+            crate::profile_scope!("render");
+            app.render(self, alpha);
+        }
+        self.win.draw(&self.ren.raster);
+        self.timer.record(self.win.frame_dt());
+        self.frames += 1;
+        Profiler::end_frame();
+    }
+
+    ///Stops `run` after the current frame.
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+
+    ///Simulated seconds, advanced only by updates.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    ///Number of updates so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    ///Number of rendered frames so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::framework::cpresenter::HeadlessBackend;
+    
+    #[derive(Default)]
+    struct Counter {
+        updates: u32,
+        alphas: Vec<f64>,
+        events: Vec<WinEvent>,
+        quit_after: Option<u32>,
+        shut_down: bool,
+    }
+
+    impl App for Counter {
+
+        fn update(&mut self, runner: &mut Runner, _dt: f64) {
+            self.updates += 1;
+            if self.quit_after == Some(self.updates) {
+                runner.quit();
+            }
+        }
+
+        fn render(&mut self, _runner: &mut Runner, alpha: f64) {
+            self.alphas.push(alpha);
+        }
+
+        fn event(&mut self, _runner: &mut Runner, event: WinEvent) {
+            self.events.push(event);
+        }
+
+        fn shutdown(&mut self, _runner: &mut Runner) {
+            self.shut_down = true;
+        }
+
+    }
+
+    //A headless runner whose frames each take `dt` seconds
+    fn headless_runner(dt: f64) -> Runner {
+        let ren: CRen = CRen::new(4, 4);
+        let mut win: CWin = CWin::headless(&ren.raster);
+        win.backend_mut::<HeadlessBackend>().unwrap().dt = dt;
+        Runner::new(win, ren)
+    }
+
+    fn headless(runner: &mut Runner) -> &mut HeadlessBackend {
+        runner.win.backend_mut::<HeadlessBackend>().unwrap()
+    }
+
+    #[test]
+    fn fixed_timestep() {
+        let mut runner: Runner = headless_runner(0.375).fixed_dt(0.25);
+        let mut app: Counter = Counter::default();
+        //The first frame has no elapsed time yet
+        runner.frame(&mut app);
+        assert_eq!(app.updates, 0);
+        runner.frame(&mut app);
+        assert_eq!((app.updates, runner.ticks(), runner.frames()), (1, 1, 2));
+        assert_eq!(app.alphas[1], 0.5);
+        runner.frame(&mut app);
+        assert_eq!(app.updates, 3);
+        assert_eq!((runner.time(), app.alphas[2]), (0.75, 0.0));
+        assert_eq!(runner.timer.frames(), 3);
+    }
+
+    #[test]
+    fn frame_skip_drops_time() {
+        let mut runner: Runner = headless_runner(10.0).fixed_dt(0.1).max_frame_skip(3);
+        let mut app: Counter = Counter::default();
+        runner.frame(&mut app);
+        runner.frame(&mut app);
+        assert_eq!(app.updates, 3);
+        assert!(app.alphas[1] < 1.0);
+        runner.frame(&mut app);
+        assert_eq!(app.updates, 6);
+    }
+
+    #[test]
+    fn bad_settings_are_clamped() {
+        assert_eq!(headless_runner(0.0).fixed_dt(0.0).fixed_dt, MIN_FIXED_DT);
+        assert_eq!(headless_runner(0.0).update_rate(1e9).fixed_dt, MIN_FIXED_DT);
+        assert_eq!(headless_runner(0.0).max_frame_skip(0).max_frame_skip, 1);
+
+        //Set directly, the fields are clamped when a frame runs
+        let mut runner: Runner = headless_runner(1.0);
+        runner.fixed_dt = -1.0;
+        runner.max_frame_skip = 0;
+        let mut app: Counter = Counter::default();
+        runner.frame(&mut app);
+        runner.frame(&mut app);
+        assert_eq!(app.updates, 1);
+        assert_eq!((runner.fixed_dt, runner.max_frame_skip), (MIN_FIXED_DT, 1));
+    }
+
+    #[test]
+    fn pause_on_focus_loss() {
+        let mut runner: Runner = headless_runner(0.1).fixed_dt(0.1);
+        let mut app: Counter = Counter::default();
+        runner.frame(&mut app);
+        headless(&mut runner).set_focused(false);
+        runner.frame(&mut app);
+        let before: u32 = app.updates;
+        runner.frame(&mut app);
+        runner.frame(&mut app);
+        assert!(runner.is_paused());
+        assert_eq!(app.updates, before);
+        assert_eq!(app.events, vec![WinEvent::FocusLost]);
+
+        headless(&mut runner).set_focused(true);
+        runner.frame(&mut app);
+        runner.frame(&mut app);
+        assert!(!runner.is_paused());
+        assert_eq!(app.updates, before + 1);
+    }
+
+    #[test]
+    fn run_until_closed_or_quit() {
+        let mut runner: Runner = headless_runner(0.1).fixed_dt(0.1);
+        *headless(&mut runner) = HeadlessBackend::new(4, 4).close_after(3);
+        let mut app: Counter = Counter::default();
+        runner.run(&mut app);
+        assert_eq!((runner.frames(), app.shut_down), (3, true));
+
+        let mut runner: Runner = headless_runner(0.1).fixed_dt(0.1);
+        let mut app: Counter = Counter { quit_after: Some(4), ..Counter::default() };
+        runner.run(&mut app);
+        assert_eq!((runner.ticks(), runner.frames(), app.shut_down), (4, 5, true));
+    }
+
+}
@@ -0,0 +1,277 @@
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "minifb")]
+use minifb::Key as MFBKey;
+
+//Declares CKey with the same variants as minifb's Key, plus the conversions and name lookups
+macro_rules! keys {
+    ($($name:ident),* $(,)?) => {
+        ///A key on the keyboard, independent of the windowing library.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum CKey {
+            $($name,)*
+        }
+
+        impl CKey {
+            ///Every key, in declaration order.
+            pub const ALL: &'static [CKey] = &[$(CKey::$name,)*];
+
+            ///The key's variant name, e.g. `"A"`, `"Key1"` or `"LeftShift"`.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(CKey::$name => stringify!($name),)*
+                }
+            }
+        }
+
+        #[cfg(feature = "minifb")]
+        impl From<MFBKey> for CKey {
+            fn from(key: MFBKey) -> CKey {
+                match key {
+                    $(MFBKey::$name => CKey::$name,)*
+                    _ => CKey::Unknown,
+                }
+            }
+        }
+
+        #[cfg(feature = "minifb")]
+        impl From<CKey> for MFBKey {
+            fn from(key: CKey) -> MFBKey {
+                match key {
+                    $(CKey::$name => MFBKey::$name,)*
+                }
+            }
+        }
+    };
+}
+
+keys!(
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15,
+    Down, Left, Right, Up,
+    Apostrophe, Backquote, Backslash, Comma, Equal, LeftBracket, Minus, Period, RightBracket, Semicolon, Slash,
+    Backspace, Delete, End, Enter, Escape, Home, Insert, Menu, PageDown, PageUp, Pause, Space, Tab,
+    NumLock, CapsLock, ScrollLock,
+    LeftShift, RightShift, LeftCtrl, RightCtrl,
+    NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9,
+    NumPadDot, NumPadSlash, NumPadAsterisk, NumPadMinus, NumPadPlus, NumPadEnter,
+    LeftAlt, RightAlt, LeftSuper, RightSuper,
+    Unknown,
+);
+
+impl CKey {
+
+    ///Looks a key up by its variant name, ignoring case.
+    pub fn from_name(name: &str) -> Option<CKey> {
+        CKey::ALL.iter().copied().find(|k| k.name().eq_ignore_ascii_case(name))
+    }
+
+}
+
+///Which modifier keys are held. Left and right keys are not distinguished.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+
+    pub const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false, logo: false };
+
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::NONE
+    }
+
+}
+
+///Per-frame keyboard state. Feed it the keys that are down and the characters typed once per frame with `update`;
+/// pressed/released/repeated queries then describe what changed since the previous frame.
+#[derive(Clone, Debug)]
+pub struct Keyboard {
+    down: HashSet<CKey>,
+    pressed: HashSet<CKey>,
+    released: HashSet<CKey>,
+    repeated: HashSet<CKey>,
+    ///Time each held key has until its next repeat.
+    repeat_timers: HashMap<CKey, f64>,
+    text: String,
+    ///Seconds a key must be held before it starts repeating.
+    pub repeat_delay: f64,
+    ///Seconds between repeats once repeating.
+    pub repeat_interval: f64,
+}
+
+impl Keyboard {
+
+    pub fn new() -> Keyboard {
+        Keyboard {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            repeated: HashSet::new(),
+            repeat_timers: HashMap::new(),
+            text: String::new(),
+            repeat_delay: 0.5,
+            repeat_interval: 1.0 / 30.0,
+        }
+    }
+
+    ///Starts a new frame: `down` lists every key held now, `typed` the characters entered since the last frame and
+    /// `dt` the seconds since the last frame.
+    pub fn update(&mut self, down: &[CKey], typed: &[char], dt: f64) {
+        let now: HashSet<CKey> = down.iter().copied().collect();
+        self.pressed = now.difference(&self.down).copied().collect();
+        self.released = self.down.difference(&now).copied().collect();
+        self.repeated.clear();
+
+        self.repeat_timers.retain(|k, _| now.contains(k));
+        for key in now.iter() {
+            if self.pressed.contains(key) {
+                self.repeat_timers.insert(*key, self.repeat_delay);
+                continue;
+            }
+            if let Some(timer) = self.repeat_timers.get_mut(key) {
+                *timer -= dt;
+                if *timer <= 0.0 {
+                    self.repeated.insert(*key);
+                    //Long frames still produce at most one repeat, so drop the ones that were missed
+                    *timer = (*timer + self.repeat_interval).max(0.0);
+                }
+            }
+        }
+
+        self.down = now;
+        self.text = typed.iter().filter(|c| !c.is_control()).collect();
+    }
+
+    pub fn is_key_down(&self, key: CKey) -> bool {
+        self.down.contains(&key)
+    }
+
+    ///True only on the frame `key` went down.
+    pub fn was_key_pressed(&self, key: CKey) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    ///True only on the frame `key` went up.
+    pub fn was_key_released(&self, key: CKey) -> bool {
+        self.released.contains(&key)
+    }
+
+    ///True on the frame `key` went down and on every auto-repeat while it is held, like typing in a text field.
+    pub fn was_key_repeated(&self, key: CKey) -> bool {
+        self.pressed.contains(&key) || self.repeated.contains(&key)
+    }
+
+    ///Keys held this frame, in no particular order.
+    pub fn keys_down(&self) -> Vec<CKey> {
+        self.down.iter().copied().collect()
+    }
+
+    pub fn keys_pressed(&self) -> Vec<CKey> {
+        self.pressed.iter().copied().collect()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        let held = |a: CKey, b: CKey| self.is_key_down(a) || self.is_key_down(b);
+        Modifiers {
+            shift: held(CKey::LeftShift, CKey::RightShift),
+            ctrl: held(CKey::LeftCtrl, CKey::RightCtrl),
+            alt: held(CKey::LeftAlt, CKey::RightAlt),
+            logo: held(CKey::LeftSuper, CKey::RightSuper),
+        }
+    }
+
+    ///Printable characters typed during the last frame, in order, with the keyboard layout and shift state applied.
+    pub fn text_input(&self) -> &str {
+        &self.text
+    }
+
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn names() {
+        for key in CKey::ALL.iter() {
+            assert_eq!(CKey::from_name(key.name()), Some(*key));
+        }
+        assert_eq!(CKey::from_name("leftshift"), Some(CKey::LeftShift));
+        assert_eq!(CKey::from_name("Key1"), Some(CKey::Key1));
+        assert_eq!(CKey::from_name("Hyper"), None);
+    }
+
+    #[cfg(feature = "minifb")]
+    #[test]
+    fn minifb_conversion() {
+        for key in CKey::ALL.iter() {
+            assert_eq!(CKey::from(MFBKey::from(*key)), *key);
+        }
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut kb: Keyboard = Keyboard::new();
+        kb.update(&[CKey::A], &[], 0.016);
+        assert!(kb.is_key_down(CKey::A) && kb.was_key_pressed(CKey::A));
+        kb.update(&[CKey::A, CKey::B], &[], 0.016);
+        assert!(!kb.was_key_pressed(CKey::A) && kb.was_key_pressed(CKey::B));
+        assert_eq!(kb.keys_pressed(), vec![CKey::B]);
+        kb.update(&[CKey::B], &[], 0.016);
+        assert!(kb.was_key_released(CKey::A) && !kb.is_key_down(CKey::A));
+        kb.update(&[CKey::B], &[], 0.016);
+        assert!(!kb.was_key_released(CKey::A));
+    }
+
+    #[test]
+    fn auto_repeat() {
+        let mut kb: Keyboard = Keyboard::new();
+        kb.repeat_delay = 0.5;
+        kb.repeat_interval = 0.1;
+        kb.update(&[CKey::Left], &[], 0.0);
+        assert!(kb.was_key_repeated(CKey::Left));
+        kb.update(&[CKey::Left], &[], 0.4);
+        assert!(!kb.was_key_repeated(CKey::Left));
+        kb.update(&[CKey::Left], &[], 0.1);
+        assert!(kb.was_key_repeated(CKey::Left));
+        kb.update(&[CKey::Left], &[], 0.05);
+        assert!(!kb.was_key_repeated(CKey::Left));
+        kb.update(&[CKey::Left], &[], 0.05);
+        assert!(kb.was_key_repeated(CKey::Left));
+
+        //A long frame gives a single repeat rather than one for every interval it spans
+        kb.update(&[CKey::Left], &[], 5.0);
+        assert!(kb.was_key_repeated(CKey::Left));
+
+        //Releasing and pressing again restarts the delay
+        kb.update(&[], &[], 0.05);
+        kb.update(&[CKey::Left], &[], 0.05);
+        kb.update(&[CKey::Left], &[], 0.2);
+        assert!(!kb.was_key_repeated(CKey::Left));
+    }
+
+    #[test]
+    fn modifiers_and_text() {
+        let mut kb: Keyboard = Keyboard::new();
+        assert!(kb.modifiers().is_empty());
+        kb.update(&[CKey::RightShift, CKey::LeftCtrl, CKey::A], &['A', '\u{8}', 'é'], 0.016);
+        assert_eq!(kb.modifiers(), Modifiers { shift: true, ctrl: true, ..Modifiers::NONE });
+        assert_eq!(kb.text_input(), "Aé");
+        kb.update(&[], &[], 0.016);
+        assert_eq!(kb.text_input(), "");
+    }
+
+}
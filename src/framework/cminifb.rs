@@ -0,0 +1,146 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use minifb::CursorStyle as MFBCursorStyle;
+use minifb::InputCallback as MFBInputCallback;
+use minifb::MouseButton as MFBMouseButton;
+use minifb::MouseMode as MFBMouseMode;
+use minifb::Scale as MFBScale;
+use minifb::ScaleMode as MFBScaleMode;
+use minifb::WindowOptions as MFBWindowOptions;
+use minifb::Window as MFBWindow;
+
+use crate::framework::ckey::CKey;
+use crate::framework::cmouse::CursorShape;
+use crate::framework::crecord::InputFrame;
+use crate::framework::cpresenter::Presenter;
+use crate::framework::cwin::CWinConfig;
+
+///A desktop window through minifb.
+pub struct MinifbBackend {
+    lib_window: MFBWindow,
+    typed: Rc<RefCell<Vec<char>>>,   //Filled by minifb's input callback between frames
+    last_input: Instant,
+}
+
+//Collects the characters minifb reports so they can be handed out once per frame
+struct TextCallback {
+    typed: Rc<RefCell<Vec<char>>>,
+}
+
+impl MFBInputCallback for TextCallback {
+    fn add_char(&mut self, uni_char: u32) {
+        if let Some(c) = char::from_u32(uni_char) {
+            self.typed.borrow_mut().push(c);
+        }
+    }
+}
+
+impl MinifbBackend {
+
+    ///Opens a window with a client area of `width` x `height` pixels.
+    pub fn new(config: &CWinConfig, width: usize, height: usize) -> MinifbBackend {
+        //Scaling is done by CWin rather than by minifb, so that every resize mode works the same way
+        let mut win = MFBWindow::new(
+            &config.title,
+            width,
+            height,
+            MFBWindowOptions {
+                resize: config.resizable,
+                borderless: config.borderless,
+                topmost: config.topmost,
+                scale: MFBScale::X1,
+                scale_mode: MFBScaleMode::UpperLeft,
+                ..MFBWindowOptions::default()
+            },
+        )
+        .expect("Unable to create the window");
+
+        win.set_position(config.position.0, config.position.1);
+        win.set_target_fps(config.target_fps);
+
+        let typed: Rc<RefCell<Vec<char>>> = Rc::new(RefCell::new(Vec::new()));
+        win.set_input_callback(Box::new(TextCallback { typed: typed.clone() }));
+
+        MinifbBackend { lib_window: win, typed, last_input: Instant::now() }
+    }
+
+}
+
+impl Presenter for MinifbBackend {
+
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize) -> Result<(), String> {
+        self.lib_window
+            .update_with_buffer(pixels, width, height)
+            .map_err(|e| format!("[MinifbBackend] {}", e))
+    }
+
+    fn poll_input(&mut self) -> InputFrame {
+        let now: Instant = Instant::now();
+        let dt: f64 = now.duration_since(self.last_input).as_secs_f64();
+        self.last_input = now;
+
+        let mut keys_down: Vec<CKey> = self.lib_window.get_keys().into_iter().map(CKey::from).collect();
+        keys_down.sort();
+        let typed: Vec<char> = self.typed.borrow_mut().drain(..).collect();
+
+        let mouse_pos: Option<(f64, f64)> = self.lib_window
+            .get_unscaled_mouse_pos(MFBMouseMode::Pass)
+            .map(|(x, y)| (x as f64, y as f64));
+        let buttons: [bool; 3] = [
+            self.lib_window.get_mouse_down(MFBMouseButton::Left),
+            self.lib_window.get_mouse_down(MFBMouseButton::Middle),
+            self.lib_window.get_mouse_down(MFBMouseButton::Right),
+        ];
+        let wheel: (f64, f64) = self.lib_window.get_scroll_wheel().map_or((0.0, 0.0), |(x, y)| (x as f64, y as f64));
+        InputFrame { dt, keys_down, typed, mouse_pos, mouse_inside: false, buttons, wheel }
+    }
+
+    fn size(&self) -> (usize, usize) {
+        self.lib_window.get_size()
+    }
+
+    fn is_open(&self) -> bool {
+        self.lib_window.is_open()
+    }
+
+    fn is_active(&mut self) -> bool {
+        self.lib_window.is_active()
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.lib_window.set_title(title);
+    }
+
+    fn set_position(&mut self, x: isize, y: isize) {
+        self.lib_window.set_position(x, y);
+    }
+
+    fn set_target_fps(&mut self, fps: usize) {
+        self.lib_window.set_target_fps(fps);
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.lib_window.set_cursor_visibility(visible);
+    }
+
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.lib_window.set_cursor_style(match shape {
+            CursorShape::Arrow => MFBCursorStyle::Arrow,
+            CursorShape::IBeam => MFBCursorStyle::Ibeam,
+            CursorShape::Crosshair => MFBCursorStyle::Crosshair,
+            CursorShape::ClosedHand => MFBCursorStyle::ClosedHand,
+            CursorShape::OpenHand => MFBCursorStyle::OpenHand,
+            CursorShape::ResizeLeftRight => MFBCursorStyle::ResizeLeftRight,
+            CursorShape::ResizeUpDown => MFBCursorStyle::ResizeUpDown,
+            CursorShape::ResizeAll => MFBCursorStyle::ResizeAll,
+        });
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
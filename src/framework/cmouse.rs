@@ -0,0 +1,161 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 3] = [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+///Cursor images the window can show while the mouse is over it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Arrow,
+    IBeam,
+    Crosshair,
+    ClosedHand,
+    OpenHand,
+    ResizeLeftRight,
+    ResizeUpDown,
+    ResizeAll,
+}
+
+///Per-frame mouse state in raster pixel coordinates. Feed it once per frame with `update`; click, release, wheel
+/// and motion queries then describe what happened since the previous frame.
+#[derive(Clone, Debug)]
+pub struct Mouse {
+    position: (f64, f64),
+    delta: (f64, f64),
+    inside: bool,
+    down: [bool; 3],
+    was_down: [bool; 3],
+    wheel: (f64, f64),
+    seen: bool,
+}
+
+impl Mouse {
+
+    pub fn new() -> Mouse {
+        Mouse {
+            position: (0.0, 0.0),
+            delta: (0.0, 0.0),
+            inside: false,
+            down: [false; 3],
+            was_down: [false; 3],
+            wheel: (0.0, 0.0),
+            seen: false,
+        }
+    }
+
+    ///Starts a new frame. `position` is the cursor in raster coordinates (it may lie outside the raster) or `None` if
+    /// it is unknown, `down` holds the left, middle and right buttons and `wheel` the scroll since the last frame.
+    pub fn update(&mut self, position: Option<(f64, f64)>, inside: bool, down: [bool; 3], wheel: (f64, f64)) {
+        self.delta = (0.0, 0.0);
+        if let Some(p) = position {
+            //No motion is reported for the first known position, so the cursor entering does not look like a jump
+            if self.seen {
+                self.delta = (p.0 - self.position.0, p.1 - self.position.1);
+            }
+            self.position = p;
+            self.seen = true;
+        }
+        self.inside = inside && position.is_some();
+        self.was_down = self.down;
+        self.down = down;
+        self.wheel = wheel;
+    }
+
+    ///Last known cursor position in raster pixels. Pixel `(x, y)` covers `x..x+1`, `y..y+1`.
+    pub fn position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    ///The raster pixel under the cursor, if the cursor is over the raster.
+    pub fn pixel(&self) -> Option<(i32, i32)> {
+        if self.inside { Some((self.position.0.floor() as i32, self.position.1.floor() as i32)) } else { None }
+    }
+
+    pub fn is_inside(&self) -> bool {
+        self.inside
+    }
+
+    ///How far the cursor moved since the last frame, in raster pixels.
+    pub fn delta(&self) -> (f64, f64) {
+        self.delta
+    }
+
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        self.down[button.index()]
+    }
+
+    ///True only on the frame `button` went down.
+    pub fn was_clicked(&self, button: MouseButton) -> bool {
+        self.down[button.index()] && !self.was_down[button.index()]
+    }
+
+    ///True only on the frame `button` went up.
+    pub fn was_released(&self, button: MouseButton) -> bool {
+        !self.down[button.index()] && self.was_down[button.index()]
+    }
+
+    ///Horizontal and vertical scroll since the last frame. Positive y scrolls up (away from the user).
+    pub fn wheel(&self) -> (f64, f64) {
+        self.wheel
+    }
+
+}
+
+impl Default for Mouse {
+    fn default() -> Mouse {
+        Mouse::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const UP: [bool; 3] = [false; 3];
+
+    #[test]
+    fn motion() {
+        let mut mouse: Mouse = Mouse::new();
+        mouse.update(None, true, UP, (0.0, 0.0));
+        assert!(!mouse.is_inside());
+        assert_eq!(mouse.pixel(), None);
+
+        //Entering the window does not count as motion
+        mouse.update(Some((10.5, 4.2)), true, UP, (0.0, 0.0));
+        assert_eq!(mouse.delta(), (0.0, 0.0));
+        assert_eq!(mouse.pixel(), Some((10, 4)));
+        mouse.update(Some((12.5, 3.2)), true, UP, (0.0, 0.0));
+        assert_eq!(mouse.delta(), (2.0, -1.0));
+
+        //An unknown position keeps the last one and reports no motion
+        mouse.update(None, true, UP, (0.0, 0.0));
+        assert_eq!((mouse.position(), mouse.delta()), ((12.5, 3.2), (0.0, 0.0)));
+
+        mouse.update(Some((-0.5, 3.0)), false, UP, (0.0, -1.0));
+        assert_eq!((mouse.pixel(), mouse.wheel()), (None, (0.0, -1.0)));
+    }
+
+    #[test]
+    fn buttons() {
+        let mut mouse: Mouse = Mouse::new();
+        mouse.update(Some((0.0, 0.0)), true, [true, false, false], (0.0, 0.0));
+        assert!(mouse.was_clicked(MouseButton::Left) && mouse.is_down(MouseButton::Left));
+        mouse.update(Some((0.0, 0.0)), true, [true, false, true], (0.0, 0.0));
+        assert!(!mouse.was_clicked(MouseButton::Left) && mouse.was_clicked(MouseButton::Right));
+        mouse.update(Some((0.0, 0.0)), true, [false, false, true], (0.0, 0.0));
+        assert!(mouse.was_released(MouseButton::Left) && !mouse.was_released(MouseButton::Right));
+        assert!(!mouse.is_down(MouseButton::Middle));
+    }
+
+}
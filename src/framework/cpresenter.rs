@@ -0,0 +1,319 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::framework::cmouse::CursorShape;
+use crate::framework::crecord::{InputFrame, InputRecording};
+
+///Somewhere `CWin` can show frames and get input from: a desktop window, or nothing at all for tests and servers.
+/// `CWin` does the scaling, so backends always present a buffer the size of their client area.
+pub trait Presenter {
+    ///Shows `pixels` (0x00RRGGBB, row-major, `width` x `height`).
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize) -> Result<(), String>;
+
+    ///Input since the last call. `mouse_pos` is in window pixels and `mouse_inside` is ignored; `dt` is the time
+    /// the frame covers.
+    fn poll_input(&mut self) -> InputFrame;
+
+    ///Client area size in window pixels.
+    fn size(&self) -> (usize, usize);
+
+    fn is_open(&self) -> bool;
+
+    fn is_active(&mut self) -> bool;
+
+    fn set_title(&mut self, _title: &str) {}
+
+    fn set_position(&mut self, _x: isize, _y: isize) {}
+
+    fn set_target_fps(&mut self, _fps: usize) {}
+
+    fn set_cursor_visible(&mut self, _visible: bool) {}
+
+    fn set_cursor_shape(&mut self, _shape: CursorShape) {}
+
+    ///Lets `CWin::backend_mut` hand back the concrete backend.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+///A window without a display. Frames are kept in memory and input comes from a script, so windowed code can run
+/// in CI and on render servers.
+pub struct HeadlessBackend {
+    width: usize,
+    height: usize,
+    open: bool,
+    focused: bool,
+    script: VecDeque<InputFrame>,
+    ///Frame delta reported once the script is used up.
+    pub dt: f64,
+    close_after: Option<usize>,
+    frames: usize,
+    last_frame: Vec<u32>,
+}
+
+impl HeadlessBackend {
+
+    pub fn new(width: usize, height: usize) -> HeadlessBackend {
+        HeadlessBackend {
+            width,
+            height,
+            open: true,
+            focused: true,
+            script: VecDeque::new(),
+            dt: 1.0 / 60.0,
+            close_after: None,
+            frames: 0,
+            last_frame: Vec::new(),
+        }
+    }
+
+    ///Feeds the recording's frames as input, one per presented frame. Mouse positions are window pixels, which
+    /// are raster pixels when the window is unscaled.
+    pub fn with_script(mut self, recording: InputRecording) -> HeadlessBackend {
+        self.script = recording.frames.into();
+        self
+    }
+
+    ///Reports the window as closed once `frames` frames have been presented, so `while win.is_open()` loops end.
+    pub fn close_after(mut self, frames: usize) -> HeadlessBackend {
+        self.close_after = Some(frames);
+        self
+    }
+
+    pub fn push_input(&mut self, frame: InputFrame) {
+        self.script.push_back(frame);
+    }
+
+    ///Simulates the user resizing the window.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    ///Simulates the user closing the window.
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    pub fn frames_presented(&self) -> usize {
+        self.frames
+    }
+
+    ///The most recently presented frame, at the window size.
+    pub fn last_frame(&self) -> &[u32] {
+        &self.last_frame
+    }
+
+}
+
+impl Presenter for HeadlessBackend {
+
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize) -> Result<(), String> {
+        if pixels.len() < width*height {
+            return Err(format!("[HeadlessBackend] frame of {} pixels is too small for {}x{}", pixels.len(), width, height));
+        }
+        self.last_frame.clear();
+        self.last_frame.extend_from_slice(&pixels[..width*height]);
+        self.frames += 1;
+        if self.close_after.is_some_and(|n| self.frames >= n) {
+            self.open = false;
+        }
+        Ok(())
+    }
+
+    fn poll_input(&mut self) -> InputFrame {
+        self.script.pop_front().unwrap_or(InputFrame { dt: self.dt, ..InputFrame::default() })
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn is_active(&mut self) -> bool {
+        self.focused
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+///How `FileSequenceBackend` writes frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    ///Binary PPM (P6) images.
+    Ppm,
+    ///Headerless 8-bit RGB, three bytes per pixel, e.g. for `ffmpeg -f rawvideo -pix_fmt rgb24`.
+    Raw,
+}
+
+///A headless window that also writes every presented frame to `directory` as `prefix00000.ppm`, `prefix00001.ppm`
+/// and so on (`.rgb` for raw frames).
+pub struct FileSequenceBackend {
+    pub headless: HeadlessBackend,
+    directory: PathBuf,
+    prefix: String,
+    format: FrameFormat,
+}
+
+impl FileSequenceBackend {
+
+    ///Creates `directory` if needed.
+    pub fn new(width: usize, height: usize, directory: &str, prefix: &str, format: FrameFormat) -> Result<FileSequenceBackend, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("[FileSequenceBackend] {}: {}", directory, e))?;
+        Ok(FileSequenceBackend {
+            headless: HeadlessBackend::new(width, height),
+            directory: PathBuf::from(directory),
+            prefix: prefix.to_string(),
+            format,
+        })
+    }
+
+    pub fn with_script(mut self, recording: InputRecording) -> FileSequenceBackend {
+        self.headless = self.headless.with_script(recording);
+        self
+    }
+
+    pub fn close_after(mut self, frames: usize) -> FileSequenceBackend {
+        self.headless = self.headless.close_after(frames);
+        self
+    }
+
+    ///Path the frame with index `frame` is written to.
+    pub fn frame_path(&self, frame: usize) -> PathBuf {
+        let extension: &str = match self.format {
+            FrameFormat::Ppm => "ppm",
+            FrameFormat::Raw => "rgb",
+        };
+        self.directory.join(format!("{}{:05}.{}", self.prefix, frame, extension))
+    }
+
+}
+
+impl Presenter for FileSequenceBackend {
+
+    fn present(&mut self, pixels: &[u32], width: usize, height: usize) -> Result<(), String> {
+        let path: PathBuf = self.frame_path(self.headless.frames_presented());
+        self.headless.present(pixels, width, height)?;
+
+        let mut bytes: Vec<u8> = match self.format {
+            FrameFormat::Ppm => format!("P6\n{} {}\n255\n", width, height).into_bytes(),
+            FrameFormat::Raw => Vec::new(),
+        };
+        bytes.reserve(width*height*3);
+        for p in pixels[..width*height].iter() {
+            bytes.extend_from_slice(&[(p >> 16) as u8, (p >> 8) as u8, *p as u8]);
+        }
+        fs::write(&path, bytes).map_err(|e| format!("[FileSequenceBackend] {}: {}", path.display(), e))
+    }
+
+    fn poll_input(&mut self) -> InputFrame {
+        self.headless.poll_input()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        self.headless.size()
+    }
+
+    fn is_open(&self) -> bool {
+        self.headless.is_open()
+    }
+
+    fn is_active(&mut self) -> bool {
+        self.headless.is_active()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::framework::ckey::CKey;
+    use crate::framework::cwin::CWin;
+    use crate::graphics::craster::CRaster;
+
+    fn key_frame(keys: &[CKey]) -> InputFrame {
+        InputFrame { dt: 0.5, keys_down: keys.to_vec(), ..InputFrame::default() }
+    }
+
+    #[test]
+    fn headless_frames_and_script() {
+        let mut backend: HeadlessBackend = HeadlessBackend::new(2, 1)
+            .with_script(InputRecording { frames: vec![key_frame(&[CKey::A])] })
+            .close_after(2);
+        assert!(backend.present(&[1], 2, 1).is_err());
+        backend.present(&[1, 2, 3], 2, 1).unwrap();
+        assert_eq!((backend.last_frame(), backend.frames_presented(), backend.is_open()), (&[1, 2][..], 1, true));
+        backend.present(&[4, 5], 2, 1).unwrap();
+        assert!(!backend.is_open());
+
+        assert_eq!(backend.poll_input().keys_down, vec![CKey::A]);
+        //Once the script runs out, empty frames of the default delta follow
+        assert_eq!(backend.poll_input(), InputFrame { dt: backend.dt, ..InputFrame::default() });
+    }
+
+    #[test]
+    fn record_and_replay_through_window() {
+        let raster: CRaster = CRaster::new(2, 2);
+        let mut win: CWin = CWin::headless(&raster);
+        let backend: &mut HeadlessBackend = win.backend_mut::<HeadlessBackend>().unwrap();
+        backend.push_input(key_frame(&[CKey::A]));
+        backend.push_input(key_frame(&[]));
+
+        win.start_recording();
+        win.draw(&raster);
+        assert!(win.was_key_pressed(CKey::A));
+        assert_eq!(win.frame_dt(), 0.5);
+        win.draw(&raster);
+        let recording: InputRecording = win.stop_recording().unwrap();
+        assert_eq!(recording.frames, vec![key_frame(&[CKey::A]), key_frame(&[])]);
+
+        //Playback replaces live input until it runs out
+        win.start_playback(recording);
+        win.draw(&raster);
+        assert!(win.was_key_pressed(CKey::A) && win.is_playing_back());
+        win.draw(&raster);
+        assert!(win.was_key_released(CKey::A));
+        win.draw(&raster);
+        assert!(!win.is_playing_back());
+        assert_eq!(win.frame_dt(), 1.0 / 60.0);
+        assert!(win.backend_mut::<FileSequenceBackend>().is_none());
+    }
+
+    #[test]
+    fn file_sequence() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("cerulean-frames-{}", std::process::id()));
+        let dir_str: String = dir.to_string_lossy().into_owned();
+        let mut ppm: FileSequenceBackend = FileSequenceBackend::new(2, 1, &dir_str, "ppm_", FrameFormat::Ppm).unwrap().close_after(2);
+        ppm.present(&[0x00102030, 0x00ffffff], 2, 1).unwrap();
+        ppm.present(&[0, 0], 2, 1).unwrap();
+        assert!(!ppm.is_open());
+        let mut raw: FileSequenceBackend = FileSequenceBackend::new(2, 1, &dir_str, "raw_", FrameFormat::Raw).unwrap();
+        raw.present(&[0x00102030, 0x00ffffff], 2, 1).unwrap();
+
+        let first: Vec<u8> = fs::read(dir.join("ppm_00000.ppm")).unwrap();
+        let second_exists: bool = ppm.frame_path(1).exists();
+        let raw_bytes: Vec<u8> = fs::read(raw.frame_path(0)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first, [b"P6\n2 1\n255\n".as_slice(), &[0x10, 0x20, 0x30, 0xff, 0xff, 0xff]].concat());
+        assert!(second_exists);
+        assert_eq!(raw_bytes, vec![0x10, 0x20, 0x30, 0xff, 0xff, 0xff]);
+    }
+
+}
@@ -0,0 +1,426 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::time::Instant;
+
+use crate::framework::ctime::FrameTimer;
+use crate::graphics::ccolor::Color;
+use crate::graphics::cren::CRen;
+
+///Times the rest of the enclosing block under `name` when profiling is on. Scopes nest, and are only recorded
+/// between `Profiler::begin_frame` and `Profiler::end_frame`.
+///
+/// ```ignore
+/// {
+///     profile_scope!("clip");
+///     Clipper::clip(&mut vertices, &mut triangles);
+/// }
+/// ```
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::framework::cprofile::ProfileScope::new($name);
+    };
+}
+
+///One timed scope. Times are in seconds since the profiler started.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileSample {
+    pub name: &'static str,
+    ///How many scopes enclose this one.
+    pub depth: usize,
+    pub start: f64,
+    pub duration: f64,
+}
+
+///The scopes recorded during one frame, in the order they were entered.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileFrame {
+    pub index: u64,
+    pub start: f64,
+    pub duration: f64,
+    pub samples: Vec<ProfileSample>,
+}
+
+impl ProfileFrame {
+
+    ///Total time spent in scopes called `name`, including the scopes inside them.
+    pub fn total(&self, name: &str) -> f64 {
+        self.samples.iter().filter(|s| s.name == name).map(|s| s.duration).sum()
+    }
+
+    ///Time spent in each scope name excluding nested scopes, in order of first appearance. The times add up to
+    /// the time covered by top-level scopes.
+    pub fn self_times(&self) -> Vec<(&'static str, f64)> {
+        let mut times: Vec<(&'static str, f64)> = Vec::new();
+        for (i, sample) in self.samples.iter().enumerate() {
+            let children: f64 = self.samples[i + 1..]
+                .iter()
+                .take_while(|s| s.depth > sample.depth)
+                .filter(|s| s.depth == sample.depth + 1)
+                .map(|s| s.duration)
+                .sum();
+            let time: f64 = (sample.duration - children).max(0.0);
+            match times.iter_mut().find(|(n, _)| *n == sample.name) {
+                Some(entry) => entry.1 += time,
+                None => times.push((sample.name, time)),
+            }
+        }
+        times
+    }
+
+}
+
+///Per-thread scope profiler. It is off until `set_enabled(true)`, and costs one flag check per scope while off.
+pub struct Profiler {
+    enabled: bool,
+    epoch: Instant,
+    current: Option<ProfileFrame>,
+    open: Vec<usize>,
+    frames: VecDeque<ProfileFrame>,
+    max_frames: usize,
+    next_index: u64,
+}
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler {
+        enabled: false,
+        epoch: Instant::now(),
+        current: None,
+        open: Vec::new(),
+        frames: VecDeque::new(),
+        max_frames: 300,
+        next_index: 0,
+    });
+}
+
+impl Profiler {
+
+    pub fn set_enabled(enabled: bool) {
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            p.enabled = enabled;
+            if !enabled {
+                p.current = None;
+                p.open.clear();
+            }
+        });
+    }
+
+    pub fn is_enabled() -> bool {
+        PROFILER.with(|p| p.borrow().enabled)
+    }
+
+    ///Sets how many finished frames are kept.
+    pub fn set_history(frames: usize) {
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            p.max_frames = frames.max(1);
+            while p.frames.len() > p.max_frames {
+                p.frames.pop_front();
+            }
+        });
+    }
+
+    ///Starts recording a frame, ending any frame still open.
+    pub fn begin_frame() {
+        Profiler::end_frame();
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            if !p.enabled {
+                return;
+            }
+            let start: f64 = p.epoch.elapsed().as_secs_f64();
+            let index: u64 = p.next_index;
+            p.next_index += 1;
+            p.current = Some(ProfileFrame { index, start, duration: 0.0, samples: Vec::new() });
+        });
+    }
+
+    pub fn end_frame() {
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            let now: f64 = p.epoch.elapsed().as_secs_f64();
+            if let Some(mut frame) = p.current.take() {
+                frame.duration = now - frame.start;
+                //Scopes still open when the frame ends are cut off at the frame's end
+                for i in p.open.drain(..) {
+                    frame.samples[i].duration = now - frame.samples[i].start;
+                }
+                if p.frames.len() == p.max_frames {
+                    p.frames.pop_front();
+                }
+                p.frames.push_back(frame);
+            }
+        });
+    }
+
+    ///Finished frames, oldest first.
+    pub fn frames() -> Vec<ProfileFrame> {
+        PROFILER.with(|p| p.borrow().frames.iter().cloned().collect())
+    }
+
+    pub fn last_frame() -> Option<ProfileFrame> {
+        PROFILER.with(|p| p.borrow().frames.back().cloned())
+    }
+
+    pub fn clear() {
+        PROFILER.with(|p| p.borrow_mut().frames.clear());
+    }
+
+    ///The finished frames as Chrome trace event JSON, for chrome://tracing, Perfetto or Speedscope.
+    pub fn chrome_trace() -> String {
+        let us = |t: f64| format!("{:.3}", t*1_000_000.0);
+        let mut events: Vec<String> = Vec::new();
+        for frame in Profiler::frames() {
+            events.push(format!(
+                r#"{{"name":"frame {}","cat":"frame","ph":"X","ts":{},"dur":{},"pid":1,"tid":1}}"#,
+                frame.index, us(frame.start), us(frame.duration)
+            ));
+            for sample in frame.samples.iter() {
+                let name: String = sample.name.replace('\\', "\\\\").replace('"', "\\\"");
+                events.push(format!(
+                    r#"{{"name":"{}","cat":"scope","ph":"X","ts":{},"dur":{},"pid":1,"tid":1}}"#,
+                    name, us(sample.start), us(sample.duration)
+                ));
+            }
+        }
+        format!("{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+
+    pub fn save_chrome_trace(path: &str) -> Result<(), String> {
+        fs::write(path, Profiler::chrome_trace()).map_err(|e| format!("[Profiler] {}: {}", path, e))
+    }
+
+}
+
+///Guard made by `profile_scope!`; the scope ends when it is dropped.
+pub struct ProfileScope {
+    index: Option<usize>,
+}
+
+impl ProfileScope {
+
+    pub fn new(name: &'static str) -> ProfileScope {
+        let index: Option<usize> = PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            if !p.enabled {
+                return None;
+            }
+            let start: f64 = p.epoch.elapsed().as_secs_f64();
+            let depth: usize = p.open.len();
+            let frame: &mut ProfileFrame = p.current.as_mut()?;
+            frame.samples.push(ProfileSample { name, depth, start, duration: 0.0 });
+            let index: usize = frame.samples.len() - 1;
+            p.open.push(index);
+            Some(index)
+        });
+        ProfileScope { index }
+    }
+
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        let Some(index) = self.index else { return };
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            let now: f64 = p.epoch.elapsed().as_secs_f64();
+            //The frame may have ended, or a new one begun, while this scope was open
+            if p.open.last() != Some(&index) {
+                return;
+            }
+            p.open.pop();
+            if let Some(sample) = p.current.as_mut().and_then(|f| f.samples.get_mut(index)) {
+                sample.duration = now - sample.start;
+            }
+        });
+    }
+}
+
+const GRAPH_COLORS: [u32; 8] = [0x4e79a7, 0xf28e2b, 0xe15759, 0x76b7b2, 0x59a14f, 0xedc948, 0xb07aa1, 0xff9da7];
+
+impl CRen {
+
+    ///Draws a frame-time overlay in the rectangle at `(x, y)`: one bar per recent frame, stacked by profiler scope
+    /// when profiling is on, with lines at 60 and 30 FPS, the FPS and percentiles from `timer`, and a legend. Text
+    /// that does not fit is cut off, and nothing is drawn in a rectangle too small for the graph.
+    pub fn draw_profiler_overlay(&mut self, timer: &FrameTimer, x: i32, y: i32, w: i32, h: i32) {
+        let text_h: i32 = 10;
+        if w <= 0 || h < text_h + 3 {
+            return;
+        }
+        let graph_top: i32 = y + text_h + 2;
+        let graph_h: i32 = (h - text_h - 2).max(1);
+        for py in y..y + h {
+            for px in x..x + w {
+                self.blend_pixel(px, py, Color::from_rgb_u32(0x000000).with_alpha(0.6), 1.0);
+            }
+        }
+
+        //The graph spans 0 to 50 ms, so both reference lines show
+        let scale: f64 = graph_h as f64 / 0.050;
+        let to_y = |t: f64| graph_top + graph_h - ((t*scale).round() as i32).min(graph_h);
+
+        let frames: Vec<ProfileFrame> = Profiler::frames();
+        let mut names: Vec<&'static str> = Vec::new();
+        let history: Vec<f64> = timer.history().collect();
+        let shown: usize = history.len().min(w.max(0) as usize);
+        for (i, dt) in history[history.len() - shown..].iter().enumerate() {
+            let px: i32 = x + w - shown as i32 + i as i32;
+            let mut bottom: i32 = graph_top + graph_h;
+            //Frames line up from the newest backwards
+            if let Some(frame) = frames.len().checked_sub(shown - i).map(|k| &frames[k]) {
+                let mut t: f64 = 0.0;
+                for (name, time) in frame.self_times() {
+                    let k: usize = names.iter().position(|n| *n == name).unwrap_or_else(|| {
+                        names.push(name);
+                        names.len() - 1
+                    });
+                    t += time;
+                    let top: i32 = to_y(t);
+                    for py in top..bottom {
+                        self.set_pixel(px, py, GRAPH_COLORS[k % GRAPH_COLORS.len()]);
+                    }
+                    bottom = top;
+                }
+            }
+            for py in to_y(*dt)..bottom {
+                self.set_pixel(px, py, 0x808080);
+            }
+        }
+
+        for (target, color) in [(1.0 / 60.0, 0x40c040), (1.0 / 30.0, 0xc04040)] {
+            let py: i32 = to_y(target);
+            for px in x..x + w {
+                self.set_pixel(px, py, color);
+            }
+        }
+
+        let summary: String = format!(
+            "{:.1} FPS  p50 {:.1}ms  p99 {:.1}ms",
+            timer.fps(), timer.percentile(50.0)*1000.0, timer.percentile(99.0)*1000.0
+        );
+        //The built-in font is 8 pixels wide
+        let summary: String = summary.chars().take(((w - 2) / 8).max(0) as usize).collect();
+        self.draw_text((x + 2, y + 1), &summary, 0xffffff);
+        let mut lx: i32 = x + 2;
+        for (k, name) in names.iter().enumerate() {
+            let width: i32 = 8*name.chars().count() as i32;
+            if lx + width > x + w || graph_top + 9 > y + h {
+                break;
+            }
+            self.draw_text((lx, graph_top + 1), name, GRAPH_COLORS[k % GRAPH_COLORS.len()]);
+            lx += width + 8;
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn sample(name: &'static str, depth: usize, start: f64, duration: f64) -> ProfileSample {
+        ProfileSample { name, depth, start, duration }
+    }
+
+    #[test]
+    fn self_times_exclude_children() {
+        let frame: ProfileFrame = ProfileFrame {
+            index: 0,
+            start: 0.0,
+            duration: 10.0,
+            samples: vec![
+                sample("update", 0, 0.0, 4.0),
+                sample("physics", 1, 0.0, 1.0),
+                sample("ai", 1, 1.0, 2.0),
+                sample("path", 2, 1.0, 1.5),
+                sample("render", 0, 4.0, 5.0),
+                sample("physics", 1, 4.0, 0.5),
+            ],
+        };
+        assert_eq!(frame.self_times(), vec![("update", 1.0), ("physics", 1.5), ("ai", 0.5), ("path", 1.5), ("render", 4.5)]);
+        assert_eq!(frame.total("physics"), 1.5);
+        let sum: f64 = frame.self_times().iter().map(|t| t.1).sum();
+        assert_eq!(sum, 9.0);
+    }
+
+    //The profiler is per thread and every test runs on its own thread, so tests do not see each other's frames
+    #[test]
+    fn records_nested_scopes() {
+        Profiler::set_enabled(true);
+        Profiler::clear();
+        Profiler::begin_frame();
+        {
+            crate::profile_scope!("outer");
+            {
+                crate::profile_scope!("inner \"quoted\"");
+            }
+        }
+        //Left open, and cut off when the frame ends
+        let open: ProfileScope = ProfileScope::new("open");
+        Profiler::end_frame();
+        drop(open);
+
+        let frame: ProfileFrame = Profiler::last_frame().unwrap();
+        let names: Vec<(&str, usize)> = frame.samples.iter().map(|s| (s.name, s.depth)).collect();
+        assert_eq!(names, vec![("outer", 0), ("inner \"quoted\"", 1), ("open", 0)]);
+        assert!(frame.samples[0].duration >= frame.samples[1].duration);
+        assert!(frame.samples[2].duration >= 0.0);
+
+        let trace: String = Profiler::chrome_trace();
+        assert!(trace.contains(r#""name":"inner \"quoted\"""#));
+        assert!(trace.contains(r#""name":"frame "#));
+        assert_eq!(trace.matches("\"ph\":\"X\"").count(), 4);
+        Profiler::set_enabled(false);
+    }
+
+    #[test]
+    fn disabled_and_history() {
+        Profiler::set_enabled(false);
+        Profiler::begin_frame();
+        crate::profile_scope!("ignored");
+        Profiler::end_frame();
+        assert!(Profiler::frames().is_empty());
+
+        Profiler::set_enabled(true);
+        Profiler::set_history(2);
+        for _ in 0..3 {
+            Profiler::begin_frame();
+        }
+        Profiler::end_frame();
+        let frames: Vec<ProfileFrame> = Profiler::frames();
+        assert_eq!(frames.iter().map(|f| f.index).collect::<Vec<u64>>(), vec![1, 2]);
+        //Scopes outside a frame are not recorded
+        {
+            crate::profile_scope!("outside");
+        }
+        assert_eq!(Profiler::frames(), frames);
+        Profiler::set_enabled(false);
+    }
+
+    #[test]
+    fn overlay_stays_in_its_rectangle() {
+        let mut timer: FrameTimer = FrameTimer::new(100);
+        for i in 0..100 {
+            timer.record(0.001*i as f64);
+        }
+        let mut ren: CRen = CRen::new(200, 100);
+        ren.fill(0x123456);
+        ren.draw_profiler_overlay(&timer, 10, 20, 150, 60);
+        for (i, p) in ren.raster.data.iter().enumerate() {
+            let (x, y) = ((i % 200) as i32, (i / 200) as i32);
+            let inside: bool = (10..160).contains(&x) && (20..80).contains(&y);
+            assert!(inside || *p == 0x123456, "pixel ({}, {}) drawn outside the overlay", x, y);
+        }
+        assert!(ren.raster.data.contains(&0x40c040));
+        //A rectangle too small for the graph is left alone
+        let before: Vec<u32> = ren.raster.data.clone();
+        ren.draw_profiler_overlay(&timer, 0, 0, 0, 0);
+        ren.draw_profiler_overlay(&timer, 0, 0, 100, 12);
+        assert_eq!(ren.raster.data, before);
+    }
+
+}
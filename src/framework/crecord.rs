@@ -0,0 +1,265 @@
+use std::fs;
+
+use crate::framework::ckey::{CKey, Keyboard};
+use crate::framework::cmouse::Mouse;
+
+const HEADER: &str = "cerulean-input 1";
+
+///Everything the window reports as input for one frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputFrame {
+    ///Seconds since the previous frame.
+    pub dt: f64,
+    pub keys_down: Vec<CKey>,
+    pub typed: Vec<char>,
+    ///Cursor position in raster pixels, if known.
+    pub mouse_pos: Option<(f64, f64)>,
+    pub mouse_inside: bool,
+    ///Left, middle and right buttons.
+    pub buttons: [bool; 3],
+    pub wheel: (f64, f64),
+}
+
+impl InputFrame {
+
+    ///Feeds this frame to keyboard and mouse state.
+    pub fn apply(&self, keyboard: &mut Keyboard, mouse: &mut Mouse) {
+        keyboard.update(&self.keys_down, &self.typed, self.dt);
+        mouse.update(self.mouse_pos, self.mouse_inside, self.buttons, self.wheel);
+    }
+
+    ///One tab-separated line: dt, keys, mouse position, inside, buttons, wheel, typed text.
+    fn to_line(&self) -> String {
+        let keys: Vec<&str> = self.keys_down.iter().map(|k| k.name()).collect();
+        let pos: String = match self.mouse_pos {
+            Some((x, y)) => format!("{},{}", x, y),
+            None => "-".to_string(),
+        };
+        let buttons: String = self.buttons.iter().map(|b| if *b { '1' } else { '0' }).collect();
+        let mut text: String = String::new();
+        for c in self.typed.iter() {
+            match c {
+                '\\' => text.push_str("\\\\"),
+                '\t' => text.push_str("\\t"),
+                '\n' => text.push_str("\\n"),
+                '\r' => text.push_str("\\r"),
+                _ => text.push(*c),
+            }
+        }
+        format!("{}\t{}\t{}\t{}\t{}\t{},{}\t{}", self.dt, keys.join(","), pos, self.mouse_inside as u8, buttons, self.wheel.0, self.wheel.1, text)
+    }
+
+    fn from_line(line: &str) -> Result<InputFrame, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(format!("expected 7 fields, found {}", fields.len()));
+        }
+        let pair = |s: &str| -> Result<(f64, f64), String> {
+            let (a, b) = s.split_once(',').ok_or_else(|| format!("expected 'x,y', found '{}'", s))?;
+            Ok((a.parse().map_err(|_| format!("bad number '{}'", a))?, b.parse().map_err(|_| format!("bad number '{}'", b))?))
+        };
+
+        let dt: f64 = fields[0].parse().map_err(|_| format!("bad frame delta '{}'", fields[0]))?;
+        let keys_down: Vec<CKey> = fields[1]
+            .split(',')
+            .filter(|k| !k.is_empty())
+            .map(|k| CKey::from_name(k).ok_or_else(|| format!("unknown key '{}'", k)))
+            .collect::<Result<Vec<CKey>, String>>()?;
+        let mouse_pos: Option<(f64, f64)> = if fields[2] == "-" { None } else { Some(pair(fields[2])?) };
+        let b: Vec<char> = fields[4].chars().collect();
+        if b.len() != 3 {
+            return Err(format!("bad button field '{}'", fields[4]));
+        }
+
+        let mut typed: Vec<char> = Vec::new();
+        let mut chars = fields[6].chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                typed.push(c);
+                continue;
+            }
+            typed.push(match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some(other) => other,
+                None => '\\',
+            });
+        }
+
+        Ok(InputFrame {
+            dt,
+            keys_down,
+            typed,
+            mouse_pos,
+            mouse_inside: fields[3] == "1",
+            buttons: [b[0] == '1', b[1] == '1', b[2] == '1'],
+            wheel: pair(fields[5])?,
+        })
+    }
+
+}
+
+///A sequence of recorded input frames. Saved as text, one frame per line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputRecording {
+    pub frames: Vec<InputFrame>,
+}
+
+impl InputRecording {
+
+    pub fn new() -> InputRecording {
+        InputRecording { frames: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    ///Total time covered by the recording, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map(|f| f.dt).sum()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out: String = format!("{}\n", HEADER);
+        for frame in self.frames.iter() {
+            out.push_str(&frame.to_line());
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<InputRecording, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim_end) != Some(HEADER) {
+            return Err("[InputRecording] missing header, not an input recording".to_string());
+        }
+        let mut frames: Vec<InputFrame> = Vec::new();
+        for (n, line) in lines.enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            frames.push(InputFrame::from_line(line).map_err(|e| format!("[InputRecording] line {}: {}", n + 2, e))?);
+        }
+        Ok(InputRecording { frames })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("[InputRecording] {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<InputRecording, String> {
+        let text: String = fs::read_to_string(path).map_err(|e| format!("[InputRecording] {}: {}", path, e))?;
+        InputRecording::from_text(&text)
+    }
+
+}
+
+///Steps through a recording one frame at a time.
+#[derive(Clone, Debug)]
+pub struct InputPlayback {
+    pub recording: InputRecording,
+    pub position: usize,
+}
+
+impl InputPlayback {
+
+    pub fn new(recording: InputRecording) -> InputPlayback {
+        InputPlayback { recording, position: 0 }
+    }
+
+    ///The next recorded frame, or `None` once the recording is used up.
+    pub fn next_frame(&mut self) -> Option<&InputFrame> {
+        let frame: Option<&InputFrame> = self.recording.frames.get(self.position);
+        if frame.is_some() {
+            self.position += 1;
+        }
+        frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.recording.frames.len()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::framework::cmouse::MouseButton;
+
+    fn recording() -> InputRecording {
+        InputRecording {
+            frames: vec![
+                InputFrame::default(),
+                InputFrame {
+                    dt: 1.0 / 60.0,
+                    keys_down: vec![CKey::LeftShift, CKey::A],
+                    typed: vec!['A', '\t', '\\', 'n', '\n', '\r', 'é'],
+                    mouse_pos: Some((-3.25, 1e-3)),
+                    mouse_inside: true,
+                    buttons: [true, false, true],
+                    wheel: (0.0, -2.5),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let rec: InputRecording = recording();
+        let text: String = rec.to_text();
+        assert!(text.starts_with("cerulean-input 1\n"));
+        assert_eq!(text.lines().count(), 3);
+        assert_eq!(InputRecording::from_text(&text).unwrap(), rec);
+        //Files edited on Windows still load
+        assert_eq!(InputRecording::from_text(&text.replace('\n', "\r\n")).unwrap(), rec);
+        assert!((rec.duration() - 1.0 / 60.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path: String = std::env::temp_dir().join(format!("cerulean-input-{}.txt", std::process::id())).to_string_lossy().into_owned();
+        recording().save(&path).unwrap();
+        let loaded: Result<InputRecording, String> = InputRecording::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), recording());
+        assert!(InputRecording::load(&path).is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |line: &str| InputRecording::from_text(&format!("cerulean-input 1\n{}\n", line)).unwrap_err();
+        assert!(InputRecording::from_text("0\t\t-\t0\t000\t0,0\t\n").unwrap_err().contains("missing header"));
+        assert!(err("0\t\t-\t0\t000\t0,0").contains("line 2: expected 7 fields"));
+        assert!(err("x\t\t-\t0\t000\t0,0\t").contains("bad frame delta"));
+        assert!(err("0\tA,Nope\t-\t0\t000\t0,0\t").contains("unknown key 'Nope'"));
+        assert!(err("0\t\t1;2\t0\t000\t0,0\t").contains("expected 'x,y'"));
+        assert!(err("0\t\t-\t0\t00\t0,0\t").contains("bad button field"));
+        assert!(err("0\t\t-\t0\t000\t0,z\t").contains("bad number 'z'"));
+    }
+
+    #[test]
+    fn playback() {
+        let mut playback: InputPlayback = InputPlayback::new(recording());
+        let mut keyboard: Keyboard = Keyboard::new();
+        let mut mouse: Mouse = Mouse::new();
+        playback.next_frame().unwrap().apply(&mut keyboard, &mut mouse);
+        assert!(!playback.is_finished());
+        playback.next_frame().unwrap().apply(&mut keyboard, &mut mouse);
+        assert!(keyboard.was_key_pressed(CKey::A) && keyboard.modifiers().shift);
+        assert_eq!(keyboard.text_input(), "A\\né");
+        assert!(mouse.was_clicked(MouseButton::Right) && mouse.is_inside());
+        assert_eq!(mouse.wheel(), (0.0, -2.5));
+        assert!(playback.next_frame().is_none());
+        assert!(playback.is_finished());
+        assert_eq!(playback.position, 2);
+    }
+
+}
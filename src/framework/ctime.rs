@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+///Wall-clock time since creation, and between calls to `tick`.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    start: Instant,
+    last: Instant,
+}
+
+impl Clock {
+
+    pub fn new() -> Clock {
+        let now: Instant = Instant::now();
+        Clock { start: now, last: now }
+    }
+
+    ///Seconds since the previous `tick` (or since creation).
+    pub fn tick(&mut self) -> f64 {
+        let now: Instant = Instant::now();
+        let dt: f64 = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        dt
+    }
+
+    ///Seconds since creation or the last `reset`.
+    pub fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Clock::new();
+    }
+
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+///Frame time statistics over a window of recent frames.
+#[derive(Clone, Debug)]
+pub struct FrameTimer {
+    clock: Clock,
+    history: VecDeque<f64>,
+    capacity: usize,
+    smoothed_fps: f64,
+    ///How quickly `fps` follows changes, from 0 (never) to 1 (no smoothing).
+    pub smoothing: f64,
+    frames: u64,
+}
+
+impl FrameTimer {
+
+    ///Keeps the last `capacity` frame times.
+    pub fn new(capacity: usize) -> FrameTimer {
+        FrameTimer {
+            clock: Clock::new(),
+            history: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            smoothed_fps: 0.0,
+            smoothing: 0.1,
+            frames: 0,
+        }
+    }
+
+    ///Measures the time since the last `tick` with the wall clock and records it.
+    pub fn tick(&mut self) -> f64 {
+        let dt: f64 = self.clock.tick();
+        self.record(dt);
+        dt
+    }
+
+    ///Records a frame that took `dt` seconds, e.g. `CWin::frame_dt` during playback.
+    pub fn record(&mut self, dt: f64) {
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(dt);
+        self.frames += 1;
+        if dt > 0.0 {
+            let fps: f64 = 1.0 / dt;
+            self.smoothed_fps = if self.smoothed_fps == 0.0 { fps } else { self.smoothed_fps + (fps - self.smoothed_fps)*self.smoothing };
+        }
+    }
+
+    ///Seconds the most recent frame took.
+    pub fn dt(&self) -> f64 {
+        self.history.back().copied().unwrap_or(0.0)
+    }
+
+    ///Exponentially smoothed frames per second.
+    pub fn fps(&self) -> f64 {
+        self.smoothed_fps
+    }
+
+    ///Mean frame time over the window, in seconds.
+    pub fn average(&self) -> f64 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        self.history.iter().sum::<f64>() / self.history.len() as f64
+    }
+
+    pub fn max(&self) -> f64 {
+        self.history.iter().copied().fold(0.0, f64::max)
+    }
+
+    ///Frame time that `percent` percent of recent frames were at most, e.g. 99.0 for the 1% worst frames.
+    pub fn percentile(&self, percent: f64) -> f64 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        let mut sorted: Vec<f64> = self.history.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank: f64 = (percent / 100.0).clamp(0.0, 1.0)*sorted.len() as f64;
+        sorted[(rank.ceil() as usize).clamp(1, sorted.len()) - 1]
+    }
+
+    ///Recent frame times in seconds, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = f64> + '_ {
+        self.history.iter().copied()
+    }
+
+    ///Frames recorded since creation.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+}
+
+impl Default for FrameTimer {
+    fn default() -> FrameTimer {
+        FrameTimer::new(240)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn frame_statistics() {
+        let mut timer: FrameTimer = FrameTimer::new(4);
+        assert_eq!((timer.dt(), timer.average(), timer.percentile(50.0), timer.fps()), (0.0, 0.0, 0.0, 0.0));
+        for dt in [0.5, 0.25, 0.125, 0.0625, 0.25] {
+            timer.record(dt);
+        }
+        //Only the last four frames are kept
+        assert_eq!(timer.history().collect::<Vec<f64>>(), vec![0.25, 0.125, 0.0625, 0.25]);
+        assert_eq!((timer.frames(), timer.dt(), timer.max()), (5, 0.25, 0.25));
+        assert_eq!(timer.average(), 0.171875);
+        assert_eq!((timer.percentile(0.0), timer.percentile(50.0), timer.percentile(99.0)), (0.0625, 0.125, 0.25));
+        assert_eq!(timer.percentile(1000.0), 0.25);
+    }
+
+    #[test]
+    fn smoothed_fps() {
+        let mut timer: FrameTimer = FrameTimer::new(10);
+        timer.smoothing = 0.5;
+        timer.record(0.0);
+        assert_eq!(timer.fps(), 0.0);
+        timer.record(0.1);
+        assert!((timer.fps() - 10.0).abs() < 1e-9);
+        timer.record(0.05);
+        assert!((timer.fps() - 15.0).abs() < 1e-9);
+        assert_eq!(FrameTimer::new(0).history().count(), 0);
+    }
+
+    #[test]
+    fn clock_moves_forward() {
+        let mut clock: Clock = Clock::new();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(clock.tick() >= 0.002);
+        assert!(clock.elapsed() >= 0.002);
+        clock.reset();
+        assert!(clock.elapsed() < 1.0);
+    }
+
+}
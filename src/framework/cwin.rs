@@ -1,48 +1,470 @@
+use std::any::Any;
 
-
-use minifb::Key as MFBKey;
-use minifb::WindowOptions as MFBWindowOptions;
-use minifb::Window as MFBWindow;
-
+use crate::framework::ckey;
+use ckey::{CKey, Keyboard, Modifiers};
+use crate::framework::cmouse;
+use cmouse::{CursorShape, Mouse, MouseButton};
+use crate::framework::crecord;
+use crecord::{InputFrame, InputPlayback, InputRecording};
+use crate::framework::cpresenter;
+use cpresenter::{HeadlessBackend, Presenter};
+#[cfg(feature = "minifb")]
+use crate::framework::cminifb::MinifbBackend;
 use crate::graphics::craster;
 use craster::CRaster;
 
+///How the raster is fitted to the window when their sizes differ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeMode {
+    ///Fill the whole window, ignoring the aspect ratio.
+    Stretch,
+    ///Scale as large as fits while keeping the aspect ratio, with bars on the sides.
+    Letterbox,
+    ///Scale by the largest whole number that fits, so every raster pixel is the same size.
+    Integer,
+    ///Draw unscaled (apart from the scale factor) from the top-left corner; the application resizes its raster to
+    /// match the window when told of a resize.
+    ResizeRaster,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinEvent {
+    ///The window's client area changed size, in window pixels.
+    Resized { width: usize, height: usize },
+    FocusGained,
+    FocusLost,
+    ///The user asked to close the window. `is_open` is false from now on.
+    CloseRequested,
+}
+
+///Builder for the options a window is created with.
+#[derive(Clone, Debug)]
+pub struct CWinConfig {
+    pub title: String,
+    pub position: (isize, isize),
+    ///Initial client size in window pixels. Defaults to the raster size times `scale`.
+    pub size: Option<(usize, usize)>,
+    pub resizable: bool,
+    pub borderless: bool,
+    pub topmost: bool,
+    ///Window pixels per raster pixel.
+    pub scale: usize,
+    ///Frames per second `draw` is limited to, or 0 for no limit.
+    pub target_fps: usize,
+    pub resize_mode: ResizeMode,
+    ///Color of the window area the raster does not cover.
+    pub background: u32,
+}
+
+impl CWinConfig {
+
+    pub fn new() -> CWinConfig {
+        CWinConfig {
+            title: "Cerluean Engine".to_string(),
+            position: (200, 50),
+            size: None,
+            resizable: false,
+            borderless: false,
+            topmost: false,
+            scale: 1,
+            target_fps: 60,
+            resize_mode: ResizeMode::Letterbox,
+            background: 0x00000000,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> CWinConfig {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn position(mut self, x: isize, y: isize) -> CWinConfig {
+        self.position = (x, y);
+        self
+    }
+
+    pub fn size(mut self, width: usize, height: usize) -> CWinConfig {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> CWinConfig {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn borderless(mut self, borderless: bool) -> CWinConfig {
+        self.borderless = borderless;
+        self
+    }
+
+    pub fn topmost(mut self, topmost: bool) -> CWinConfig {
+        self.topmost = topmost;
+        self
+    }
+
+    pub fn scale(mut self, scale: usize) -> CWinConfig {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn target_fps(mut self, fps: usize) -> CWinConfig {
+        self.target_fps = fps;
+        self
+    }
+
+    pub fn resize_mode(mut self, mode: ResizeMode) -> CWinConfig {
+        self.resize_mode = mode;
+        self
+    }
+
+    pub fn background(mut self, color: u32) -> CWinConfig {
+        self.background = color;
+        self
+    }
+
+}
+
+impl Default for CWinConfig {
+    fn default() -> CWinConfig {
+        CWinConfig::new()
+    }
+}
+
 pub struct CWin {
     pub width: usize,
     pub height: usize,
     pub visible: bool,
-    lib_window: MFBWindow,
+    pub keyboard: Keyboard,
+    pub mouse: Mouse,
+    lib_window: Box<dyn Presenter>,
+    frame_dt: f64,
+    recording: Option<InputRecording>,
+    playback: Option<InputPlayback>,
+    config: CWinConfig,
+    frame: Vec<u32>,                    //Window-sized buffer the raster is scaled into
+    viewport: (i64, i64, usize, usize), //Where the raster appears in the window
+    window_size: (usize, usize),
+    focused: bool,
+    open: bool,
+    events: Vec<WinEvent>,
+    on_resize: Option<Box<dyn FnMut(usize, usize)>>,
 }
 
 impl CWin {
 
     pub fn new(raster: &CRaster) -> CWin {
-        let mut win = MFBWindow::new(
-            "Cerluean Engine",
-            raster.width,
-            raster.height,
-            MFBWindowOptions {
-                resize: false,
-                ..MFBWindowOptions::default()
-            },
-        )
-        .expect("Unable to create the window");
-    
-        win.set_position(200, 50);
-        win.set_target_fps(60);
-    
+        CWin::with_config(raster, CWinConfig::new())
+    }
+
+    ///Opens a desktop window, or a headless one when built without the `minifb` feature.
+    pub fn with_config(raster: &CRaster, config: CWinConfig) -> CWin {
+        let (width, height) = config.size.unwrap_or((raster.width*config.scale, raster.height*config.scale));
+        #[cfg(feature = "minifb")]
+        let backend: Box<dyn Presenter> = Box::new(MinifbBackend::new(&config, width, height));
+        #[cfg(not(feature = "minifb"))]
+        let backend: Box<dyn Presenter> = {
+            println!("[CWin/WARN] Built without the minifb feature, opening a headless window");
+            Box::new(HeadlessBackend::new(width, height))
+        };
+        CWin::with_backend(raster, config, backend)
+    }
+
+    ///A window that presents nothing and reads no input until a script is given, for tests and servers.
+    pub fn headless(raster: &CRaster) -> CWin {
+        CWin::with_backend(raster, CWinConfig::new(), Box::new(HeadlessBackend::new(raster.width, raster.height)))
+    }
+
+    ///Wraps any backend. `config.size`, `resizable` and the other window options are up to whoever created
+    /// `backend`; the scale factor, resize mode and background are applied here.
+    pub fn with_backend(raster: &CRaster, config: CWinConfig, backend: Box<dyn Presenter>) -> CWin {
+        let window_size: (usize, usize) = backend.size();
         return CWin { 
             width: raster.width, 
             height: raster.height,
             visible: true,
-            lib_window: win,
+            keyboard: Keyboard::new(),
+            mouse: Mouse::new(),
+            lib_window: backend,
+            frame_dt: 0.0,
+            recording: None,
+            playback: None,
+            config,
+            frame: Vec::new(),
+            viewport: (0, 0, raster.width, raster.height),
+            window_size,
+            focused: true,
+            open: true,
+            events: Vec::new(),
+            on_resize: None,
         };
     }
 
+    ///The backend, if it is a `T`, e.g. to script a `HeadlessBackend` or read back its frames.
+    pub fn backend_mut<T: Presenter + 'static>(&mut self) -> Option<&mut T> {
+        let backend: &mut dyn Any = self.lib_window.as_any_mut();
+        backend.downcast_mut::<T>()
+    }
+
+    ///Presents `raster`, scaled to the window by the resize mode, then polls events and input. Input queries
+    /// describe the frame between the two most recent `draw` calls.
     pub fn draw(&mut self, raster: &CRaster) {
-        self.lib_window
-            .update_with_buffer(&raster.data, self.width, self.height)
-            .unwrap();
+        crate::profile_scope!("present");
+        self.width = raster.width;
+        self.height = raster.height;
+        let (ww, wh) = self.lib_window.size();
+        if (ww, wh) == (raster.width, raster.height) || ww == 0 || wh == 0 {
+            self.viewport = (0, 0, raster.width, raster.height);
+            if let Err(e) = self.lib_window.present(&raster.data, raster.width, raster.height) {
+                println!("[CWin/WARN] Cannot present frame: {}", e);
+            }
+        }
+        else {
+            self.viewport = self.compute_viewport(ww, wh);
+            self.compose(raster, ww, wh);
+            if let Err(e) = self.lib_window.present(&self.frame, ww, wh) {
+                println!("[CWin/WARN] Cannot present frame: {}", e);
+            }
+        }
+        self.update_events();
+        self.update_input();
+    }
+
+    fn compute_viewport(&self, ww: usize, wh: usize) -> (i64, i64, usize, usize) {
+        let (rw, rh) = (self.width.max(1), self.height.max(1));
+        let (w, h) = match self.config.resize_mode {
+            ResizeMode::Stretch => (ww, wh),
+            ResizeMode::Letterbox => {
+                let s: f64 = (ww as f64 / rw as f64).min(wh as f64 / rh as f64);
+                (((rw as f64*s).round() as usize).max(1), ((rh as f64*s).round() as usize).max(1))
+            }
+            ResizeMode::Integer => {
+                let k: usize = (ww / rw).min(wh / rh).max(1);
+                (rw*k, rh*k)
+            }
+            ResizeMode::ResizeRaster => return (0, 0, rw*self.config.scale, rh*self.config.scale),
+        };
+        ((ww as i64 - w as i64) / 2, (wh as i64 - h as i64) / 2, w, h)
+    }
+
+    //Nearest-neighbour scales the raster into the viewport of a window-sized buffer
+    fn compose(&mut self, raster: &CRaster, ww: usize, wh: usize) {
+        self.frame.clear();
+        self.frame.resize(ww*wh, self.config.background);
+        let (vx, vy, vw, vh) = self.viewport;
+        let columns: Vec<(usize, usize)> = (0..vw)
+            .filter_map(|i| {
+                let x: i64 = vx + i as i64;
+                if x < 0 || x >= ww as i64 { None } else { Some((x as usize, i*raster.width / vw)) }
+            })
+            .collect();
+        for j in 0..vh {
+            let y: i64 = vy + j as i64;
+            if y < 0 || y >= wh as i64 {
+                continue;
+            }
+            let src: &[u32] = &raster.data[(j*raster.height / vh)*raster.width..][..raster.width];
+            let dst: &mut [u32] = &mut self.frame[y as usize*ww..][..ww];
+            for &(x, sx) in columns.iter() {
+                dst[x] = src[sx];
+            }
+        }
+    }
+
+    fn update_events(&mut self) {
+        let size: (usize, usize) = self.lib_window.size();
+        if size != self.window_size {
+            self.window_size = size;
+            self.events.push(WinEvent::Resized { width: size.0, height: size.1 });
+            let (w, h) = self.raster_size_for_window();
+            if let Some(callback) = self.on_resize.as_mut() {
+                callback(w, h);
+            }
+        }
+        let focused: bool = self.lib_window.is_active();
+        if focused != self.focused {
+            self.focused = focused;
+            self.events.push(if focused { WinEvent::FocusGained } else { WinEvent::FocusLost });
+        }
+        if self.open && !self.lib_window.is_open() {
+            self.open = false;
+            self.events.push(WinEvent::CloseRequested);
+        }
+    }
+
+    ///Returns and clears the window events since the last call.
+    pub fn poll_events(&mut self) -> Vec<WinEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    ///Calls `callback` with `raster_size_for_window` whenever the window is resized. In `ResizeRaster` mode, resize
+    /// the raster to that size (for example with `CRen::resize`) and pass it to the next `draw`.
+    pub fn set_resize_callback(&mut self, callback: Box<dyn FnMut(usize, usize)>) {
+        self.on_resize = Some(callback);
+    }
+
+    ///The raster size that fills the window at the configured scale factor.
+    pub fn raster_size_for_window(&self) -> (usize, usize) {
+        let s: usize = self.config.scale.max(1);
+        ((self.window_size.0 / s).max(1), (self.window_size.1 / s).max(1))
+    }
+
+    pub fn window_size(&self) -> (usize, usize) {
+        self.window_size
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn config(&self) -> &CWinConfig {
+        &self.config
+    }
+
+    pub fn set_resize_mode(&mut self, mode: ResizeMode) {
+        self.config.resize_mode = mode;
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.config.title = title.to_string();
+        self.lib_window.set_title(title);
+    }
+
+    pub fn set_position(&mut self, x: isize, y: isize) {
+        self.config.position = (x, y);
+        self.lib_window.set_position(x, y);
+    }
+
+    pub fn set_target_fps(&mut self, fps: usize) {
+        self.config.target_fps = fps;
+        self.lib_window.set_target_fps(fps);
+    }
+
+    //Replaces live input with the next recorded frame while a playback is running, and records the frame in use
+    fn update_input(&mut self) {
+        let live: InputFrame = self.poll_input();
+        let replayed: Option<InputFrame> = self.playback.as_mut().and_then(|p| p.next_frame().cloned());
+        if replayed.is_none() {
+            self.playback = None;
+        }
+        let frame: InputFrame = replayed.unwrap_or(live);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.frames.push(frame.clone());
+        }
+        frame.apply(&mut self.keyboard, &mut self.mouse);
+        self.frame_dt = frame.dt;
+    }
+
+    fn poll_input(&mut self) -> InputFrame {
+        let mut frame: InputFrame = self.lib_window.poll_input();
+        frame.mouse_pos = frame.mouse_pos.map(|(x, y)| self.window_to_raster(x, y));
+        frame.mouse_inside = frame.mouse_pos.is_some_and(|(x, y)| x >= 0.0 && y >= 0.0 && x < self.width as f64 && y < self.height as f64);
+        frame
+    }
+
+    ///Seconds between the two most recent `draw` calls, or the recorded value during playback. Drive simulation
+    /// with this so replays are deterministic.
+    pub fn frame_dt(&self) -> f64 {
+        self.frame_dt
+    }
+
+    ///Starts recording the input of every following frame, discarding any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(InputRecording::new());
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    ///Replays `recording` in place of live input, one frame per `draw`. Live input resumes when it runs out.
+    pub fn start_playback(&mut self, recording: InputRecording) {
+        self.playback = Some(InputPlayback::new(recording));
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    ///Maps a point in window pixels to raster pixels, accounting for scaling and letterboxing.
+    pub fn window_to_raster(&self, x: f64, y: f64) -> (f64, f64) {
+        let (vx, vy, vw, vh) = self.viewport;
+        if vw == 0 || vh == 0 {
+            return (x, y);
+        }
+        ((x - vx as f64)*self.width as f64 / vw as f64, (y - vy as f64)*self.height as f64 / vh as f64)
+    }
+
+    pub fn is_key_down(&self, key: CKey) -> bool {
+        self.keyboard.is_key_down(key)
+    }
+
+    pub fn was_key_pressed(&self, key: CKey) -> bool {
+        self.keyboard.was_key_pressed(key)
+    }
+
+    pub fn was_key_released(&self, key: CKey) -> bool {
+        self.keyboard.was_key_released(key)
+    }
+
+    pub fn was_key_repeated(&self, key: CKey) -> bool {
+        self.keyboard.was_key_repeated(key)
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.keyboard.modifiers()
+    }
+
+    pub fn text_input(&self) -> &str {
+        self.keyboard.text_input()
+    }
+
+    ///Cursor position in raster pixels (last known position if the cursor left the window).
+    pub fn mouse_pos(&self) -> (f64, f64) {
+        self.mouse.position()
+    }
+
+    ///How far the cursor moved since the last frame, in raster pixels.
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse.delta()
+    }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse.is_down(button)
+    }
+
+    pub fn was_mouse_clicked(&self, button: MouseButton) -> bool {
+        self.mouse.was_clicked(button)
+    }
+
+    pub fn was_mouse_released(&self, button: MouseButton) -> bool {
+        self.mouse.was_released(button)
+    }
+
+    pub fn mouse_wheel(&self) -> (f64, f64) {
+        self.mouse.wheel()
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.lib_window.set_cursor_visible(visible);
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.lib_window.set_cursor_shape(shape);
+    }
+
+    ///Sets how long a key is held before it repeats and the time between repeats, in seconds.
+    pub fn set_key_repeat(&mut self, delay: f64, interval: f64) {
+        self.keyboard.repeat_delay = delay;
+        self.keyboard.repeat_interval = interval;
     }
 
     pub fn set_visibility(&mut self, visibility: bool) {
@@ -77,3 +499,88 @@ impl CWin {
 //         lib_window: win,
 //     };
 // }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    //A 2x1 raster showing pixels 1 and 2 in a headless window of `size`
+    fn window(config: CWinConfig, size: (usize, usize)) -> (CWin, CRaster) {
+        let mut raster: CRaster = CRaster::new(2, 1);
+        raster.data = vec![1, 2];
+        let win: CWin = CWin::with_backend(&raster, config, Box::new(HeadlessBackend::new(size.0, size.1)));
+        (win, raster)
+    }
+
+    fn shown(mode: ResizeMode) -> Vec<u32> {
+        let (mut win, raster) = window(CWinConfig::new().resize_mode(mode).background(9), (6, 4));
+        win.draw(&raster);
+        win.backend_mut::<HeadlessBackend>().unwrap().last_frame().to_vec()
+    }
+
+    #[test]
+    fn config_builder() {
+        let config: CWinConfig = CWinConfig::new().title("Test").size(320, 200).scale(0).target_fps(0).resizable(true);
+        assert_eq!((config.title.as_str(), config.size, config.scale), ("Test", Some((320, 200)), 1));
+        assert_eq!((config.target_fps, config.resizable, config.resize_mode), (0, true, ResizeMode::Letterbox));
+    }
+
+    #[test]
+    fn resize_modes() {
+        let bars: Vec<u32> = vec![9; 6];
+        let row: Vec<u32> = vec![1, 1, 1, 2, 2, 2];
+        assert_eq!(shown(ResizeMode::Stretch), [row.clone(), row.clone(), row.clone(), row.clone()].concat());
+        assert_eq!(shown(ResizeMode::Letterbox), [row.clone(), row.clone(), row.clone(), bars.clone()].concat());
+        assert_eq!(shown(ResizeMode::Integer), [row.clone(), row.clone(), row.clone(), bars.clone()].concat());
+        assert_eq!(shown(ResizeMode::ResizeRaster), [vec![1, 2, 9, 9, 9, 9], bars.clone(), bars.clone(), bars].concat());
+
+        //An integer scale leaves bars around the raster when the window is not a multiple of its size
+        let (mut win, raster) = window(CWinConfig::new().resize_mode(ResizeMode::Integer).background(9), (7, 5));
+        win.draw(&raster);
+        let frame: Vec<u32> = win.backend_mut::<HeadlessBackend>().unwrap().last_frame().to_vec();
+        let row: Vec<u32> = vec![1, 1, 1, 2, 2, 2, 9];
+        assert_eq!(frame, [vec![9; 7], row.clone(), row.clone(), row, vec![9; 7]].concat());
+    }
+
+    #[test]
+    fn mouse_in_raster_pixels() {
+        let (mut win, raster) = window(CWinConfig::new(), (6, 4));
+        win.draw(&raster);
+        assert_eq!(win.window_to_raster(3.0, 1.5), (1.0, 0.5));
+        win.backend_mut::<HeadlessBackend>().unwrap().push_input(InputFrame { mouse_pos: Some((3.0, 1.5)), ..InputFrame::default() });
+        win.draw(&raster);
+        assert_eq!(win.mouse_pos(), (1.0, 0.5));
+        assert!(win.mouse.is_inside());
+        //The letterbox bar is outside the raster
+        win.backend_mut::<HeadlessBackend>().unwrap().push_input(InputFrame { mouse_pos: Some((3.0, 3.5)), ..InputFrame::default() });
+        win.draw(&raster);
+        assert!(!win.mouse.is_inside());
+    }
+
+    #[test]
+    fn events() {
+        let (mut win, raster) = window(CWinConfig::new().scale(2), (4, 2));
+        let seen: Rc<Cell<(usize, usize)>> = Rc::new(Cell::new((0, 0)));
+        let s: Rc<Cell<(usize, usize)>> = seen.clone();
+        win.set_resize_callback(Box::new(move |w, h| s.set((w, h))));
+        win.draw(&raster);
+        assert!(win.poll_events().is_empty());
+
+        let backend: &mut HeadlessBackend = win.backend_mut::<HeadlessBackend>().unwrap();
+        backend.resize(6, 4);
+        backend.set_focused(false);
+        win.draw(&raster);
+        assert_eq!(win.poll_events(), vec![WinEvent::Resized { width: 6, height: 4 }, WinEvent::FocusLost]);
+        assert_eq!((seen.get(), win.raster_size_for_window(), win.is_focused()), ((3, 2), (3, 2), false));
+        assert!(win.poll_events().is_empty());
+
+        win.backend_mut::<HeadlessBackend>().unwrap().close();
+        win.draw(&raster);
+        assert_eq!(win.poll_events(), vec![WinEvent::CloseRequested]);
+        assert!(!win.is_open());
+    }
+
+}
@@ -1,7 +1,15 @@
 pub mod ccamera;
+pub mod ccolor;
+pub mod cfilter;
+pub mod cfont;
 pub mod cmodel_instance;
 pub mod cmodel;
+pub mod cpath;
 pub mod craster;
 pub mod cren;
+pub mod cshapes;
+pub mod csprite;
+pub mod ctilemap;
 pub mod ctransform;
+pub mod cttf;
 pub mod triangle;
\ No newline at end of file
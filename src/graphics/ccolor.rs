@@ -0,0 +1,421 @@
+use std::ops::{Add, Mul, Sub};
+use std::sync::OnceLock;
+
+///An RGBA color with f32 channels in the range 0.0-1.0. Unless a function says otherwise, `r`, `g` and `b` are
+/// sRGB-encoded (the same values you would write in a hex code) and `a` is linear coverage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+///A color packed as `0xAARRGGBB`, the pixel layout used by `CRaster`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Color32(pub u32);
+
+impl Color {
+
+    pub const TRANSPARENT: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const GRAY: Color = Color::rgb(0.5, 0.5, 0.5);
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+    pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+    pub const CYAN: Color = Color::rgb(0.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::rgb(1.0, 0.0, 1.0);
+    pub const ORANGE: Color = Color::rgb(1.0, 0.647, 0.0);
+    pub const PURPLE: Color = Color::rgb(0.627, 0.125, 0.941);
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Color {
+        Color { r, g, b, a: 1.0 }
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub fn from_rgba8(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color::rgba(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+    }
+
+    ///Reads a raster pixel (`0x00RRGGBB`). The alpha byte is ignored and the color is treated as opaque, as it is
+    /// everywhere else in the renderer.
+    pub fn from_rgb_u32(color: u32) -> Color {
+        Color::from_rgba8((color >> 16) as u8, (color >> 8) as u8, color as u8, 255)
+    }
+
+    ///Packs the color as a raster pixel (`0x00RRGGBB`), dropping alpha.
+    pub fn to_rgb_u32(&self) -> u32 {
+        self.to_color32().0 & 0x00ffffff
+    }
+
+    pub fn to_color32(&self) -> Color32 {
+        Color32::from_rgba8(unit_to_u8(self.r), unit_to_u8(self.g), unit_to_u8(self.b), unit_to_u8(self.a))
+    }
+
+    ///Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`. The leading `#` is optional.
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let s: &str = hex.trim().trim_start_matches('#');
+        if !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let nibble = |i: usize| u8::from_str_radix(&s[i..i+1], 16).ok().map(|v| v*17);
+        let byte = |i: usize| u8::from_str_radix(&s[i..i+2], 16).ok();
+        match s.len() {
+            3 => Some(Color::from_rgba8(nibble(0)?, nibble(1)?, nibble(2)?, 255)),
+            4 => Some(Color::from_rgba8(nibble(0)?, nibble(1)?, nibble(2)?, nibble(3)?)),
+            6 => Some(Color::from_rgba8(byte(0)?, byte(2)?, byte(4)?, 255)),
+            8 => Some(Color::from_rgba8(byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+            _ => None,
+        }
+    }
+
+    ///Formats the color as `#rrggbbaa`.
+    pub fn to_hex(&self) -> String {
+        let c: Color32 = self.to_color32();
+        format!("#{:02x}{:02x}{:02x}{:02x}", c.r(), c.g(), c.b(), c.a())
+    }
+
+    pub fn with_alpha(&self, a: f32) -> Color {
+        Color { a, ..*self }
+    }
+
+    ///Converts the sRGB-encoded channels to linear light. The returned color holds linear values.
+    pub fn to_linear(&self) -> Color {
+        Color::rgba(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b), self.a)
+    }
+
+    ///Converts a color holding linear values back to sRGB encoding.
+    pub fn from_linear(linear: Color) -> Color {
+        Color::rgba(linear_to_srgb(linear.r), linear_to_srgb(linear.g), linear_to_srgb(linear.b), linear.a)
+    }
+
+    ///Interpolates between two colors in linear light, which avoids the dark band you get from mixing sRGB values.
+    pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+        let (la, lb) = (a.to_linear(), b.to_linear());
+        Color::from_linear(Color::rgba(
+            la.r + (lb.r - la.r)*t,
+            la.g + (lb.g - la.g)*t,
+            la.b + (lb.b - la.b)*t,
+            la.a + (lb.a - la.a)*t,
+        ))
+    }
+
+    ///Interpolates the raw sRGB values. Cheaper than `lerp` but not gamma-correct.
+    pub fn lerp_srgb(a: Color, b: Color, t: f32) -> Color {
+        a + (b - a)*t
+    }
+
+    ///Scales the color's intensity in linear light, e.g. by a lighting term.
+    pub fn shade(&self, intensity: f32) -> Color {
+        let mut l: Color = self.to_linear();
+        l.r *= intensity;
+        l.g *= intensity;
+        l.b *= intensity;
+        Color::from_linear(l).clamped()
+    }
+
+    pub fn clamped(&self) -> Color {
+        Color::rgba(self.r.clamp(0.0, 1.0), self.g.clamp(0.0, 1.0), self.b.clamp(0.0, 1.0), self.a.clamp(0.0, 1.0))
+    }
+
+    ///Relative luminance (Rec. 709) of the color, computed in linear light.
+    pub fn luminance(&self) -> f32 {
+        let l: Color = self.to_linear();
+        0.2126*l.r + 0.7152*l.g + 0.0722*l.b
+    }
+
+    ///Returns `(hue, saturation, value)`. Hue is in degrees (0-360), the others are 0.0-1.0.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max: f32 = self.r.max(self.g).max(self.b);
+        let min: f32 = self.r.min(self.g).min(self.b);
+        let d: f32 = max - min;
+        let s: f32 = if max > 0.0 { d / max } else { 0.0 };
+        (hue(self.r, self.g, self.b, max, d), s, max)
+    }
+
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> Color {
+        let c: f32 = v*s;
+        let (r, g, b) = hue_to_rgb(h, c);
+        let m: f32 = v - c;
+        Color::rgb(r + m, g + m, b + m)
+    }
+
+    ///Returns `(hue, saturation, lightness)`. Hue is in degrees (0-360), the others are 0.0-1.0.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let max: f32 = self.r.max(self.g).max(self.b);
+        let min: f32 = self.r.min(self.g).min(self.b);
+        let d: f32 = max - min;
+        let l: f32 = (max + min) / 2.0;
+        let s: f32 = if d == 0.0 { 0.0 } else { d / (1.0 - (2.0*l - 1.0).abs()) };
+        (hue(self.r, self.g, self.b, max, d), s, l)
+    }
+
+    pub fn from_hsl(h: f32, s: f32, l: f32) -> Color {
+        let c: f32 = (1.0 - (2.0*l - 1.0).abs()) * s;
+        let (r, g, b) = hue_to_rgb(h, c);
+        let m: f32 = l - c/2.0;
+        Color::rgb(r + m, g + m, b + m)
+    }
+
+    ///Returns the Oklab `(L, a, b)` coordinates of the color.
+    pub fn to_oklab(&self) -> (f32, f32, f32) {
+        let c: Color = self.to_linear();
+        let (r, g, b) = (c.r as f64, c.g as f64, c.b as f64);
+        let l: f64 = (0.4122214708*r + 0.5363325363*g + 0.0514459929*b).cbrt();
+        let m: f64 = (0.2119034982*r + 0.6806995451*g + 0.1073969566*b).cbrt();
+        let s: f64 = (0.0883024619*r + 0.2817188376*g + 0.6299787005*b).cbrt();
+        (
+            (0.2104542553*l + 0.7936177850*m - 0.0040720468*s) as f32,
+            (1.9779984951*l - 2.4285922050*m + 0.4505937099*s) as f32,
+            (0.0259040371*l + 0.7827717662*m - 0.8086757660*s) as f32,
+        )
+    }
+
+    pub fn from_oklab(l: f32, a: f32, b: f32) -> Color {
+        let (l, a, b) = (l as f64, a as f64, b as f64);
+        let l_: f64 = l + 0.3963377774*a + 0.2158037573*b;
+        let m_: f64 = l - 0.1055613458*a - 0.0638541728*b;
+        let s_: f64 = l - 0.0894841775*a - 1.2914855480*b;
+        let (l3, m3, s3) = (l_*l_*l_, m_*m_*m_, s_*s_*s_);
+        Color::from_linear(Color::rgb(
+            (4.0767416621*l3 - 3.3077115913*m3 + 0.2309699292*s3) as f32,
+            (-1.2684380046*l3 + 2.6097574011*m3 - 0.3413193965*s3) as f32,
+            (-0.0041960863*l3 - 0.7034186147*m3 + 1.7076147010*s3) as f32,
+        )).clamped()
+    }
+
+}
+
+impl Default for Color {
+    fn default() -> Color {
+        Color::BLACK
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+    fn add(self, o: Color) -> Color {
+        Color::rgba(self.r + o.r, self.g + o.g, self.b + o.b, self.a + o.a)
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, o: Color) -> Color {
+        Color::rgba(self.r - o.r, self.g - o.g, self.b - o.b, self.a - o.a)
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Color;
+    fn mul(self, s: f32) -> Color {
+        Color::rgba(self.r*s, self.g*s, self.b*s, self.a*s)
+    }
+}
+
+///Component-wise multiplication (modulation).
+impl Mul for Color {
+    type Output = Color;
+    fn mul(self, o: Color) -> Color {
+        Color::rgba(self.r*o.r, self.g*o.g, self.b*o.b, self.a*o.a)
+    }
+}
+
+impl Color32 {
+
+    pub const fn from_rgba8(r: u8, g: u8, b: u8, a: u8) -> Color32 {
+        Color32(((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32))
+    }
+
+    pub fn a(&self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    pub fn r(&self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub fn g(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn b(&self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn to_color(&self) -> Color {
+        Color::from_rgba8(self.r(), self.g(), self.b(), self.a())
+    }
+
+}
+
+impl From<Color> for Color32 {
+    fn from(c: Color) -> Color32 {
+        c.to_color32()
+    }
+}
+
+impl From<Color32> for Color {
+    fn from(c: Color32) -> Color {
+        c.to_color()
+    }
+}
+
+impl From<Color32> for u32 {
+    fn from(c: Color32) -> u32 {
+        c.0
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    }
+    else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    }
+    else {
+        1.055*c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+const LINEAR_TO_SRGB_STEPS: usize = 4096;
+
+///Table lookup version of `srgb_to_linear` for 8-bit channels, for use in per-pixel loops.
+pub fn srgb8_to_linear(c: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut t: [f32; 256] = [0.0; 256];
+        for (i, v) in t.iter_mut().enumerate() {
+            *v = srgb_to_linear(i as f32 / 255.0);
+        }
+        t
+    });
+    table[c as usize]
+}
+
+///Table lookup version of `linear_to_srgb` producing an 8-bit channel, for use in per-pixel loops.
+pub fn linear_to_srgb8(c: f32) -> u8 {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        (0..=LINEAR_TO_SRGB_STEPS)
+            .map(|i| unit_to_u8(linear_to_srgb(i as f32 / LINEAR_TO_SRGB_STEPS as f32)))
+            .collect()
+    });
+    table[(c.clamp(0.0, 1.0) * LINEAR_TO_SRGB_STEPS as f32 + 0.5) as usize]
+}
+
+fn unit_to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+fn hue(r: f32, g: f32, b: f32, max: f32, d: f32) -> f32 {
+    if d == 0.0 {
+        return 0.0;
+    }
+    let h: f32 = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    }
+    else if max == g {
+        (b - r) / d + 2.0
+    }
+    else {
+        (r - g) / d + 4.0
+    };
+    h * 60.0
+}
+
+///Returns the `(r, g, b)` of a fully saturated hue with chroma `c`, before the lightness offset is added.
+fn hue_to_rgb(h: f32, c: f32) -> (f32, f32, f32) {
+    let hp: f32 = h.rem_euclid(360.0) / 60.0;
+    let x: f32 = c * (1.0 - (hp.rem_euclid(2.0) - 1.0).abs());
+    match hp as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn close(a: Color, b: Color, eps: f32) -> bool {
+        (a.r - b.r).abs() < eps && (a.g - b.g).abs() < eps && (a.b - b.b).abs() < eps && (a.a - b.a).abs() < eps
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(Color::from_hex("#f80").unwrap().to_hex(), "#ff8800ff");
+        assert_eq!(Color::from_hex("12345678").unwrap().to_hex(), "#12345678");
+        assert_eq!(Color::from_hex("#abc").unwrap().to_rgb_u32(), 0x00aabbcc);
+        assert!(Color::from_hex("#12345").is_none());
+        assert!(Color::from_hex("#éé").is_none());
+        assert_eq!(Color::from_rgb_u32(0xff123456).to_rgb_u32(), 0x00123456);
+    }
+
+    #[test]
+    fn srgb_linear_round_trip() {
+        for i in 0..=255u8 {
+            let c: f32 = i as f32 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5);
+            assert_eq!(srgb8_to_linear(i), srgb_to_linear(c));
+            assert_eq!(linear_to_srgb8(srgb8_to_linear(i)), i);
+        }
+        assert_eq!(linear_to_srgb8(-1.0), 0);
+        assert_eq!(linear_to_srgb8(2.0), 255);
+    }
+
+    #[test]
+    fn lerp_is_gamma_correct() {
+        //Half the light of white is about 0.735 in sRGB, not 0.5
+        let mid: Color = Color::lerp(Color::BLACK, Color::WHITE, 0.5);
+        assert!((mid.r - 0.7354).abs() < 1e-3);
+        assert!(close(Color::lerp_srgb(Color::BLACK, Color::WHITE, 0.5), Color::GRAY, 1e-6));
+        assert!(close(Color::WHITE.shade(0.5), mid, 1e-6));
+    }
+
+    #[test]
+    fn hsv_and_hsl_round_trip() {
+        for c in [Color::RED, Color::ORANGE, Color::PURPLE, Color::rgb(0.2, 0.4, 0.6), Color::GRAY] {
+            let (h, s, v) = c.to_hsv();
+            assert!(close(Color::from_hsv(h, s, v), c, 1e-5));
+            let (h, s, l) = c.to_hsl();
+            assert!(close(Color::from_hsl(h, s, l), c, 1e-5));
+        }
+        assert_eq!(Color::BLUE.to_hsv().0, 240.0);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for c in [Color::CYAN, Color::rgb(0.9, 0.1, 0.3), Color::WHITE] {
+            let (l, a, b) = c.to_oklab();
+            assert!(close(Color::from_oklab(l, a, b), c, 1e-4));
+        }
+        assert!((Color::WHITE.to_oklab().0 - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn color32_channels() {
+        let c: Color32 = Color32::from_rgba8(1, 2, 3, 4);
+        assert_eq!((c.r(), c.g(), c.b(), c.a()), (1, 2, 3, 4));
+        assert_eq!(u32::from(c), 0x04010203);
+        assert_eq!(Color32::from(c.to_color()), c);
+    }
+
+}
@@ -0,0 +1,423 @@
+use crate::graphics::craster::CRaster;
+
+///Describes how a filter samples pixels that fall outside of the raster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeMode {
+    ///Repeats the nearest edge pixel.
+    Clamp,
+    ///Wraps around to the opposite edge.
+    Wrap,
+    ///Reflects back into the raster (`2 1 0 | 0 1 2`).
+    Mirror,
+    ///Uses the given color for every pixel outside of the raster.
+    Constant(u32),
+}
+
+///A 2D convolution kernel. Weights are stored row by row, and the kernel is centered on
+/// `(width/2, height/2)`.
+#[derive(Clone, Debug)]
+pub struct Kernel {
+    pub width: usize,
+    pub height: usize,
+    pub weights: Vec<f64>,
+}
+
+impl Kernel {
+
+    pub fn new(width: usize, height: usize, weights: Vec<f64>) -> Kernel {
+        assert!(weights.len() == width*height, "[Kernel] Expected {} weights for a {}x{} kernel, got {}", width*height, width, height, weights.len());
+        Kernel { width, height, weights }
+    }
+
+    ///Scales the weights so that they sum to 1. Kernels whose weights sum to 0 (edge detectors) are left untouched.
+    pub fn normalize(&mut self) {
+        let sum: f64 = self.weights.iter().sum();
+        if sum != 0.0 {
+            for w in self.weights.iter_mut() {
+                *w /= sum;
+            }
+        }
+    }
+
+    fn get(&self, i: usize, j: usize) -> f64 {
+        self.weights[i + self.width*j]
+    }
+
+}
+
+///Convolves the raster with a non-separable kernel and returns the result.
+pub fn convolve(src: &CRaster, kernel: &Kernel, edge: EdgeMode) -> CRaster {
+    let mut dst: CRaster = CRaster::new(src.width, src.height);
+    let (cx, cy) = ((kernel.width / 2) as i64, (kernel.height / 2) as i64);
+
+    for y in 0..src.height {
+        for x in 0..src.width {
+            let mut acc: [f64; 4] = [0.0; 4];
+            for j in 0..kernel.height {
+                for i in 0..kernel.width {
+                    let w: f64 = kernel.get(i, j);
+                    if w == 0.0 {
+                        continue;
+                    }
+                    let c: u32 = sample(src, x as i64 + i as i64 - cx, y as i64 + j as i64 - cy, edge);
+                    accumulate(&mut acc, c, w);
+                }
+            }
+            dst.data[x + src.width*y] = pack(&acc);
+        }
+    }
+
+    dst
+}
+
+///Convolves the raster with a separable kernel, applying `horizontal` along rows and then `vertical` along columns.
+pub fn convolve_separable(src: &CRaster, horizontal: &[f64], vertical: &[f64], edge: EdgeMode) -> CRaster {
+    let (w, h) = (src.width, src.height);
+    let (cx, cy) = ((horizontal.len() / 2) as i64, (vertical.len() / 2) as i64);
+
+    //The intermediate pass is kept in floating point so rounding only happens once.
+    let mut tmp: Vec<[f64; 4]> = vec![[0.0; 4]; w*h];
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc: [f64; 4] = [0.0; 4];
+            for (i, weight) in horizontal.iter().enumerate() {
+                let c: u32 = sample(src, x as i64 + i as i64 - cx, y as i64, edge);
+                accumulate(&mut acc, c, *weight);
+            }
+            tmp[x + w*y] = acc;
+        }
+    }
+
+    let mut dst: CRaster = CRaster::new(w, h);
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc: [f64; 4] = [0.0; 4];
+            for (j, weight) in vertical.iter().enumerate() {
+                let sy: i64 = y as i64 + j as i64 - cy;
+                let px: [f64; 4] = match edge_index(sy, h, edge) {
+                    Some(iy) => tmp[x + w*iy],
+                    None => {
+                        let c: u32 = match edge { EdgeMode::Constant(c) => c, _ => 0 };
+                        unpack(c)
+                    }
+                };
+                for k in 0..4 {
+                    acc[k] += px[k] * weight;
+                }
+            }
+            dst.data[x + w*y] = pack(&acc);
+        }
+    }
+
+    dst
+}
+
+///Returns the normalized 1D gaussian kernel for `sigma`. The kernel extends 3 standard deviations to each side.
+pub fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+    let radius: i64 = (sigma * 3.0).ceil() as i64;
+    let mut k: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i*i) as f64) / (2.0*sigma*sigma)).exp())
+        .collect();
+    let sum: f64 = k.iter().sum();
+    for w in k.iter_mut() {
+        *w /= sum;
+    }
+    k
+}
+
+pub fn gaussian_blur(src: &CRaster, sigma: f64, edge: EdgeMode) -> CRaster {
+    let k: Vec<f64> = gaussian_kernel(sigma);
+    convolve_separable(src, &k, &k, edge)
+}
+
+///Averages every pixel with its neighbours in a `(2*radius + 1)` square.
+pub fn box_blur(src: &CRaster, radius: usize, edge: EdgeMode) -> CRaster {
+    let n: usize = 2*radius + 1;
+    let k: Vec<f64> = vec![1.0 / n as f64; n];
+    convolve_separable(src, &k, &k, edge)
+}
+
+///Sharpens the raster with an unsharp mask. `amount` of 1.0 adds back the full difference between the
+/// raster and a gaussian blur of radius `sigma`.
+pub fn sharpen(src: &CRaster, amount: f64, sigma: f64, edge: EdgeMode) -> CRaster {
+    let blurred: CRaster = gaussian_blur(src, sigma, edge);
+    let mut dst: CRaster = CRaster::new(src.width, src.height);
+    for i in 0..src.data.len() {
+        let a: [f64; 4] = unpack(src.data[i]);
+        let b: [f64; 4] = unpack(blurred.data[i]);
+        let mut c: [f64; 4] = [0.0; 4];
+        for k in 0..3 {
+            c[k] = a[k] + amount*(a[k] - b[k]);
+        }
+        c[3] = a[3];
+        dst.data[i] = pack(&c);
+    }
+    dst
+}
+
+///Sobel edge detection on the luminance of the raster. Returns a grayscale raster of the gradient magnitude.
+pub fn sobel(src: &CRaster, edge: EdgeMode) -> CRaster {
+    const GX: [f64; 9] = [-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0];
+    const GY: [f64; 9] = [-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0];
+
+    let mut dst: CRaster = CRaster::new(src.width, src.height);
+    for y in 0..src.height {
+        for x in 0..src.width {
+            let (mut gx, mut gy) = (0.0, 0.0);
+            for j in 0..3 {
+                for i in 0..3 {
+                    let c: u32 = sample(src, x as i64 + i as i64 - 1, y as i64 + j as i64 - 1, edge);
+                    let l: f64 = luminance(c);
+                    gx += GX[i + 3*j] * l;
+                    gy += GY[i + 3*j] * l;
+                }
+            }
+            let m: f64 = (gx*gx + gy*gy).sqrt();
+            dst.data[x + src.width*y] = pack(&[m, m, m, 0.0]);
+        }
+    }
+    dst
+}
+
+///Replaces every channel of every pixel with the median of its `(2*radius + 1)` square neighbourhood.
+pub fn median(src: &CRaster, radius: usize, edge: EdgeMode) -> CRaster {
+    let r: i64 = radius as i64;
+    let n: usize = (2*radius + 1) * (2*radius + 1);
+    let mut dst: CRaster = CRaster::new(src.width, src.height);
+    let mut channels: [Vec<u8>; 4] = [Vec::with_capacity(n), Vec::with_capacity(n), Vec::with_capacity(n), Vec::with_capacity(n)];
+
+    for y in 0..src.height {
+        for x in 0..src.width {
+            for c in channels.iter_mut() {
+                c.clear();
+            }
+            for j in -r..=r {
+                for i in -r..=r {
+                    let c: u32 = sample(src, x as i64 + i, y as i64 + j, edge);
+                    for (k, ch) in channels.iter_mut().enumerate() {
+                        ch.push((c >> (8*k)) as u8);
+                    }
+                }
+            }
+            let mut out: u32 = 0;
+            for (k, ch) in channels.iter_mut().enumerate() {
+                ch.sort_unstable();
+                out |= (ch[n / 2] as u32) << (8*k);
+            }
+            dst.data[x + src.width*y] = out;
+        }
+    }
+    dst
+}
+
+///Adds `amount` (-1.0 to 1.0) of full intensity to every color channel.
+pub fn brightness(raster: &mut CRaster, amount: f64) {
+    map_channels(raster, |c| c + amount*255.0);
+}
+
+///Scales every color channel away from (or towards, for `amount < 1.0`) middle gray.
+pub fn contrast(raster: &mut CRaster, amount: f64) {
+    map_channels(raster, |c| (c - 127.5)*amount + 127.5);
+}
+
+///Scales the saturation of every pixel. 0.0 gives grayscale, 1.0 leaves the raster untouched.
+pub fn saturation(raster: &mut CRaster, amount: f64) {
+    for p in raster.data.iter_mut() {
+        let c: [f64; 4] = unpack(*p);
+        let l: f64 = luminance(*p);
+        *p = pack(&[
+            l + (c[0] - l)*amount,
+            l + (c[1] - l)*amount,
+            l + (c[2] - l)*amount,
+            c[3],
+        ]);
+    }
+}
+
+///Applies `out = in^(1/gamma)` to every color channel, with channels normalized to 0.0-1.0.
+pub fn gamma(raster: &mut CRaster, gamma: f64) {
+    let mut table: [u8; 256] = [0; 256];
+    for (i, t) in table.iter_mut().enumerate() {
+        *t = ((i as f64 / 255.0).powf(1.0 / gamma) * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+    }
+    for p in raster.data.iter_mut() {
+        let a: u32 = *p & 0xff000000;
+        let r: u32 = table[((*p >> 16) & 0xff) as usize] as u32;
+        let g: u32 = table[((*p >> 8) & 0xff) as usize] as u32;
+        let b: u32 = table[(*p & 0xff) as usize] as u32;
+        *p = a | (r << 16) | (g << 8) | b;
+    }
+}
+
+pub fn invert(raster: &mut CRaster) {
+    for p in raster.data.iter_mut() {
+        *p ^= 0x00ffffff;
+    }
+}
+
+pub fn grayscale(raster: &mut CRaster) {
+    saturation(raster, 0.0);
+}
+
+///Returns the pixel at `(x, y)`, resolving coordinates outside of the raster with `edge`.
+pub fn sample(raster: &CRaster, x: i64, y: i64, edge: EdgeMode) -> u32 {
+    match (edge_index(x, raster.width, edge), edge_index(y, raster.height, edge)) {
+        (Some(ix), Some(iy)) => raster.data[ix + raster.width*iy],
+        _ => match edge {
+            EdgeMode::Constant(c) => c,
+            _ => 0,
+        },
+    }
+}
+
+fn edge_index(i: i64, n: usize, edge: EdgeMode) -> Option<usize> {
+    let n: i64 = n as i64;
+    if n == 0 {
+        return None;
+    }
+    if i >= 0 && i < n {
+        return Some(i as usize);
+    }
+    match edge {
+        EdgeMode::Clamp => Some(i.clamp(0, n - 1) as usize),
+        EdgeMode::Wrap => Some(i.rem_euclid(n) as usize),
+        EdgeMode::Mirror => {
+            let period: i64 = 2*n;
+            let m: i64 = i.rem_euclid(period);
+            Some(if m < n { m } else { period - 1 - m } as usize)
+        }
+        EdgeMode::Constant(_) => None,
+    }
+}
+
+fn map_channels<F: Fn(f64) -> f64>(raster: &mut CRaster, f: F) {
+    let mut table: [u8; 256] = [0; 256];
+    for (i, t) in table.iter_mut().enumerate() {
+        *t = (f(i as f64) + 0.5).clamp(0.0, 255.0) as u8;
+    }
+    for p in raster.data.iter_mut() {
+        let a: u32 = *p & 0xff000000;
+        let r: u32 = table[((*p >> 16) & 0xff) as usize] as u32;
+        let g: u32 = table[((*p >> 8) & 0xff) as usize] as u32;
+        let b: u32 = table[(*p & 0xff) as usize] as u32;
+        *p = a | (r << 16) | (g << 8) | b;
+    }
+}
+
+///Rec. 601 luma of a 0x00RRGGBB color, in the range 0.0-255.0.
+fn luminance(c: u32) -> f64 {
+    let v: [f64; 4] = unpack(c);
+    0.299*v[0] + 0.587*v[1] + 0.114*v[2]
+}
+
+///Splits a color into `[r, g, b, a]` channels.
+fn unpack(c: u32) -> [f64; 4] {
+    [
+        ((c >> 16) & 0xff) as f64,
+        ((c >> 8) & 0xff) as f64,
+        (c & 0xff) as f64,
+        ((c >> 24) & 0xff) as f64,
+    ]
+}
+
+fn pack(c: &[f64; 4]) -> u32 {
+    let ch = |v: f64| (v + 0.5).clamp(0.0, 255.0) as u32;
+    (ch(c[3]) << 24) | (ch(c[0]) << 16) | (ch(c[1]) << 8) | ch(c[2])
+}
+
+fn accumulate(acc: &mut [f64; 4], c: u32, w: f64) {
+    let v: [f64; 4] = unpack(c);
+    for k in 0..4 {
+        acc[k] += v[k] * w;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn raster(width: usize, height: usize, pixels: &[u32]) -> CRaster {
+        let mut r: CRaster = CRaster::new(width, height);
+        r.data.copy_from_slice(pixels);
+        r
+    }
+
+    #[test]
+    fn edge_modes_resolve_outside_pixels() {
+        let r: CRaster = raster(3, 1, &[1, 2, 3]);
+        assert_eq!(sample(&r, -1, 0, EdgeMode::Clamp), 1);
+        assert_eq!(sample(&r, -1, 0, EdgeMode::Wrap), 3);
+        assert_eq!(sample(&r, -1, 0, EdgeMode::Mirror), 1);
+        assert_eq!(sample(&r, -2, 0, EdgeMode::Mirror), 2);
+        assert_eq!(sample(&r, 4, 0, EdgeMode::Mirror), 2);
+        assert_eq!(sample(&r, 5, 0, EdgeMode::Constant(7)), 7);
+    }
+
+    #[test]
+    fn non_square_rasters_index_by_width() {
+        let mut r: CRaster = CRaster::new(4, 2);
+        r.set(3, 1, 9);
+        assert_eq!(r.data[7], 9);
+        assert_eq!(r.get(3, 1), 9);
+        assert_eq!(sample(&r, 3, 1, EdgeMode::Clamp), 9);
+    }
+
+    #[test]
+    fn gaussian_kernel_is_normalized_and_symmetric() {
+        let k: Vec<f64> = gaussian_kernel(1.5);
+        assert_eq!(k.len(), 11);
+        assert!((k.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        for i in 0..k.len() / 2 {
+            assert!((k[i] - k[k.len() - 1 - i]).abs() < 1e-15);
+        }
+        assert_eq!(gaussian_kernel(0.0), vec![1.0]);
+    }
+
+    #[test]
+    fn blurs_keep_flat_images_flat() {
+        let r: CRaster = raster(4, 3, &[0x00808080; 12]);
+        for out in [gaussian_blur(&r, 2.0, EdgeMode::Clamp), box_blur(&r, 1, EdgeMode::Mirror), median(&r, 1, EdgeMode::Wrap)] {
+            assert!(out.data.iter().all(|p| *p == 0x00808080));
+        }
+    }
+
+    #[test]
+    fn identity_kernel_copies_the_raster() {
+        let r: CRaster = raster(3, 2, &[0x010203, 0x040506, 0x070809, 0x0a0b0c, 0x0d0e0f, 0x101112]);
+        let identity: Kernel = Kernel::new(3, 3, vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(convolve(&r, &identity, EdgeMode::Clamp).data, r.data);
+    }
+
+    #[test]
+    fn median_removes_a_single_hot_pixel() {
+        let mut pixels: Vec<u32> = vec![0; 9];
+        pixels[4] = 0x00ffffff;
+        let out: CRaster = median(&raster(3, 3, &pixels), 1, EdgeMode::Clamp);
+        assert_eq!(out.data[4], 0);
+    }
+
+    #[test]
+    fn sobel_is_zero_on_flat_images() {
+        let out: CRaster = sobel(&raster(3, 3, &[0x00404040; 9]), EdgeMode::Clamp);
+        assert!(out.data.iter().all(|p| *p & 0x00ffffff == 0));
+    }
+
+    #[test]
+    fn color_adjustments() {
+        let mut r: CRaster = raster(2, 1, &[0x00ff0000, 0x00102030]);
+        invert(&mut r);
+        assert_eq!(r.data, vec![0x0000ffff, 0x00efdfcf]);
+        invert(&mut r);
+        grayscale(&mut r);
+        let p: u32 = r.data[0];
+        assert!(p & 0xff == (p >> 8) & 0xff && p & 0xff == (p >> 16) & 0xff);
+        let mut g: CRaster = raster(1, 1, &[0x00808080]);
+        gamma(&mut g, 1.0);
+        assert_eq!(g.data[0], 0x00808080);
+    }
+
+}
@@ -0,0 +1,726 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::sync::OnceLock;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::craster::CRaster;
+use crate::graphics::cren::CRen;
+
+///A single rasterized character. `coverage` holds `width*height` values from 0 (empty) to 255 (solid), row by row.
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    pub width: usize,
+    pub height: usize,
+    ///Horizontal offset from the pen position to the left edge of the bitmap.
+    pub bearing_x: i32,
+    ///Distance from the baseline up to the top edge of the bitmap.
+    pub bearing_y: i32,
+    ///How far the pen moves after drawing this glyph.
+    pub advance: i32,
+    pub coverage: Vec<u8>,
+}
+
+///Horizontal alignment of each line of text relative to the anchor point or rectangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    ///The data is not a font of the expected format, or is truncated.
+    Format(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "[Font] {}", e),
+            FontError::Format(s) => write!(f, "[Font] {}", s),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<std::io::Error> for FontError {
+    fn from(e: std::io::Error) -> FontError {
+        FontError::Io(e)
+    }
+}
+
+///A fixed-size bitmap font.
+#[derive(Clone, Debug)]
+pub struct Font {
+    ///Distance from the top of a line to its baseline.
+    pub ascent: i32,
+    ///Distance between the tops of consecutive lines.
+    pub line_height: i32,
+    ///Drawn in place of characters the font has no glyph for.
+    pub fallback: char,
+    pub glyphs: HashMap<char, Glyph>,
+}
+
+impl Font {
+
+    pub fn new(ascent: i32, line_height: i32) -> Font {
+        Font { ascent, line_height, fallback: '?', glyphs: HashMap::new() }
+    }
+
+    ///The 8x8 font compiled into the crate. It covers printable ASCII.
+    pub fn builtin() -> &'static Font {
+        static FONT: OnceLock<Font> = OnceLock::new();
+        FONT.get_or_init(|| {
+            let mut font: Font = Font::new(7, 9);
+            for (i, rows) in FONT8X8_BASIC.iter().enumerate() {
+                let mut coverage: Vec<u8> = Vec::with_capacity(64);
+                for row in rows.iter() {
+                    for bit in 0..8 {
+                        coverage.push(if row & (1 << bit) != 0 { 255 } else { 0 });
+                    }
+                }
+                let glyph: Glyph = Glyph { width: 8, height: 8, bearing_x: 0, bearing_y: 7, advance: 8, coverage };
+                font.glyphs.insert((0x20 + i as u8) as char, glyph);
+            }
+            font
+        })
+    }
+
+    ///Parses a font in the Glyph Bitmap Distribution Format (BDF).
+    pub fn from_bdf(text: &str) -> Result<Font, FontError> {
+        let mut ascent: Option<i32> = None;
+        let mut descent: Option<i32> = None;
+        let mut bbox_height: i32 = 0;
+        let mut glyphs: HashMap<char, Glyph> = HashMap::new();
+        let mut default_char: Option<char> = None;
+
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => {
+                    bbox_height = words.nth(1).and_then(|v| v.parse().ok()).unwrap_or(0);
+                }
+                Some("FONT_ASCENT") => ascent = words.next().and_then(|v| v.parse().ok()),
+                Some("FONT_DESCENT") => descent = words.next().and_then(|v| v.parse().ok()),
+                Some("DEFAULT_CHAR") => default_char = words.next().and_then(|v| v.parse().ok()).and_then(char::from_u32),
+                Some("STARTCHAR") => {
+                    let mut code: Option<u32> = None;
+                    let mut advance: i32 = 0;
+                    let mut bbx: (usize, usize, i32, i32) = (0, 0, 0, 0);
+                    let mut coverage: Vec<u8> = Vec::new();
+                    loop {
+                        let l: &str = lines.next().ok_or_else(|| FontError::Format("unterminated STARTCHAR".to_string()))?;
+                        let mut w = l.split_whitespace();
+                        match w.next() {
+                            Some("ENCODING") => {
+                                code = w.next().and_then(|v| v.parse::<i64>().ok()).filter(|v| *v >= 0).map(|v| v as u32);
+                            }
+                            Some("DWIDTH") => advance = w.next().and_then(|v| v.parse().ok()).unwrap_or(0),
+                            Some("BBX") => {
+                                let v: Vec<i32> = w.filter_map(|v| v.parse().ok()).collect();
+                                if v.len() != 4 || v[0] < 0 || v[1] < 0 {
+                                    return Err(FontError::Format(format!("bad BBX line '{}'", l)));
+                                }
+                                bbx = (v[0] as usize, v[1] as usize, v[2], v[3]);
+                            }
+                            Some("BITMAP") => {
+                                coverage.clear();
+                                for _ in 0..bbx.1 {
+                                    let row: &str = lines.next().ok_or_else(|| FontError::Format("truncated BITMAP".to_string()))?.trim();
+                                    if row.len() % 2 != 0 {
+                                        return Err(FontError::Format(format!("BITMAP row '{}' has an odd number of digits", row)));
+                                    }
+                                    //Pairs of bytes rather than of chars, so stray non-ASCII text is an error and not a split char
+                                    let bytes: Vec<u8> = row.as_bytes().chunks_exact(2)
+                                        .map(|pair| std::str::from_utf8(pair).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+                                        .collect::<Option<Vec<u8>>>()
+                                        .ok_or_else(|| FontError::Format(format!("bad BITMAP row '{}'", row)))?;
+                                    for x in 0..bbx.0 {
+                                        let set: bool = bytes.get(x / 8).is_some_and(|b| b & (0x80 >> (x % 8)) != 0);
+                                        coverage.push(if set { 255 } else { 0 });
+                                    }
+                                }
+                            }
+                            Some("ENDCHAR") => break,
+                            _ => {}
+                        }
+                    }
+                    //BITMAP before BBX, or a repeated BBX, leaves the bitmap the wrong size for the glyph
+                    if coverage.len() != bbx.0*bbx.1 {
+                        return Err(FontError::Format(format!("glyph bitmap does not match its {}x{} BBX", bbx.0, bbx.1)));
+                    }
+                    if let Some(c) = code.and_then(char::from_u32) {
+                        glyphs.insert(c, Glyph {
+                            width: bbx.0,
+                            height: bbx.1,
+                            bearing_x: bbx.2,
+                            bearing_y: bbx.3 + bbx.1 as i32,
+                            advance,
+                            coverage,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if glyphs.is_empty() {
+            return Err(FontError::Format("no glyphs found in BDF data".to_string()));
+        }
+        let ascent: i32 = ascent.unwrap_or(bbox_height);
+        let line_height: i32 = ascent + descent.unwrap_or(0);
+        let mut font: Font = Font::new(ascent, line_height.max(1));
+        font.glyphs = glyphs;
+        if let Some(c) = default_char {
+            font.fallback = c;
+        }
+        Ok(font)
+    }
+
+    pub fn load_bdf(path: &str) -> Result<Font, FontError> {
+        Font::from_bdf(&fs::read_to_string(path)?)
+    }
+
+    ///Parses a PC Screen Font (PSF1 or PSF2), including its unicode table if it has one. Fonts without a table map
+    /// glyph `i` to character `i`.
+    pub fn from_psf(data: &[u8]) -> Result<Font, FontError> {
+        let truncated = || FontError::Format("truncated PSF data".to_string());
+        let u32_at = |i: usize| -> Result<u32, FontError> {
+            data.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(truncated)
+        };
+
+        let (count, width, height, bytes_per_glyph, glyphs_start, has_table, psf2) =
+            if data.len() >= 4 && data[0] == 0x36 && data[1] == 0x04 {
+                let mode: u8 = data[2];
+                let height: usize = data[3] as usize;
+                let count: usize = if mode & 0x01 != 0 { 512 } else { 256 };
+                (count, 8, height, height, 4, mode & 0x06 != 0, false)
+            }
+            else if data.len() >= 32 && data[0..4] == [0x72, 0xb5, 0x4a, 0x86] {
+                let header_size: usize = u32_at(8)? as usize;
+                let flags: u32 = u32_at(12)?;
+                let count: usize = u32_at(16)? as usize;
+                let bytes_per_glyph: usize = u32_at(20)? as usize;
+                let height: usize = u32_at(24)? as usize;
+                let width: usize = u32_at(28)? as usize;
+                (count, width, height, bytes_per_glyph, header_size, flags & 0x01 != 0, true)
+            }
+            else {
+                return Err(FontError::Format("not a PSF1 or PSF2 font".to_string()));
+            };
+
+        let row_bytes: usize = width.div_ceil(8);
+        if bytes_per_glyph == 0 || row_bytes*height > bytes_per_glyph {
+            return Err(FontError::Format("PSF glyph size does not match its dimensions".to_string()));
+        }
+        let table_start: usize = glyphs_start + count*bytes_per_glyph;
+        if data.len() < table_start {
+            return Err(truncated());
+        }
+
+        let mut bitmaps: Vec<Glyph> = Vec::with_capacity(count);
+        for g in 0..count {
+            let bytes: &[u8] = &data[glyphs_start + g*bytes_per_glyph..glyphs_start + (g + 1)*bytes_per_glyph];
+            let mut coverage: Vec<u8> = Vec::with_capacity(width*height);
+            for y in 0..height {
+                for x in 0..width {
+                    let set: bool = bytes[y*row_bytes + x / 8] & (0x80 >> (x % 8)) != 0;
+                    coverage.push(if set { 255 } else { 0 });
+                }
+            }
+            let ascent: i32 = (height as i32 * 4) / 5;
+            bitmaps.push(Glyph { width, height, bearing_x: 0, bearing_y: ascent, advance: width as i32, coverage });
+        }
+
+        let mut glyphs: HashMap<char, Glyph> = HashMap::new();
+        if has_table {
+            let mut pos: usize = table_start;
+            for glyph in bitmaps.iter() {
+                //Each entry lists the characters the glyph is used for. Multi-codepoint sequences are skipped.
+                if psf2 {
+                    let end: usize = data[pos..].iter().position(|b| *b == 0xff).map_or(data.len(), |e| pos + e);
+                    let entry: &[u8] = &data[pos..end];
+                    let singles: &[u8] = match entry.iter().position(|b| *b == 0xfe) {
+                        Some(s) => &entry[..s],
+                        None => entry,
+                    };
+                    for c in String::from_utf8_lossy(singles).chars() {
+                        glyphs.entry(c).or_insert_with(|| glyph.clone());
+                    }
+                    pos = end + 1;
+                }
+                else {
+                    let mut in_sequence: bool = false;
+                    while pos + 1 < data.len() {
+                        let v: u16 = u16::from_le_bytes([data[pos], data[pos + 1]]);
+                        pos += 2;
+                        match v {
+                            0xffff => break,
+                            0xfffe => in_sequence = true,
+                            _ if !in_sequence => {
+                                if let Some(c) = char::from_u32(v as u32) {
+                                    glyphs.entry(c).or_insert_with(|| glyph.clone());
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                if pos >= data.len() {
+                    break;
+                }
+            }
+        }
+        else {
+            for (i, glyph) in bitmaps.into_iter().enumerate() {
+                if let Some(c) = char::from_u32(i as u32) {
+                    glyphs.insert(c, glyph);
+                }
+            }
+        }
+
+        let mut font: Font = Font::new((height as i32 * 4) / 5, height as i32);
+        font.glyphs = glyphs;
+        Ok(font)
+    }
+
+    pub fn load_psf(path: &str) -> Result<Font, FontError> {
+        Font::from_psf(&fs::read(path)?)
+    }
+
+    ///Slices a glyph atlas into a monospaced font. Cells are `cell_width` x `cell_height` pixels, laid out left to
+    /// right and top to bottom in the order of `chars`. The brightness of each pixel becomes the glyph's coverage, so
+    /// atlases should be drawn light-on-dark.
+    pub fn from_atlas(atlas: &CRaster, cell_width: usize, cell_height: usize, chars: &str) -> Result<Font, FontError> {
+        if cell_width == 0 || cell_height == 0 || cell_width > atlas.width || cell_height > atlas.height {
+            return Err(FontError::Format(format!("{}x{} cells do not fit a {}x{} atlas", cell_width, cell_height, atlas.width, atlas.height)));
+        }
+        let columns: usize = atlas.width / cell_width;
+        let rows: usize = atlas.height / cell_height;
+        let n: usize = chars.chars().count();
+        if n > columns*rows {
+            return Err(FontError::Format(format!("{} characters given for an atlas with {} cells", n, columns*rows)));
+        }
+
+        let ascent: i32 = cell_height as i32;
+        let mut font: Font = Font::new(ascent, cell_height as i32);
+        for (i, c) in chars.chars().enumerate() {
+            let (cx, cy) = ((i % columns)*cell_width, (i / columns)*cell_height);
+            let mut coverage: Vec<u8> = Vec::with_capacity(cell_width*cell_height);
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    let p: Color = Color::from_rgb_u32(atlas.data[(cx + x) + atlas.width*(cy + y)]);
+                    coverage.push((p.r.max(p.g).max(p.b)*255.0 + 0.5) as u8);
+                }
+            }
+            font.glyphs.insert(c, Glyph {
+                width: cell_width,
+                height: cell_height,
+                bearing_x: 0,
+                bearing_y: ascent,
+                advance: cell_width as i32,
+                coverage,
+            });
+        }
+        Ok(font)
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&self.fallback))
+    }
+
+    ///Width in pixels of a single line of text (newlines are not expected).
+    pub fn line_width(&self, line: &str) -> i32 {
+        line.chars().map(|c| self.glyph(c).map_or(0, |g| g.advance)).sum()
+    }
+
+    ///Returns the `(width, height)` of the box `text` occupies, counting every line separated by `\n`.
+    pub fn measure(&self, text: &str) -> (i32, i32) {
+        let mut width: i32 = 0;
+        let mut lines: i32 = 0;
+        for line in text.split('\n') {
+            width = width.max(self.line_width(line));
+            lines += 1;
+        }
+        (width, lines*self.line_height)
+    }
+
+    ///Word-wraps `text` into lines no wider than `max_width` pixels.
+    pub fn wrap(&self, text: &str, max_width: i32) -> Vec<String> {
+        wrap_text(text, max_width as f64, |s| self.line_width(s) as f64)
+    }
+
+}
+
+impl CRen {
+
+    ///Draws `text` with the built-in font, with its top-left corner at `(x, y)`.
+    pub fn draw_text(&mut self, (x, y): (i32, i32), text: &str, color: u32) {
+        self.draw_text_with(Font::builtin(), (x, y), text, Color::from_rgb_u32(color));
+    }
+
+    ///Draws `text` with `font`, with its top-left corner at `(x, y)`. Each `\n` starts a new line.
+    pub fn draw_text_with(&mut self, font: &Font, (x, y): (i32, i32), text: &str, color: Color) {
+        self.draw_text_aligned(font, (x, y), text, TextAlign::Left, color);
+    }
+
+    ///Draws `text` starting at `(x, y)` with each line aligned to `x`: `Left` starts lines at `x`, `Center` centers
+    /// them on it and `Right` ends them at it.
+    pub fn draw_text_aligned(&mut self, font: &Font, (x, y): (i32, i32), text: &str, align: TextAlign, color: Color) {
+        for (i, line) in text.split('\n').enumerate() {
+            let lx: i32 = align_x(x, font.line_width(line), align);
+            self.draw_text_line(font, lx, y + (i as i32)*font.line_height, line, color);
+        }
+    }
+
+    ///Word-wraps `text` to the rectangle `(x, y, w, h)` and draws the lines that fit, aligned within the rectangle.
+    pub fn draw_text_in_rect(&mut self, font: &Font, rect: (i32, i32, i32, i32), text: &str, align: TextAlign, color: Color) {
+        let (x, y, w, h) = rect;
+        let anchor: i32 = match align {
+            TextAlign::Left => x,
+            TextAlign::Center => x + w/2,
+            TextAlign::Right => x + w,
+        };
+        for (i, line) in font.wrap(text, w).iter().enumerate() {
+            let ly: i32 = y + (i as i32)*font.line_height;
+            if ly + font.line_height > y + h {
+                break;
+            }
+            self.draw_text_line(font, align_x(anchor, font.line_width(line), align), ly, line, color);
+        }
+    }
+
+    fn draw_text_line(&mut self, font: &Font, x: i32, y: i32, line: &str, color: Color) {
+        let mut pen: i32 = x;
+        let baseline: i32 = y + font.ascent;
+        for c in line.chars() {
+            let glyph: &Glyph = match font.glyph(c) {
+                Some(g) => g,
+                None => continue,
+            };
+            self.draw_glyph(glyph, pen + glyph.bearing_x, baseline - glyph.bearing_y, color);
+            pen += glyph.advance;
+        }
+    }
+
+    ///Blends a glyph's coverage over the raster with its top-left corner at `(x, y)`.
+    pub fn draw_glyph(&mut self, glyph: &Glyph, x: i32, y: i32, color: Color) {
+        for j in 0..glyph.height {
+            for i in 0..glyph.width {
+                let c: u8 = glyph.coverage[i + glyph.width*j];
+                if c > 0 {
+                    self.blend_pixel(x + i as i32, y + j as i32, color, c as f32 / 255.0);
+                }
+            }
+        }
+    }
+
+}
+
+///Breaks `text` into lines no wider than `max_width`, where `width_of` measures a single line. Lines break at
+/// spaces where possible, words wider than `max_width` are broken between characters, and existing newlines are kept.
+pub(crate) fn wrap_text<F: Fn(&str) -> f64>(text: &str, max_width: f64, width_of: F) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line: String = String::new();
+        for word in paragraph.split(' ') {
+            let candidate: String = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if width_of(&candidate) <= max_width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                out.push(std::mem::take(&mut line));
+            }
+            //The word does not fit on a line of its own, so split it
+            for c in word.chars() {
+                line.push(c);
+                if width_of(&line) > max_width && line.chars().count() > 1 {
+                    line.pop();
+                    out.push(std::mem::take(&mut line));
+                    line.push(c);
+                }
+            }
+        }
+        out.push(line);
+    }
+    out
+}
+
+fn align_x(anchor: i32, width: i32, align: TextAlign) -> i32 {
+    match align {
+        TextAlign::Left => anchor,
+        TextAlign::Center => anchor - width/2,
+        TextAlign::Right => anchor - width,
+    }
+}
+
+///Public domain 8x8 font (font8x8_basic by Daniel Hepper, after the IBM PC BIOS font) for U+0020 to U+007E. Each
+/// byte is a row, and bit 0 is the leftmost pixel.
+const FONT8X8_BASIC: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const BDF: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -1
+STARTPROPERTIES 3
+FONT_ASCENT 5
+FONT_DESCENT 1
+DEFAULT_CHAR 63
+ENDPROPERTIES
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 5 0
+BBX 4 3 0 1
+BITMAP
+60
+90
+F0
+ENDCHAR
+STARTCHAR question
+ENCODING 63
+DWIDTH 5 0
+BBX 2 2 1 0
+BITMAP
+C0
+40
+ENDCHAR
+ENDFONT
+";
+
+    fn bdf_error(text: &str) -> String {
+        match Font::from_bdf(text) {
+            Err(FontError::Format(s)) => s,
+            other => panic!("expected a format error, got {:?}", other.map(|f| f.glyphs.len())),
+        }
+    }
+
+    #[test]
+    fn parse_bdf() {
+        let font: Font = Font::from_bdf(BDF).unwrap();
+        assert_eq!((font.ascent, font.line_height, font.fallback), (5, 6, '?'));
+        let a: &Glyph = font.glyph('A').unwrap();
+        assert_eq!((a.width, a.height, a.bearing_x, a.bearing_y, a.advance), (4, 3, 0, 4, 5));
+        assert_eq!(a.coverage, vec![0, 255, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255]);
+        //Missing characters fall back to DEFAULT_CHAR
+        assert_eq!(font.glyph('z').unwrap().width, 2);
+        assert_eq!(font.measure("AA\nA"), (10, 12));
+    }
+
+    #[test]
+    fn bdf_errors() {
+        assert!(bdf_error("STARTFONT 2.1\nENDFONT\n").contains("no glyphs"));
+        assert!(bdf_error("STARTCHAR A\nENCODING 65\n").contains("unterminated"));
+        assert!(bdf_error("STARTCHAR A\nBBX 8 2 0 0\nBITMAP\nFF\n").contains("truncated"));
+        assert!(bdf_error("STARTCHAR A\nBBX 8 1 0 0\nBITMAP\nF\nENDCHAR\n").contains("odd"));
+        assert!(bdf_error("STARTCHAR A\nBBX 8 1 0 0\nBITMAP\nZZ\nENDCHAR\n").contains("bad BITMAP"));
+        assert!(bdf_error("STARTCHAR A\nBBX 8 -1 0 0\nENDCHAR\n").contains("bad BBX"));
+        //A bitmap read before BBX, or under a different BBX, does not match the glyph size
+        assert!(bdf_error("STARTCHAR A\nBITMAP\nBBX 8 1 0 0\nENDCHAR\n").contains("does not match"));
+        assert!(bdf_error("STARTCHAR A\nBBX 8 1 0 0\nBITMAP\nFF\nBBX 8 2 0 0\nENDCHAR\n").contains("does not match"));
+    }
+
+    fn psf1(mode: u8, height: u8, glyphs: usize, table: &[u16]) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0x36, 0x04, mode, height];
+        for g in 0..glyphs {
+            data.extend((0..height).map(|_| g as u8));
+        }
+        for v in table.iter() {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn parse_psf1() {
+        let font: Font = Font::from_psf(&psf1(0, 4, 256, &[])).unwrap();
+        assert_eq!((font.ascent, font.line_height), (3, 4));
+        //Without a table glyph i is char i, and bit 7 is the leftmost pixel
+        let g: &Glyph = font.glyph('\u{81}').unwrap();
+        assert_eq!(&g.coverage[0..8], &[255, 0, 0, 0, 0, 0, 0, 255]);
+
+        //Glyph 0 maps to 'a' and 'b', a sequence after 0xfffe is skipped, glyph 1 maps to 'c'
+        let mut table: Vec<u16> = vec![0x61, 0x62, 0xfffe, 0x64, 0x65, 0xffff, 0x63, 0xffff];
+        table.extend(std::iter::repeat_n(0xffff, 254));
+        let font: Font = Font::from_psf(&psf1(0x02, 4, 256, &table)).unwrap();
+        assert_eq!(font.glyphs.len(), 3);
+        assert_eq!(font.glyph('c').unwrap().coverage[7], 255);
+        assert!(!font.glyphs.contains_key(&'d'));
+
+        assert!(Font::from_psf(&psf1(0, 4, 255, &[])).is_err());
+        assert!(Font::from_psf(&psf1(0, 0, 256, &[])).is_err());
+        assert!(Font::from_psf(b"not a font").is_err());
+    }
+
+    fn psf2(count: u32, bytes_per_glyph: u32, height: u32, width: u32, table: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0x72, 0xb5, 0x4a, 0x86];
+        for v in [0, 32, table.is_empty() as u32 ^ 1, count, bytes_per_glyph, height, width] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for g in 0..count {
+            data.extend((0..bytes_per_glyph).map(|_| g as u8 | 0x80));
+        }
+        data.extend_from_slice(table);
+        data
+    }
+
+    #[test]
+    fn parse_psf2() {
+        //10 pixels wide, so each row takes two bytes
+        let table: Vec<u8> = ["é".as_bytes(), &[0xfe], b"xy", &[0xff], "€".as_bytes(), &[0xff]].concat();
+        let font: Font = Font::from_psf(&psf2(2, 6, 3, 10, &table)).unwrap();
+        assert_eq!(font.glyphs.len(), 2);
+        let g: &Glyph = font.glyph('€').unwrap();
+        assert_eq!((g.width, g.height), (10, 3));
+        assert_eq!(&g.coverage[0..10], &[255, 0, 0, 0, 0, 0, 0, 255, 255, 0]);
+        assert!(font.glyphs.contains_key(&'é') && !font.glyphs.contains_key(&'x'));
+
+        let font: Font = Font::from_psf(&psf2(3, 1, 1, 8, &[])).unwrap();
+        assert_eq!(font.glyph('\u{2}').unwrap().coverage[6], 255);
+
+        assert!(Font::from_psf(&psf2(2, 0, 0, 0, &[])).is_err());
+        assert!(Font::from_psf(&psf2(2, 4, 3, 10, &[])).is_err());
+        let mut short: Vec<u8> = psf2(2, 1, 1, 8, &[]);
+        short.pop();
+        assert!(Font::from_psf(&short).is_err());
+    }
+
+    #[test]
+    fn atlas() {
+        let mut atlas: CRaster = CRaster::new(4, 2);
+        atlas.data[1] = 0x00ffffff;
+        atlas.data[6] = 0x00008000;
+        let font: Font = Font::from_atlas(&atlas, 2, 2, "ab").unwrap();
+        assert_eq!(font.glyph('a').unwrap().coverage, vec![0, 255, 0, 0]);
+        assert_eq!(font.glyph('b').unwrap().coverage, vec![0, 0, 128, 0]);
+        assert!(Font::from_atlas(&atlas, 2, 2, "abc").is_err());
+        assert!(Font::from_atlas(&atlas, 0, 2, "a").is_err());
+    }
+
+    #[test]
+    fn wrap() {
+        let width = |s: &str| s.chars().count() as f64;
+        assert_eq!(wrap_text("one two three", 7.0, width), vec!["one two", "three"]);
+        assert_eq!(wrap_text("abcdefgh", 3.0, width), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap_text("a\n\nb", 10.0, width), vec!["a", "", "b"]);
+        //A single character wider than the line still goes somewhere
+        assert_eq!(wrap_text("ab", 0.0, width), vec!["a", "b"]);
+        assert_eq!(Font::builtin().wrap("hi there", 40), vec!["hi", "there"]);
+    }
+
+    #[test]
+    fn aligned_text() {
+        let font: &Font = Font::builtin();
+        let mut ren: CRen = CRen::new(32, 16);
+        ren.draw_text_aligned(font, (16, 0), "|", TextAlign::Right, Color::WHITE);
+        assert!((0..8).any(|y| (8..16).any(|x| ren.raster.data[x + 32*y] != 0)));
+        assert!(ren.raster.data.iter().enumerate().all(|(i, p)| *p == 0 || i % 32 < 16));
+        assert_eq!(align_x(10, 4, TextAlign::Center), 8);
+    }
+
+}
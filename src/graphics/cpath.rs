@@ -0,0 +1,663 @@
+use std::f64::consts::PI;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::cren::{join_shape, normalize, CRen, JoinShape, LineCap, StrokeStyle};
+
+///Default flattening tolerance, in pixels: the furthest a flattened curve may stray from the true curve.
+pub const DEFAULT_TOLERANCE: f64 = 0.2;
+
+///Number of sub-scanlines sampled per pixel row. Coverage along a sub-scanline is computed exactly.
+const SUBSAMPLES: usize = 16;
+
+///A single path command. Coordinates are absolute, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCmd {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    ///Quadratic bezier: control point, then end point.
+    QuadTo(f64, f64, f64, f64),
+    ///Cubic bezier: two control points, then end point.
+    CubicTo(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+///Decides which regions enclosed by a path are inside it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    ///Inside wherever the path winds around a point a non-zero number of times.
+    NonZero,
+    ///Inside wherever a ray from the point crosses the path an odd number of times.
+    EvenOdd,
+}
+
+///A flattened subpath.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<(f64, f64)>,
+    pub closed: bool,
+}
+
+///A vector path made of lines and bezier curves, built SVG/canvas style:
+///
+///```ignore
+///let mut p = Path::new();
+///p.move_to(10.0, 10.0).line_to(90.0, 10.0).quad_to(90.0, 90.0, 10.0, 90.0).close();
+///ren.fill_path(&p, FillRule::NonZero, Color::RED);
+///```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    pub cmds: Vec<PathCmd>,
+    start: Option<(f64, f64)>,
+    current: Option<(f64, f64)>,
+}
+
+impl Path {
+
+    pub fn new() -> Path {
+        Path { cmds: Vec::new(), start: None, current: None }
+    }
+
+    ///Starts a new subpath at `(x, y)`.
+    pub fn move_to(&mut self, x: f64, y: f64) -> &mut Path {
+        self.cmds.push(PathCmd::MoveTo(x, y));
+        self.start = Some((x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    pub fn line_to(&mut self, x: f64, y: f64) -> &mut Path {
+        self.ensure_subpath(x, y);
+        self.cmds.push(PathCmd::LineTo(x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    pub fn quad_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) -> &mut Path {
+        self.ensure_subpath(cx, cy);
+        self.cmds.push(PathCmd::QuadTo(cx, cy, x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    pub fn cubic_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) -> &mut Path {
+        self.ensure_subpath(c1x, c1y);
+        self.cmds.push(PathCmd::CubicTo(c1x, c1y, c2x, c2y, x, y));
+        self.current = Some((x, y));
+        self
+    }
+
+    ///Canvas-style tangent arc: adds a circular arc of `radius` that is tangent to the line from the current point
+    /// to `(x1, y1)` and to the line from `(x1, y1)` to `(x2, y2)`, preceded by a straight line to the arc's start.
+    pub fn arc_to(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, radius: f64) -> &mut Path {
+        let p0: (f64, f64) = match self.current {
+            Some(p) => p,
+            None => return self.move_to(x1, y1),
+        };
+        let v0: (f64, f64) = normalize(p0.0 - x1, p0.1 - y1);
+        let v2: (f64, f64) = normalize(x2 - x1, y2 - y1);
+        let cos_theta: f64 = (v0.0*v2.0 + v0.1*v2.1).clamp(-1.0, 1.0);
+        let cross: f64 = v0.0*v2.1 - v0.1*v2.0;
+        if radius <= 0.0 || cross.abs() < 1e-9 || (v0 == (0.0, 0.0)) || (v2 == (0.0, 0.0)) {
+            return self.line_to(x1, y1);
+        }
+
+        let half: f64 = cos_theta.acos() / 2.0;
+        let d: f64 = radius / half.tan();
+        let t0: (f64, f64) = (x1 + v0.0*d, y1 + v0.1*d);
+        let t2: (f64, f64) = (x1 + v2.0*d, y1 + v2.1*d);
+        let bis: (f64, f64) = normalize(v0.0 + v2.0, v0.1 + v2.1);
+        let h: f64 = radius / half.sin();
+        let c: (f64, f64) = (x1 + bis.0*h, y1 + bis.1*h);
+
+        let a0: f64 = (t0.1 - c.1).atan2(t0.0 - c.0);
+        let mut a1: f64 = (t2.1 - c.1).atan2(t2.0 - c.0);
+        //Take the short way around
+        let mut sweep: f64 = a1 - a0;
+        if sweep > PI {
+            sweep -= 2.0*PI;
+        }
+        else if sweep < -PI {
+            sweep += 2.0*PI;
+        }
+        a1 = a0 + sweep;
+
+        self.line_to(t0.0, t0.1);
+        self.arc_segments(c.0, c.1, radius, radius, a0, a1);
+        self
+    }
+
+    ///Adds a circular arc around `(cx, cy)` from angle `start` to `end` (radians, clockwise on screen from the positive
+    /// x-axis). A line connects the current point to the start of the arc.
+    pub fn arc(&mut self, cx: f64, cy: f64, r: f64, start: f64, end: f64) -> &mut Path {
+        self.ellipse_arc(cx, cy, r, r, start, end)
+    }
+
+    pub fn ellipse_arc(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, start: f64, end: f64) -> &mut Path {
+        let p: (f64, f64) = (cx + rx*start.cos(), cy + ry*start.sin());
+        if self.current.is_some() {
+            self.line_to(p.0, p.1);
+        }
+        else {
+            self.move_to(p.0, p.1);
+        }
+        self.arc_segments(cx, cy, rx, ry, start, end);
+        self
+    }
+
+    ///Closes the current subpath with a straight line back to its start.
+    pub fn close(&mut self) -> &mut Path {
+        if self.current.is_some() {
+            self.cmds.push(PathCmd::Close);
+            self.current = self.start;
+        }
+        self
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, w: f64, h: f64) -> &mut Path {
+        self.move_to(x, y).line_to(x + w, y).line_to(x + w, y + h).line_to(x, y + h).close()
+    }
+
+    pub fn circle(&mut self, cx: f64, cy: f64, r: f64) -> &mut Path {
+        self.ellipse(cx, cy, r, r)
+    }
+
+    pub fn ellipse(&mut self, cx: f64, cy: f64, rx: f64, ry: f64) -> &mut Path {
+        self.move_to(cx + rx, cy);
+        self.arc_segments(cx, cy, rx, ry, 0.0, 2.0*PI);
+        self.close()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    ///Converts the path into polylines, subdividing each curve into just enough segments to stay within
+    /// `tolerance` pixels of it.
+    pub fn flatten(&self, tolerance: f64) -> Vec<Polyline> {
+        let tolerance: f64 = tolerance.max(1e-3);
+        let mut out: Vec<Polyline> = Vec::new();
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut last: (f64, f64) = (0.0, 0.0);
+
+        let finish = |points: &mut Vec<(f64, f64)>, closed: bool, out: &mut Vec<Polyline>| {
+            if points.len() > 1 {
+                out.push(Polyline { points: std::mem::take(points), closed });
+            }
+            points.clear();
+        };
+
+        for cmd in self.cmds.iter() {
+            //Drawing after a Close continues from the closed subpath's start.
+            if points.is_empty() && !matches!(cmd, PathCmd::MoveTo(..) | PathCmd::Close) {
+                points.push(last);
+            }
+            match *cmd {
+                PathCmd::MoveTo(x, y) => {
+                    finish(&mut points, false, &mut out);
+                    points.push((x, y));
+                    last = (x, y);
+                }
+                PathCmd::LineTo(x, y) => {
+                    points.push((x, y));
+                    last = (x, y);
+                }
+                PathCmd::QuadTo(cx, cy, x, y) => {
+                    let (p0, p1, p2) = (last, (cx, cy), (x, y));
+                    let dd: f64 = length(p0.0 - 2.0*p1.0 + p2.0, p0.1 - 2.0*p1.1 + p2.1);
+                    let n: usize = segment_count(0.25*dd, tolerance);
+                    for i in 1..(n + 1) {
+                        let t: f64 = i as f64 / n as f64;
+                        let mt: f64 = 1.0 - t;
+                        points.push((
+                            mt*mt*p0.0 + 2.0*mt*t*p1.0 + t*t*p2.0,
+                            mt*mt*p0.1 + 2.0*mt*t*p1.1 + t*t*p2.1,
+                        ));
+                    }
+                    last = p2;
+                }
+                PathCmd::CubicTo(c1x, c1y, c2x, c2y, x, y) => {
+                    let (p0, p1, p2, p3) = (last, (c1x, c1y), (c2x, c2y), (x, y));
+                    let dd0: f64 = length(p0.0 - 2.0*p1.0 + p2.0, p0.1 - 2.0*p1.1 + p2.1);
+                    let dd1: f64 = length(p1.0 - 2.0*p2.0 + p3.0, p1.1 - 2.0*p2.1 + p3.1);
+                    let n: usize = segment_count(0.75*dd0.max(dd1), tolerance);
+                    for i in 1..(n + 1) {
+                        let t: f64 = i as f64 / n as f64;
+                        let mt: f64 = 1.0 - t;
+                        let (a, b, c, d) = (mt*mt*mt, 3.0*mt*mt*t, 3.0*mt*t*t, t*t*t);
+                        points.push((
+                            a*p0.0 + b*p1.0 + c*p2.0 + d*p3.0,
+                            a*p0.1 + b*p1.1 + c*p2.1 + d*p3.1,
+                        ));
+                    }
+                    last = p3;
+                }
+                PathCmd::Close => {
+                    if let Some(s) = points.first().copied() {
+                        last = s;
+                    }
+                    finish(&mut points, true, &mut out);
+                }
+            }
+        }
+        finish(&mut points, false, &mut out);
+        out
+    }
+
+    fn ensure_subpath(&mut self, x: f64, y: f64) {
+        if self.current.is_none() {
+            self.move_to(x, y);
+        }
+    }
+
+    ///Approximates an elliptical arc with cubic beziers of at most 90 degrees each.
+    fn arc_segments(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, start: f64, end: f64) {
+        let sweep: f64 = end - start;
+        let n: usize = ((sweep.abs() / (PI / 2.0)).ceil() as usize).max(1);
+        let step: f64 = sweep / n as f64;
+        let k: f64 = 4.0 / 3.0 * (step / 4.0).tan();
+        for i in 0..n {
+            let a: f64 = start + step*(i as f64);
+            let b: f64 = a + step;
+            let (ca, sa, cb, sb) = (a.cos(), a.sin(), b.cos(), b.sin());
+            self.cubic_to(
+                cx + rx*(ca - k*sa), cy + ry*(sa + k*ca),
+                cx + rx*(cb + k*sb), cy + ry*(sb - k*cb),
+                cx + rx*cb, cy + ry*sb,
+            );
+        }
+    }
+
+}
+
+///Anti-aliased coverage (0.0-1.0) of a filled shape over a rectangular area, produced by `rasterize`.
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub x0: i32,
+    pub y0: i32,
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f32>,
+}
+
+impl Coverage {
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        self.data[i + self.width*j]
+    }
+}
+
+///Scanline-rasterizes closed polygons with the given fill rule, clipped to `(0, 0)-(clip_w, clip_h)`. Pixel
+/// centers are at integer coordinates. Each row is sampled on several sub-scanlines, and along every
+/// sub-scanline the horizontal coverage of the pixels at the span ends is computed exactly.
+pub fn rasterize(polygons: &[Vec<(f64, f64)>], rule: FillRule, clip_w: usize, clip_h: usize) -> Coverage {
+    //Edges as (x_top, y_top, x_bottom, y_bottom, winding)
+    let mut edges: Vec<(f64, f64, f64, f64, i32)> = Vec::new();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for poly in polygons.iter() {
+        let n: usize = poly.len();
+        for i in 0..n {
+            let (a, b) = (poly[i], poly[(i + 1) % n]);
+            min_x = min_x.min(a.0);
+            min_y = min_y.min(a.1);
+            max_x = max_x.max(a.0);
+            max_y = max_y.max(a.1);
+            if a.1 == b.1 {
+                continue;
+            }
+            if a.1 < b.1 {
+                edges.push((a.0, a.1, b.0, b.1, 1));
+            }
+            else {
+                edges.push((b.0, b.1, a.0, a.1, -1));
+            }
+        }
+    }
+
+    let empty = Coverage { x0: 0, y0: 0, width: 0, height: 0, data: Vec::new() };
+    if edges.is_empty() {
+        return empty;
+    }
+
+    let x0: i32 = (min_x.floor() as i32 - 1).max(0);
+    let y0: i32 = (min_y.floor() as i32 - 1).max(0);
+    let x1: i32 = (max_x.ceil() as i32 + 1).min(clip_w as i32 - 1);
+    let y1: i32 = (max_y.ceil() as i32 + 1).min(clip_h as i32 - 1);
+    if x1 < x0 || y1 < y0 {
+        return empty;
+    }
+    let width: usize = (x1 - x0 + 1) as usize;
+    let height: usize = (y1 - y0 + 1) as usize;
+    let mut data: Vec<f32> = vec![0.0; width*height];
+
+    edges.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut next_edge: usize = 0;
+    let mut active: Vec<usize> = Vec::new();
+    let mut crossings: Vec<(f64, i32)> = Vec::new();
+    let weight: f32 = 1.0 / SUBSAMPLES as f32;
+
+    for row in 0..height {
+        let py: f64 = (y0 + row as i32) as f64;
+        let line: &mut [f32] = &mut data[row*width..(row + 1)*width];
+
+        for s in 0..SUBSAMPLES {
+            let sy: f64 = py - 0.5 + (s as f64 + 0.5) / SUBSAMPLES as f64;
+
+            while next_edge < edges.len() && edges[next_edge].1 <= sy {
+                active.push(next_edge);
+                next_edge += 1;
+            }
+            active.retain(|&e| edges[e].3 > sy);
+
+            crossings.clear();
+            for &e in active.iter() {
+                let (xa, ya, xb, yb, w) = edges[e];
+                if ya <= sy && sy < yb {
+                    crossings.push((xa + (sy - ya) / (yb - ya) * (xb - xa), w));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut winding: i32 = 0;
+            for i in 0..crossings.len() {
+                winding += crossings[i].1;
+                let inside: bool = match rule {
+                    FillRule::NonZero => winding != 0,
+                    FillRule::EvenOdd => winding % 2 != 0,
+                };
+                if inside && i + 1 < crossings.len() {
+                    add_span(line, x0, crossings[i].0, crossings[i + 1].0, weight);
+                }
+            }
+        }
+    }
+
+    for c in data.iter_mut() {
+        *c = c.min(1.0);
+    }
+
+    Coverage { x0, y0, width, height, data }
+}
+
+///Adds `weight` times the horizontal overlap of `[xa, xb)` with each pixel of the row.
+fn add_span(line: &mut [f32], x0: i32, xa: f64, xb: f64, weight: f32) {
+    //Pixel i of the row covers [x0 + i - 0.5, x0 + i + 0.5)
+    let fa: f64 = xa - x0 as f64 + 0.5;
+    let fb: f64 = xb - x0 as f64 + 0.5;
+    let n: f64 = line.len() as f64;
+    let (fa, fb) = (fa.clamp(0.0, n), fb.clamp(0.0, n));
+    if fb <= fa {
+        return;
+    }
+    let ia: usize = fa.floor() as usize;
+    let ib: usize = fb.floor() as usize;
+    if ia == ib {
+        line[ia] += weight * (fb - fa) as f32;
+        return;
+    }
+    line[ia] += weight * (ia as f64 + 1.0 - fa) as f32;
+    for c in line.iter_mut().take(ib).skip(ia + 1) {
+        *c += weight;
+    }
+    if ib < line.len() {
+        line[ib] += weight * (fb - ib as f64) as f32;
+    }
+}
+
+///Converts a stroke of the given polylines into polygons whose non-zero union is the stroke outline.
+pub fn stroke_polygons(polylines: &[Polyline], style: &StrokeStyle, tolerance: f64) -> Vec<Vec<(f64, f64)>> {
+    let hw: f64 = style.width / 2.0;
+    let mut out: Vec<Vec<(f64, f64)>> = Vec::new();
+    if hw <= 0.0 {
+        return out;
+    }
+
+    for pl in polylines.iter() {
+        let mut pts: Vec<(f64, f64)> = Vec::with_capacity(pl.points.len());
+        for p in pl.points.iter() {
+            if pts.last().is_none_or(|l: &(f64, f64)| length(l.0 - p.0, l.1 - p.1) > 1e-9) {
+                pts.push(*p);
+            }
+        }
+        if pl.closed && pts.len() > 2 && length(pts[0].0 - pts[pts.len() - 1].0, pts[0].1 - pts[pts.len() - 1].1) <= 1e-9 {
+            pts.pop();
+        }
+        let n: usize = pts.len();
+        if n == 0 {
+            continue;
+        }
+        if n == 1 {
+            let (x, y) = pts[0];
+            match style.cap {
+                LineCap::Round => out.push(circle_polygon(x, y, hw, tolerance)),
+                LineCap::Square => out.push(vec![(x - hw, y - hw), (x + hw, y - hw), (x + hw, y + hw), (x - hw, y + hw)]),
+                LineCap::Butt => {}
+            }
+            continue;
+        }
+
+        let closed: bool = pl.closed && n > 2;
+        let segments: usize = if closed { n } else { n - 1 };
+        for i in 0..segments {
+            let (mut a, mut b) = (pts[i], pts[(i + 1) % n]);
+            let (dx, dy) = normalize(b.0 - a.0, b.1 - a.1);
+            if !closed && style.cap == LineCap::Square {
+                if i == 0 {
+                    a = (a.0 - dx*hw, a.1 - dy*hw);
+                }
+                if i == segments - 1 {
+                    b = (b.0 + dx*hw, b.1 + dy*hw);
+                }
+            }
+            let (nx, ny) = (-dy*hw, dx*hw);
+            out.push(vec![(a.0 + nx, a.1 + ny), (b.0 + nx, b.1 + ny), (b.0 - nx, b.1 - ny), (a.0 - nx, a.1 - ny)]);
+        }
+
+        if !closed && style.cap == LineCap::Round {
+            out.push(circle_polygon(pts[0].0, pts[0].1, hw, tolerance));
+            out.push(circle_polygon(pts[n - 1].0, pts[n - 1].1, hw, tolerance));
+        }
+
+        let joins: Vec<usize> = if closed { (0..n).collect() } else { (1..(n - 1)).collect() };
+        for i in joins {
+            let a: (f64, f64) = pts[(i + n - 1) % n];
+            let c: (f64, f64) = pts[(i + 1) % n];
+            if let Some(poly) = join_polygon(a, pts[i], c, hw, style, tolerance) {
+                out.push(poly);
+            }
+        }
+    }
+
+    //Non-zero union only works when every piece winds the same way.
+    for poly in out.iter_mut() {
+        if signed_area(poly) < 0.0 {
+            poly.reverse();
+        }
+    }
+    out
+}
+
+///The polygon filling the outer side of the corner at `b` between segments `a-b` and `b-c`.
+fn join_polygon(a: (f64, f64), b: (f64, f64), c: (f64, f64), hw: f64, style: &StrokeStyle, tolerance: f64) -> Option<Vec<(f64, f64)>> {
+    match join_shape(a, b, c, hw, style)? {
+        JoinShape::Disc => Some(circle_polygon(b.0, b.1, hw, tolerance)),
+        JoinShape::Polygon(poly) => Some(poly),
+    }
+}
+
+fn circle_polygon(cx: f64, cy: f64, r: f64, tolerance: f64) -> Vec<(f64, f64)> {
+    //Segment count keeping the chord's sagitta within tolerance
+    let n: usize = if r <= tolerance { 8 } else { ((PI / (1.0 - tolerance / r).acos()).ceil() as usize).clamp(8, 256) };
+    (0..n)
+        .map(|i| {
+            let a: f64 = 2.0*PI*(i as f64) / n as f64;
+            (cx + r*a.cos(), cy + r*a.sin())
+        })
+        .collect()
+}
+
+fn signed_area(poly: &[(f64, f64)]) -> f64 {
+    let n: usize = poly.len();
+    let mut area: f64 = 0.0;
+    for i in 0..n {
+        let (a, b) = (poly[i], poly[(i + 1) % n]);
+        area += a.0*b.1 - b.0*a.1;
+    }
+    area / 2.0
+}
+
+///Wang's formula: number of line segments needed for a bezier whose scaled second difference is `dd`.
+fn segment_count(dd: f64, tolerance: f64) -> usize {
+    ((dd / tolerance).sqrt().ceil() as usize).clamp(1, 1024)
+}
+
+fn length(x: f64, y: f64) -> f64 {
+    (x*x + y*y).sqrt()
+}
+
+impl CRen {
+
+    ///Fills `path` with anti-aliasing. Open subpaths are closed implicitly.
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule, color: Color) {
+        let polygons: Vec<Vec<(f64, f64)>> = path
+            .flatten(DEFAULT_TOLERANCE)
+            .into_iter()
+            .map(|p| p.points)
+            .collect();
+        self.fill_polygons(&polygons, rule, color);
+    }
+
+    ///Strokes `path` with anti-aliasing, using the width, caps and joins of `style`.
+    pub fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, color: Color) {
+        let polylines: Vec<Polyline> = path.flatten(DEFAULT_TOLERANCE);
+        let polygons: Vec<Vec<(f64, f64)>> = stroke_polygons(&polylines, style, DEFAULT_TOLERANCE);
+        self.fill_polygons(&polygons, FillRule::NonZero, color);
+    }
+
+    ///Fills arbitrary (possibly concave or self-intersecting) polygons with anti-aliasing.
+    pub fn fill_polygons(&mut self, polygons: &[Vec<(f64, f64)>], rule: FillRule, color: Color) {
+        let coverage: Coverage = rasterize(polygons, rule, self.raster.width, self.raster.height);
+        self.blend_coverage(&coverage, color);
+    }
+
+    ///Blends `color` over the raster, weighted by `coverage`.
+    pub fn blend_coverage(&mut self, coverage: &Coverage, color: Color) {
+        for j in 0..coverage.height {
+            for i in 0..coverage.width {
+                let c: f32 = coverage.get(i, j);
+                if c > 0.0 {
+                    self.blend_pixel(coverage.x0 + i as i32, coverage.y0 + j as i32, color, c);
+                }
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn square(x: f64, y: f64, s: f64) -> Vec<(f64, f64)> {
+        vec![(x, y), (x + s, y), (x + s, y + s), (x, y + s)]
+    }
+
+    fn total(c: &Coverage) -> f64 {
+        c.data.iter().map(|v| *v as f64).sum()
+    }
+
+    fn at(c: &Coverage, x: i32, y: i32) -> f32 {
+        c.get((x - c.x0) as usize, (y - c.y0) as usize)
+    }
+
+    #[test]
+    fn flatten_lines_and_curves() {
+        let mut p: Path = Path::new();
+        p.rect(1.0, 2.0, 3.0, 4.0);
+        let pl: Vec<Polyline> = p.flatten(DEFAULT_TOLERANCE);
+        assert_eq!(pl, vec![Polyline { points: vec![(1.0, 2.0), (4.0, 2.0), (4.0, 6.0), (1.0, 6.0)], closed: true }]);
+
+        //Drawing after a close starts from the closed subpath's start
+        p.line_to(9.0, 9.0);
+        let pl: Vec<Polyline> = p.flatten(DEFAULT_TOLERANCE);
+        assert_eq!(pl[1], Polyline { points: vec![(1.0, 2.0), (9.0, 9.0)], closed: false });
+
+        let mut q: Path = Path::new();
+        q.move_to(0.0, 0.0).quad_to(50.0, 100.0, 100.0, 0.0);
+        let pts: Vec<(f64, f64)> = q.flatten(0.1).remove(0).points;
+        assert!(pts.len() > 10);
+        assert_eq!(pts[pts.len() - 1], (100.0, 0.0));
+        //The curve peaks at half the control point's height
+        let top: f64 = pts.iter().map(|p| p.1).fold(0.0, f64::max);
+        assert!((top - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn rasterize_square() {
+        let c: Coverage = rasterize(&[square(2.5, 2.5, 4.0)], FillRule::NonZero, 16, 16);
+        assert!((total(&c) - 16.0).abs() < 1e-3);
+        assert_eq!(at(&c, 4, 4), 1.0);
+        assert_eq!(at(&c, 2, 4), 0.0);
+
+        //A square on pixel boundaries is half covered along its edges
+        let c: Coverage = rasterize(&[square(2.0, 2.0, 4.0)], FillRule::NonZero, 16, 16);
+        assert!((at(&c, 2, 4) - 0.5).abs() < 1e-3);
+        assert!((at(&c, 2, 2) - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rasterize_clips_to_area() {
+        let c: Coverage = rasterize(&[square(-10.0, -10.0, 15.0)], FillRule::NonZero, 8, 8);
+        assert!(c.x0 >= 0 && c.y0 >= 0);
+        assert!((c.x0 as usize + c.width) <= 8 && (c.y0 as usize + c.height) <= 8);
+        assert_eq!(at(&c, 0, 0), 1.0);
+    }
+
+    #[test]
+    fn fill_rules() {
+        let polys: Vec<Vec<(f64, f64)>> = vec![square(0.5, 0.5, 12.0), square(4.5, 4.5, 4.0)];
+        let non_zero: Coverage = rasterize(&polys, FillRule::NonZero, 16, 16);
+        let even_odd: Coverage = rasterize(&polys, FillRule::EvenOdd, 16, 16);
+        assert_eq!(at(&non_zero, 6, 6), 1.0);
+        assert_eq!(at(&even_odd, 6, 6), 0.0);
+        assert_eq!(at(&even_odd, 2, 2), 1.0);
+
+        //With the inner square wound the other way both rules cut a hole
+        let mut inner: Vec<(f64, f64)> = square(4.5, 4.5, 4.0);
+        inner.reverse();
+        let hole: Coverage = rasterize(&[square(0.5, 0.5, 12.0), inner], FillRule::NonZero, 16, 16);
+        assert_eq!(at(&hole, 6, 6), 0.0);
+    }
+
+    #[test]
+    fn circle_area() {
+        let mut p: Path = Path::new();
+        p.circle(20.0, 20.0, 10.0);
+        let polys: Vec<Vec<(f64, f64)>> = p.flatten(DEFAULT_TOLERANCE).into_iter().map(|pl| pl.points).collect();
+        let c: Coverage = rasterize(&polys, FillRule::NonZero, 40, 40);
+        //Coverage is exact for the flattened polygon, which stays within the tolerance of the circle
+        assert!((total(&c) - signed_area(&polys[0])).abs() < 1e-2);
+        assert!((total(&c) - PI*100.0).abs() < 2.0*PI*10.0*DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn stroke_outline() {
+        let pl: Vec<Polyline> = vec![Polyline { points: square(4.5, 4.5, 10.0), closed: true }];
+        let polys: Vec<Vec<(f64, f64)>> = stroke_polygons(&pl, &StrokeStyle::new(2.0), DEFAULT_TOLERANCE);
+        assert!(polys.iter().all(|p| signed_area(p) >= 0.0));
+        let c: Coverage = rasterize(&polys, FillRule::NonZero, 20, 20);
+        assert_eq!(at(&c, 9, 9), 0.0);
+        assert_eq!(at(&c, 9, 4), 1.0);
+        //Miter joins fill the outer corners
+        assert_eq!(at(&c, 4, 4), 1.0);
+        //The 12x12 outer square minus the 8x8 inner one
+        assert!((total(&c) - 80.0).abs() < 1e-2);
+
+        //A single point with butt caps draws nothing, round caps draw a disc
+        let dot: Vec<Polyline> = vec![Polyline { points: vec![(5.0, 5.0)], closed: false }];
+        assert!(stroke_polygons(&dot, &StrokeStyle::new(2.0), DEFAULT_TOLERANCE).is_empty());
+        let round: StrokeStyle = StrokeStyle { cap: LineCap::Round, ..StrokeStyle::new(2.0) };
+        assert_eq!(stroke_polygons(&dot, &round, DEFAULT_TOLERANCE).len(), 1);
+        assert!(stroke_polygons(&pl, &StrokeStyle::new(0.0), DEFAULT_TOLERANCE).is_empty());
+    }
+
+}
@@ -16,7 +16,7 @@ impl CRaster {
 
     pub fn get(&self, x: usize, y: usize) -> u32 {
         if x < self.width && y < self.height {
-            return self.data[x + self.height*y];
+            return self.data[x + self.width*y];
         }
         else {
             println!("[CRaster/WARN] Cannot get index ({}, {}) in a {}x{} raster", x, y, self.width, self.height);
@@ -26,11 +26,36 @@ impl CRaster {
 
     pub fn set(&mut self, x: usize, y: usize, value: u32) {
         if x < self.width && y < self.height {
-            self.data[x + self.height*y] = value;
+            self.data[x + self.width*y] = value;
         }
         else {
             println!("[CRaster/WARN] Cannot set index ({}, {}) in a {}x{} raster", x, y, self.width, self.height);
         }
     }
 
-}
\ No newline at end of file
+    ///Like `set`, but takes signed coordinates and silently skips pixels outside the raster, for shapes that are
+    /// allowed to run off the edge.
+    pub fn set_clipped(&mut self, x: i32, y: i32, value: u32) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.data[x as usize + self.width*y as usize] = value;
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn set_clipped_skips_pixels_outside() {
+        let mut raster: CRaster = CRaster::new(3, 2);
+        raster.set_clipped(2, 1, 7);
+        for (x, y) in [(-1, 0), (0, -1), (3, 0), (0, 2), (i32::MIN, i32::MAX)] {
+            raster.set_clipped(x, y, 9);
+        }
+        assert_eq!(raster.data, vec![0, 0, 0, 0, 0, 7]);
+    }
+
+}
@@ -3,6 +3,8 @@ use std::backtrace;
 use std::env::args;
 
 use crate::graphics::craster::CRaster;
+use crate::graphics::ccolor;
+use ccolor::Color;
 use crate::math::{cmath, cmatrix};
 use crate::graphics::ccamera::CCamera;
 use crate::graphics::cmodel_instance::CModelInstance;
@@ -18,6 +20,49 @@ pub struct CRen {
     pub raster: CRaster,    //CRen owns its raster
 }
 
+///The shape drawn at the open ends of a thick line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    ///The line stops exactly at its endpoints.
+    Butt,
+    ///A half-circle is added past each endpoint.
+    Round,
+    ///The line is extended past each endpoint by half its width.
+    Square,
+}
+
+///The shape drawn where two segments of a thick polyline meet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    ///The outer edges are extended until they meet, falling back to `Bevel` past the miter limit.
+    Miter,
+    ///The outer corners are connected with a straight edge.
+    Bevel,
+    ///The outer corners are connected with an arc.
+    Round,
+}
+
+///Width, caps and joins used when drawing thick lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f64,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    ///Longest allowed miter, as a multiple of half the line width.
+    pub miter_limit: f64,
+}
+
+impl StrokeStyle {
+    pub fn new(width: f64) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 4.0,
+        }
+    }
+}
+
 pub struct Clipper {
 
 }
@@ -981,6 +1026,13 @@ impl CRen {
         return CRen {raster};
     }
 
+    ///Replaces the raster with a cleared one of the new size, e.g. when the window it is shown in is resized.
+    pub fn resize(&mut self, w: usize, h: usize) {
+        if (w, h) != (self.raster.width, self.raster.height) {
+            self.raster = CRaster::new(w, h);
+        }
+    }
+
     pub fn clear(&mut self) {
         for i in self.raster.data.iter_mut() {
             *i = 0x000000;
@@ -1001,41 +1053,28 @@ impl CRen {
         }
     }
 
-    pub fn draw_line_f64(&mut self, mut x0: f64, mut y0: f64, mut x1: f64, mut y1: f64, color: u32) {
+    ///Draws a 1-pixel line between two sub-pixel endpoints. Pixel centers are at integer coordinates, so every
+    /// column (or row, for steep lines) the line crosses gets the pixel nearest to the true line.
+    pub fn draw_line_f64(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, color: u32) {
+        let dx = x1 - x0;
+        let dy = y1 - y0;
 
-        if x0 == x1 {
-            if y0 < y1 {
-                for y in (y0 as i32)..((y1 + 1.0) as i32) {
-                    self.set_pixel(x0 as i32, y as i32, color);
-                }
-            }
-            else {
-                for y in (y1 as i32)..((y0 + 1.0) as i32) {
-                    self.set_pixel(x0 as i32, y as i32, color);
-                }
+        if dx.abs() >= dy.abs() {
+            let (xa, ya, xb) = if x0 <= x1 { (x0, y0, x1) } else { (x1, y1, x0) };
+            let m: f64 = if dx == 0.0 { 0.0 } else { dy / dx };
+            for x in (xa.round() as i32)..((xb.round() as i32) + 1) {
+                let y: f64 = ya + m*(x as f64 - xa);
+                self.set_pixel(x, y.round() as i32, color);
             }
         }
-        else if y0 == y1 {
-            if x0 < x1 {
-                for x in (x0 as i32)..((x1 + 1.0) as i32) {
-                    self.set_pixel(x as i32, y0 as i32, color);
-                }
-            }
-            else {
-                for x in (x1 as i32)..((x0 + 1.0) as i32) {
-                    self.set_pixel(x as i32, y0 as i32, color);
-                }
+        else {
+            let (xa, ya, yb) = if y0 <= y1 { (x0, y0, y1) } else { (x1, y1, y0) };
+            let m: f64 = dx / dy;
+            for y in (ya.round() as i32)..((yb.round() as i32) + 1) {
+                let x: f64 = xa + m*(y as f64 - ya);
+                self.set_pixel(x.round() as i32, y, color);
             }
         }
-
-        let dx = x1 - x0;
-        let dy = y1 - y0;
-        let D = 2.0*dy - dx;
-        let y = y0;
-
-        for x in (x0 as i32)..((x1 + 1.0) as i32) {
-            self.set_pixel(x, y as i32, color);
-        }
     }
 
     pub fn draw_line(&mut self, mut x0: i32, mut y0: i32, mut x1: i32, mut y1: i32, color: u32) {
@@ -1129,6 +1168,170 @@ impl CRen {
         }
     }
 
+    ///Blends `color` over the pixel at `(x, y)`. The effective opacity is `color.a * coverage`, and the mix is done in
+    /// linear light so anti-aliased edges keep their perceived weight.
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if x < 0 || y < 0 || x >= self.raster.width as i32 || y >= self.raster.height as i32 {
+            return;
+        }
+        let a: f32 = (color.a * coverage).clamp(0.0, 1.0);
+        if a <= 0.0 {
+            return;
+        }
+        let i: usize = (x + (self.raster.width as i32)*y) as usize;
+        if a >= 1.0 {
+            self.raster.data[i] = color.to_rgb_u32();
+            return;
+        }
+        let dst: u32 = self.raster.data[i];
+        let src: Color = color.to_linear();
+        let mix = |s: f32, d: u32| -> u32 {
+            let d: f32 = ccolor::srgb8_to_linear(d as u8);
+            ccolor::linear_to_srgb8(s*a + d*(1.0 - a)) as u32
+        };
+        self.raster.data[i] = (dst & 0xff000000)
+            | (mix(src.r, dst >> 16) << 16)
+            | (mix(src.g, dst >> 8) << 8)
+            | mix(src.b, dst);
+    }
+
+    ///Draws a 1-pixel wide anti-aliased line using Xiaolin Wu's algorithm.
+    pub fn draw_line_aa(&mut self, mut x0: f64, mut y0: f64, mut x1: f64, mut y1: f64, color: Color) {
+        let steep: bool = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+
+        let dx: f64 = x1 - x0;
+        let gradient: f64 = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+        //Plots with the axes swapped back for steep lines.
+        let mut plot = |ren: &mut CRen, x: i32, y: i32, c: f64| {
+            if steep {
+                ren.blend_pixel(y, x, color, c as f32);
+            }
+            else {
+                ren.blend_pixel(x, y, color, c as f32);
+            }
+        };
+
+        //Distance past the pixel boundary below. Unlike `fract` this stays in 0..1 for negative coordinates.
+        let frac = |v: f64| v - v.floor();
+
+        //First endpoint
+        let x_end: f64 = x0.round();
+        let y_end: f64 = y0 + gradient*(x_end - x0);
+        let x_gap: f64 = 1.0 - frac(x0 + 0.5);
+        let x_px0: i32 = x_end as i32;
+        let y_px0: i32 = y_end.floor() as i32;
+        plot(self, x_px0, y_px0, (1.0 - frac(y_end))*x_gap);
+        plot(self, x_px0, y_px0 + 1, frac(y_end)*x_gap);
+        if dx == 0.0 {
+            return; //both endpoints are the same point, which is already drawn
+        }
+        let mut inter_y: f64 = y_end + gradient;
+
+        //Second endpoint
+        let x_end: f64 = x1.round();
+        let y_end: f64 = y1 + gradient*(x_end - x1);
+        let x_gap: f64 = frac(x1 + 0.5);
+        let x_px1: i32 = x_end as i32;
+        let y_px1: i32 = y_end.floor() as i32;
+        plot(self, x_px1, y_px1, (1.0 - frac(y_end))*x_gap);
+        plot(self, x_px1, y_px1 + 1, frac(y_end)*x_gap);
+
+        for x in (x_px0 + 1)..x_px1 {
+            let y: i32 = inter_y.floor() as i32;
+            let f: f64 = inter_y - inter_y.floor();
+            plot(self, x, y, 1.0 - f);
+            plot(self, x, y + 1, f);
+            inter_y += gradient;
+        }
+    }
+
+    ///Draws an anti-aliased line of any width, with the end caps given by `style.cap`.
+    pub fn draw_thick_line(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, style: &StrokeStyle, color: Color) {
+        self.draw_polyline(&[(x0, y0), (x1, y1)], style, color);
+    }
+
+    ///Draws an anti-aliased polyline through `points`, joining consecutive segments according to `style.join`.
+    pub fn draw_polyline(&mut self, points: &[(f64, f64)], style: &StrokeStyle, color: Color) {
+        //Strokes thinner than a pixel are drawn 1 pixel wide and faded instead, which reads as a thinner line.
+        let width: f64 = style.width.max(1.0);
+        let color: Color = color.with_alpha(color.a * style.width.min(1.0) as f32);
+        let hw: f64 = width / 2.0;
+
+        //Drop repeated points, they have no direction.
+        let mut pts: Vec<(f64, f64)> = Vec::with_capacity(points.len());
+        for p in points.iter() {
+            if pts.last().is_none_or(|l: &(f64, f64)| (l.0 - p.0).abs() > 1e-9 || (l.1 - p.1).abs() > 1e-9) {
+                pts.push(*p);
+            }
+        }
+        if pts.is_empty() {
+            return;
+        }
+
+        let mut mask: CoverageMask = CoverageMask::around(&pts, hw*style.miter_limit.max(1.5) + 2.0, &self.raster);
+
+        if pts.len() == 1 {
+            if style.cap != LineCap::Butt {
+                let (x, y) = pts[0];
+                if style.cap == LineCap::Round {
+                    mask.add_disc(x, y, hw);
+                }
+                else {
+                    mask.add_convex(&[(x - hw, y - hw), (x + hw, y - hw), (x + hw, y + hw), (x - hw, y + hw)]);
+                }
+            }
+            self.blend_mask(&mask, color);
+            return;
+        }
+
+        let n: usize = pts.len();
+        for i in 0..(n - 1) {
+            let (mut a, mut b) = (pts[i], pts[i + 1]);
+            let (dx, dy) = normalize(b.0 - a.0, b.1 - a.1);
+            if style.cap == LineCap::Square {
+                if i == 0 {
+                    a = (a.0 - dx*hw, a.1 - dy*hw);
+                }
+                if i == n - 2 {
+                    b = (b.0 + dx*hw, b.1 + dy*hw);
+                }
+            }
+            let (nx, ny) = (-dy*hw, dx*hw);
+            mask.add_convex(&[(a.0 + nx, a.1 + ny), (b.0 + nx, b.1 + ny), (b.0 - nx, b.1 - ny), (a.0 - nx, a.1 - ny)]);
+        }
+
+        if style.cap == LineCap::Round {
+            mask.add_disc(pts[0].0, pts[0].1, hw);
+            mask.add_disc(pts[n - 1].0, pts[n - 1].1, hw);
+        }
+
+        for i in 1..(n - 1) {
+            add_join(&mut mask, pts[i - 1], pts[i], pts[i + 1], hw, style);
+        }
+
+        self.blend_mask(&mask, color);
+    }
+
+    pub(crate) fn blend_mask(&mut self, mask: &CoverageMask, color: Color) {
+        for j in 0..mask.height {
+            for i in 0..mask.width {
+                let c: f32 = mask.coverage(i, j);
+                if c > 0.0 {
+                    self.blend_pixel(mask.x0 + i as i32, mask.y0 + j as i32, color, c);
+                }
+            }
+        }
+    }
+
     pub fn draw_raster(&mut self, x: i32, y: i32, raster: &CRaster) {
         for i in 0..raster.width {
             for j in 0..raster.height {
@@ -1316,35 +1519,21 @@ impl CRen {
 
     }
 
+    ///Scales the intensity of `color` by `scalar`. The multiplication is done in linear light, so a scalar of 0.5
+    /// gives half the emitted light rather than half the sRGB value.
     fn scale_color(&mut self, color: u32, scalar: f64) -> u32 {
-        let r = (self.get_red(color) as f64 * scalar + 0.5) as u8;
-        let g = (self.get_green(color) as f64 * scalar + 0.5) as u8;
-        let b = (self.get_blue(color) as f64 * scalar + 0.5) as u8;
-        return self.get_color(r, g, b);
-    }
-
-    fn get_alpha(&mut self, color: u32) -> u8 {
-        return (color / 0x01000000) as u8;
-    }
-
-    fn get_red(&mut self, color: u32) -> u8 {
-        return ((color % 0x01000000) / 0x00010000) as u8;
-    }
-
-    fn get_green(&mut self, color: u32) -> u8 {
-        return ((color % 0x00010000) / 0x00000100) as u8;
-    }
-
-    fn get_blue(&mut self, color: u32) -> u8 {
-        return ((color % 0x00000100) / 0x00000001) as u8;
-    }
-
-    fn get_color(&mut self, r: u8, g: u8, b: u8) -> u32 {
-        return  (r as u32)*256*256 + (g as u32)*256 + (b as u32);
+        //Called per pixel, so the sRGB conversions go through the lookup tables
+        let scalar: f32 = scalar as f32;
+        let channel = |shift: u32| {
+            let c: f32 = ccolor::srgb8_to_linear((color >> shift) as u8)*scalar;
+            (ccolor::linear_to_srgb8(c) as u32) << shift
+        };
+        return channel(16) | channel(8) | channel(0);
     }
 
     ///Renders model instances to the raster.
     pub fn render_scene(&mut self, camera: &CCamera, scene: &Vec<CModelInstance>) {
+        crate::profile_scope!("render_scene");
 
         let fov: f64 = camera.fov; //60 degrees
         let aspect_ratio: f64 = (self.raster.width as f64) / (self.raster.height as f64);
@@ -1470,13 +1659,19 @@ impl CRen {
             let M = cmatrix::matrix_4x4_mult(&A, &B);
 
             //Model space --> Clip space
-            for v in vertices.iter_mut() {
-                M.applyTo(v);
+            {
+                crate::profile_scope!("transform");
+                for v in vertices.iter_mut() {
+                    M.applyTo(v);
+                }
             }
 
             //TODO: Clipping
-            Clipper::clip(&mut vertices, &mut triangles);
-            // self.clip(&mut vertices, &mut triangles);
+            {
+                crate::profile_scope!("clip");
+                Clipper::clip(&mut vertices, &mut triangles);
+                // self.clip(&mut vertices, &mut triangles);
+            }
 
             //Clip space --> NDC space
             for v in vertices.iter_mut() {
@@ -1502,6 +1697,7 @@ impl CRen {
             }
 
             //Draw triangles
+            crate::profile_scope!("rasterize");
             for t in triangles {
                 
                 let v0: (f64, f64, f64, f64) = vertices[t.i0];
@@ -1637,3 +1833,234 @@ impl CRen {
 
 }
 
+///Anti-aliased coverage of a set of shapes over a rectangular area of a raster. Each pixel keeps a 4x4 grid of
+/// samples, so shapes are unioned exactly: pieces of a stroke that touch leave no seams, and pieces that overlap are
+/// not blended twice. With `aa` off, only pixel centers are tested and each pixel is either fully covered or not.
+pub(crate) struct CoverageMask {
+    x0: i32,
+    y0: i32,
+    width: usize,
+    height: usize,
+    aa: bool,
+    data: Vec<u16>,
+}
+
+impl CoverageMask {
+
+    ///Creates a mask covering `(min_x, min_y)-(max_x, max_y)` plus a pixel of margin, clipped to the raster.
+    pub(crate) fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64, raster: &CRaster, aa: bool) -> CoverageMask {
+        let x0: i32 = ((min_x - 1.0).floor().max(0.0) as i32).min(raster.width as i32);
+        let y0: i32 = ((min_y - 1.0).floor().max(0.0) as i32).min(raster.height as i32);
+        let x1: i32 = ((max_x + 1.0).ceil().max(-1.0) as i32).min(raster.width as i32 - 1);
+        let y1: i32 = ((max_y + 1.0).ceil().max(-1.0) as i32).min(raster.height as i32 - 1);
+        let width: usize = if x1 >= x0 { (x1 - x0 + 1) as usize } else { 0 };
+        let height: usize = if y1 >= y0 { (y1 - y0 + 1) as usize } else { 0 };
+        CoverageMask { x0, y0, width, height, aa, data: vec![0; width*height] }
+    }
+
+    ///Creates an anti-aliased mask covering the bounding box of `points` grown by `margin`.
+    fn around(points: &[(f64, f64)], margin: f64, raster: &CRaster) -> CoverageMask {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for p in points.iter() {
+            min_x = min_x.min(p.0);
+            min_y = min_y.min(p.1);
+            max_x = max_x.max(p.0);
+            max_y = max_y.max(p.1);
+        }
+        CoverageMask::new(min_x - margin, min_y - margin, max_x + margin, max_y + margin, raster, true)
+    }
+
+    ///Fraction of the samples of pixel `(i, j)` (relative to the mask origin) that are covered.
+    fn coverage(&self, i: usize, j: usize) -> f32 {
+        self.data[i + self.width*j].count_ones() as f32 / 16.0
+    }
+
+    ///Marks every sample inside `(min_x, min_y)-(max_x, max_y)` for which `inside(x, y)` holds.
+    pub(crate) fn add<F: Fn(f64, f64) -> bool>(&mut self, min_x: f64, min_y: f64, max_x: f64, max_y: f64, inside: F) {
+        let i0: i32 = ((min_x.floor() as i32) - self.x0).max(0);
+        let j0: i32 = ((min_y.floor() as i32) - self.y0).max(0);
+        let i1: i32 = ((max_x.ceil() as i32) - self.x0).min(self.width as i32 - 1);
+        let j1: i32 = ((max_y.ceil() as i32) - self.y0).min(self.height as i32 - 1);
+        for j in j0..(j1 + 1) {
+            for i in i0..(i1 + 1) {
+                let (px, py) = ((self.x0 + i) as f64, (self.y0 + j) as f64);
+                let mut bits: u16 = 0;
+                if !self.aa {
+                    if inside(px, py) {
+                        bits = 0xffff;
+                    }
+                }
+                else {
+                    for k in 0..16 {
+                        let sx: f64 = px + ((k % 4) as f64 + 0.5) / 4.0 - 0.5;
+                        let sy: f64 = py + ((k / 4) as f64 + 0.5) / 4.0 - 0.5;
+                        if inside(sx, sy) {
+                            bits |= 1 << k;
+                        }
+                    }
+                }
+                self.data[i as usize + self.width*(j as usize)] |= bits;
+            }
+        }
+    }
+
+    pub(crate) fn add_convex(&mut self, poly: &[(f64, f64)]) {
+        let n: usize = poly.len();
+        let mut area: f64 = 0.0;
+        for i in 0..n {
+            let (a, b) = (poly[i], poly[(i + 1) % n]);
+            area += a.0*b.1 - b.0*a.1;
+        }
+        if area == 0.0 {
+            return;
+        }
+        let s: f64 = area.signum();
+
+        //Outward edge normals as (nx, ny, offset), so a point is inside when nx*x + ny*y <= offset for every edge
+        let mut edges: Vec<(f64, f64, f64)> = Vec::with_capacity(n);
+        for i in 0..n {
+            let (a, b) = (poly[i], poly[(i + 1) % n]);
+            let (nx, ny) = ((b.1 - a.1)*s, -(b.0 - a.0)*s);
+            edges.push((nx, ny, nx*a.0 + ny*a.1));
+        }
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for p in poly.iter() {
+            min_x = min_x.min(p.0);
+            min_y = min_y.min(p.1);
+            max_x = max_x.max(p.0);
+            max_y = max_y.max(p.1);
+        }
+
+        self.add(min_x - 1.0, min_y - 1.0, max_x + 1.0, max_y + 1.0, |x, y| {
+            edges.iter().all(|e| e.0*x + e.1*y <= e.2)
+        });
+    }
+
+    pub(crate) fn add_disc(&mut self, cx: f64, cy: f64, r: f64) {
+        self.add(cx - r - 1.0, cy - r - 1.0, cx + r + 1.0, cy + r + 1.0, |x, y| {
+            (x - cx)*(x - cx) + (y - cy)*(y - cy) <= r*r
+        });
+    }
+
+}
+
+///Shape that fills the outer side of a corner between two stroke segments.
+pub(crate) enum JoinShape {
+    ///A disc of the stroke's half width, centered on the corner.
+    Disc,
+    Polygon(Vec<(f64, f64)>),
+}
+
+///The join at `b` between segments `a-b` and `b-c`, or `None` when they run straight on and already meet.
+pub(crate) fn join_shape(a: (f64, f64), b: (f64, f64), c: (f64, f64), hw: f64, style: &StrokeStyle) -> Option<JoinShape> {
+    let d0: (f64, f64) = normalize(b.0 - a.0, b.1 - a.1);
+    let d1: (f64, f64) = normalize(c.0 - b.0, c.1 - b.1);
+    let cross: f64 = d0.0*d1.1 - d0.1*d1.0;
+    if cross.abs() < 1e-9 && d0.0*d1.0 + d0.1*d1.1 > 0.0 {
+        return None; //straight through, the segments already meet
+    }
+
+    if style.join == LineJoin::Round {
+        return Some(JoinShape::Disc);
+    }
+
+    let s: f64 = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0: (f64, f64) = (-d0.1*s, d0.0*s);
+    let n1: (f64, f64) = (-d1.1*s, d1.0*s);
+    let p0: (f64, f64) = (b.0 + n0.0*hw, b.1 + n0.1*hw);
+    let p1: (f64, f64) = (b.0 + n1.0*hw, b.1 + n1.1*hw);
+
+    if style.join == LineJoin::Miter {
+        let m: (f64, f64) = normalize(n0.0 + n1.0, n0.1 + n1.1);
+        let cos_half: f64 = m.0*n0.0 + m.1*n0.1;
+        if cos_half > 1e-9 && 1.0 / cos_half <= style.miter_limit {
+            let l: f64 = hw / cos_half;
+            return Some(JoinShape::Polygon(vec![b, p0, (b.0 + m.0*l, b.1 + m.1*l), p1]));
+        }
+    }
+
+    Some(JoinShape::Polygon(vec![b, p0, p1]))
+}
+
+///Adds the join between segments `a-b` and `b-c` on the outer side of the turn.
+fn add_join(mask: &mut CoverageMask, a: (f64, f64), b: (f64, f64), c: (f64, f64), hw: f64, style: &StrokeStyle) {
+    match join_shape(a, b, c, hw, style) {
+        Some(JoinShape::Disc) => mask.add_disc(b.0, b.1, hw),
+        Some(JoinShape::Polygon(poly)) => mask.add_convex(&poly),
+        None => {}
+    }
+}
+
+pub(crate) fn normalize(x: f64, y: f64) -> (f64, f64) {
+    let l: f64 = (x*x + y*y).sqrt();
+    if l == 0.0 { (0.0, 0.0) } else { (x / l, y / l) }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn pixel(ren: &CRen, x: usize, y: usize) -> u32 {
+        ren.raster.data[x + ren.raster.width*y]
+    }
+
+    #[test]
+    fn zero_length_aa_line_is_blended_once() {
+        let half: Color = Color::rgba(1.0, 1.0, 1.0, 0.5);
+        let mut line: CRen = CRen::new(8, 8);
+        line.draw_line_aa(3.0, 3.0, 3.0, 3.0, half);
+        let mut once: CRen = CRen::new(8, 8);
+        //An endpoint on a pixel center gets half coverage, the other half belongs to the next pixel along the line
+        once.blend_pixel(3, 3, half, 0.5);
+        assert_eq!(line.raster.data, once.raster.data);
+        assert_ne!(pixel(&line, 3, 3), 0);
+    }
+
+    #[test]
+    fn aa_line_with_negative_coordinates() {
+        let mut ren: CRen = CRen::new(8, 8);
+        ren.draw_line_aa(-3.5, 2.0, 5.0, 2.0, Color::WHITE);
+        for x in 0..5 {
+            assert_eq!(pixel(&ren, x, 2), 0x00ffffff);
+            assert_eq!(pixel(&ren, x, 3), 0);
+        }
+        assert_eq!(pixel(&ren, 7, 2), 0);
+    }
+
+    #[test]
+    fn thick_line_caps() {
+        let mut butt: CRen = CRen::new(16, 8);
+        butt.draw_thick_line(2.0, 4.0, 10.0, 4.0, &StrokeStyle::new(2.0), Color::WHITE);
+        assert_eq!(pixel(&butt, 6, 4), 0x00ffffff);
+        assert_eq!(pixel(&butt, 12, 4), 0);
+        assert_eq!(pixel(&butt, 6, 7), 0);
+
+        let mut round: CRen = CRen::new(16, 8);
+        let style: StrokeStyle = StrokeStyle { cap: LineCap::Round, ..StrokeStyle::new(4.0) };
+        round.draw_thick_line(2.0, 4.0, 10.0, 4.0, &style, Color::WHITE);
+        assert_eq!(pixel(&round, 11, 4), 0x00ffffff);
+        assert_eq!(pixel(&round, 14, 4), 0);
+    }
+
+    #[test]
+    fn join_shapes() {
+        let (a, b, c) = ((0.0, 0.0), (10.0, 0.0), (10.0, 10.0));
+        let miter: StrokeStyle = StrokeStyle::new(2.0);
+        assert!(join_shape(a, b, (20.0, 0.0), 1.0, &miter).is_none());
+        match join_shape(a, b, c, 1.0, &miter) {
+            Some(JoinShape::Polygon(p)) => {
+                assert_eq!(p.len(), 4);
+                //The miter tip of a right angle sits on the outer corner
+                assert!((p[2].0 - 11.0).abs() < 1e-9 && (p[2].1 + 1.0).abs() < 1e-9);
+            }
+            _ => panic!("expected a miter"),
+        }
+        let bevel: StrokeStyle = StrokeStyle { miter_limit: 1.2, ..miter };
+        assert!(matches!(join_shape(a, b, c, 1.0, &bevel), Some(JoinShape::Polygon(p)) if p.len() == 3));
+        let round: StrokeStyle = StrokeStyle { join: LineJoin::Round, ..miter };
+        assert!(matches!(join_shape(a, b, c, 1.0, &round), Some(JoinShape::Disc)));
+    }
+
+}
@@ -0,0 +1,238 @@
+use std::f64::consts::PI;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::cren::{CRen, CoverageMask};
+
+///Curved shape primitives. Every shape comes in a hard-edged and an anti-aliased (`_aa`) variant, and both blend
+/// `color` over the raster using its alpha. Outlines are centered on the shape's edge and `thickness` pixels wide.
+/// Angles are in radians, measured clockwise (on screen) from the positive x-axis.
+impl CRen {
+
+    pub fn draw_circle(&mut self, center: (f64, f64), r: f64, thickness: f64, color: Color) {
+        self.circle_outline(center, r, thickness, color, false);
+    }
+
+    pub fn draw_circle_aa(&mut self, center: (f64, f64), r: f64, thickness: f64, color: Color) {
+        self.circle_outline(center, r, thickness, color, true);
+    }
+
+    pub fn fill_circle(&mut self, center: (f64, f64), r: f64, color: Color) {
+        self.fill_ellipse(center, r, r, color);
+    }
+
+    pub fn fill_circle_aa(&mut self, center: (f64, f64), r: f64, color: Color) {
+        self.fill_ellipse_aa(center, r, r, color);
+    }
+
+    pub fn draw_ellipse(&mut self, center: (f64, f64), rx: f64, ry: f64, thickness: f64, color: Color) {
+        self.ellipse_outline(center, (rx, ry), thickness, color, false);
+    }
+
+    pub fn draw_ellipse_aa(&mut self, center: (f64, f64), rx: f64, ry: f64, thickness: f64, color: Color) {
+        self.ellipse_outline(center, (rx, ry), thickness, color, true);
+    }
+
+    pub fn fill_ellipse(&mut self, center: (f64, f64), rx: f64, ry: f64, color: Color) {
+        self.ellipse_fill(center, (rx, ry), color, false);
+    }
+
+    pub fn fill_ellipse_aa(&mut self, center: (f64, f64), rx: f64, ry: f64, color: Color) {
+        self.ellipse_fill(center, (rx, ry), color, true);
+    }
+
+    ///Draws the part of a circle's outline from angle `start` to `end`.
+    pub fn draw_arc(&mut self, center: (f64, f64), r: f64, start: f64, end: f64, thickness: f64, color: Color) {
+        self.arc(center, (r - thickness/2.0, r + thickness/2.0), (start, end), color, false);
+    }
+
+    pub fn draw_arc_aa(&mut self, center: (f64, f64), r: f64, start: f64, end: f64, thickness: f64, color: Color) {
+        self.arc(center, (r - thickness/2.0, r + thickness/2.0), (start, end), color, true);
+    }
+
+    ///Fills the slice of a circle between angles `start` and `end`.
+    pub fn fill_pie(&mut self, center: (f64, f64), r: f64, start: f64, end: f64, color: Color) {
+        self.arc(center, (0.0, r), (start, end), color, false);
+    }
+
+    pub fn fill_pie_aa(&mut self, center: (f64, f64), r: f64, start: f64, end: f64, color: Color) {
+        self.arc(center, (0.0, r), (start, end), color, true);
+    }
+
+    ///Draws the outline of the rectangle between corners `p0` and `p1` with corners rounded to `radius`.
+    pub fn draw_rounded_rect(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, thickness: f64, color: Color) {
+        self.rounded_rect_outline(p0, p1, radius, thickness, color, false);
+    }
+
+    pub fn draw_rounded_rect_aa(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, thickness: f64, color: Color) {
+        self.rounded_rect_outline(p0, p1, radius, thickness, color, true);
+    }
+
+    pub fn fill_rounded_rect(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, color: Color) {
+        self.rounded_rect(p0, p1, radius, color, false);
+    }
+
+    pub fn fill_rounded_rect_aa(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, color: Color) {
+        self.rounded_rect(p0, p1, radius, color, true);
+    }
+
+    ///Rasterizes the region where `inside(x, y)` holds within the bounds `min`-`max` and blends it over the raster.
+    fn fill_region<F: Fn(f64, f64) -> bool>(&mut self, min: (f64, f64), max: (f64, f64), color: Color, aa: bool, inside: F) {
+        let mut mask: CoverageMask = CoverageMask::new(min.0, min.1, max.0, max.1, &self.raster, aa);
+        mask.add(min.0, min.1, max.0, max.1, inside);
+        self.blend_mask(&mask, color);
+    }
+
+    fn circle_outline(&mut self, (cx, cy): (f64, f64), r: f64, thickness: f64, color: Color, aa: bool) {
+        let (r_in, r_out) = ((r - thickness/2.0).max(0.0), r + thickness/2.0);
+        self.fill_region((cx - r_out, cy - r_out), (cx + r_out, cy + r_out), color, aa, |x, y| {
+            let d2: f64 = (x - cx)*(x - cx) + (y - cy)*(y - cy);
+            d2 <= r_out*r_out && d2 >= r_in*r_in
+        });
+    }
+
+    fn ellipse_fill(&mut self, (cx, cy): (f64, f64), (rx, ry): (f64, f64), color: Color, aa: bool) {
+        if rx <= 0.0 || ry <= 0.0 {
+            return;
+        }
+        self.fill_region((cx - rx, cy - ry), (cx + rx, cy + ry), color, aa, |x, y| {
+            inside_ellipse(x - cx, y - cy, rx, ry)
+        });
+    }
+
+    fn ellipse_outline(&mut self, (cx, cy): (f64, f64), (rx, ry): (f64, f64), thickness: f64, color: Color, aa: bool) {
+        let (ox, oy) = (rx + thickness/2.0, ry + thickness/2.0);
+        let (ix, iy) = (rx - thickness/2.0, ry - thickness/2.0);
+        self.fill_region((cx - ox, cy - oy), (cx + ox, cy + oy), color, aa, |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            inside_ellipse(dx, dy, ox, oy) && (ix <= 0.0 || iy <= 0.0 || !inside_ellipse(dx, dy, ix, iy))
+        });
+    }
+
+    //The ring between radii `r_in` and `r_out`, from angle `start` to `end`
+    fn arc(&mut self, (cx, cy): (f64, f64), (r_in, r_out): (f64, f64), (start, end): (f64, f64), color: Color, aa: bool) {
+        let (start, sweep) = if end >= start { (start, end - start) } else { (end, start - end) };
+        let r_in: f64 = r_in.max(0.0);
+        self.fill_region((cx - r_out, cy - r_out), (cx + r_out, cy + r_out), color, aa, |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let d2: f64 = dx*dx + dy*dy;
+            if d2 > r_out*r_out || d2 < r_in*r_in {
+                return false;
+            }
+            sweep >= 2.0*PI || (dy.atan2(dx) - start).rem_euclid(2.0*PI) <= sweep
+        });
+    }
+
+    fn rounded_rect(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, color: Color, aa: bool) {
+        let (x0, x1) = (p0.0.min(p1.0), p0.0.max(p1.0));
+        let (y0, y1) = (p0.1.min(p1.1), p0.1.max(p1.1));
+        self.fill_region((x0, y0), (x1, y1), color, aa, |x, y| {
+            inside_rounded_rect(x, y, x0, y0, x1, y1, radius)
+        });
+    }
+
+    fn rounded_rect_outline(&mut self, p0: (f64, f64), p1: (f64, f64), radius: f64, thickness: f64, color: Color, aa: bool) {
+        let (x0, x1) = (p0.0.min(p1.0), p0.0.max(p1.0));
+        let (y0, y1) = (p0.1.min(p1.1), p0.1.max(p1.1));
+        let h: f64 = thickness / 2.0;
+        let r_out: f64 = radius + h;
+        let r_in: f64 = (radius - h).max(0.0);
+        self.fill_region((x0 - h, y0 - h), (x1 + h, y1 + h), color, aa, |x, y| {
+            inside_rounded_rect(x, y, x0 - h, y0 - h, x1 + h, y1 + h, r_out)
+                && !inside_rounded_rect(x, y, x0 + h, y0 + h, x1 - h, y1 - h, r_in)
+        });
+    }
+
+}
+
+fn inside_ellipse(dx: f64, dy: f64, rx: f64, ry: f64) -> bool {
+    (dx*dx) / (rx*rx) + (dy*dy) / (ry*ry) <= 1.0
+}
+
+fn inside_rounded_rect(x: f64, y: f64, x0: f64, y0: f64, x1: f64, y1: f64, radius: f64) -> bool {
+    if x < x0 || x > x1 || y < y0 || y > y1 {
+        return false;
+    }
+    let r: f64 = radius.min((x1 - x0) / 2.0).min((y1 - y0) / 2.0).max(0.0);
+    let nx: f64 = x.clamp(x0 + r, x1 - r);
+    let ny: f64 = y.clamp(y0 + r, y1 - r);
+    (x - nx)*(x - nx) + (y - ny)*(y - ny) <= r*r
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn pixel(ren: &CRen, x: usize, y: usize) -> u32 {
+        ren.raster.data[x + ren.raster.width*y]
+    }
+
+    #[test]
+    fn filled_and_outlined_circles() {
+        let mut ren: CRen = CRen::new(21, 21);
+        ren.fill_circle((10.0, 10.0), 5.0, Color::WHITE);
+        assert_eq!(pixel(&ren, 10, 10), 0x00ffffff);
+        assert_eq!(pixel(&ren, 15, 10), 0x00ffffff);
+        assert_eq!(pixel(&ren, 14, 14), 0);
+
+        let mut ring: CRen = CRen::new(21, 21);
+        ring.draw_circle((10.0, 10.0), 5.0, 1.0, Color::WHITE);
+        assert_eq!(pixel(&ring, 10, 10), 0);
+        assert_eq!(pixel(&ring, 10, 5), 0x00ffffff);
+    }
+
+    #[test]
+    fn aa_edges_are_partial() {
+        let mut ren: CRen = CRen::new(21, 21);
+        ren.fill_circle_aa((10.0, 10.0), 5.0, Color::WHITE);
+        assert_eq!(pixel(&ren, 10, 10), 0x00ffffff);
+        let edge: u32 = pixel(&ren, 10, 15) & 0xff;
+        assert!(edge > 0 && edge < 0xff);
+    }
+
+    #[test]
+    fn empty_ellipse_draws_nothing() {
+        let mut ren: CRen = CRen::new(8, 8);
+        ren.fill_ellipse((4.0, 4.0), 0.0, 3.0, Color::WHITE);
+        ren.fill_ellipse_aa((4.0, 4.0), 3.0, -1.0, Color::WHITE);
+        assert!(ren.raster.data.iter().all(|p| *p == 0));
+    }
+
+    #[test]
+    fn pie_covers_its_quadrant() {
+        //Angles run clockwise on screen, so 0..PI/2 is the lower right quadrant
+        let mut ren: CRen = CRen::new(21, 21);
+        ren.fill_pie((10.0, 10.0), 8.0, 0.0, PI/2.0, Color::WHITE);
+        assert_eq!(pixel(&ren, 13, 13), 0x00ffffff);
+        assert_eq!(pixel(&ren, 7, 13), 0);
+        assert_eq!(pixel(&ren, 13, 7), 0);
+        assert_eq!(pixel(&ren, 7, 7), 0);
+
+        //Reversed angles cover the same slice
+        let mut reversed: CRen = CRen::new(21, 21);
+        reversed.fill_pie((10.0, 10.0), 8.0, PI/2.0, 0.0, Color::WHITE);
+        assert_eq!(ren.raster.data, reversed.raster.data);
+    }
+
+    #[test]
+    fn rounded_rect_corners() {
+        let mut ren: CRen = CRen::new(16, 16);
+        ren.fill_rounded_rect((2.0, 2.0), (13.0, 13.0), 4.0, Color::WHITE);
+        assert_eq!(pixel(&ren, 2, 2), 0);
+        assert_eq!(pixel(&ren, 7, 2), 0x00ffffff);
+        assert_eq!(pixel(&ren, 7, 7), 0x00ffffff);
+
+        //The radius is limited to half the shorter side, and the corners may be given in any order
+        assert!(inside_rounded_rect(5.0, 5.0, 0.0, 0.0, 10.0, 10.0, 100.0));
+        assert!(!inside_rounded_rect(1.0, 1.0, 0.0, 0.0, 10.0, 10.0, 100.0));
+        let mut swapped: CRen = CRen::new(16, 16);
+        swapped.fill_rounded_rect((13.0, 13.0), (2.0, 2.0), 4.0, Color::WHITE);
+        assert_eq!(ren.raster.data, swapped.raster.data);
+
+        let mut outline: CRen = CRen::new(16, 16);
+        outline.draw_rounded_rect((2.0, 2.0), (13.0, 13.0), 4.0, 1.0, Color::WHITE);
+        assert_eq!(pixel(&outline, 7, 7), 0);
+        assert_eq!(pixel(&outline, 7, 2), 0x00ffffff);
+    }
+
+}
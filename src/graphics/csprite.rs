@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::craster::CRaster;
+use crate::graphics::cren::CRen;
+
+///A rectangle of pixels within a raster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl SpriteRect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> SpriteRect {
+        SpriteRect { x, y, width, height }
+    }
+
+    ///The part of this rectangle that lies inside `raster`.
+    pub fn clipped_to(&self, raster: &CRaster) -> SpriteRect {
+        let x: usize = self.x.min(raster.width);
+        let y: usize = self.y.min(raster.height);
+        SpriteRect::new(x, y, self.width.min(raster.width - x), self.height.min(raster.height - y))
+    }
+}
+
+///A view of part of a source raster plus the settings used to draw it.
+#[derive(Clone, Copy)]
+pub struct Sprite<'a> {
+    pub source: &'a CRaster,
+    pub rect: SpriteRect,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    ///Source pixels of exactly this color (ignoring the top byte) are not drawn.
+    pub color_key: Option<u32>,
+    ///Treat the top byte of each source pixel as alpha (0xFF opaque) instead of ignoring it.
+    pub pixel_alpha: bool,
+    ///Opacity of the whole sprite, multiplied with the tint's alpha.
+    pub alpha: f32,
+    ///Multiplied with every source pixel. White leaves the sprite unchanged.
+    pub tint: Color,
+    ///The point, relative to the top-left of `rect`, that is placed at the draw position. Flipping mirrors the
+    /// sprite around its pivot.
+    pub pivot: (i32, i32),
+}
+
+impl<'a> Sprite<'a> {
+
+    ///A sprite showing the whole of `source`.
+    pub fn new(source: &'a CRaster) -> Sprite<'a> {
+        Sprite::from_rect(source, SpriteRect::new(0, 0, source.width, source.height))
+    }
+
+    ///A sprite showing `rect` of `source`, clipped to the raster.
+    pub fn from_rect(source: &'a CRaster, rect: SpriteRect) -> Sprite<'a> {
+        Sprite {
+            source,
+            rect: rect.clipped_to(source),
+            flip_x: false,
+            flip_y: false,
+            color_key: None,
+            pixel_alpha: false,
+            alpha: 1.0,
+            tint: Color::WHITE,
+            pivot: (0, 0),
+        }
+    }
+
+    pub fn with_flip(self, flip_x: bool, flip_y: bool) -> Sprite<'a> {
+        Sprite { flip_x, flip_y, ..self }
+    }
+
+    pub fn with_color_key(self, key: u32) -> Sprite<'a> {
+        Sprite { color_key: Some(key & 0x00ffffff), ..self }
+    }
+
+    pub fn with_pixel_alpha(self, pixel_alpha: bool) -> Sprite<'a> {
+        Sprite { pixel_alpha, ..self }
+    }
+
+    pub fn with_alpha(self, alpha: f32) -> Sprite<'a> {
+        Sprite { alpha, ..self }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Sprite<'a> {
+        Sprite { tint, ..self }
+    }
+
+    pub fn with_pivot(self, x: i32, y: i32) -> Sprite<'a> {
+        Sprite { pivot: (x, y), ..self }
+    }
+
+    ///Places the pivot at the center of the sprite.
+    pub fn centered(self) -> Sprite<'a> {
+        let pivot: (i32, i32) = (self.rect.width as i32 / 2, self.rect.height as i32 / 2);
+        Sprite { pivot, ..self }
+    }
+
+}
+
+///A raster divided into frames, either on a regular grid or as a packed atlas of arbitrary rectangles.
+pub struct SpriteSheet {
+    pub raster: CRaster,
+    pub frames: Vec<SpriteRect>,
+    ///Pivot of each frame, relative to its top-left corner.
+    pub pivots: Vec<(i32, i32)>,
+    pub names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+
+    ///Slices `raster` into `cell_width` x `cell_height` frames, row by row. `margin` is the border around the whole
+    /// grid and `spacing` the gap between cells, both in pixels.
+    pub fn grid(raster: CRaster, cell_width: usize, cell_height: usize, margin: usize, spacing: usize) -> SpriteSheet {
+        let mut frames: Vec<SpriteRect> = Vec::new();
+        if cell_width > 0 && cell_height > 0 {
+            let mut y: usize = margin;
+            while y + cell_height + margin <= raster.height {
+                let mut x: usize = margin;
+                while x + cell_width + margin <= raster.width {
+                    frames.push(SpriteRect::new(x, y, cell_width, cell_height));
+                    x += cell_width + spacing;
+                }
+                y += cell_height + spacing;
+            }
+        }
+        let pivots: Vec<(i32, i32)> = vec![(0, 0); frames.len()];
+        SpriteSheet { raster, frames, pivots, names: HashMap::new() }
+    }
+
+    ///A packed atlas with one named frame per rectangle. Rectangles that fall outside the raster are clipped.
+    pub fn packed(raster: CRaster, frames: &[(&str, SpriteRect)]) -> SpriteSheet {
+        let mut sheet: SpriteSheet = SpriteSheet { raster, frames: Vec::new(), pivots: Vec::new(), names: HashMap::new() };
+        for (name, rect) in frames.iter() {
+            sheet.add_frame(name, *rect, (0, 0));
+        }
+        sheet
+    }
+
+    ///Parses a packed atlas description with one frame per line: `name x y width height [pivot_x pivot_y]`. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn from_atlas_text(raster: CRaster, text: &str) -> Result<SpriteSheet, String> {
+        let mut sheet: SpriteSheet = SpriteSheet::packed(raster, &[]);
+        for (n, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let numbers: Vec<i64> = fields[1..]
+                .iter()
+                .map(|f| f.parse::<i64>())
+                .collect::<Result<Vec<i64>, _>>()
+                .map_err(|_| format!("[SpriteSheet] line {}: expected numbers after the frame name", n + 1))?;
+            if (numbers.len() != 4 && numbers.len() != 6) || numbers[..4].iter().any(|v| *v < 0) {
+                return Err(format!("[SpriteSheet] line {}: expected 'name x y width height [pivot_x pivot_y]'", n + 1));
+            }
+            let rect: SpriteRect = SpriteRect::new(numbers[0] as usize, numbers[1] as usize, numbers[2] as usize, numbers[3] as usize);
+            let pivot: (i32, i32) = if numbers.len() == 6 { (numbers[4] as i32, numbers[5] as i32) } else { (0, 0) };
+            sheet.add_frame(fields[0], rect, pivot);
+        }
+        Ok(sheet)
+    }
+
+    ///Adds a frame and returns its index.
+    pub fn add_frame(&mut self, name: &str, rect: SpriteRect, pivot: (i32, i32)) -> usize {
+        self.frames.push(rect.clipped_to(&self.raster));
+        self.pivots.push(pivot);
+        if !name.is_empty() {
+            self.names.insert(name.to_string(), self.frames.len() - 1);
+        }
+        self.frames.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    ///A sprite for frame `index`, with the frame's pivot.
+    pub fn sprite(&self, index: usize) -> Sprite<'_> {
+        Sprite::from_rect(&self.raster, self.frames[index]).with_pivot(self.pivots[index].0, self.pivots[index].1)
+    }
+
+    pub fn named(&self, name: &str) -> Option<Sprite<'_>> {
+        self.index_of(name).map(|i| self.sprite(i))
+    }
+
+}
+
+///What an animation does after its last frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayMode {
+    Loop,
+    ///Stop on the last frame.
+    Once,
+    ///Play forwards then backwards, repeating.
+    PingPong,
+}
+
+///Plays a sequence of sprite sheet frames, each shown for its own duration in seconds.
+#[derive(Clone, Debug)]
+pub struct SpriteAnimation {
+    pub frames: Vec<usize>,
+    pub durations: Vec<f64>,
+    pub mode: PlayMode,
+    ///Playback rate, where 1.0 is normal speed.
+    pub speed: f64,
+    pub elapsed: f64,
+    pub playing: bool,
+}
+
+impl SpriteAnimation {
+
+    ///An animation over the sheet frames `frames`, all shown for `1/fps` seconds.
+    pub fn new(frames: &[usize], fps: f64, mode: PlayMode) -> SpriteAnimation {
+        let duration: f64 = if fps > 0.0 { 1.0 / fps } else { f64::INFINITY };
+        SpriteAnimation::with_durations(frames, &vec![duration; frames.len()], mode)
+    }
+
+    pub fn with_durations(frames: &[usize], durations: &[f64], mode: PlayMode) -> SpriteAnimation {
+        SpriteAnimation {
+            frames: frames.to_vec(),
+            durations: durations.iter().map(|d| d.max(0.0)).collect(),
+            mode,
+            speed: 1.0,
+            elapsed: 0.0,
+            playing: true,
+        }
+    }
+
+    ///Advances playback by `dt` seconds.
+    pub fn update(&mut self, dt: f64) {
+        if self.playing {
+            self.elapsed += dt*self.speed;
+        }
+        if self.mode == PlayMode::Once && self.is_finished() {
+            self.playing = false;
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.playing = true;
+    }
+
+    ///Total time to play every frame once.
+    pub fn duration(&self) -> f64 {
+        self.durations.iter().sum()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.mode == PlayMode::Once && self.elapsed >= self.duration()
+    }
+
+    ///Position in `frames` that is showing at the current time.
+    pub fn frame_position(&self) -> usize {
+        let n: usize = self.frames.len().min(self.durations.len());
+        let total: f64 = self.durations[..n].iter().sum();
+        if n <= 1 || total <= 0.0 {
+            return 0;
+        }
+        let (mut t, reversed) = match self.mode {
+            PlayMode::Once if self.elapsed >= total => return n - 1,
+            PlayMode::Once => (self.elapsed.max(0.0), false),
+            PlayMode::Loop => (self.elapsed.rem_euclid(total), false),
+            PlayMode::PingPong => {
+                let t: f64 = self.elapsed.rem_euclid(2.0*total);
+                if t < total { (t, false) } else { (t - total, true) }
+            }
+        };
+        for i in 0..n {
+            let k: usize = if reversed { n - 1 - i } else { i };
+            if t < self.durations[k] {
+                return k;
+            }
+            t -= self.durations[k];
+        }
+        if reversed { 0 } else { n - 1 }
+    }
+
+    ///Sheet frame index that is showing at the current time.
+    pub fn current_frame(&self) -> usize {
+        self.frames.get(self.frame_position()).copied().unwrap_or(0)
+    }
+
+    pub fn sprite<'a>(&self, sheet: &'a SpriteSheet) -> Sprite<'a> {
+        sheet.sprite(self.current_frame())
+    }
+
+}
+
+impl CRen {
+
+    ///Draws `sprite` with its pivot at `(x, y)`, skipping color-keyed pixels and blending when the sprite is
+    /// translucent or tinted.
+    pub fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32) {
+        let rect: SpriteRect = sprite.rect;
+        let (w, h) = (rect.width as i32, rect.height as i32);
+        let pivot_x: i32 = if sprite.flip_x { w - 1 - sprite.pivot.0 } else { sprite.pivot.0 };
+        let pivot_y: i32 = if sprite.flip_y { h - 1 - sprite.pivot.1 } else { sprite.pivot.1 };
+        let (left, top) = (x - pivot_x, y - pivot_y);
+
+        //Only visit the destination pixels that land on the raster
+        let i0: i32 = (-left).max(0);
+        let j0: i32 = (-top).max(0);
+        let i1: i32 = w.min(self.raster.width as i32 - left);
+        let j1: i32 = h.min(self.raster.height as i32 - top);
+        let plain: bool = sprite.alpha >= 1.0 && sprite.tint == Color::WHITE && !sprite.pixel_alpha;
+        let opacity: f32 = sprite.alpha*sprite.tint.a;
+
+        for j in j0..j1 {
+            let sy: i32 = if sprite.flip_y { h - 1 - j } else { j };
+            for i in i0..i1 {
+                let sx: i32 = if sprite.flip_x { w - 1 - i } else { i };
+                let src: u32 = sprite.source.data[(rect.x + sx as usize) + sprite.source.width*(rect.y + sy as usize)];
+                if sprite.color_key == Some(src & 0x00ffffff) {
+                    continue;
+                }
+                if plain {
+                    self.raster.data[(left + i) as usize + self.raster.width*(top + j) as usize] = src;
+                    continue;
+                }
+                let mut color: Color = Color::from_rgb_u32(src)*sprite.tint;
+                color.a = opacity;
+                let coverage: f32 = if sprite.pixel_alpha { (src >> 24) as f32 / 255.0 } else { 1.0 };
+                self.blend_pixel(left + i, top + j, color, coverage);
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    //A 4x2 raster whose pixels are numbered 1 to 8, row by row
+    fn numbered() -> CRaster {
+        let mut raster: CRaster = CRaster::new(4, 2);
+        for (i, p) in raster.data.iter_mut().enumerate() {
+            *p = i as u32 + 1;
+        }
+        raster
+    }
+
+    #[test]
+    fn clipping() {
+        let raster: CRaster = numbered();
+        assert_eq!(SpriteRect::new(3, 1, 5, 5).clipped_to(&raster), SpriteRect::new(3, 1, 1, 1));
+        assert_eq!(SpriteRect::new(9, 9, 5, 5).clipped_to(&raster), SpriteRect::new(4, 2, 0, 0));
+    }
+
+    #[test]
+    fn grid_and_atlas() {
+        //Two 3x3 cells with a 1 pixel margin and 2 pixels between them
+        let sheet: SpriteSheet = SpriteSheet::grid(CRaster::new(10, 6), 3, 3, 1, 2);
+        assert_eq!(sheet.frames, vec![SpriteRect::new(1, 1, 3, 3), SpriteRect::new(6, 1, 3, 3)]);
+        assert!(SpriteSheet::grid(CRaster::new(10, 6), 0, 3, 0, 0).is_empty());
+
+        let text: &str = "# name x y w h\n\nidle 0 0 2 2\nrun 2 0 2 2 1 1\n";
+        let sheet: SpriteSheet = SpriteSheet::from_atlas_text(numbered(), text).unwrap();
+        assert_eq!(sheet.len(), 2);
+        assert_eq!(sheet.index_of("run"), Some(1));
+        let run: Sprite = sheet.named("run").unwrap();
+        assert_eq!((run.rect, run.pivot), (SpriteRect::new(2, 0, 2, 2), (1, 1)));
+        assert!(sheet.named("jump").is_none());
+
+        assert!(SpriteSheet::from_atlas_text(numbered(), "a 0 0 2").is_err());
+        assert!(SpriteSheet::from_atlas_text(numbered(), "a 0 0 x 2").is_err());
+        assert!(SpriteSheet::from_atlas_text(numbered(), "a 0 -1 2 2").is_err());
+    }
+
+    #[test]
+    fn draw_flipped_and_keyed() {
+        let raster: CRaster = numbered();
+        let mut ren: CRen = CRen::new(4, 2);
+        //Flipping mirrors around the pivot, so a top-left pivot becomes the top-right corner
+        ren.draw_sprite(&Sprite::new(&raster).with_flip(true, false), 3, 0);
+        assert_eq!(ren.raster.data, vec![4, 3, 2, 1, 8, 7, 6, 5]);
+
+        //The key ignores the top byte of both the key and the pixels
+        let mut ren: CRen = CRen::new(4, 2);
+        ren.fill(0x00abcdef);
+        ren.draw_sprite(&Sprite::new(&raster).with_color_key(0xff000002).with_flip(false, true), 0, 1);
+        assert_eq!(ren.raster.data, vec![5, 6, 7, 8, 1, 0x00abcdef, 3, 4]);
+    }
+
+    #[test]
+    fn draw_with_pivot_off_screen() {
+        let raster: CRaster = numbered();
+        let mut ren: CRen = CRen::new(4, 2);
+        ren.draw_sprite(&Sprite::new(&raster).centered(), 0, 0);
+        assert_eq!(ren.raster.data, vec![7, 8, 0, 0, 0, 0, 0, 0]);
+        ren.draw_sprite(&Sprite::new(&raster), -10, 5);
+        assert_eq!(ren.raster.data, vec![7, 8, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn draw_translucent() {
+        let mut raster: CRaster = CRaster::new(2, 1);
+        raster.data = vec![0x00ffffff, 0x00ffffff];
+        let mut ren: CRen = CRen::new(2, 1);
+        ren.draw_sprite(&Sprite::new(&raster).with_alpha(0.0), 0, 0);
+        assert_eq!(ren.raster.data, vec![0, 0]);
+        ren.draw_sprite(&Sprite::new(&raster).with_tint(Color::RED), 0, 0);
+        assert_eq!(ren.raster.data, vec![0x00ff0000, 0x00ff0000]);
+
+        raster.data = vec![0x00ffffff, 0xff00ff00];
+        let mut ren: CRen = CRen::new(2, 1);
+        ren.draw_sprite(&Sprite::new(&raster).with_pixel_alpha(true), 0, 0);
+        assert_eq!(ren.raster.data, vec![0, 0x0000ff00]);
+    }
+
+    #[test]
+    fn animation_modes() {
+        let mut anim: SpriteAnimation = SpriteAnimation::new(&[5, 6, 7], 10.0, PlayMode::Loop);
+        assert_eq!(anim.current_frame(), 5);
+        anim.update(0.25);
+        assert_eq!(anim.current_frame(), 7);
+        anim.update(0.1);
+        assert_eq!(anim.current_frame(), 5);
+
+        let mut anim: SpriteAnimation = SpriteAnimation::new(&[5, 6, 7], 10.0, PlayMode::PingPong);
+        anim.update(0.35);
+        assert_eq!(anim.current_frame(), 7);
+        anim.update(0.1);
+        assert_eq!(anim.current_frame(), 6);
+        anim.update(0.2);
+        assert_eq!(anim.current_frame(), 5);
+
+        let mut anim: SpriteAnimation = SpriteAnimation::with_durations(&[1, 2], &[0.1, 0.3], PlayMode::Once);
+        anim.update(0.2);
+        assert_eq!((anim.current_frame(), anim.is_finished()), (2, false));
+        anim.update(1.0);
+        assert_eq!((anim.current_frame(), anim.is_finished(), anim.playing), (2, true, false));
+        anim.reset();
+        assert_eq!((anim.current_frame(), anim.playing), (1, true));
+
+        //No frames or a zero frame rate never panic
+        assert_eq!(SpriteAnimation::new(&[], 10.0, PlayMode::Loop).current_frame(), 0);
+        let mut still: SpriteAnimation = SpriteAnimation::new(&[3, 4], 0.0, PlayMode::Loop);
+        still.update(100.0);
+        assert_eq!(still.current_frame(), 3);
+    }
+
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::cren::CRen;
+use crate::graphics::csprite::{SpriteAnimation, SpriteRect, SpriteSheet};
+
+///One cell of a tile layer. `id` 0 is empty, otherwise `id - 1` is the tileset frame. The image is flipped first and
+/// then rotated clockwise by `rotation` quarter turns; rotation is ignored for non-square tiles.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tile {
+    pub id: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub rotation: u8,
+}
+
+impl Tile {
+
+    pub const EMPTY: Tile = Tile { id: 0, flip_x: false, flip_y: false, rotation: 0 };
+
+    pub fn new(id: u32) -> Tile {
+        Tile { id, ..Tile::EMPTY }
+    }
+
+    pub fn with_flip(self, flip_x: bool, flip_y: bool) -> Tile {
+        Tile { flip_x, flip_y, ..self }
+    }
+
+    pub fn with_rotation(self, quarter_turns: u8) -> Tile {
+        Tile { rotation: quarter_turns % 4, ..self }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id == 0
+    }
+
+}
+
+pub struct TileLayer {
+    pub name: String,
+    pub tiles: Vec<Tile>,
+    pub visible: bool,
+    pub opacity: f32,
+    ///How fast the layer scrolls relative to the camera. (1.0, 1.0) moves with the world, smaller values give
+    /// distant backgrounds.
+    pub parallax: (f64, f64),
+}
+
+///The view onto a tilemap: `(x, y)` is the world position shown at the top-left of the raster and `zoom` is screen
+/// pixels per world pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileCamera {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+impl TileCamera {
+
+    pub fn new(x: f64, y: f64, zoom: f64) -> TileCamera {
+        TileCamera { x, y, zoom }
+    }
+
+    ///A camera at zoom `zoom` whose view of size `view_width` x `view_height` screen pixels is centered on the
+    /// world point `(x, y)`.
+    pub fn centered_on(x: f64, y: f64, zoom: f64, view_width: usize, view_height: usize) -> TileCamera {
+        TileCamera::new(x - view_width as f64 / (2.0*zoom), y - view_height as f64 / (2.0*zoom), zoom)
+    }
+
+    pub fn world_to_screen(&self, wx: f64, wy: f64) -> (f64, f64) {
+        ((wx - self.x)*self.zoom, (wy - self.y)*self.zoom)
+    }
+
+    pub fn screen_to_world(&self, sx: f64, sy: f64) -> (f64, f64) {
+        (sx / self.zoom + self.x, sy / self.zoom + self.y)
+    }
+
+}
+
+impl Default for TileCamera {
+    fn default() -> TileCamera {
+        TileCamera::new(0.0, 0.0, 1.0)
+    }
+}
+
+///A grid of tiles in one or more layers, drawn from a tileset sprite sheet. Layers are drawn in order, so later
+/// layers cover earlier ones.
+pub struct Tilemap {
+    pub tileset: SpriteSheet,
+    pub tile_width: usize,
+    pub tile_height: usize,
+    ///Size of every layer, in tiles.
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<TileLayer>,
+    ///Tileset pixels of this color are transparent, letting lower layers show through.
+    pub color_key: Option<u32>,
+    ///Animated tiles, keyed by tile id. The animation's frames are tileset frame indices.
+    pub animations: HashMap<u32, SpriteAnimation>,
+}
+
+impl Tilemap {
+
+    ///An empty map of `width` x `height` tiles with no layers. The tile size is the size of the tileset's first frame.
+    pub fn new(tileset: SpriteSheet, width: usize, height: usize) -> Tilemap {
+        let (tile_width, tile_height) = tileset.frames.first().map_or((0, 0), |f| (f.width, f.height));
+        Tilemap {
+            tileset,
+            tile_width,
+            tile_height,
+            width,
+            height,
+            layers: Vec::new(),
+            color_key: None,
+            animations: HashMap::new(),
+        }
+    }
+
+    ///Adds an empty layer on top of the others and returns its index.
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            tiles: vec![Tile::EMPTY; self.width*self.height],
+            visible: true,
+            opacity: 1.0,
+            parallax: (1.0, 1.0),
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    pub fn get(&self, layer: usize, tx: i32, ty: i32) -> Option<Tile> {
+        if tx < 0 || ty < 0 || tx as usize >= self.width || ty as usize >= self.height {
+            return None;
+        }
+        self.layers.get(layer).map(|l| l.tiles[tx as usize + self.width*ty as usize])
+    }
+
+    ///Sets a tile, ignoring positions outside the map.
+    pub fn set(&mut self, layer: usize, tx: i32, ty: i32, tile: Tile) {
+        if tx < 0 || ty < 0 || tx as usize >= self.width || ty as usize >= self.height {
+            return;
+        }
+        if let Some(l) = self.layers.get_mut(layer) {
+            l.tiles[tx as usize + self.width*ty as usize] = tile;
+        }
+    }
+
+    ///Makes every tile with `id` play `animation` instead of showing a single frame.
+    pub fn animate(&mut self, id: u32, animation: SpriteAnimation) {
+        self.animations.insert(id, animation);
+    }
+
+    ///Advances all tile animations by `dt` seconds.
+    pub fn update(&mut self, dt: f64) {
+        for animation in self.animations.values_mut() {
+            animation.update(dt);
+        }
+    }
+
+    ///Tile containing the world point `(wx, wy)`. The result may lie outside the map.
+    pub fn world_to_tile(&self, wx: f64, wy: f64) -> (i32, i32) {
+        ((wx / self.tile_width.max(1) as f64).floor() as i32, (wy / self.tile_height.max(1) as f64).floor() as i32)
+    }
+
+    ///World position of the top-left corner of tile `(tx, ty)`.
+    pub fn tile_to_world(&self, tx: i32, ty: i32) -> (f64, f64) {
+        ((tx*self.tile_width as i32) as f64, (ty*self.tile_height as i32) as f64)
+    }
+
+    pub fn tile_center(&self, tx: i32, ty: i32) -> (f64, f64) {
+        let (x, y) = self.tile_to_world(tx, ty);
+        (x + self.tile_width as f64 / 2.0, y + self.tile_height as f64 / 2.0)
+    }
+
+    ///Size of the whole map in world pixels.
+    pub fn world_size(&self) -> (f64, f64) {
+        ((self.width*self.tile_width) as f64, (self.height*self.tile_height) as f64)
+    }
+
+    ///Tileset frame currently shown for tile `id`, following its animation if it has one.
+    pub fn frame_of(&self, id: u32) -> Option<usize> {
+        if id == 0 {
+            return None;
+        }
+        let frame: usize = match self.animations.get(&id) {
+            Some(a) => a.current_frame(),
+            None => id as usize - 1,
+        };
+        if frame < self.tileset.len() { Some(frame) } else { None }
+    }
+
+}
+
+impl CRen {
+
+    ///Draws every visible layer of `map` as seen through `camera`. Only the tiles that overlap the raster are
+    /// visited.
+    pub fn draw_tilemap(&mut self, map: &Tilemap, camera: &TileCamera) {
+        for layer in 0..map.layers.len() {
+            self.draw_tile_layer(map, layer, camera);
+        }
+    }
+
+    pub fn draw_tile_layer(&mut self, map: &Tilemap, layer: usize, camera: &TileCamera) {
+        let Some(l) = map.layers.get(layer) else {
+            return;
+        };
+        if !l.visible || l.opacity <= 0.0 || camera.zoom <= 0.0 || map.tile_width == 0 || map.tile_height == 0 {
+            return;
+        }
+        let view: TileCamera = TileCamera::new(camera.x*l.parallax.0, camera.y*l.parallax.1, camera.zoom);
+        let (tw, th) = (map.tile_width as f64, map.tile_height as f64);
+
+        //Visible tile range, clamped to the map
+        let (wx0, wy0) = view.screen_to_world(0.0, 0.0);
+        let (wx1, wy1) = view.screen_to_world(self.raster.width as f64, self.raster.height as f64);
+        let tx0: i32 = ((wx0 / tw).floor() as i32).max(0);
+        let ty0: i32 = ((wy0 / th).floor() as i32).max(0);
+        let tx1: i32 = ((wx1 / tw).ceil() as i32).min(map.width as i32);
+        let ty1: i32 = ((wy1 / th).ceil() as i32).min(map.height as i32);
+
+        for ty in ty0..ty1 {
+            for tx in tx0..tx1 {
+                let tile: Tile = l.tiles[tx as usize + map.width*ty as usize];
+                if let Some(frame) = map.frame_of(tile.id) {
+                    self.draw_tile(map, map.tileset.frames[frame], tile, (tx, ty), &view, l.opacity);
+                }
+            }
+        }
+    }
+
+    fn draw_tile(&mut self, map: &Tilemap, src: SpriteRect, tile: Tile, (tx, ty): (i32, i32), view: &TileCamera, opacity: f32) {
+        let (wx, wy) = map.tile_to_world(tx, ty);
+        let (tw, th) = (src.width.min(map.tile_width), src.height.min(map.tile_height));
+        if tw == 0 || th == 0 {
+            return;
+        }
+        //Rounding both edges of every tile the same way keeps neighbouring tiles from leaving gaps at any zoom
+        let (sx0, sy0) = view.world_to_screen(wx, wy);
+        let (sx1, sy1) = view.world_to_screen(wx + map.tile_width as f64, wy + map.tile_height as f64);
+        let (sx0, sy0, sx1, sy1) = (sx0.round() as i32, sy0.round() as i32, sx1.round() as i32, sy1.round() as i32);
+        let rotation: u8 = if tw == th { tile.rotation % 4 } else { 0 };
+        //Keys compare on the color channels only, as `Sprite::with_color_key` does
+        let key: Option<u32> = map.color_key.map(|k| k & 0x00ffffff);
+
+        let raster_w: i32 = self.raster.width as i32;
+        let raster_h: i32 = self.raster.height as i32;
+        for py in sy0.max(0)..sy1.min(raster_h) {
+            let v: usize = (((py as f64 + 0.5) / view.zoom + view.y - wy) as usize).min(th - 1);
+            for px in sx0.max(0)..sx1.min(raster_w) {
+                let u: usize = (((px as f64 + 0.5) / view.zoom + view.x - wx) as usize).min(tw - 1);
+                //Undo the rotation, then the flips, to find the source pixel
+                let (mut x, mut y) = (u, v);
+                for _ in 0..rotation {
+                    (x, y) = (y, tw - 1 - x);
+                }
+                if tile.flip_x {
+                    x = tw - 1 - x;
+                }
+                if tile.flip_y {
+                    y = th - 1 - y;
+                }
+                let color: u32 = map.tileset.raster.data[(src.x + x) + map.tileset.raster.width*(src.y + y)];
+                if key == Some(color & 0x00ffffff) {
+                    continue;
+                }
+                if opacity >= 1.0 {
+                    self.raster.data[px as usize + self.raster.width*py as usize] = color;
+                }
+                else {
+                    self.blend_pixel(px, py, Color::from_rgb_u32(color).with_alpha(opacity), 1.0);
+                }
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::graphics::craster::CRaster;
+    use crate::graphics::csprite::PlayMode;
+
+    //Two 2x2 tiles: frame 0 holds pixels [1, 2, 5, 6] and frame 1 holds [3, 4, 7, 8], row by row
+    fn map(width: usize, height: usize) -> Tilemap {
+        let mut raster: CRaster = CRaster::new(4, 2);
+        for (i, p) in raster.data.iter_mut().enumerate() {
+            *p = i as u32 + 1;
+        }
+        let mut map: Tilemap = Tilemap::new(SpriteSheet::grid(raster, 2, 2, 0, 0), width, height);
+        map.add_layer("ground");
+        map
+    }
+
+    fn draw(map: &Tilemap, w: usize, h: usize, camera: TileCamera) -> Vec<u32> {
+        let mut ren: CRen = CRen::new(w, h);
+        ren.draw_tilemap(map, &camera);
+        ren.raster.data
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut map: Tilemap = map(2, 2);
+        assert_eq!((map.tile_width, map.tile_height), (2, 2));
+        map.set(0, 1, 1, Tile::new(2));
+        map.set(0, 2, 0, Tile::new(2));
+        map.set(5, 0, 0, Tile::new(2));
+        assert_eq!(map.get(0, 1, 1), Some(Tile::new(2)));
+        assert_eq!(map.get(0, 0, 0), Some(Tile::EMPTY));
+        assert_eq!(map.get(0, -1, 0), None);
+        assert_eq!(map.get(1, 0, 0), None);
+        assert_eq!(map.layer_index("ground"), Some(0));
+        assert_eq!(Tile::new(1).with_rotation(6).rotation, 2);
+    }
+
+    #[test]
+    fn coordinates() {
+        let map: Tilemap = map(3, 2);
+        assert_eq!(map.world_to_tile(-0.5, 3.9), (-1, 1));
+        assert_eq!(map.tile_to_world(2, 1), (4.0, 2.0));
+        assert_eq!(map.tile_center(0, 0), (1.0, 1.0));
+        assert_eq!(map.world_size(), (6.0, 4.0));
+
+        let camera: TileCamera = TileCamera::centered_on(10.0, 10.0, 2.0, 40, 20);
+        assert_eq!((camera.x, camera.y), (0.0, 5.0));
+        assert_eq!(camera.world_to_screen(10.0, 10.0), (20.0, 10.0));
+        assert_eq!(camera.screen_to_world(20.0, 10.0), (10.0, 10.0));
+    }
+
+    #[test]
+    fn draw_tiles() {
+        let mut map: Tilemap = map(2, 1);
+        map.set(0, 0, 0, Tile::new(1));
+        map.set(0, 1, 0, Tile::new(2));
+        assert_eq!(draw(&map, 4, 2, TileCamera::default()), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        //Scrolled one tile to the left, with the rest of the view past the map's edge
+        assert_eq!(draw(&map, 4, 2, TileCamera::new(2.0, 0.0, 1.0)), vec![3, 4, 0, 0, 7, 8, 0, 0]);
+        assert_eq!(draw(&map, 4, 2, TileCamera::new(0.0, 0.0, 2.0)), vec![1, 1, 2, 2, 1, 1, 2, 2]);
+
+        //Layers that do not scroll stay put
+        map.layers[0].parallax = (0.0, 0.0);
+        assert_eq!(draw(&map, 4, 2, TileCamera::new(2.0, 0.0, 1.0)), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        map.layers[0].visible = false;
+        assert_eq!(draw(&map, 4, 2, TileCamera::default()), vec![0; 8]);
+
+        //Layers that do not exist draw nothing
+        let mut ren: CRen = CRen::new(4, 2);
+        ren.draw_tile_layer(&map, 3, &TileCamera::default());
+        assert_eq!(ren.raster.data, vec![0; 8]);
+    }
+
+    #[test]
+    fn flips_and_rotation() {
+        let mut map: Tilemap = map(1, 1);
+        map.set(0, 0, 0, Tile::new(1).with_flip(true, false));
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![2, 1, 6, 5]);
+        map.set(0, 0, 0, Tile::new(1).with_flip(false, true));
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![5, 6, 1, 2]);
+        map.set(0, 0, 0, Tile::new(1).with_rotation(1));
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![5, 1, 6, 2]);
+        map.set(0, 0, 0, Tile::new(1).with_rotation(2));
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![6, 5, 2, 1]);
+    }
+
+    #[test]
+    fn color_key_ignores_alpha() {
+        let mut map: Tilemap = map(1, 1);
+        map.add_layer("top");
+        map.set(0, 0, 0, Tile::new(2));
+        map.set(1, 0, 0, Tile::new(1));
+        map.color_key = Some(0xff000002);
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![1, 4, 5, 6]);
+    }
+
+    #[test]
+    fn animated_tiles() {
+        let mut map: Tilemap = map(1, 1);
+        map.set(0, 0, 0, Tile::new(1));
+        map.animate(1, SpriteAnimation::new(&[0, 1], 1.0, PlayMode::Loop));
+        assert_eq!(map.frame_of(1), Some(0));
+        map.update(1.0);
+        assert_eq!(map.frame_of(1), Some(1));
+        assert_eq!(draw(&map, 2, 2, TileCamera::default()), vec![3, 4, 7, 8]);
+        assert_eq!(map.frame_of(0), None);
+        assert_eq!(map.frame_of(3), None);
+    }
+
+}
@@ -0,0 +1,826 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::graphics::ccolor::Color;
+use crate::graphics::cfont::{self, FontError, TextAlign};
+use crate::graphics::cpath::{self, Coverage, FillRule, Path, DEFAULT_TOLERANCE};
+use crate::graphics::craster::CRaster;
+use crate::graphics::cren::CRen;
+
+const ATLAS_WIDTH: usize = 512;
+const MAX_ATLAS_HEIGHT: usize = 4096;
+//Composite glyphs may nest; deeper nesting than this is treated as a malformed font
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+///Vertical metrics from the `hhea` table, in font units with y pointing up.
+#[derive(Clone, Copy, Debug)]
+pub struct VMetrics {
+    pub ascender: f64,
+    pub descender: f64,
+    pub line_gap: f64,
+}
+
+///Where a rasterized glyph lives in the atlas, and where its top-left corner goes relative to the pen position on
+/// the baseline.
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasEntry {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+///Glyph coverage bitmaps packed into shelves of a single `CRaster`. Coverage is stored as a gray level in every
+/// color channel (0x00 to 0xFF). The atlas grows downward when full, and starts over once it reaches its limit.
+pub struct GlyphAtlas {
+    pub raster: CRaster,
+    entries: HashMap<(u16, u32), AtlasEntry>,
+    shelf_x: usize,
+    shelf_y: usize,
+    shelf_height: usize,
+}
+
+impl GlyphAtlas {
+
+    pub fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            raster: CRaster::new(ATLAS_WIDTH, 256),
+            entries: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.raster.data.iter_mut().for_each(|p| *p = 0);
+        self.shelf_x = 0;
+        self.shelf_y = 0;
+        self.shelf_height = 0;
+    }
+
+    fn get(&self, glyph: u16, size: f64) -> Option<AtlasEntry> {
+        self.entries.get(&(glyph, size_key(size))).copied()
+    }
+
+    ///Copies `coverage` into free space in the atlas and records it under `(glyph, size)`.
+    fn insert(&mut self, glyph: u16, size: f64, coverage: &Coverage, offset_x: i32, offset_y: i32) -> AtlasEntry {
+        let (w, h) = (coverage.width.min(ATLAS_WIDTH), coverage.height.min(MAX_ATLAS_HEIGHT));
+        if self.shelf_x + w > ATLAS_WIDTH {
+            self.shelf_y += self.shelf_height + 1;
+            self.shelf_x = 0;
+            self.shelf_height = 0;
+        }
+        while self.shelf_y + h > self.raster.height {
+            if self.raster.height*2 > MAX_ATLAS_HEIGHT {
+                self.clear();
+                break;
+            }
+            let mut grown: CRaster = CRaster::new(ATLAS_WIDTH, self.raster.height*2);
+            grown.data[..self.raster.data.len()].copy_from_slice(&self.raster.data);
+            self.raster = grown;
+        }
+
+        let entry: AtlasEntry = AtlasEntry { x: self.shelf_x, y: self.shelf_y, width: w, height: h, offset_x, offset_y };
+        for j in 0..h {
+            for i in 0..w {
+                let level: u32 = (coverage.get(i, j).clamp(0.0, 1.0)*255.0 + 0.5) as u32;
+                self.raster.data[(entry.x + i) + ATLAS_WIDTH*(entry.y + j)] = level*0x010101;
+            }
+        }
+        self.shelf_x += w + 1;
+        self.shelf_height = self.shelf_height.max(h);
+        self.entries.insert((glyph, size_key(size)), entry);
+        entry
+    }
+
+}
+
+impl Default for GlyphAtlas {
+    fn default() -> GlyphAtlas {
+        GlyphAtlas::new()
+    }
+}
+
+///A TrueType (or OpenType with `glyf` outlines) font. Glyphs are rasterized on demand at any pixel size, where the
+/// size is the height of the em square in pixels, and cached in `atlas`.
+pub struct TrueTypeFont {
+    data: Vec<u8>,
+    pub units_per_em: u16,
+    pub metrics: VMetrics,
+    pub num_glyphs: u16,
+    glyf: usize,
+    loca: Vec<u32>,
+    hmetrics: Vec<(u16, i16)>,
+    cmap: HashMap<u32, u16>,
+    kern: HashMap<(u16, u16), i16>,
+    pub atlas: GlyphAtlas,
+}
+
+impl TrueTypeFont {
+
+    pub fn load(path: &str) -> Result<TrueTypeFont, FontError> {
+        TrueTypeFont::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<TrueTypeFont, FontError> {
+        let version: u32 = read_u32(&data, 0)?;
+        if version == 0x4f54544f {
+            return Err(FontError::Format("OpenType fonts with CFF outlines are not supported".to_string()));
+        }
+        if version != 0x00010000 && version != 0x74727565 {
+            return Err(FontError::Format("not a TrueType font".to_string()));
+        }
+
+        let mut tables: HashMap<[u8; 4], (usize, usize)> = HashMap::new();
+        let num_tables: usize = read_u16(&data, 4)? as usize;
+        for i in 0..num_tables {
+            let record: usize = 12 + 16*i;
+            let tag: [u8; 4] = bytes(&data, record, 4)?.try_into().unwrap();
+            let offset: usize = read_u32(&data, record + 8)? as usize;
+            let length: usize = read_u32(&data, record + 12)? as usize;
+            bytes(&data, offset, length)?;
+            tables.insert(tag, (offset, length));
+        }
+        let table = |tag: &[u8; 4]| -> Result<usize, FontError> {
+            tables.get(tag).map(|t| t.0).ok_or_else(|| {
+                FontError::Format(format!("missing '{}' table", String::from_utf8_lossy(tag)))
+            })
+        };
+
+        let head: usize = table(b"head")?;
+        let units_per_em: u16 = read_u16(&data, head + 18)?;
+        let long_loca: bool = read_i16(&data, head + 50)? != 0;
+        let num_glyphs: u16 = read_u16(&data, table(b"maxp")? + 4)?;
+
+        let hhea: usize = table(b"hhea")?;
+        let metrics: VMetrics = VMetrics {
+            ascender: read_i16(&data, hhea + 4)? as f64,
+            descender: read_i16(&data, hhea + 6)? as f64,
+            line_gap: read_i16(&data, hhea + 8)? as f64,
+        };
+        let num_hmetrics: usize = (read_u16(&data, hhea + 34)? as usize).max(1);
+        let hmtx: usize = table(b"hmtx")?;
+        let mut hmetrics: Vec<(u16, i16)> = Vec::with_capacity(num_glyphs as usize);
+        for i in 0..num_glyphs as usize {
+            //Glyphs past numberOfHMetrics reuse the last advance and only store a side bearing
+            if i < num_hmetrics {
+                hmetrics.push((read_u16(&data, hmtx + 4*i)?, read_i16(&data, hmtx + 4*i + 2)?));
+            }
+            else {
+                let lsb: i16 = read_i16(&data, hmtx + 4*num_hmetrics + 2*(i - num_hmetrics)).unwrap_or(0);
+                hmetrics.push((hmetrics[num_hmetrics - 1].0, lsb));
+            }
+        }
+
+        let loca_offset: usize = table(b"loca")?;
+        let mut loca: Vec<u32> = Vec::with_capacity(num_glyphs as usize + 1);
+        for i in 0..=num_glyphs as usize {
+            loca.push(if long_loca { read_u32(&data, loca_offset + 4*i)? } else { read_u16(&data, loca_offset + 2*i)? as u32 * 2 });
+        }
+
+        let cmap: HashMap<u32, u16> = parse_cmap(&data, table(b"cmap")?)?;
+        let kern: HashMap<(u16, u16), i16> = match tables.get(b"kern") {
+            Some(&(offset, _)) => parse_kern(&data, offset).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+
+        Ok(TrueTypeFont {
+            glyf: table(b"glyf")?,
+            data,
+            units_per_em,
+            metrics,
+            num_glyphs,
+            loca,
+            hmetrics,
+            cmap,
+            kern,
+            atlas: GlyphAtlas::new(),
+        })
+    }
+
+    ///Glyph index for `c`, or 0 (the "missing glyph") if the font does not map it.
+    pub fn glyph_index(&self, c: char) -> u16 {
+        self.cmap.get(&(c as u32)).copied().unwrap_or(0)
+    }
+
+    ///Pixels per font unit at `size`.
+    pub fn scale(&self, size: f64) -> f64 {
+        size / self.units_per_em.max(1) as f64
+    }
+
+    pub fn advance(&self, glyph: u16) -> f64 {
+        self.hmetrics.get(glyph as usize).map_or(0.0, |m| m.0 as f64)
+    }
+
+    ///Kerning adjustment between two glyphs from the `kern` table, in font units.
+    pub fn kerning(&self, left: u16, right: u16) -> f64 {
+        self.kern.get(&(left, right)).map_or(0.0, |k| *k as f64)
+    }
+
+    pub fn ascent(&self, size: f64) -> f64 {
+        self.metrics.ascender*self.scale(size)
+    }
+
+    pub fn line_height(&self, size: f64) -> f64 {
+        (self.metrics.ascender - self.metrics.descender + self.metrics.line_gap)*self.scale(size)
+    }
+
+    ///Width in pixels of a single line of text at `size`, including kerning.
+    pub fn line_width(&self, line: &str, size: f64) -> f64 {
+        let mut width: f64 = 0.0;
+        let mut previous: Option<u16> = None;
+        for c in line.chars() {
+            let glyph: u16 = self.glyph_index(c);
+            if let Some(p) = previous {
+                width += self.kerning(p, glyph);
+            }
+            width += self.advance(glyph);
+            previous = Some(glyph);
+        }
+        width*self.scale(size)
+    }
+
+    ///Returns the `(width, height)` in pixels of the box `text` occupies at `size`.
+    pub fn measure(&self, text: &str, size: f64) -> (f64, f64) {
+        let mut width: f64 = 0.0;
+        let mut lines: usize = 0;
+        for line in text.split('\n') {
+            width = width.max(self.line_width(line, size));
+            lines += 1;
+        }
+        (width, lines as f64*self.line_height(size))
+    }
+
+    ///Word-wraps `text` into lines no wider than `max_width` pixels at `size`.
+    pub fn wrap(&self, text: &str, size: f64, max_width: f64) -> Vec<String> {
+        cfont::wrap_text(text, max_width, |s| self.line_width(s, size))
+    }
+
+    ///The outline of `glyph` at `size` as a path in pixels, with the origin on the baseline and y pointing down.
+    pub fn glyph_path(&self, glyph: u16, size: f64) -> Result<Path, FontError> {
+        let mut contours: Vec<Vec<(f64, f64, bool)>> = Vec::new();
+        self.read_contours(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours)?;
+
+        let s: f64 = self.scale(size);
+        let mut path: Path = Path::new();
+        for contour in contours.iter() {
+            let pts: Vec<(f64, f64, bool)> = contour.iter().map(|&(x, y, on)| (x*s, -y*s, on)).collect();
+            let n: usize = pts.len();
+            //Start on an on-curve point, or halfway between two off-curve points if there are none
+            let (first, start) = match pts.iter().position(|p| p.2) {
+                Some(i) => (i, (pts[i].0, pts[i].1)),
+                None => (0, ((pts[0].0 + pts[1].0)/2.0, (pts[0].1 + pts[1].1)/2.0)),
+            };
+            path.move_to(start.0, start.1);
+            let mut control: Option<(f64, f64)> = if pts[first].2 { None } else { Some((pts[first].0, pts[first].1)) };
+            for k in 1..=n {
+                let p: (f64, f64, bool) = pts[(first + k) % n];
+                match (p.2, control) {
+                    (true, None) => { path.line_to(p.0, p.1); }
+                    (true, Some(c)) => {
+                        path.quad_to(c.0, c.1, p.0, p.1);
+                        control = None;
+                    }
+                    (false, None) => control = Some((p.0, p.1)),
+                    (false, Some(c)) => {
+                        path.quad_to(c.0, c.1, (c.0 + p.0)/2.0, (c.1 + p.1)/2.0);
+                        control = Some((p.0, p.1));
+                    }
+                }
+            }
+            if let Some(c) = control {
+                path.quad_to(c.0, c.1, start.0, start.1);
+            }
+            path.close();
+        }
+        Ok(path)
+    }
+
+    ///Returns the atlas entry for `glyph` at `size`, rasterizing it first if it is not cached yet. A glyph too big to
+    /// fit in the atlas is an error.
+    pub fn rasterize_glyph(&mut self, glyph: u16, size: f64) -> Result<AtlasEntry, FontError> {
+        if let Some(entry) = self.atlas.get(glyph, size) {
+            return Ok(entry);
+        }
+        let path: Path = self.glyph_path(glyph, size)?;
+        let polygons: Vec<Vec<(f64, f64)>> = path.flatten(DEFAULT_TOLERANCE).into_iter().map(|p| p.points).collect();
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        if let Some(&(x, y)) = polygons.iter().flatten().next() {
+            (min_x, min_y, max_x, max_y) = (x, y, x, y);
+        }
+        for &(x, y) in polygons.iter().flatten() {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        //The atlas could not hold a bigger bitmap, so do not spend memory rasterizing one
+        if !(max_x - min_x < (ATLAS_WIDTH - 3) as f64 && max_y - min_y < (MAX_ATLAS_HEIGHT - 3) as f64) {
+            return Err(FontError::Format(format!("glyph {} at size {} is too large for the glyph atlas", glyph, size)));
+        }
+        //One pixel of margin keeps the anti-aliased edge inside the bitmap
+        let (x0, y0) = (min_x.floor() as i32 - 1, min_y.floor() as i32 - 1);
+        let width: usize = (max_x.ceil() as i32 + 2 - x0) as usize;
+        let height: usize = (max_y.ceil() as i32 + 2 - y0) as usize;
+        let shifted: Vec<Vec<(f64, f64)>> = polygons
+            .iter()
+            .map(|p| p.iter().map(|&(x, y)| (x - x0 as f64, y - y0 as f64)).collect())
+            .collect();
+
+        let raw: Coverage = cpath::rasterize(&shifted, FillRule::NonZero, width, height);
+        let mut coverage: Coverage = Coverage { x0: 0, y0: 0, width, height, data: vec![0.0; width*height] };
+        for j in 0..raw.height {
+            for i in 0..raw.width {
+                let (x, y) = (raw.x0 as usize + i, raw.y0 as usize + j);
+                coverage.data[x + width*y] = raw.get(i, j);
+            }
+        }
+        Ok(self.atlas.insert(glyph, size, &coverage, x0, y0))
+    }
+
+    ///Appends the contours of `glyph`, transformed by the 2x3 matrix `m`, to `out`. Points are in font units with y
+    /// pointing up, and flagged true when they are on the curve.
+    fn read_contours(&self, glyph: u16, m: [f64; 6], depth: usize, out: &mut Vec<Vec<(f64, f64, bool)>>) -> Result<(), FontError> {
+        if depth > MAX_COMPONENT_DEPTH {
+            return Err(FontError::Format("composite glyphs nested too deeply".to_string()));
+        }
+        let i: usize = glyph as usize;
+        if i + 1 >= self.loca.len() || self.loca[i] >= self.loca[i + 1] {
+            return Ok(());  //Empty glyph, such as a space
+        }
+        let d: &[u8] = &self.data;
+        let start: usize = self.glyf + self.loca[i] as usize;
+        let num_contours: i16 = read_i16(d, start)?;
+        let transform = |x: f64, y: f64| (m[0]*x + m[2]*y + m[4], m[1]*x + m[3]*y + m[5]);
+
+        if num_contours >= 0 {
+            let n: usize = num_contours as usize;
+            let mut end_points: Vec<usize> = Vec::with_capacity(n);
+            for c in 0..n {
+                end_points.push(read_u16(d, start + 10 + 2*c)? as usize);
+            }
+            let num_points: usize = end_points.last().map_or(0, |e| e + 1);
+            let instructions: usize = read_u16(d, start + 10 + 2*n)? as usize;
+            let mut pos: usize = start + 12 + 2*n + instructions;
+
+            let mut flags: Vec<u8> = Vec::with_capacity(num_points);
+            while flags.len() < num_points {
+                let flag: u8 = read_u8(d, pos)?;
+                pos += 1;
+                flags.push(flag);
+                if flag & 0x08 != 0 {
+                    let repeat: u8 = read_u8(d, pos)?;
+                    pos += 1;
+                    for _ in 0..repeat {
+                        flags.push(flag);
+                    }
+                }
+            }
+            flags.truncate(num_points);
+
+            //Coordinates are deltas, either a byte with a sign flag or a signed word unless repeated
+            let mut read_coords = |short_bit: u8, same_bit: u8| -> Result<Vec<f64>, FontError> {
+                let mut value: i32 = 0;
+                let mut coords: Vec<f64> = Vec::with_capacity(num_points);
+                for flag in flags.iter() {
+                    if flag & short_bit != 0 {
+                        let delta: i32 = read_u8(d, pos)? as i32;
+                        pos += 1;
+                        value += if flag & same_bit != 0 { delta } else { -delta };
+                    }
+                    else if flag & same_bit == 0 {
+                        value += read_i16(d, pos)? as i32;
+                        pos += 2;
+                    }
+                    coords.push(value as f64);
+                }
+                Ok(coords)
+            };
+            let xs: Vec<f64> = read_coords(0x02, 0x10)?;
+            let ys: Vec<f64> = read_coords(0x04, 0x20)?;
+
+            let mut first: usize = 0;
+            for &end in end_points.iter() {
+                if end < first || end >= num_points {
+                    return Err(FontError::Format(format!("bad contour end point in glyph {}", glyph)));
+                }
+                let contour: Vec<(f64, f64, bool)> = (first..=end)
+                    .map(|p| {
+                        let (x, y) = transform(xs[p], ys[p]);
+                        (x, y, flags[p] & 0x01 != 0)
+                    })
+                    .collect();
+                if contour.len() > 1 {
+                    out.push(contour);
+                }
+                first = end + 1;
+            }
+        }
+        else {
+            let mut pos: usize = start + 10;
+            loop {
+                let flags: u16 = read_u16(d, pos)?;
+                let component: u16 = read_u16(d, pos + 2)?;
+                pos += 4;
+                let (dx, dy) = if flags & 0x0001 != 0 {
+                    pos += 4;
+                    (read_i16(d, pos - 4)? as f64, read_i16(d, pos - 2)? as f64)
+                }
+                else {
+                    pos += 2;
+                    (read_u8(d, pos - 2)? as i8 as f64, read_u8(d, pos - 1)? as i8 as f64)
+                };
+                //Point-matched placement (ARGS_ARE_XY_VALUES unset) is rare and not supported; such parts go at the origin
+                let (dx, dy) = if flags & 0x0002 != 0 { (dx, dy) } else { (0.0, 0.0) };
+                let f2dot14 = |o: usize| -> Result<f64, FontError> { Ok(read_i16(d, o)? as f64 / 16384.0) };
+                let (a, b, c, e) = if flags & 0x0008 != 0 {
+                    pos += 2;
+                    let s: f64 = f2dot14(pos - 2)?;
+                    (s, 0.0, 0.0, s)
+                }
+                else if flags & 0x0040 != 0 {
+                    pos += 4;
+                    (f2dot14(pos - 4)?, 0.0, 0.0, f2dot14(pos - 2)?)
+                }
+                else if flags & 0x0080 != 0 {
+                    pos += 8;
+                    (f2dot14(pos - 8)?, f2dot14(pos - 6)?, f2dot14(pos - 4)?, f2dot14(pos - 2)?)
+                }
+                else {
+                    (1.0, 0.0, 0.0, 1.0)
+                };
+                let child: [f64; 6] = [
+                    m[0]*a + m[2]*b,
+                    m[1]*a + m[3]*b,
+                    m[0]*c + m[2]*e,
+                    m[1]*c + m[3]*e,
+                    m[0]*dx + m[2]*dy + m[4],
+                    m[1]*dx + m[3]*dy + m[5],
+                ];
+                self.read_contours(component, child, depth + 1, out)?;
+                if flags & 0x0020 == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl CRen {
+
+    ///Draws `text` with `font` at `size` pixels per em, with the top-left corner of its first line at `(x, y)`.
+    pub fn draw_ttf_text(&mut self, font: &mut TrueTypeFont, size: f64, (x, y): (f64, f64), text: &str, color: Color) {
+        self.draw_ttf_text_aligned(font, size, (x, y), text, TextAlign::Left, color);
+    }
+
+    ///Draws `text` starting at `(x, y)` with each line aligned to `x`, as in `draw_text_aligned`.
+    pub fn draw_ttf_text_aligned(&mut self, font: &mut TrueTypeFont, size: f64, (x, y): (f64, f64), text: &str, align: TextAlign, color: Color) {
+        for (i, line) in text.split('\n').enumerate() {
+            let lx: f64 = align_x(x, font.line_width(line, size), align);
+            self.draw_ttf_line(font, size, lx, y + i as f64*font.line_height(size), line, color);
+        }
+    }
+
+    ///Word-wraps `text` to the rectangle at `(x, y)` of size `w` x `h` and draws the lines that fit.
+    pub fn draw_ttf_text_in_rect(&mut self, font: &mut TrueTypeFont, size: f64, rect: (f64, f64, f64, f64), text: &str, align: TextAlign, color: Color) {
+        let (x, y, w, h) = rect;
+        let anchor: f64 = match align {
+            TextAlign::Left => x,
+            TextAlign::Center => x + w/2.0,
+            TextAlign::Right => x + w,
+        };
+        let line_height: f64 = font.line_height(size);
+        for (i, line) in font.wrap(text, size, w).iter().enumerate() {
+            let ly: f64 = y + i as f64*line_height;
+            if ly + line_height > y + h {
+                break;
+            }
+            let lx: f64 = align_x(anchor, font.line_width(line, size), align);
+            self.draw_ttf_line(font, size, lx, ly, line, color);
+        }
+    }
+
+    fn draw_ttf_line(&mut self, font: &mut TrueTypeFont, size: f64, x: f64, y: f64, line: &str, color: Color) {
+        let scale: f64 = font.scale(size);
+        let baseline: i32 = (y + font.ascent(size)).round() as i32;
+        let mut pen: f64 = x;
+        let mut previous: Option<u16> = None;
+        for c in line.chars() {
+            let glyph: u16 = font.glyph_index(c);
+            if let Some(p) = previous {
+                pen += font.kerning(p, glyph)*scale;
+            }
+            //Malformed glyphs are skipped rather than aborting the whole line
+            if let Ok(entry) = font.rasterize_glyph(glyph, size) {
+                let (gx, gy) = (pen.round() as i32 + entry.offset_x, baseline + entry.offset_y);
+                for j in 0..entry.height {
+                    for i in 0..entry.width {
+                        let level: u32 = font.atlas.raster.data[(entry.x + i) + font.atlas.raster.width*(entry.y + j)] & 0xff;
+                        if level > 0 {
+                            self.blend_pixel(gx + i as i32, gy + j as i32, color, level as f32 / 255.0);
+                        }
+                    }
+                }
+            }
+            pen += font.advance(glyph)*scale;
+            previous = Some(glyph);
+        }
+    }
+
+}
+
+fn align_x(anchor: f64, width: f64, align: TextAlign) -> f64 {
+    match align {
+        TextAlign::Left => anchor,
+        TextAlign::Center => anchor - width/2.0,
+        TextAlign::Right => anchor - width,
+    }
+}
+
+//Sizes are cached in 1/64 pixel steps
+fn size_key(size: f64) -> u32 {
+    (size*64.0).round().max(0.0) as u32
+}
+
+///Maps character codes to glyph indices from the best Unicode subtable: format 12 (full range) or 4 (BMP).
+fn parse_cmap(d: &[u8], cmap: usize) -> Result<HashMap<u32, u16>, FontError> {
+    let mut best: Option<(u32, usize)> = None;
+    for i in 0..read_u16(d, cmap + 2)? as usize {
+        let platform: u16 = read_u16(d, cmap + 4 + 8*i)?;
+        let encoding: u16 = read_u16(d, cmap + 4 + 8*i + 2)?;
+        let offset: usize = cmap + read_u32(d, cmap + 4 + 8*i + 4)? as usize;
+        let format: u16 = read_u16(d, offset)?;
+        let rank: u32 = match (platform, encoding, format) {
+            (3, 10, 12) | (0, _, 12) => 3,
+            (3, 1, 4) | (0, _, 4) => 2,
+            (_, _, 4) | (_, _, 12) => 1,
+            _ => 0,
+        };
+        if rank > 0 && best.is_none_or(|b| rank > b.0) {
+            best = Some((rank, offset));
+        }
+    }
+    let offset: usize = best.ok_or_else(|| FontError::Format("no supported Unicode cmap subtable".to_string()))?.1;
+
+    let mut map: HashMap<u32, u16> = HashMap::new();
+    if read_u16(d, offset)? == 12 {
+        for g in 0..read_u32(d, offset + 12)? as usize {
+            let group: usize = offset + 16 + 12*g;
+            let (first, last, glyph) = (read_u32(d, group)?, read_u32(d, group + 4)?, read_u32(d, group + 8)?);
+            let last: u32 = last.min(0x10ffff);
+            //Groups whose glyph ids run past what a font can hold are corrupt
+            if last < first || glyph.checked_add(last - first).is_none_or(|end| end > u16::MAX as u32) {
+                continue;
+            }
+            for c in first..=last {
+                map.insert(c, (glyph + (c - first)) as u16);
+            }
+        }
+    }
+    else {
+        let segments: usize = read_u16(d, offset + 6)? as usize / 2;
+        let ends: usize = offset + 14;
+        let starts: usize = ends + 2*segments + 2;
+        let deltas: usize = starts + 2*segments;
+        let range_offsets: usize = deltas + 2*segments;
+        for s in 0..segments {
+            let (start, end) = (read_u16(d, starts + 2*s)?, read_u16(d, ends + 2*s)?);
+            let delta: u16 = read_u16(d, deltas + 2*s)?;
+            let range_offset: usize = read_u16(d, range_offsets + 2*s)? as usize;
+            for c in start..=end {
+                if c == 0xffff {
+                    break;
+                }
+                let glyph: u16 = if range_offset == 0 {
+                    c.wrapping_add(delta)
+                }
+                else {
+                    //The offset is relative to the idRangeOffset entry itself
+                    let at: usize = range_offsets + 2*s + range_offset + 2*(c - start) as usize;
+                    match read_u16(d, at)? {
+                        0 => 0,
+                        g => g.wrapping_add(delta),
+                    }
+                };
+                if glyph != 0 {
+                    map.insert(c as u32, glyph);
+                }
+            }
+        }
+    }
+    Ok(map)
+}
+
+///Reads horizontal format 0 subtables of a version 0 `kern` table.
+fn parse_kern(d: &[u8], kern: usize) -> Result<HashMap<(u16, u16), i16>, FontError> {
+    let mut pairs: HashMap<(u16, u16), i16> = HashMap::new();
+    if read_u16(d, kern)? != 0 {
+        return Ok(pairs);
+    }
+    let mut pos: usize = kern + 4;
+    for _ in 0..read_u16(d, kern + 2)? {
+        let length: usize = read_u16(d, pos + 2)? as usize;
+        let coverage: u16 = read_u16(d, pos + 4)?;
+        if coverage >> 8 == 0 && coverage & 0x01 != 0 {
+            for p in 0..read_u16(d, pos + 6)? as usize {
+                let pair: usize = pos + 14 + 6*p;
+                pairs.insert((read_u16(d, pair)?, read_u16(d, pair + 2)?), read_i16(d, pair + 4)?);
+            }
+        }
+        pos += length.max(6);
+    }
+    Ok(pairs)
+}
+
+fn bytes(d: &[u8], offset: usize, len: usize) -> Result<&[u8], FontError> {
+    d.get(offset..offset.saturating_add(len)).ok_or_else(|| FontError::Format("unexpected end of font data".to_string()))
+}
+
+fn read_u8(d: &[u8], offset: usize) -> Result<u8, FontError> {
+    Ok(bytes(d, offset, 1)?[0])
+}
+
+fn read_u16(d: &[u8], offset: usize) -> Result<u16, FontError> {
+    let b: &[u8] = bytes(d, offset, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_i16(d: &[u8], offset: usize) -> Result<i16, FontError> {
+    Ok(read_u16(d, offset)? as i16)
+}
+
+fn read_u32(d: &[u8], offset: usize) -> Result<u32, FontError> {
+    let b: &[u8] = bytes(d, offset, 4)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn be16(out: &mut Vec<u8>, values: &[i32]) {
+        for v in values.iter() {
+            out.extend_from_slice(&(*v as u16).to_be_bytes());
+        }
+    }
+
+    ///Format 4 cmap mapping 'A' and 'B' to glyphs 1 and 2.
+    fn cmap4() -> Vec<u8> {
+        let mut t: Vec<u8> = Vec::new();
+        be16(&mut t, &[0, 1, 3, 1]);
+        t.extend_from_slice(&12u32.to_be_bytes());
+        be16(&mut t, &[4, 32, 0, 4, 0, 0, 0]);
+        be16(&mut t, &[0x42, 0xffff, 0, 0x41, 0xffff, 1 - 0x41, 1, 0, 0]);
+        t
+    }
+
+    ///A font with 1000 units per em, an empty glyph 0, a 500x500 square as glyph 1 and glyph 2 made of glyph 1 moved
+    /// 200 units right.
+    fn font_data(cmap: Vec<u8>) -> Vec<u8> {
+        let mut head: Vec<u8> = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut maxp: Vec<u8> = vec![0, 0, 0x50, 0];
+        be16(&mut maxp, &[3]);
+        let mut hhea: Vec<u8> = vec![0; 36];
+        hhea[4..10].copy_from_slice(&[0x03, 0x20, 0xff, 0x38, 0, 0]);
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+        let mut hmtx: Vec<u8> = Vec::new();
+        be16(&mut hmtx, &[700, 0, 500, 100, 0]);
+        let mut glyf: Vec<u8> = Vec::new();
+        be16(&mut glyf, &[1, 100, 0, 600, 500, 3, 0]);
+        glyf.extend_from_slice(&[1, 1, 1, 1]);
+        be16(&mut glyf, &[100, 500, 0, -500, 0, 0, 500, 0]);
+        be16(&mut glyf, &[-1, 300, 0, 800, 500, 0x0003, 1, 200, 0]);
+        let mut loca: Vec<u8> = Vec::new();
+        be16(&mut loca, &[0, 0, 17, 26]);
+        let mut kern: Vec<u8> = Vec::new();
+        be16(&mut kern, &[0, 1, 0, 20, 0x0001, 1, 0, 0, 0, 1, 2, -50]);
+
+        let tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (b"cmap", cmap), (b"glyf", glyf), (b"head", head), (b"hhea", hhea),
+            (b"hmtx", hmtx), (b"kern", kern), (b"loca", loca), (b"maxp", maxp),
+        ];
+        let mut data: Vec<u8> = vec![0, 1, 0, 0];
+        be16(&mut data, &[tables.len() as i32, 0, 0, 0]);
+        let mut offset: usize = 12 + 16*tables.len();
+        for (tag, t) in tables.iter() {
+            data.extend_from_slice(*tag);
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(t.len() as u32).to_be_bytes());
+            offset += t.len();
+        }
+        for (_, t) in tables.iter() {
+            data.extend_from_slice(t);
+        }
+        data
+    }
+
+    fn font() -> TrueTypeFont {
+        TrueTypeFont::from_bytes(font_data(cmap4())).unwrap()
+    }
+
+    #[test]
+    fn parse_tables() {
+        let font: TrueTypeFont = font();
+        assert_eq!((font.units_per_em, font.num_glyphs), (1000, 3));
+        assert_eq!((font.metrics.ascender, font.metrics.descender), (800.0, -200.0));
+        assert_eq!((font.glyph_index('A'), font.glyph_index('B'), font.glyph_index('C')), (1, 2, 0));
+        //Glyph 2 is past numberOfHMetrics, so it reuses the last advance
+        assert_eq!((font.advance(0), font.advance(1), font.advance(2)), (700.0, 500.0, 500.0));
+        assert_eq!(font.kerning(1, 2), -50.0);
+        assert_eq!(font.line_width("AB", 1000.0), 950.0);
+        assert_eq!((font.ascent(100.0), font.line_height(100.0)), (80.0, 100.0));
+        assert_eq!(font.measure("A\nAB", 10.0), (9.5, 20.0));
+    }
+
+    #[test]
+    fn glyph_outlines() {
+        let font: TrueTypeFont = font();
+        //Contours are closed with a line back to their first point
+        let square: Vec<(f64, f64)> = vec![(100.0, 0.0), (600.0, 0.0), (600.0, -500.0), (100.0, -500.0), (100.0, 0.0)];
+        let outline = |glyph: u16| font.glyph_path(glyph, 1000.0).unwrap().flatten(DEFAULT_TOLERANCE);
+        assert_eq!(outline(1)[0].points, square);
+        let moved: Vec<(f64, f64)> = square.iter().map(|p| (p.0 + 200.0, p.1)).collect();
+        assert_eq!(outline(2)[0].points, moved);
+        assert!(outline(0).is_empty());
+    }
+
+    #[test]
+    fn rasterize_and_cache() {
+        let mut font: TrueTypeFont = font();
+        let entry: AtlasEntry = font.rasterize_glyph(1, 10.0).unwrap();
+        assert_eq!((entry.offset_x, entry.offset_y, entry.width, entry.height), (0, -6, 8, 8));
+        let mut total: f64 = 0.0;
+        for j in 0..entry.height {
+            for i in 0..entry.width {
+                total += (font.atlas.raster.data[(entry.x + i) + ATLAS_WIDTH*(entry.y + j)] & 0xff) as f64 / 255.0;
+            }
+        }
+        assert!((total - 25.0).abs() < 0.1);
+
+        let again: AtlasEntry = font.rasterize_glyph(1, 10.0).unwrap();
+        assert_eq!((again.x, again.y, font.atlas.len()), (entry.x, entry.y, 1));
+        font.rasterize_glyph(1, 12.0).unwrap();
+        assert_eq!(font.atlas.len(), 2);
+
+        //1000 pixels wide does not fit the atlas
+        assert!(font.rasterize_glyph(1, 2000.0).is_err());
+        assert!(font.rasterize_glyph(1, f64::NAN).is_err());
+        assert_eq!(font.atlas.len(), 2);
+    }
+
+    #[test]
+    fn draw_text() {
+        let mut font: TrueTypeFont = font();
+        let mut ren: CRen = CRen::new(32, 16);
+        ren.draw_ttf_text(&mut font, 10.0, (2.0, 0.0), "A", Color::WHITE);
+        //The square spans x 1..6 past the pen and sits on the baseline 8 pixels down
+        assert_eq!(ren.raster.data[5 + 32*5], 0x00ffffff);
+        assert_eq!(ren.raster.data[5 + 32*10], 0);
+        assert_eq!(ren.raster.data[12 + 32*5], 0);
+    }
+
+    #[test]
+    fn bad_fonts() {
+        assert!(TrueTypeFont::from_bytes(vec![0, 1]).is_err());
+        assert!(TrueTypeFont::from_bytes(b"OTTO\0\0\0\0\0\0\0\0".to_vec()).is_err());
+        let mut data: Vec<u8> = font_data(cmap4());
+        data.truncate(data.len() - 1);
+        assert!(TrueTypeFont::from_bytes(data).is_err());
+        //No Unicode subtable at all
+        let mut cmap: Vec<u8> = Vec::new();
+        be16(&mut cmap, &[0, 1, 3, 1, 0, 12, 6]);
+        assert!(TrueTypeFont::from_bytes(font_data(cmap)).is_err());
+    }
+
+    #[test]
+    fn cmap_format_12() {
+        let mut cmap: Vec<u8> = Vec::new();
+        be16(&mut cmap, &[0, 1, 3, 10, 0, 12, 12, 0]);
+        for v in [16 + 12*3, 0, 3, 0x1f600, 0x1f601, 1, 0x41, 0x42, 0xffff, 0x50, 0x40, 5] {
+            cmap.extend_from_slice(&(v as u32).to_be_bytes());
+        }
+        let map: HashMap<u32, u16> = parse_cmap(&cmap, 0).unwrap();
+        assert_eq!((map[&0x1f600], map[&0x1f601]), (1, 2));
+        //Glyph ids past 65535 and reversed ranges are skipped
+        assert_eq!(map.len(), 2);
+    }
+
+}
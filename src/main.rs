@@ -1,4 +1,5 @@
 
+pub mod audio;
 pub mod framework;
 pub mod graphics;
 pub mod math;
@@ -11,6 +12,7 @@ use math::*;
 
 use cren::CRen;
 use cwin::CWin;
+use capp::{App, Runner};
 use cmodel::CModel;
 use cmodel_instance::CModelInstance;
 use ctransform::CTransform;
@@ -19,47 +21,63 @@ use math::cmatrix;
 use cmatrix::Matrix3x3;
 use cmatrix::Vec3;
 
-fn main() {
+//Spins the cube at a constant speed whatever the frame rate
+struct Demo {
+    camera: CCamera,
+    scene: Vec<CModelInstance>,  //The CModelInstances live here!!
+    prev_rot: (f64, f64, f64),
+}
 
-    let mut angle: f64 = 0.0;
+impl App for Demo {
 
-    // let mut cube1: CModelInstance = cmodel_instance::new_cube(-2.0, 0.0, 10.0);
-    // let mut cube2: CModelInstance = cmodel_instance::new_cube(2.0, 0.0, 10.0);
-    let mut cube3: CModelInstance = cmodel_instance::new_cube(0.0, 0.0, 10.0);
+    fn update(&mut self, _runner: &mut Runner, dt: f64) {
+        self.prev_rot = self.scene[0].transform.rot;
+
+        self.scene[0].transform.rot.0 += 0.6*dt;
+        self.scene[0].transform.rot.1 += 0.3*dt;
+        self.scene[0].transform.rot.2 += 0.15*dt;
 
-    let mut camera: CCamera = ccamera::new();
-    let mut ren: CRen = CRen::new(1066, 800);
-    let mut win: cwin::CWin = CWin::new(&ren.raster);
+        // self.scene[1].transform.rot.1 += 0.6*dt;
+        // self.scene[1].transform.rot.2 -= 0.3*dt;
+        // self.scene[1].transform.rot.0 += 0.15*dt;
 
-    let mut scene: Vec<CModelInstance> = Vec::<CModelInstance>::new();  //The CModelInstances live here!!
-    // scene.push(cube1);
-    // scene.push(cube2);
-    scene.push(cube3);
+        // self.scene[0].transform.pos.2 += 3.0*dt;
 
-    while win.is_open() {
+        // self.camera.transform.rot.1 += 0.06*dt;
+    }
 
-        scene[0].transform.rot.0 += 0.01;
-        scene[0].transform.rot.1 += 0.005;
-        scene[0].transform.rot.2 += 0.0025;
+    fn render(&mut self, runner: &mut Runner, alpha: f64) {
+        //Draw the cube part of the way between its last two updates
+        let rot: (f64, f64, f64) = self.scene[0].transform.rot;
+        let lerp = |a: f64, b: f64| a + (b - a)*alpha;
+        self.scene[0].transform.rot = (lerp(self.prev_rot.0, rot.0), lerp(self.prev_rot.1, rot.1), lerp(self.prev_rot.2, rot.2));
 
-        // scene[1].transform.rot.1 += 0.01;
-        // scene[1].transform.rot.2 -= 0.005;
-        // scene[1].transform.rot.0 += 0.0025;
+        runner.ren.clear();
+        runner.ren.render_scene(&self.camera, &self.scene);
 
-        // scene[0].transform.rot.1 -= 0.001;
-        // scene[0].transform.rot.0 += 0.0005;
-        // scene[0].transform.rot.2 -= 0.00025;
+        self.scene[0].transform.rot = rot;
+    }
 
-        // scene[0].transform.pos.2 += 0.05;
+}
 
-        // camera.transform.rot.1 += 0.001;
+fn main() {
 
-        // camera.transform.rot.0 -= 0.01;
+    // let mut cube1: CModelInstance = cmodel_instance::new_cube(-2.0, 0.0, 10.0);
+    // let mut cube2: CModelInstance = cmodel_instance::new_cube(2.0, 0.0, 10.0);
+    let cube3: CModelInstance = cmodel_instance::new_cube(0.0, 0.0, 10.0);
 
-        ren.render_scene(&camera, &scene);
-        win.draw(&ren.raster);
-        ren.clear();
+    let ren: CRen = CRen::new(1066, 800);
+    let win: cwin::CWin = CWin::new(&ren.raster);
 
+    let mut demo: Demo = Demo {
+        camera: ccamera::new(),
+        scene: Vec::<CModelInstance>::new(),
+        prev_rot: cube3.transform.rot,
     };
+    // demo.scene.push(cube1);
+    // demo.scene.push(cube2);
+    demo.scene.push(cube3);
+
+    Runner::new(win, ren).run(&mut demo);
 
 }